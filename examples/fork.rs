@@ -9,7 +9,7 @@ fn print(msg: impl AsRef<str>) {
 fn main() {
     match unsafe { fork() } {
         Ok(ForkResult::Child) => {
-            print(format!("child"));
+            print("child");
         }
         Ok(ForkResult::Parent { child }) => {
             print(format!("parent of {}", child));
@@ -0,0 +1,234 @@
+//! Notifications for when the total RSS crosses a watermark, so `max_rss` can be used as a simple
+//! memory watchdog.
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Error, Result};
+use nix::unistd::Pid;
+use serde_json::{json, Value};
+
+use crate::cli::Args;
+use crate::time::Clock;
+use crate::units::format_bytes;
+
+/// How long to wait before alerting again if `--alert-cooldown` isn't given.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// How an alert is delivered. A warning is always printed to stderr as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notify {
+    /// Runs a shell command, with the details of the alert in its environment.
+    Exec(String),
+    /// Writes the alert as a line of JSON to a unix socket.
+    Socket(PathBuf),
+    /// Shows a desktop notification with `notify-send`.
+    Desktop,
+}
+
+impl FromStr for Notify {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            Some(("exec", cmd)) => Notify::Exec(cmd.into()),
+            Some(("socket", path)) => Notify::Socket(path.into()),
+            None if s == "desktop" => Notify::Desktop,
+            _ => bail!("unknown notification: {}", s),
+        })
+    }
+}
+
+impl Notify {
+    fn send(&self, details: &Value, message: &str) -> Result<()> {
+        match self {
+            Notify::Exec(cmd) => {
+                let mut command = Command::new("sh");
+                command
+                    .args(["-c", cmd])
+                    .env("MAX_RSS_ALERT_RSS", details["rss"].to_string())
+                    .env("MAX_RSS_ALERT_WATERMARK", details["watermark"].to_string())
+                    .stdin(Stdio::null());
+                spawn(command);
+            }
+            Notify::Socket(path) => {
+                let mut stream = UnixStream::connect(path)?;
+                writeln!(stream, "{}", details)?;
+            }
+            Notify::Desktop => {
+                let mut command = Command::new("notify-send");
+                command
+                    .args([env!("CARGO_PKG_NAME"), message])
+                    .stdin(Stdio::null());
+                spawn(command);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The notification commands which are still running. These are our children just like the
+/// processes orphaned by COMMAND, so the tracer needs to be able to tell them apart.
+static HELPERS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Whether the process is a notification command we're running.
+pub fn is_helper(pid: Pid) -> bool {
+    HELPERS
+        .lock()
+        .is_ok_and(|helpers| helpers.contains(&(pid.as_raw() as u32)))
+}
+
+/// Runs the command in the background, so a slow notification doesn't hold up tracing. It's started
+/// on the calling (tracer's) thread, so it's known to be a helper before the tracer can see it.
+fn spawn(mut command: Command) {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("{}: failed to send alert: {}", env!("CARGO_PKG_NAME"), e);
+            return;
+        }
+    };
+    let id = child.id();
+    if let Ok(mut helpers) = HELPERS.lock() {
+        helpers.push(id);
+    }
+
+    thread::spawn(move || {
+        match child.wait() {
+            // the tracer waits for any of our children, so may have reaped it first
+            Err(e) if e.raw_os_error() == Some(nix::libc::ECHILD) => {}
+            Err(e) => eprintln!("{}: failed to send alert: {}", env!("CARGO_PKG_NAME"), e),
+            Ok(_) => {}
+        }
+        if let Ok(mut helpers) = HELPERS.lock() {
+            helpers.retain(|helper| *helper != id);
+        }
+    });
+}
+
+pub struct Alert {
+    watermark: u64,
+    cooldown: Duration,
+    notify: Vec<Notify>,
+    clock: Clock,
+    /// Whether the last sample was over the watermark, so we only alert when it's crossed.
+    above: bool,
+    last_fired: Option<Instant>,
+    fired: usize,
+}
+
+impl Alert {
+    /// Starts watching for the watermark, if one was given.
+    pub fn create(args: &Args) -> Option<Alert> {
+        Some(Alert {
+            watermark: args.alert?,
+            cooldown: args.alert_cooldown,
+            notify: args.alert_notify.clone(),
+            clock: args.clock,
+            above: false,
+            last_fired: None,
+            fired: 0,
+        })
+    }
+
+    /// Whether an alert should fire for this sample.
+    fn crossed(&mut self, rss: u64) -> bool {
+        let was_above = self.above;
+        self.above = rss >= self.watermark;
+
+        let cooled_down = self
+            .last_fired
+            .is_none_or(|last| last.elapsed() >= self.cooldown);
+        self.above && !was_above && cooled_down
+    }
+
+    /// Checks a sample of the total RSS, and sends notifications if it crossed the watermark.
+    pub fn check(&mut self, rss: u64) {
+        if !self.crossed(rss) {
+            return;
+        }
+
+        self.last_fired = Some(Instant::now());
+        self.fired += 1;
+
+        let message = format!(
+            "total RSS {} crossed the watermark of {}",
+            format_bytes(rss),
+            format_bytes(self.watermark)
+        );
+        eprintln!("{}: alert: {}", env!("CARGO_PKG_NAME"), message);
+
+        let details = json!({
+            "rss": rss,
+            "watermark": self.watermark,
+            "time": self.clock.timestamp(SystemTime::now()),
+        });
+
+        // failing to notify shouldn't interrupt the COMMAND we're watching
+        for notify in &self.notify {
+            if let Err(e) = notify.send(&details, &message) {
+                eprintln!("{}: failed to send alert: {}", env!("CARGO_PKG_NAME"), e);
+            }
+        }
+    }
+
+    /// How many alerts fired.
+    pub fn fired(&self) -> usize {
+        self.fired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify() {
+        assert_eq!(
+            "exec:echo hi".parse::<Notify>().unwrap(),
+            Notify::Exec("echo hi".into())
+        );
+        assert_eq!(
+            "socket:/tmp/a.sock".parse::<Notify>().unwrap(),
+            Notify::Socket("/tmp/a.sock".into())
+        );
+        assert_eq!("desktop".parse::<Notify>().unwrap(), Notify::Desktop);
+        assert!("email:me".parse::<Notify>().is_err());
+    }
+
+    #[test]
+    fn crossings() {
+        let args = Args {
+            alert: Some(100),
+            alert_cooldown: Duration::ZERO,
+            ..Args::default()
+        };
+        let mut alert = Alert::create(&args).unwrap();
+        let fired = [50, 150, 200, 50, 100]
+            .into_iter()
+            .map(|rss| alert.crossed(rss))
+            .collect::<Vec<_>>();
+        assert_eq!(fired, [false, true, false, false, true]);
+    }
+
+    #[test]
+    fn cooldown() {
+        let args = Args {
+            alert: Some(100),
+            alert_cooldown: Duration::from_secs(3600),
+            ..Args::default()
+        };
+        let mut alert = Alert::create(&args).unwrap();
+        alert.check(150);
+        alert.check(50);
+        alert.check(150);
+        assert_eq!(alert.fired(), 1);
+    }
+}
@@ -0,0 +1,356 @@
+//! The `max_rss` command line program: runs COMMAND with the chosen backend, and then writes and
+//! acts on the results.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Result};
+use serde_json::{json, Map, Value};
+
+use crate::child::{self, Inherited, Trace};
+use crate::cli::{Args, Subcommand};
+use crate::exec::ExecFailed;
+use crate::exitcode::ExitWith;
+use crate::output::{self, OutputFormat};
+use crate::permission::TraceDenied;
+use crate::rundir::RunDir;
+use crate::stats::Stats;
+use crate::{
+    bazel, calibrate, compare, github, merge, noise, otlp, platform, redact, statsd, steps,
+};
+
+/// The name of the COMMAND, as used in output file names.
+fn command_name(args: &Args) -> String {
+    match args.command.first() {
+        Some(cmd) => Path::new(cmd)
+            .file_name()
+            .unwrap_or(cmd)
+            .to_string_lossy()
+            .into_owned(),
+        None => match args.pid {
+            Some(pid) => format!("pid-{}", pid),
+            None => format!("{:?}", args.subcommand).to_lowercase(),
+        },
+    }
+}
+
+/// Describes the run itself: exactly what was run, when (per `--clock`) and for how long, on which
+/// machine, and by which version of this program.
+fn meta(args: &Args, started_at: SystemTime, finished_at: SystemTime, elapsed: Duration) -> Value {
+    let command = args
+        .command
+        .iter()
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>();
+
+    let tags = args
+        .tags
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+        .collect::<Map<_, _>>();
+
+    let mut meta = json!({
+        "command": (!command.is_empty()).then_some(command),
+        "started_at": args.clock.timestamp(started_at),
+        "finished_at": args.clock.timestamp(finished_at),
+        "duration_ms": elapsed.as_secs_f64() * 1000.0,
+        "version": env!("CARGO_PKG_VERSION"),
+        "tags": (!tags.is_empty()).then_some(tags),
+    });
+    if let (Value::Object(meta), Value::Object(host)) = (&mut meta, platform::host()) {
+        meta.extend(host);
+    }
+
+    meta
+}
+
+/// Runs COMMAND `--runs` times (once by default), and returns the results of the last run along
+/// with the max_rss of each. Stops early if a run fails, since the rest would likely fail as well.
+fn trace_runs(args: &Args, inherited: &Inherited) -> Result<(Trace, Vec<u64>)> {
+    let runs = args.runs.unwrap_or(1);
+    let mut values = vec![];
+    loop {
+        if args.debug && runs > 1 {
+            eprintln!("::: run {}/{}", values.len() + 1, runs);
+        }
+
+        let trace = args.backend.trace(args, inherited)?;
+        values.push(trace.report["max_rss"].as_u64().unwrap_or(0));
+        if values.len() == runs || trace.exit_code != 0 || trace.error.is_some() {
+            return Ok((trace, values));
+        }
+    }
+}
+
+fn write_report(args: &Args, report: &Value, snapshots: &HashMap<i32, String>) -> Result<()> {
+    let cmd = command_name(args);
+    let redact = |s: &str| match &args.redact_args {
+        Some(pattern) => redact::redact_str(s, pattern),
+        None => s.to_string(),
+    };
+
+    let mut report = report.clone();
+    if let Some(pattern) = &args.redact_args {
+        redact::redact_strings(&mut report, pattern);
+    }
+
+    match &args.out_dir {
+        Some(base) => {
+            let mut dir = RunDir::create(base, &cmd)?;
+            dir.write_report(args.output_format, &report)?;
+
+            let mut snapshots = snapshots.iter().collect::<Vec<_>>();
+            snapshots.sort();
+            for (pid, text) in snapshots {
+                dir.write_artifact("smaps", &format!("smaps/{}.txt", pid), redact(text))?;
+            }
+
+            let command = args
+                .command
+                .iter()
+                .map(|s| redact(&s.to_string_lossy()))
+                .collect::<Vec<_>>();
+            dir.write_index(&command)?;
+
+            if args.debug {
+                eprintln!("::: wrote run directory: {}", dir.path().display());
+            }
+
+            Ok(())
+        }
+        None if output::is_stdout(&args.output) => {
+            output::write_stdout(args.output_format, &report)
+        }
+        None => {
+            let path = output::resolve_path(&args.output, &cmd)?;
+            if args.append {
+                let timestamp = args.clock.timestamp(SystemTime::now());
+                return output::append_file(&path, &report, timestamp);
+            }
+
+            output::write_file(&path, args.output_format, &report)
+        }
+    }
+}
+
+/// Runs `max_rss` with the arguments it was given, see `src/main.rs`.
+pub fn main() -> Result<()> {
+    // take note of what was inherited from our parent before we change anything
+    let inherited = Inherited::capture()?;
+
+    // if we were re-executed as a calibration workload, then run it instead
+    calibrate::run_workload_from_env();
+
+    let mut args = Args::parse()?;
+
+    // under Bazel, relative outputs are written where they'll be collected
+    if !output::is_stdout(&args.output) {
+        args.output = bazel::output_path(&args.output);
+    }
+    args.out_dir = args.out_dir.as_deref().map(bazel::output_path);
+    args.badge = args.badge.as_deref().map(bazel::output_path);
+    args.timeline = args.timeline.as_deref().map(bazel::output_path);
+    args.log_child = args.log_child.as_deref().map(bazel::output_path);
+    args.stdout = args.stdout.as_deref().map(bazel::output_path);
+    args.stderr = args.stderr.as_deref().map(bazel::output_path);
+
+    match args.subcommand {
+        Subcommand::Run => {
+            // read before running anything, so a missing baseline fails fast
+            let baseline = args
+                .baseline
+                .as_deref()
+                .map(compare::read_report)
+                .transpose()?;
+            // likewise, since COMMAND can't report it once it's been forked
+            if let Some(cwd) = args.cwd.as_deref().filter(|cwd| !cwd.is_dir()) {
+                bail!("--cwd isn't a directory: {}", cwd.display());
+            }
+            let setup = steps::setup(&args.setup, args.debug)?;
+
+            let (started_at, start) = (SystemTime::now(), Instant::now());
+            let trace = trace_runs(&args, &inherited);
+            let finished_at = SystemTime::now();
+            let elapsed = start.elapsed();
+
+            // teardown runs even if tracing failed, since setup has already happened
+            let teardown = steps::teardown(&args.teardown, args.debug);
+            let (mut trace, runs) = trace?;
+            // partial results are still written, and the error is returned once that's done
+            let error = trace.error.take();
+            trace.report["wall_time_ms"] = (elapsed.as_secs_f64() * 1000.0).into();
+            trace.report["command"] = command_name(&args).into();
+            if args.runs.is_some_and(|n| n > 1) {
+                let values = runs.iter().map(|v| *v as f64).collect::<Vec<_>>();
+                trace.report["stats"] = Stats::new(&values).as_ref().map(Stats::to_json).into();
+                trace.report["runs"] = runs.into();
+            }
+            if args.shell {
+                trace.report["shell"] = true.into();
+            }
+            if !args.setup.is_empty() || !args.teardown.is_empty() {
+                trace.report["steps"] = json!({ "setup": setup, "teardown": teardown });
+            }
+
+            let max_rss = trace.report["max_rss"].as_u64().unwrap_or(0);
+            if let Some(threshold) = args.threshold {
+                trace.report["threshold"] = threshold.into();
+                trace.report["over_threshold"] = (max_rss > threshold).into();
+            }
+            if let Some(baseline) = &baseline {
+                let max_regression = args.max_regression.unwrap_or(0.0);
+                trace.report["baseline"] =
+                    compare::check_baseline(baseline, &trace.report, max_regression);
+            }
+            trace.report["meta"] = meta(&args, started_at, finished_at, elapsed);
+            trace.report["environment"] = platform::detect();
+            platform::warn_headroom(max_rss, &trace.report["environment"]);
+            if args.capture_env {
+                trace.report["env"] =
+                    redact::capture_env(child::command_env(&args), args.redact.as_ref());
+            }
+
+            write_report(&args, &trace.report, &trace.snapshots)?;
+            if args.summary {
+                eprint!("{}", output::summary(&trace.report));
+            }
+
+            if let Some(path) = &args.badge {
+                let badge = output::badge(max_rss, args.threshold);
+                output::write_file(path, OutputFormat::Json, &badge)?;
+            }
+
+            if let Some(path) = bazel::xml_output_file() {
+                let name = bazel::test_target().unwrap_or_else(|| command_name(&args));
+                fs::write(path, bazel::junit(&name, &trace.report))?;
+            }
+
+            if args.github_summary {
+                // workflow commands go to stdout, unless that's where the results went
+                let name = command_name(&args);
+                if args.out_dir.is_none() && output::is_stdout(&args.output) {
+                    github::report(&name, &trace.report, &mut io::stderr())?;
+                } else {
+                    github::report(&name, &trace.report, &mut io::stdout())?;
+                }
+            }
+
+            if let Some(endpoint) = &args.otlp {
+                let command = command_name(&args);
+                if let Err(e) = otlp::export(endpoint, &command, &trace.report, started_at) {
+                    eprintln!(
+                        "{}: warning: failed to push to --otlp: {:#}",
+                        env!("CARGO_PKG_NAME"),
+                        e
+                    );
+                }
+            }
+
+            if let Some(address) = &args.statsd {
+                if let Err(e) = statsd::send(address, &command_name(&args), &trace.report) {
+                    eprintln!(
+                        "{}: warning: failed to send to --statsd: {:#}",
+                        env!("CARGO_PKG_NAME"),
+                        e
+                    );
+                }
+            }
+
+            // COMMAND never started, which is reported the way a shell would
+            if let Some(failed) = error.as_ref().and_then(|e| e.downcast_ref::<ExecFailed>()) {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), failed);
+                match args.exit_with {
+                    ExitWith::AlwaysZero => process::exit(0),
+                    _ => process::exit(failed.exit_code),
+                }
+            }
+
+            // nothing was measured, so there's nothing partial about the results
+            if let Some(denied) = error.as_ref().and_then(|e| e.downcast_ref::<TraceDenied>()) {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), denied);
+                match args.exit_with {
+                    ExitWith::AlwaysZero => process::exit(0),
+                    _ => process::exit(trace.exit_code),
+                }
+            }
+
+            let error = error.map(|e| e.context("tracing failed, partial results were written"));
+            if args.exit_with == ExitWith::AlwaysZero {
+                if let Some(e) = error {
+                    eprintln!("Error: {:?}", e);
+                }
+                process::exit(0);
+            }
+            if let Some(e) = error {
+                return Err(e);
+            }
+
+            let over_threshold = trace.report["over_threshold"].as_bool() == Some(true);
+            if let (0, true, Some(code)) =
+                (trace.exit_code, over_threshold, args.exit_codes.threshold)
+            {
+                process::exit(code);
+            }
+
+            let regressed = trace.report["baseline"]["regressed"].as_bool() == Some(true);
+            if trace.exit_code == 0 && regressed {
+                process::exit(args.exit_codes.regression);
+            }
+
+            process::exit(trace.exit_code);
+        }
+        Subcommand::Calibrate => {
+            let report = calibrate::calibrate(&args, &inherited)?;
+            write_report(&args, &report, &HashMap::new())?;
+            calibrate::print_summary(&report);
+            Ok(())
+        }
+        Subcommand::Noise => {
+            let report = noise::noise(&args, &inherited)?;
+            write_report(&args, &report, &HashMap::new())?;
+            noise::print_summary(&report);
+            Ok(())
+        }
+        Subcommand::Compare if !args.commands.is_empty() => {
+            let report = compare::compare_commands(&args, &inherited)?;
+            write_report(&args, &report, &HashMap::new())
+        }
+        Subcommand::Compare => {
+            let (old, new) = (&args.files[0], &args.files[1]);
+            let report = compare::compare(
+                &old.to_string_lossy(),
+                &compare::read_report(old)?,
+                &new.to_string_lossy(),
+                &compare::read_report(new)?,
+            );
+            write_report(&args, &report, &HashMap::new())
+        }
+        Subcommand::Report => {
+            let report = compare::read_report(&args.files[0])?;
+            print!("{}", output::summary(&report));
+            let tree = output::tree(&report);
+            if !tree.is_empty() {
+                println!();
+                print!("{}", tree);
+            }
+            Ok(())
+        }
+        Subcommand::Merge => {
+            let reports = args
+                .files
+                .iter()
+                .map(|file| {
+                    Ok((
+                        file.to_string_lossy().into_owned(),
+                        compare::read_report(file)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            write_report(&args, &merge::merge(&reports), &HashMap::new())
+        }
+    }
+}
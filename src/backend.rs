@@ -0,0 +1,85 @@
+//! The different ways COMMAND can be measured.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::child::{Inherited, Trace};
+use crate::cli::Args;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+use crate::kqueue;
+use crate::rusage;
+#[cfg(target_os = "linux")]
+use crate::{cgroup, tracer};
+
+/// Only `rusage` works everywhere, so it's the default outside of Linux.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Trace every process in the tree with ptrace, reading each one's RSS just before it exits.
+    #[cfg_attr(target_os = "linux", default)]
+    Ptrace,
+    /// Run COMMAND in a cgroup of its own, and have the kernel account for the whole tree.
+    Cgroup,
+    /// Watch every process in the tree with kqueue, and sample their RSS while they run.
+    Kqueue,
+    /// Only wait for COMMAND, and report the kernel's `ru_maxrss` for it.
+    #[cfg_attr(not(target_os = "linux"), default)]
+    Rusage,
+}
+
+impl Backend {
+    pub const ALL: &'static [(&'static str, Backend)] = &[
+        ("ptrace", Backend::Ptrace),
+        ("cgroup", Backend::Cgroup),
+        ("kqueue", Backend::Kqueue),
+        ("rusage", Backend::Rusage),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Backend::ALL
+            .iter()
+            .find_map(|(name, backend)| (backend == self).then_some(*name))
+            .expect("unnamed backend")
+    }
+
+    /// Whether this backend can be used on the platform we were built for.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Backend::Ptrace | Backend::Cgroup => cfg!(target_os = "linux"),
+            Backend::Kqueue => cfg!(any(target_os = "macos", target_os = "freebsd")),
+            Backend::Rusage => true,
+        }
+    }
+
+    /// Runs and measures the COMMAND given in `args`.
+    pub fn trace(&self, args: &Args, inherited: &Inherited) -> Result<Trace> {
+        let mut trace = match self {
+            #[cfg(target_os = "linux")]
+            Backend::Ptrace => tracer::trace(args, inherited)?,
+            #[cfg(target_os = "linux")]
+            Backend::Cgroup => cgroup::trace(args, inherited)?,
+            #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+            Backend::Kqueue => kqueue::trace(args, inherited)?,
+            Backend::Rusage => rusage::trace(args, inherited)?,
+            _ => anyhow::bail!(
+                "--backend {} isn't available on {}",
+                self.name(),
+                std::env::consts::OS
+            ),
+        };
+
+        trace.report["backend"] = self.name().into();
+        Ok(trace)
+    }
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .iter()
+            .find_map(|(name, backend)| (*name == s).then_some(*backend))
+            .ok_or_else(|| anyhow!("unknown backend: {}", s))
+    }
+}
@@ -0,0 +1,111 @@
+//! Support for running as (or wrapping) a Bazel test action.
+//!
+//! When Bazel runs a test it sets a handful of environment variables which describe where the test
+//! may write its outputs. Anything written to `TEST_UNDECLARED_OUTPUTS_DIR` is collected and zipped
+//! up into the test's outputs, and `XML_OUTPUT_FILE` is where it looks for a JUnit style report.
+//! See: https://bazel.build/reference/test-encyclopedia
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+/// Returns the label of the test target being run, if running under Bazel.
+pub fn test_target() -> Option<String> {
+    env::var("TEST_TARGET").ok()
+}
+
+/// Returns the directory Bazel collects undeclared test outputs from, if running under Bazel.
+pub fn undeclared_outputs_dir() -> Option<PathBuf> {
+    env::var_os("TEST_UNDECLARED_OUTPUTS_DIR").map(PathBuf::from)
+}
+
+/// Returns the path at which Bazel expects a JUnit XML report, if running under Bazel.
+pub fn xml_output_file() -> Option<PathBuf> {
+    env::var_os("XML_OUTPUT_FILE").map(PathBuf::from)
+}
+
+/// Relative paths are resolved inside the undeclared outputs directory (if any), since the working
+/// directory of a Bazel test is its runfiles tree, which isn't collected.
+pub fn output_path(path: &Path) -> PathBuf {
+    match undeclared_outputs_dir() {
+        Some(dir) if path.is_relative() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Renders a JUnit XML report with a single test case for the run, which fails if the command did
+/// or if its max_rss was over the threshold.
+pub fn junit(name: &str, report: &Value) -> String {
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    let mut failures = vec![];
+    match report["exit_code"].as_i64() {
+        Some(0) | None => {}
+        Some(code) => failures.push(format!("command exited with code {}", code)),
+    }
+    if let Some(signal) = report["exit_signal"].as_i64() {
+        failures.push(format!("command was killed by signal {}", signal));
+    }
+    if report["over_threshold"].as_bool() == Some(true) {
+        failures.push(format!(
+            "max_rss {} is over the threshold of {}",
+            report["max_rss"], report["threshold"]
+        ));
+    }
+
+    let name = escape(name);
+    let failure = match failures.is_empty() {
+        true => String::new(),
+        false => format!(
+            "    <failure message=\"{}\"></failure>\n",
+            escape(&failures.join(", "))
+        ),
+    };
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="{bin}" tests="1" failures="{failures}">
+    <testcase name="{name}" classname="{bin}">
+{failure}      <system-out>max_rss: {max_rss}</system-out>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#,
+        bin = env!("CARGO_PKG_NAME"),
+        failures = failures.len().min(1),
+        max_rss = report["max_rss"],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn junit_pass() {
+        let xml = junit("ls", &json!({ "max_rss": 10, "exit_code": 0 }));
+        assert!(xml.contains(r#"failures="0""#));
+        assert!(xml.contains(r#"<testcase name="ls""#));
+        assert!(!xml.contains("<failure"));
+    }
+
+    #[test]
+    fn junit_fail() {
+        let xml = junit(
+            "<ls>",
+            &json!({ "max_rss": 10, "exit_code": 2, "threshold": 5, "over_threshold": true }),
+        );
+        assert!(xml.contains(r#"failures="1""#));
+        assert!(xml.contains(r#"<testcase name="&lt;ls&gt;""#));
+        assert!(xml.contains("command exited with code 2, max_rss 10 is over the threshold of 5"));
+    }
+}
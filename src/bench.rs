@@ -0,0 +1,132 @@
+//! A Criterion.rs measurement which reports the max_rss of a command, so memory benchmarks get
+//! Criterion's statistics and reporting for free.
+//!
+//! ```no_run
+//! use criterion::{criterion_group, criterion_main, Criterion};
+//! use max_rss::bench::MaxRss;
+//!
+//! fn bench(c: &mut Criterion<MaxRss>) {
+//!     c.bench_function("ls", |b| MaxRss::iter_command(b, &["ls", "-la"]));
+//! }
+//!
+//! criterion_group! {
+//!     name = benches;
+//!     config = Criterion::default().with_measurement(MaxRss);
+//!     targets = bench
+//! }
+//! criterion_main!(benches);
+//! ```
+
+use std::ffi::OsStr;
+use std::process::Command;
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{Bencher, Throughput};
+
+use crate::measure::measure;
+
+/// A Criterion measurement of the max_rss of a command, in bytes.
+///
+/// Memory can't be measured around an arbitrary closure, so benchmarks must use
+/// [`MaxRss::iter_command`] (or [`MaxRss::run`] within `iter_custom`) rather than `iter`.
+#[derive(Debug, Clone, Default)]
+pub struct MaxRss;
+
+impl MaxRss {
+    /// Traces the command once, and returns its max_rss in bytes.
+    ///
+    /// Panics if the command can't be measured, since that should fail the benchmark.
+    pub fn run<S: AsRef<OsStr>>(&self, command: &[S]) -> u64 {
+        let (program, args) = command.split_first().expect("no command was given");
+        let mut command = Command::new(program);
+        command.args(args);
+
+        let name = format!("{:?}", command);
+        let report =
+            measure(command).unwrap_or_else(|e| panic!("failed to measure {}: {:#}", name, e));
+        assert!(
+            report.exit_code == Some(0),
+            "benchmarked command failed: exit code {:?}, signal {:?}",
+            report.exit_code,
+            report.exit_signal
+        );
+
+        report.max_rss
+    }
+
+    /// Benchmarks the command, running it once per iteration.
+    pub fn iter_command<S: AsRef<OsStr>>(b: &mut Bencher<MaxRss>, command: &[S]) {
+        let measurement = MaxRss;
+        b.iter_custom(|iters| (0..iters).map(|_| measurement.run(command)).sum());
+    }
+}
+
+impl Measurement for MaxRss {
+    type Intermediate = ();
+    type Value = u64;
+
+    fn start(&self) -> Self::Intermediate {}
+
+    fn end(&self, _: Self::Intermediate) -> Self::Value {
+        0
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &BytesFormatter
+    }
+}
+
+struct BytesFormatter;
+
+impl ValueFormatter for BytesFormatter {
+    fn scale_values(&self, typical_value: f64, values: &mut [f64]) -> &'static str {
+        let (factor, unit) = match typical_value {
+            v if v >= 1024.0 * 1024.0 * 1024.0 => (1024.0 * 1024.0 * 1024.0, "GiB"),
+            v if v >= 1024.0 * 1024.0 => (1024.0 * 1024.0, "MiB"),
+            v if v >= 1024.0 => (1024.0, "KiB"),
+            _ => (1.0, "B"),
+        };
+
+        for v in values {
+            *v /= factor;
+        }
+
+        unit
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let per = match *throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) | Throughput::Elements(n) => n,
+        };
+
+        for v in values {
+            *v /= per as f64;
+        }
+
+        match throughput {
+            Throughput::Elements(_) => "B/elem",
+            _ => "B/B",
+        }
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "B"
+    }
+}
@@ -0,0 +1,233 @@
+//! `cargo max-rss`: builds the current project with cargo, and measures the resulting binary (or
+//! benchmarks) with `max_rss`, writing the results to `target/max_rss/`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+#[cfg(target_os = "linux")]
+use max_rss::Tracer;
+use serde_json::Value;
+
+const USAGE: &str = "\
+Build the project with cargo, and measure the max_rss of what was built.
+
+USAGE:
+    cargo max-rss run [CARGO OPTIONS] [-- ARGS...]
+    cargo max-rss bench [CARGO OPTIONS] [-- ARGS...]
+
+`run` builds with `cargo build` and measures the binary that was built
+(choose one with --bin or --example if there are several). `bench` builds
+with `cargo bench --no-run` and measures each benchmark executable in turn.
+
+CARGO OPTIONS (such as --release, --package or --features) are passed to
+cargo, and ARGS to what's being measured. The results are written as JSON
+to `target/max_rss/NAME.json`.
+
+Only ARGS can be given to what's measured, which is traced just as
+`max_rss` would with its default flags. This is only available on Linux.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subcommand {
+    Run,
+    Bench,
+}
+
+/// An executable cargo built, as reported in its `compiler-artifact` messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Executable {
+    name: String,
+    kind: String,
+    path: PathBuf,
+}
+
+/// Picks out what should be measured from cargo's `--message-format=json` output.
+fn executables(subcommand: Subcommand, messages: &str) -> Vec<Executable> {
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter(|message| match subcommand {
+            // tests and benchmarks are built with the test harness, and aren't what's wanted here
+            Subcommand::Run => message["profile"]["test"] == false,
+            Subcommand::Bench => message["profile"]["test"] == true,
+        })
+        .filter_map(|message| {
+            Some(Executable {
+                name: message["target"]["name"].as_str()?.into(),
+                kind: message["target"]["kind"][0].as_str()?.into(),
+                path: message["executable"].as_str()?.into(),
+            })
+        })
+        .collect()
+}
+
+/// Runs cargo with the given arguments, and returns its output.
+fn cargo(args: &[OsString], stdout: Stdio) -> Result<String> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(&cargo)
+        .args(args)
+        .stdout(stdout)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run {:?}", cargo))?;
+    if !output.status.success() {
+        bail!("{:?} failed ({})", cargo, output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Where cargo puts its build outputs, so the results can be written alongside them.
+fn target_directory(cargo_args: &[OsString]) -> Result<PathBuf> {
+    let mut args = vec![
+        "metadata".into(),
+        "--format-version=1".into(),
+        "--no-deps".into(),
+    ];
+    // the project being built may not be the one in the current directory
+    if let Some(i) = cargo_args.iter().position(|a| a == "--manifest-path") {
+        args.extend(cargo_args[i..].iter().take(2).cloned());
+    }
+    if let Some(arg) = cargo_args
+        .iter()
+        .find(|a| a.to_string_lossy().starts_with("--manifest-path="))
+    {
+        args.push(arg.clone());
+    }
+
+    let metadata = serde_json::from_str::<Value>(&cargo(&args, Stdio::piped())?)?;
+    match metadata["target_directory"].as_str() {
+        Some(dir) => Ok(dir.into()),
+        None => bail!("cargo metadata didn't include a target_directory"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn measure(executable: &Executable, args: &[OsString], out_dir: &Path) -> Result<bool> {
+    let mut command = Command::new(&executable.path);
+    command.args(args);
+    if executable.kind == "bench" {
+        command.arg("--bench");
+    }
+
+    let report = Tracer::spawn(command).run()?;
+
+    let path = out_dir.join(format!("{}.json", executable.name));
+    fs::write(&path, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    eprintln!(
+        "{}: max_rss {} bytes, written to {}",
+        executable.name,
+        report.max_rss,
+        path.display()
+    );
+
+    Ok(report.exit_code == Some(0))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn measure(_: &Executable, _: &[OsString], _: &Path) -> Result<bool> {
+    bail!("cargo max-rss is only available on Linux");
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args_os().skip(1).peekable();
+    // cargo runs us as `cargo-max-rss max-rss ...`
+    if args.peek().is_some_and(|arg| arg == "max-rss") {
+        args.next();
+    }
+
+    let subcommand = match args.next().as_ref().and_then(|arg| arg.to_str()) {
+        Some("run") => Subcommand::Run,
+        Some("bench") => Subcommand::Bench,
+        Some("-h" | "--help" | "help") => {
+            print!("{}", USAGE);
+            return Ok(());
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let args = args.collect::<Vec<_>>();
+    let (cargo_args, program_args) = match args.iter().position(|arg| arg == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (&args[..], &[][..]),
+    };
+
+    let mut build = match subcommand {
+        Subcommand::Run => vec!["build".into()],
+        Subcommand::Bench => vec!["bench".into(), "--no-run".into()],
+    };
+    build.push("--message-format=json-render-diagnostics".into());
+    build.extend(cargo_args.iter().cloned());
+    let messages = cargo(&build, Stdio::piped())?;
+
+    let mut executables = executables(subcommand, &messages);
+    if subcommand == Subcommand::Run {
+        executables.retain(|e| e.kind == "bin" || e.kind == "example");
+        if executables.len() > 1 {
+            let names = executables
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<_>>();
+            bail!(
+                "several binaries were built ({}), choose one with --bin or --example",
+                names.join(", ")
+            );
+        }
+    }
+    if executables.is_empty() {
+        bail!("cargo didn't build anything to measure");
+    }
+
+    let out_dir = target_directory(cargo_args)?.join("max_rss");
+    fs::create_dir_all(&out_dir)
+        .with_context(|| format!("failed to create {}", out_dir.display()))?;
+
+    let mut succeeded = true;
+    for executable in &executables {
+        succeeded &= measure(executable, program_args, &out_dir)?;
+    }
+    if !succeeded {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: &str = r#"{"reason":"compiler-artifact","target":{"kind":["lib"],"name":"foo"},"profile":{"test":false},"executable":null}
+{"reason":"compiler-artifact","target":{"kind":["bin"],"name":"foo"},"profile":{"test":false},"executable":"/t/release/foo"}
+{"reason":"compiler-artifact","target":{"kind":["bench"],"name":"speed"},"profile":{"test":true},"executable":"/t/release/deps/speed-123"}
+{"reason":"build-finished","success":true}"#;
+
+    #[test]
+    fn picks_executables() {
+        assert_eq!(
+            executables(Subcommand::Run, MESSAGES),
+            [Executable {
+                name: "foo".into(),
+                kind: "bin".into(),
+                path: "/t/release/foo".into(),
+            }]
+        );
+        assert_eq!(
+            executables(Subcommand::Bench, MESSAGES),
+            [Executable {
+                name: "speed".into(),
+                kind: "bench".into(),
+                path: "/t/release/deps/speed-123".into(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,220 @@
+//! Measures the overhead of tracing on this machine, by running a set of reference workloads both
+//! directly and under the tracer, and comparing the results.
+
+use std::env;
+use std::ffi::OsString;
+use std::hint::black_box;
+use std::process::{self, Command};
+use std::thread;
+use std::time::Instant;
+
+use anyhow::{bail, Result};
+use nix::sys::wait::{waitpid, WaitPidFlag};
+use nix::unistd::{fork, ForkResult, Pid};
+use serde_json::{json, Value};
+
+use crate::child::{self, Inherited};
+use crate::cli::Args;
+use crate::rusage;
+
+/// When set, the process runs the named workload instead of its usual behaviour.
+pub const WORKLOAD_ENV: &str = "MAX_RSS_CALIBRATE_WORKLOAD";
+
+/// How many times each workload is run in each mode.
+const RUNS: usize = 5;
+
+const MIB: usize = 1024 * 1024;
+
+const WORKLOADS: &[(&str, fn())] = &[
+    ("alloc", workload_alloc),
+    ("fork", workload_fork),
+    ("threads", workload_threads),
+    ("churn", workload_churn),
+];
+
+fn touch(size: usize) {
+    let vec = vec![1_u8; size];
+    black_box(&vec);
+}
+
+/// A single process which allocates a large block of memory.
+fn workload_alloc() {
+    touch(64 * MIB);
+}
+
+/// A process which forks several children, each of which allocates memory.
+fn workload_fork() {
+    for _ in 0..4 {
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                touch(16 * MIB);
+                process::exit(0);
+            }
+            Ok(ForkResult::Parent { child }) => {
+                let _ = waitpid(child, None);
+            }
+            Err(e) => panic!("failed to fork: {}", e),
+        }
+    }
+}
+
+/// A process which spawns several threads, each of which allocates memory.
+fn workload_threads() {
+    let handles = (0..4)
+        .map(|_| thread::spawn(|| touch(16 * MIB)))
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.join().expect("thread failed");
+    }
+}
+
+/// A process which creates many short-lived children, to measure the cost of tracing events.
+fn workload_churn() {
+    for _ in 0..64 {
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => process::exit(0),
+            Ok(ForkResult::Parent { child }) => {
+                let _ = waitpid(child, None);
+            }
+            Err(e) => panic!("failed to fork: {}", e),
+        }
+    }
+}
+
+/// Runs the workload with the given name, if this process was started as a workload.
+pub fn run_workload_from_env() {
+    let Some(name) = env::var_os(WORKLOAD_ENV) else {
+        return;
+    };
+
+    match WORKLOADS.iter().find(|(n, _)| *n == name) {
+        Some((_, workload)) => {
+            workload();
+            process::exit(0);
+        }
+        None => {
+            eprintln!("unknown calibration workload: {:?}", name);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the command directly, returning its wall time in milliseconds and `ru_maxrss` in bytes.
+fn run_direct(command: &[OsString]) -> Result<(f64, u64)> {
+    let start = Instant::now();
+    let child = Command::new(&command[0]).args(&command[1..]).spawn()?;
+
+    let (_, usage) = child::wait4(Pid::from_raw(child.id() as i32), WaitPidFlag::empty())?;
+    let Some(usage) = usage else {
+        bail!("failed to get resource usage of workload");
+    };
+
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    Ok((elapsed, rusage::max_rss_bytes(&usage)))
+}
+
+/// Runs the command under the tracer (or whichever `--backend` was chosen), returning its wall time
+/// in milliseconds and `max_rss` in bytes.
+fn run_traced(args: &Args, inherited: &Inherited) -> Result<(f64, u64)> {
+    let start = Instant::now();
+    let trace = args.backend.trace(args, inherited)?.complete()?;
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+
+    Ok((elapsed, trace.report["max_rss"].as_u64().unwrap_or(0)))
+}
+
+fn median<T: Copy + PartialOrd>(mut values: Vec<T>) -> T {
+    values.sort_by(|a, b| a.partial_cmp(b).expect("unordered value"));
+    values[values.len() / 2]
+}
+
+fn ratio(new: f64, old: f64) -> Option<f64> {
+    (old > 0.0).then(|| (new - old) / old)
+}
+
+pub fn calibrate(args: &Args, inherited: &Inherited) -> Result<Value> {
+    let command = vec![env::current_exe()?.into_os_string()];
+    let traced_args = Args {
+        command: command.clone(),
+        ..args.clone()
+    };
+
+    let mut results = vec![];
+    for (name, _) in WORKLOADS {
+        if args.debug {
+            eprintln!("::: calibrating workload: {}", name);
+        }
+
+        // the workloads are run by re-executing ourselves with this set
+        env::set_var(WORKLOAD_ENV, name);
+
+        let mut direct = vec![];
+        let mut traced = vec![];
+        for _ in 0..RUNS {
+            direct.push(run_direct(&command)?);
+            traced.push(run_traced(&traced_args, inherited)?);
+        }
+
+        let direct_time = median(direct.iter().map(|r| r.0).collect());
+        let direct_rss = median(direct.iter().map(|r| r.1).collect());
+        let traced_time = median(traced.iter().map(|r| r.0).collect());
+        let traced_rss = median(traced.iter().map(|r| r.1).collect());
+
+        results.push(json!({
+            "name": name,
+            "runs": RUNS,
+            "direct": { "wall_time_ms": direct_time, "max_rss": direct_rss },
+            "traced": { "wall_time_ms": traced_time, "max_rss": traced_rss },
+            "time_overhead": ratio(traced_time, direct_time),
+            "rss_skew": ratio(traced_rss as f64, direct_rss as f64),
+        }));
+    }
+
+    env::remove_var(WORKLOAD_ENV);
+
+    Ok(json!({ "workloads": results }))
+}
+
+/// Prints a human readable table of calibration results.
+pub fn print_summary(report: &Value) {
+    println!(
+        "{:<10} {:>12} {:>12} {:>9} {:>12} {:>12} {:>9}",
+        "workload", "direct ms", "traced ms", "time", "direct rss", "traced rss", "rss"
+    );
+
+    let percent = |v: &Value| match v.as_f64() {
+        Some(v) => format!("{:+.1}%", v * 100.0),
+        None => "-".into(),
+    };
+
+    for w in report["workloads"].as_array().into_iter().flatten() {
+        println!(
+            "{:<10} {:>12.2} {:>12.2} {:>9} {:>12} {:>12} {:>9}",
+            w["name"].as_str().unwrap_or_default(),
+            w["direct"]["wall_time_ms"].as_f64().unwrap_or_default(),
+            w["traced"]["wall_time_ms"].as_f64().unwrap_or_default(),
+            percent(&w["time_overhead"]),
+            w["direct"]["max_rss"],
+            w["traced"]["max_rss"],
+            percent(&w["rss_skew"]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn medians() {
+        assert_eq!(median(vec![3, 1, 2]), 2);
+        assert_eq!(median(vec![1.0, 4.0, 2.0, 3.0]), 3.0);
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(ratio(150.0, 100.0), Some(0.5));
+        assert_eq!(ratio(1.0, 0.0), None);
+    }
+}
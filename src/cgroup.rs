@@ -0,0 +1,400 @@
+//! The cgroup v2 backend, which runs COMMAND in a cgroup of its own and has the kernel account for
+//! the memory of the whole tree, rather than tracing each of its processes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, ForkResult};
+use serde_json::{json, Map};
+
+use crate::alert::Alert;
+use crate::child::{self, Forwarding, Inherited, Trace};
+use crate::childlog::{ChildLog, ChildOutput, LogCapture};
+use crate::cli::Args;
+use crate::exec::ExecCheck;
+use crate::report::{Report, SCHEMA_VERSION};
+use crate::seccomp::Filter;
+use crate::timeline::Timeline;
+use crate::timeout::Timeout;
+use crate::tracer;
+
+/// How often we check whether COMMAND, or anything it left behind in its cgroup, has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The cgroup we move ourselves into when our own cgroup needs the memory controller enabled for
+/// its children, since a cgroup which has processes in it can't do that.
+const SUPERVISOR: &str = concat!(env!("CARGO_PKG_NAME"), ".supervisor");
+
+/// Finds where the cgroup v2 hierarchy is mounted from the contents of `/proc/self/mountinfo`,
+/// returning the mount point and the path of the cgroup mounted there.
+fn find_mount(mountinfo: &str) -> Option<(PathBuf, &str)> {
+    mountinfo.lines().find_map(|line| {
+        let (mount, fs) = line.split_once(" - ")?;
+        if fs.split(' ').next()? != "cgroup2" {
+            return None;
+        }
+
+        let mut fields = mount.split(' ').skip(3);
+        let root = fields.next()?;
+        let point = fields.next()?;
+        Some((PathBuf::from(point), root))
+    })
+}
+
+/// Finds the path of our own cgroup in the v2 hierarchy from the contents of `/proc/self/cgroup`.
+fn find_own(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|line| line.strip_prefix("0::"))
+}
+
+/// Whether a cgroup still has processes in it, from the contents of its `cgroup.events`.
+fn is_populated(events: &str) -> bool {
+    events.lines().any(|line| line == "populated 1")
+}
+
+/// Whether a list of controllers (as in `cgroup.controllers`) includes the memory controller.
+fn has_memory(controllers: &str) -> bool {
+    controllers.split_ascii_whitespace().any(|c| c == "memory")
+}
+
+/// Finds the value of `key` in a flat keyed file (such as `cpu.stat` or `memory.stat`), which has a
+/// `key value` pair on each line.
+fn find_key(stat: &str, key: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(' ')?.parse().ok())
+}
+
+fn read_u64(path: &Path) -> Result<u64> {
+    Ok(fs::read_to_string(path)?.trim().parse()?)
+}
+
+/// Makes sure the memory controller is enabled for the children of `parent`, which is the cgroup
+/// we're running in.
+fn enable_memory(parent: &Path) -> Result<()> {
+    let read = |file: &str| fs::read_to_string(parent.join(file));
+    if has_memory(&read("cgroup.subtree_control")?) {
+        return Ok(());
+    }
+    if !has_memory(&read("cgroup.controllers")?) {
+        bail!(
+            "the memory controller isn't available in the cgroup {}",
+            parent.display()
+        );
+    }
+
+    let hint = || {
+        format!(
+            "failed to enable the memory controller for the cgroup {}, try running {} in a \
+            cgroup of its own, e.g.: systemd-run --user --scope -p Delegate=yes {} ...",
+            parent.display(),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_NAME"),
+        )
+    };
+
+    // a cgroup can only enable controllers for its children if it has no processes of its own
+    let supervisor = parent.join(SUPERVISOR);
+    match fs::create_dir(&supervisor) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(e).with_context(hint),
+        _ => {}
+    }
+    fs::write(supervisor.join("cgroup.procs"), "0").with_context(hint)?;
+
+    if let Err(e) = fs::write(parent.join("cgroup.subtree_control"), "+memory") {
+        // put ourselves back where we were
+        let _ = fs::write(parent.join("cgroup.procs"), "0");
+        let _ = fs::remove_dir(&supervisor);
+        return Err(e).with_context(hint);
+    }
+
+    Ok(())
+}
+
+/// A cgroup created for a single run of COMMAND, which is removed again when dropped.
+#[derive(Debug)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Creates a new cgroup as a child of the one we're running in.
+    pub fn create() -> Result<Cgroup> {
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+        let (mount, root) =
+            find_mount(&mountinfo).ok_or_else(|| anyhow!("cgroup v2 isn't mounted"))?;
+
+        let own = fs::read_to_string("/proc/self/cgroup")?;
+        let own = find_own(&own).ok_or_else(|| anyhow!("not running in a cgroup v2 hierarchy"))?;
+        let own = own
+            .strip_prefix(root)
+            .unwrap_or(own)
+            .trim_start_matches('/');
+
+        let mut parent = match own {
+            "" => mount,
+            own => mount.join(own),
+        };
+        // we've already moved ourselves aside for an earlier run (e.g.: with `noise`)
+        if parent.file_name() == Some(SUPERVISOR.as_ref()) {
+            parent.pop();
+        }
+        enable_memory(&parent)?;
+
+        let path = parent.join(format!("{}-{}", env!("CARGO_PKG_NAME"), process::id()));
+        fs::create_dir(&path)
+            .with_context(|| format!("failed to create the cgroup {}", path.display()))?;
+
+        Ok(Cgroup { path })
+    }
+
+    /// Moves the calling process into the cgroup.
+    fn join(&self) -> Result<()> {
+        Ok(fs::write(self.path.join("cgroup.procs"), "0")?)
+    }
+
+    fn is_populated(&self) -> Result<bool> {
+        Ok(is_populated(&fs::read_to_string(
+            self.path.join("cgroup.events"),
+        )?))
+    }
+
+    /// The memory currently used by the processes in the cgroup.
+    fn current(&self) -> Result<u64> {
+        read_u64(&self.path.join("memory.current"))
+    }
+
+    /// The most memory ever used by the processes in the cgroup at once. Only available since
+    /// linux 5.19.
+    fn peak(&self) -> Option<u64> {
+        read_u64(&self.path.join("memory.peak")).ok()
+    }
+
+    /// The user and system CPU time used by every process which has run in the cgroup.
+    fn cpu_times(&self) -> Option<(Duration, Duration)> {
+        let stat = fs::read_to_string(self.path.join("cpu.stat")).ok()?;
+        Some((
+            Duration::from_micros(find_key(&stat, "user_usec")?),
+            Duration::from_micros(find_key(&stat, "system_usec")?),
+        ))
+    }
+
+    /// The minor and major page faults caused by every process which has run in the cgroup.
+    fn faults(&self) -> Option<(u64, u64)> {
+        let stat = fs::read_to_string(self.path.join("memory.stat")).ok()?;
+        // unlike a process' minflt, pgfault counts major faults as well
+        let (all, major) = (find_key(&stat, "pgfault")?, find_key(&stat, "pgmajfault")?);
+        Some((all.saturating_sub(major), major))
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir(&self.path);
+    }
+}
+
+/// Runs the COMMAND given in `args` in a cgroup of its own, and measures the peak memory used by
+/// the whole tree at once.
+pub fn trace(args: &Args, inherited: &Inherited) -> Result<Trace> {
+    let cgroup = Cgroup::create()?;
+    let log = ChildLog::create(args.log_child.as_deref())?;
+    let output = ChildOutput::create(args.stdout.as_deref(), args.stderr.as_deref())?;
+    let check = ExecCheck::create()?;
+    let seccomp = args.seccomp.as_deref().map(Filter::load).transpose()?;
+
+    let child = match unsafe { fork() }? {
+        ForkResult::Child => {
+            match tracer::exec_command(
+                args,
+                inherited,
+                log.as_ref(),
+                output.as_ref(),
+                &check,
+                seccomp.as_ref(),
+                None,
+                || cgroup.join(),
+            )? {}
+        }
+        ForkResult::Parent { child } => child,
+    };
+    if let Some(failed) = check.wait(args)? {
+        return failed.finish(child);
+    }
+
+    if args.debug {
+        eprintln!("::: pid of tracee: {:?}", child);
+        eprintln!("::: cgroup: {}", cgroup.path.display());
+    }
+
+    let capture = log.map(|log| log.start(child)).transpose()?;
+    let mut timeout = Timeout::create(args, child, Instant::now());
+    let _forwarding = Forwarding::start(args, child)?;
+
+    let mut exit_code = None;
+    let mut exit_signal = None;
+    let mut rusage_max_rss = None;
+    let mut exited = false;
+
+    // memory.current is always sampled, since it's all there is on kernels without memory.peak
+    let mut timeline = Timeline::create(args)?;
+    let mut alert = Alert::create(args);
+    let mut last_sample: Option<Instant> = None;
+    let mut sampled_peak = 0;
+
+    loop {
+        if !exited {
+            let (status, usage) = child::wait4(child, WaitPidFlag::WNOHANG)?;
+            match status {
+                WaitStatus::Exited(_, code) => (exited, exit_code) = (true, Some(code)),
+                WaitStatus::Signaled(_, signal, _) => {
+                    (exited, exit_signal) = (true, Some(signal as i32))
+                }
+                _ => {}
+            }
+            if let Some(usage) = usage {
+                rusage_max_rss = Some(usage.ru_maxrss as u64 * 1024);
+            }
+        }
+
+        // just as when tracing, descendants which outlive COMMAND are still measured
+        if exited && !cgroup.is_populated()? {
+            break;
+        }
+
+        if let Some(timeout) = &mut timeout {
+            timeout.check();
+        }
+
+        if last_sample.is_none_or(|last| last.elapsed() >= args.interval) {
+            last_sample = Some(Instant::now());
+            let current = cgroup.current()?;
+            sampled_peak = sampled_peak.max(current);
+            if let Some(timeline) = &mut timeline {
+                timeline.sample(current)?;
+            }
+            if let Some(alert) = &mut alert {
+                alert.check(current);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL.min(args.interval));
+    }
+
+    let peak = cgroup.peak();
+    let times = cgroup.cpu_times();
+    let faults = cgroup.faults();
+    let ru_maxrss = rusage_max_rss.filter(|_| args.rusage);
+
+    let report = Report {
+        schema_version: Some(SCHEMA_VERSION),
+        max_rss: peak.unwrap_or(sampled_peak),
+        count_policy: None,
+        source: None,
+        mem_source: None,
+        max_rss_exit: None,
+        max_uss: None,
+        max_swap: None,
+        rusage_max_rss,
+        ru_maxrss,
+        utime_ms: times.map(|(user, _)| user.as_millis() as u64),
+        stime_ms: times.map(|(_, system)| system.as_millis() as u64),
+        minor_faults: faults.map(|(minor, _)| minor),
+        major_faults: faults.map(|(_, major)| major),
+        io: None,
+        total_pids: None,
+        total_processes: None,
+        total_threads: None,
+        total_reads: None,
+        events: None,
+        sample_interval_ms: peak
+            .is_none()
+            .then_some(args.interval.as_secs_f64() * 1000.0),
+        degraded: None,
+        exit_code,
+        exit_signal,
+        timed_out: timeout.as_ref().map(Timeout::timed_out),
+        killed_at_teardown: None,
+        left_running: None,
+        attached: None,
+        interrupted: None,
+        verify: None,
+        timeline: timeline.map(Timeline::finish).transpose()?,
+        alerts: alert.as_ref().map(Alert::fired),
+        child_log: capture.map(LogCapture::finish),
+        child_output: output.map(ChildOutput::finish),
+        cgroup: Some(json!({
+            "memory_peak": peak,
+            "sampled_peak": sampled_peak,
+        })),
+        graph: None,
+        error: None,
+        error_cause: None,
+        other: Map::new(),
+    };
+
+    Ok(Trace {
+        report: serde_json::to_value(report)?,
+        exit_code: args.exit_with.exit_code(exit_code, exit_signal),
+        snapshots: HashMap::new(),
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount() {
+        let mountinfo = "\
+22 1 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:5 - proc proc rw
+31 24 0:26 / /sys/fs/cgroup/memory rw,nosuid shared:13 - cgroup cgroup rw,memory
+35 24 0:30 / /sys/fs/cgroup rw,nosuid,nodev,noexec,relatime shared:9 - cgroup2 cgroup2 rw
+";
+        assert_eq!(
+            find_mount(mountinfo),
+            Some((PathBuf::from("/sys/fs/cgroup"), "/"))
+        );
+        assert_eq!(find_mount("22 1 0:21 / /proc rw - proc proc rw\n"), None);
+        assert_eq!(find_mount(""), None);
+    }
+
+    #[test]
+    fn own() {
+        assert_eq!(
+            find_own("0::/user.slice/foo.scope\n"),
+            Some("/user.slice/foo.scope")
+        );
+        assert_eq!(find_own("4:memory:/foo\n1:cpu:/\n0::/bar\n"), Some("/bar"));
+        assert_eq!(find_own("4:memory:/foo\n"), None);
+    }
+
+    #[test]
+    fn events() {
+        assert!(is_populated("populated 1\nfrozen 0\n"));
+        assert!(!is_populated("populated 0\nfrozen 0\n"));
+        assert!(!is_populated(""));
+    }
+
+    #[test]
+    fn keyed() {
+        let stat = "usage_usec 1500\nuser_usec 1000\nsystem_usec 500\nnr_periods 0\n";
+        assert_eq!(find_key(stat, "user_usec"), Some(1000));
+        assert_eq!(find_key(stat, "system_usec"), Some(500));
+        assert_eq!(find_key(stat, "usage"), None);
+        assert_eq!(find_key("pgmajfault 3\npgfault 20\n", "pgfault"), Some(20));
+    }
+
+    #[test]
+    fn controllers() {
+        assert!(has_memory("cpuset cpu io memory pids\n"));
+        assert!(!has_memory("cpu io pids\n"));
+        assert!(!has_memory(""));
+    }
+}
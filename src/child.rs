@@ -0,0 +1,251 @@
+//! Running COMMAND as our child, in the same way whichever backend measures it: what it inherits
+//! from us, the environment it gets, passing signals on to it, and waiting for it.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::os::fd::RawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use anyhow::{Error, Result};
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg};
+use nix::libc::rusage;
+use nix::sys::signal::Signal::{SIGINT, SIGPIPE, SIGTERM};
+use nix::sys::signal::{sigaction, signal, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{close, Pid};
+use serde_json::Value;
+
+use crate::cli::Args;
+
+/// Where signals sent to us are forwarded to while COMMAND runs: its pid, or its process group as a
+/// negative number. Zero when not forwarding.
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+/// Whether a signal's `si_code` says the kernel sent it (e.g.: for Ctrl-C in the terminal), rather
+/// than another process.
+#[cfg(target_os = "linux")]
+fn from_kernel(code: nix::libc::c_int) -> bool {
+    // the si_code of signals sent by the kernel itself, which libc doesn't define
+    const SI_KERNEL: nix::libc::c_int = 0x80;
+    code == SI_KERNEL
+}
+
+/// Whether a signal's `si_code` says the kernel sent it (e.g.: for Ctrl-C in the terminal), rather
+/// than another process. There's no code for the kernel itself elsewhere, so it's anything which
+/// doesn't say it came from a process.
+#[cfg(not(target_os = "linux"))]
+fn from_kernel(code: nix::libc::c_int) -> bool {
+    // the si_codes of signals sent with kill and sigqueue, which libc doesn't define everywhere
+    const SI_USER: nix::libc::c_int = 0x10001;
+    const SI_QUEUE: nix::libc::c_int = 0x10002;
+    code != SI_USER && code != SI_QUEUE
+}
+
+extern "C" fn on_forward(
+    signal: nix::libc::c_int,
+    info: *mut nix::libc::siginfo_t,
+    _: *mut nix::libc::c_void,
+) {
+    let target = FORWARD_TO.load(Ordering::SeqCst);
+
+    // signals from the terminal are sent to its whole foreground process group, so COMMAND will
+    // have already received it, unless it's in a process group of its own
+    // SAFETY: the kernel always passes a valid siginfo to SA_SIGINFO handlers
+    let from_terminal = from_kernel(unsafe { (*info).si_code });
+    if target != 0 && (target < 0 || !from_terminal) {
+        // SAFETY: kill is async-signal-safe
+        unsafe { nix::libc::kill(target, signal) };
+    }
+}
+
+/// Passes SIGINT and SIGTERM on to COMMAND while it runs, rather than letting them kill us and leave
+/// it without a tracer, so the results can still be written once it exits. The previous handlers
+/// are restored when dropped.
+pub struct Forwarding {
+    previous: Vec<(Signal, SigAction)>,
+}
+
+impl Forwarding {
+    pub fn start(args: &Args, child: Pid) -> nix::Result<Forwarding> {
+        // with --timeout, COMMAND has a process group of its own, so the whole tree can be signalled
+        let target = match args.timeout {
+            Some(_) => -child.as_raw(),
+            None => child.as_raw(),
+        };
+        FORWARD_TO.store(target, Ordering::SeqCst);
+
+        let action = SigAction::new(
+            SigHandler::SigAction(on_forward),
+            SaFlags::SA_SIGINFO,
+            SigSet::empty(),
+        );
+        let previous = [SIGINT, SIGTERM]
+            .into_iter()
+            // SAFETY: the handler only calls async-signal-safe functions
+            .map(|signal| Ok((signal, unsafe { sigaction(signal, &action) }?)))
+            .collect::<nix::Result<_>>()?;
+
+        Ok(Forwarding { previous })
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        FORWARD_TO.store(0, Ordering::SeqCst);
+        for (signal, action) in &self.previous {
+            // SAFETY: these are the handlers which were installed before
+            let _ = unsafe { sigaction(*signal, action) };
+        }
+    }
+}
+
+/// The state this process inherited from its parent, captured before we change anything so the
+/// COMMAND can receive exactly the same.
+#[derive(Debug, Clone)]
+pub struct Inherited {
+    fds: Vec<i32>,
+    mask: SigSet,
+}
+
+impl Inherited {
+    pub fn capture() -> Result<Inherited> {
+        Ok(Inherited {
+            fds: open_fds()?,
+            mask: SigSet::thread_get_mask()?,
+        })
+    }
+
+    /// Restores the environment the COMMAND would have had if it were run directly, undoing
+    /// anything the tracer itself may have changed, except for the descriptors in `keep`. Must only
+    /// be called in the forked child.
+    pub fn restore(&self, keep: &[RawFd]) -> Result<()> {
+        // close any descriptors the tracer opened itself and which may be missing close-on-exec
+        for fd in open_fds()? {
+            if !self.fds.contains(&fd) && !keep.contains(&fd) {
+                let _ = close(fd);
+            }
+        }
+
+        // the rust runtime ignores SIGPIPE, and ignored signals are inherited across exec
+        unsafe { signal(SIGPIPE, SigHandler::SigDfl) }?;
+        self.mask.thread_set_mask()?;
+
+        Ok(())
+    }
+}
+
+/// Like `waitpid`, but also returns the resource usage of the process if it has terminated.
+pub fn wait4(pid: Pid, flags: WaitPidFlag) -> nix::Result<(WaitStatus, Option<rusage>)> {
+    let mut status = 0;
+    let mut usage = unsafe { std::mem::zeroed::<rusage>() };
+
+    // SAFETY: status and usage are both valid for writes
+    let res = unsafe { nix::libc::wait4(pid.as_raw(), &mut status, flags.bits(), &mut usage) };
+    match Errno::result(res)? {
+        0 => Ok((WaitStatus::StillAlive, None)),
+        res => {
+            let status = WaitStatus::from_raw(Pid::from_raw(res), status)?;
+            let usage = matches!(status, WaitStatus::Exited(..) | WaitStatus::Signaled(..))
+                .then_some(usage);
+            Ok((status, usage))
+        }
+    }
+}
+
+/// The outcome of tracing a COMMAND.
+#[derive(Debug)]
+pub struct Trace {
+    /// The results report.
+    pub report: Value,
+    /// The exit code that should be returned.
+    pub exit_code: i32,
+    /// The contents of `smaps_rollup` for each process, as read at exit. Only collected when
+    /// writing to an output directory.
+    pub snapshots: HashMap<i32, String>,
+    /// What went wrong if tracing failed part way through, in which case the report only has what
+    /// was collected until then (and its `error` field says why).
+    pub error: Option<Error>,
+}
+
+impl Trace {
+    /// Fails if tracing didn't finish, for callers which have no use for partial results.
+    pub fn complete(self) -> Result<Trace> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self),
+        }
+    }
+}
+
+/// The environment COMMAND is run with: ours, changed by `--clear-env`, `--env-remove` and `--env`.
+pub fn command_env(args: &Args) -> Vec<(OsString, OsString)> {
+    let mut vars = match args.clear_env {
+        true => vec![],
+        false => env::vars_os().collect::<Vec<_>>(),
+    };
+    vars.retain(|(key, _)| !args.env_remove.contains(key));
+    for (key, value) in &args.env {
+        vars.retain(|(k, _)| k != key);
+        vars.push((key.clone(), value.clone()));
+    }
+
+    vars
+}
+
+/// Creates a pipe, both ends of which are closed on exec.
+#[cfg(target_os = "linux")]
+pub fn pipe() -> nix::Result<(RawFd, RawFd)> {
+    nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)
+}
+
+/// Creates a pipe, both ends of which are closed on exec. Not every platform has `pipe2`, so this
+/// is set afterwards, which leaves a window where a process forked by another thread inherits them.
+#[cfg(not(target_os = "linux"))]
+pub fn pipe() -> nix::Result<(RawFd, RawFd)> {
+    let (read, write) = nix::unistd::pipe()?;
+    for fd in [read, write] {
+        fcntl(fd, FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC))?;
+    }
+    Ok((read, write))
+}
+
+/// Returns the file descriptors currently open in this process.
+fn open_fds() -> Result<Vec<i32>> {
+    let dir = match cfg!(target_os = "linux") {
+        true => "/proc/self/fd",
+        false => "/dev/fd",
+    };
+    let fds = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<i32>().ok())
+        .collect::<Vec<_>>();
+
+    // the listing includes the descriptor used to read the directory itself, which has since been
+    // closed, so only keep those which are still valid
+    Ok(fds
+        .into_iter()
+        .filter(|fd| fcntl(*fd, FcntlArg::F_GETFD).is_ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fds() {
+        let fds = open_fds().unwrap();
+        assert!(fds.contains(&0));
+        assert!(fds.contains(&1));
+        assert!(fds.contains(&2));
+
+        let file = fs::File::open("/dev/null").unwrap();
+        let fd = std::os::fd::AsRawFd::as_raw_fd(&file);
+        assert!(open_fds().unwrap().contains(&fd));
+        drop(file);
+        assert!(!open_fds().unwrap().contains(&fd));
+    }
+}
@@ -0,0 +1,248 @@
+//! Captures COMMAND's stdout and stderr into a single log, with each line prefixed by when it was
+//! written, so it can be correlated with memory samples (see `--timeline`), or sends them to files
+//! of their own (see `--stdout` and `--stderr`).
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Error, Result};
+use nix::fcntl::{open, OFlag};
+use nix::sys::stat::Mode;
+use nix::unistd::{close, dup2, Pid};
+use serde_json::{json, Value};
+
+use crate::child;
+
+/// How long to wait for the last lines after COMMAND exits. Descendants which outlive it may keep
+/// the pipes open, and we don't want to wait for them forever.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Formats a line of the log, e.g.: `    12.345 1234 stderr: some message`.
+fn prefix(elapsed: Duration, pid: Pid, stream: &str) -> String {
+    format!(
+        "{:>10.3} {} {}: ",
+        elapsed.as_secs_f64(),
+        pid.as_raw(),
+        stream
+    )
+}
+
+/// Where COMMAND's stdout is sent when it isn't being logged, so it can be kept apart from results
+/// written to stdout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChildStdout {
+    #[default]
+    Inherit,
+    Stderr,
+    Null,
+}
+
+impl ChildStdout {
+    pub const ALL: &'static [(&'static str, ChildStdout)] = &[
+        ("inherit", ChildStdout::Inherit),
+        ("stderr", ChildStdout::Stderr),
+        ("null", ChildStdout::Null),
+    ];
+
+    /// Points stdout where it should go. Must only be called in the forked child.
+    pub fn redirect(&self) -> Result<()> {
+        match self {
+            ChildStdout::Inherit => {}
+            ChildStdout::Stderr => {
+                dup2(2, 1)?;
+            }
+            ChildStdout::Null => {
+                let null = open("/dev/null", OFlag::O_WRONLY, Mode::empty())?;
+                dup2(null, 1)?;
+                close(null)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ChildStdout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ChildStdout::ALL
+            .iter()
+            .find_map(|(name, mode)| (*name == s).then_some(*mode))
+            .ok_or_else(|| anyhow!("unknown --child-stdout: {}", s))
+    }
+}
+
+/// The pipes COMMAND's output is captured through, created before it's started.
+pub struct ChildLog {
+    path: PathBuf,
+    stdout: (RawFd, RawFd),
+    stderr: (RawFd, RawFd),
+}
+
+impl ChildLog {
+    pub fn create(path: Option<&Path>) -> Result<Option<ChildLog>> {
+        let Some(path) = path else {
+            return Ok(None);
+        };
+
+        Ok(Some(ChildLog {
+            path: path.to_path_buf(),
+            stdout: child::pipe()?,
+            stderr: child::pipe()?,
+        }))
+    }
+
+    /// Points stdout and stderr at the pipes. Must only be called in the forked child.
+    pub fn redirect(&self) -> Result<()> {
+        dup2(self.stdout.1, 1)?;
+        dup2(self.stderr.1, 2)?;
+        Ok(())
+    }
+
+    /// Starts copying whatever COMMAND writes into the log. Must only be called in the tracer.
+    pub fn start(self, pid: Pid) -> Result<LogCapture> {
+        // the child has its own copies of the write ends, and we'll never see EOF while we hold ours
+        close(self.stdout.1)?;
+        close(self.stderr.1)?;
+
+        let file = File::create(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        let out = Arc::new(Mutex::new(LineWriter::new(file)));
+        let lines = Arc::new(AtomicUsize::new(0));
+        let start = Instant::now();
+
+        let threads = [("stdout", self.stdout.0), ("stderr", self.stderr.0)]
+            .into_iter()
+            .map(|(stream, fd)| {
+                let out = out.clone();
+                let lines = lines.clone();
+
+                // SAFETY: we created this descriptor, and nothing else uses it
+                let reader = BufReader::new(unsafe { File::from_raw_fd(fd) });
+                thread::spawn(move || {
+                    for line in reader.split(b'\n') {
+                        let Ok(line) = line else { break };
+                        let prefix = prefix(start.elapsed(), pid, stream);
+
+                        let mut out = out.lock().expect("poisoned log");
+                        let _ = out
+                            .write_all(prefix.as_bytes())
+                            .and_then(|_| out.write_all(&line))
+                            .and_then(|_| out.write_all(b"\n"));
+                        lines.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+
+        Ok(LogCapture {
+            path: self.path,
+            threads,
+            lines,
+        })
+    }
+}
+
+/// The files COMMAND's stdout and stderr are written to, created before it's started so any errors
+/// are reported by us rather than the forked child.
+pub struct ChildOutput {
+    stdout: Option<(PathBuf, File)>,
+    stderr: Option<(PathBuf, File)>,
+}
+
+impl ChildOutput {
+    pub fn create(stdout: Option<&Path>, stderr: Option<&Path>) -> Result<Option<ChildOutput>> {
+        if stdout.is_none() && stderr.is_none() {
+            return Ok(None);
+        }
+
+        let create = |path: &Path| {
+            File::create(path)
+                .map(|file| (path.to_path_buf(), file))
+                .with_context(|| format!("failed to create {}", path.display()))
+        };
+        let stdout = stdout.map(create).transpose()?;
+        let stderr = match (stderr, &stdout) {
+            // share the file, otherwise each stream would write over the other
+            (Some(path), Some((out, file))) if path == out => {
+                Some((out.clone(), file.try_clone()?))
+            }
+            (path, _) => path.map(create).transpose()?,
+        };
+
+        Ok(Some(ChildOutput { stdout, stderr }))
+    }
+
+    /// Points stdout and stderr at their files. Must only be called in the forked child.
+    pub fn redirect(&self) -> Result<()> {
+        if let Some((_, file)) = &self.stdout {
+            dup2(file.as_raw_fd(), 1)?;
+        }
+        if let Some((_, file)) = &self.stderr {
+            dup2(file.as_raw_fd(), 2)?;
+        }
+        Ok(())
+    }
+
+    /// Returns where each stream was written, and how many bytes were written to it for the
+    /// results. Anything that isn't a regular file (such as `/dev/null`) has no size.
+    pub fn finish(self) -> Value {
+        let summary = |stream: Option<(PathBuf, File)>| {
+            stream.map(|(path, file)| {
+                let bytes = file
+                    .metadata()
+                    .ok()
+                    .filter(|m| m.is_file())
+                    .map(|m| m.len());
+                json!({ "path": path.to_string_lossy(), "bytes": bytes })
+            })
+        };
+
+        json!({
+            "stdout": summary(self.stdout),
+            "stderr": summary(self.stderr),
+        })
+    }
+}
+
+pub struct LogCapture {
+    path: PathBuf,
+    threads: Vec<JoinHandle<()>>,
+    lines: Arc<AtomicUsize>,
+}
+
+impl LogCapture {
+    /// Waits (briefly) for the rest of COMMAND's output, and returns a summary for the results.
+    pub fn finish(self) -> Value {
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        while Instant::now() < deadline && !self.threads.iter().all(|t| t.is_finished()) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        json!({
+            "path": self.path.to_string_lossy(),
+            "lines": self.lines.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        assert_eq!(
+            prefix(Duration::from_millis(12345), Pid::from_raw(42), "stderr"),
+            "    12.345 42 stderr: "
+        );
+    }
+}
@@ -1,9 +1,27 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
+use std::ops::RangeInclusive;
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
 
 use anyhow::{bail, Result};
 use lexopt::Parser;
+use regex::Regex;
+
+use crate::alert::Notify;
+use crate::backend::Backend;
+use crate::childlog::ChildStdout;
+use crate::exitcode::{ExitCodes, ExitWith};
+use crate::otlp::Endpoint;
+use crate::output::{self, OutputFormat};
+use crate::policy::{CountPolicy, MemSource, ProcessFilter, RssSource};
+use crate::statsd::Address;
+use crate::time::Clock;
+use crate::units::{parse_duration, parse_size};
+
+/// How often the total RSS is sampled (for timelines and alerts) if `--interval` isn't given.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
 
 fn print_version() {
     println!(
@@ -31,29 +49,506 @@ Project home page: {crate_homepage}
 USAGE:
     {bin} [flags] <COMMAND>...
     {bin} [flags] -- <COMMAND>...
+    {bin} [flags] -p <PID>
+    {bin} run [flags] [--] <COMMAND>...
+    {bin} calibrate [flags]
+    {bin} noise [flags] [--] <COMMAND>...
+    {bin} compare [flags] <OLD> <NEW>
+    {bin} compare [flags] -- <OLD_COMMAND> -- <NEW_COMMAND>
+    {bin} report <FILE>
+    {bin} merge [flags] <FILE> <FILE>...
+
+SUBCOMMANDS:
+    run
+        Trace and measure COMMAND. This is what happens when no subcommand is
+        given, and is only needed to run a program with the same name as one.
+
+    calibrate
+        Run a set of built-in reference workloads both directly and under
+        {bin}, and report the overhead of tracing (in time and memory) on
+        this machine. Knowing this helps decide whether a small regression is
+        real, or just the observer effect.
+
+    noise
+        Run COMMAND many times in a row (see --runs) and report the
+        run-to-run variance of its max_rss on this machine, along with a
+        recommended regression threshold.
+
+    compare
+        Compare two results files (written with `--output-format json`) and
+        report the change in max_rss, both in total and for each process. Use
+        `--output-format html` for a visual side-by-side diff.
+
+        Processes are matched by their name and arguments (so the same one is
+        found even if it ran in a different order), falling back to their
+        position in the graph. The one which grew the most is noted in
+        `largest_increase`.
+
+        Alternatively, give two commands (each run with `sh -c`) separated by
+        `--`, e.g.: `{bin} compare -- 'gzip -k a' -- 'zstd a'`, to run and
+        compare them directly. With --runs, they're run alternately that many
+        times, the runs and their statistics are included, and the total
+        compares the median max_rss of each.
+
+        `diff` is another name for `compare`.
+
+    report
+        Print a results file (written with `--output-format json`) for people
+        to read: the headline numbers, followed by the process tree.
+
+    merge
+        Combine results files (written with `--output-format json`), such as
+        those from sharded CI jobs, into one. Its max_rss is the largest of
+        them, its total_pids their sum, and each is kept under `reports`.
 
 OPTIONS:
+    -p PID, --pid PID
+        Instead of running COMMAND, attach to the process PID which is already
+        running, along with its threads and descendants, and measure it (and
+        any processes it starts) until it exits. Press Ctrl-C (or send {bin}
+        SIGTERM) to stop early: the processes are left running, their current
+        RSS is taken as their final value, and the results are written as usual.
+
+    -S, --shell
+        Run COMMAND with `sh -c`, so it can be a shell script rather than a
+        single program, e.g.: `{bin} -S 'make -j && ./run.sh'`. If it's given
+        as more than one argument, they're joined with spaces. The shell is
+        then the root of the graph (unless it execs the last command itself)
+        and counts towards max_rss, and the results include `shell: true`.
+
     -o OUTPUT, --output OUTPUT
         Specify output path for the results JSON file. If not provided it
         defaults to {bin}.json in the current working directory.
 
+        The path may contain the following placeholders, which is useful when
+        running {bin} in a loop:
+            {{cmd}}   the name of COMMAND
+            {{date}}  the current UTC date and time, e.g.: 20240102T030405Z
+            {{n}}     a counter, which is incremented until the path is unique
+
+        Use `-` to write the results to stdout, e.g.: `{bin} -o - -- cmd | jq`.
+        COMMAND's stdout is then sent to stderr instead, see --child-stdout.
+
+        Instances writing to the same OUTPUT at once take turns, using a
+        lockfile next to it (OUTPUT.lock) which is removed once they're done.
+
+    --append
+        Add the results to those already in OUTPUT instead of replacing them,
+        with a `run_id` (counting up from 1) and the `timestamp` they were
+        added. OUTPUT holds an array of runs, or a line per run if it ends in
+        `.jsonl` or `.ndjson`. Only the json format can be appended to.
+
+    --out-dir DIR
+        Instead of writing a single results file, create a new directory
+        inside DIR for this run, and write all artifacts into it: the results,
+        a snapshot of each process' smaps_rollup at exit, and an index.json
+        file which lists them. Takes precedence over --output.
+
+    -O FORMAT, --output-format FORMAT, --format FORMAT
+        Specify the format of the results file. Defaults to `json`.
+        Possible values: {formats}.
+
+        `sqlite` adds each run to the database at --output (creating it if
+        needed) with tables for the `runs`, their `processes` and `samples`,
+        rather than replacing it. It's only available when {bin} is built
+        with the `sqlite` feature.
+
+    --tag KEY=VALUE
+        Label the results with KEY, e.g.: `--tag branch=main --tag cc=clang`.
+        May be given more than once. Tags are recorded in `meta.tags`, as
+        labels on the prometheus metrics, in the `tags` column of the sqlite
+        `runs` table, and alongside comparisons, so runs can be grouped
+        without relying on file names.
+
+    --clock CLOCK
+        Which clock the timestamps in the results are taken from. Defaults to
+        `utc`. `local` uses the local timezone (with its offset), and
+        `monotonic` omits timestamps entirely, recording only durations.
+        Possible values: {clocks}.
+
+    --threshold SIZE
+        The max_rss COMMAND is expected to stay under, e.g.: 500M or 1.5GiB.
+        When given, the report records it along with whether it was exceeded,
+        and the badge (see --badge) is green when under it and red otherwise.
+
+    --baseline PATH
+        A previous results file (written with `--output-format json`) to
+        compare max_rss against. The report records the change in `baseline`,
+        and if COMMAND succeeded but its max_rss grew by more than
+        --max-regression, {bin} exits with 1 (see --exit-code-map) so it can
+        be used as a regression gate in CI.
+
+    --max-regression PERCENT
+        How much max_rss may grow over --baseline before it's considered a
+        regression, e.g.: 5%. Defaults to 0%, so any growth is one; the
+        `noise` subcommand can help choose a value which allows for noise.
+
+    --badge PATH
+        Also write a shields.io endpoint badge showing the max_rss to PATH,
+        so it can be displayed in a README.
+
+    --summary
+        Also print a short summary of the results to stderr once COMMAND has
+        finished, in the spirit of `time -v`: max_rss, the number of
+        processes, wall and CPU time, page faults and the exit code.
+
+    --github-summary
+        Also append a markdown table of the results (peak RSS, the number of
+        processes, the wall time and the change from --baseline) to the job
+        summary in $GITHUB_STEP_SUMMARY, and print a `::warning::` annotation
+        when --threshold or --max-regression is exceeded.
+
+    --otlp ENDPOINT
+        Also push the results to an OpenTelemetry collector as OTLP gauges,
+        e.g.: --otlp http://localhost:4318. These are max_rss, the number of
+        processes and the wall and CPU time, along with every process' RSS
+        over time with --record-samples. Only plain HTTP (with OTLP's JSON
+        encoding) is supported, and the path defaults to `/v1/metrics`. A
+        failed push is a warning, since the results were already written.
+
+    --statsd HOST:PORT
+        Also send max_rss, the number of processes and the wall time to statsd
+        as gauges, named like `max_rss.COMMAND.max_rss`. They're sent over UDP,
+        so nothing is reported if they don't arrive.
+
+    --env KEY=VALUE, --env-remove KEY
+        Set (or remove) an environment variable for COMMAND, leaving our own
+        environment as it is. May be given more than once. Variables are
+        removed before any are set.
+
+    --clear-env
+        Run COMMAND with an empty environment, apart from any --env, so runs
+        don't depend on whatever environment they happened to start in.
+
+    --cwd PATH
+        Run COMMAND in the directory PATH, rather than the current one.
+        Relative paths in COMMAND (including its program) are then relative
+        to PATH, but --output and other paths given to {bin} are not.
+
+    --capture-env
+        Record the environment variables COMMAND was run with in the results,
+        to help reproduce them later. The values of variables whose names
+        match --redact are replaced with `{redacted}`.
+
+    --redact REGEX
+        Which environment variables to redact when using --capture-env.
+        Defaults to `{redact_env}`.
+
+    --redact-args REGEX
+        Replace every match of REGEX with `{redacted}` in everything that's
+        written: the results, COMMAND's arguments and any other artifacts.
+        Useful to remove internal paths or secrets passed on the command line
+        before sharing results, e.g.: --redact-args '/home/[^/]+|--token=\S+'
+
+    --setup CMD, --teardown CMD
+        Run CMD with `sh -c` before (or after) COMMAND, without measuring it.
+        May be given more than once, and steps are run in the order given.
+        If a setup step fails then COMMAND isn't run. Teardown steps always
+        run, even if COMMAND or an earlier teardown step failed.
+
+    --log-child PATH
+        Capture COMMAND's stdout and stderr (instead of letting them through)
+        and write them to PATH as a single log, with each line prefixed by
+        the seconds since COMMAND started, its PID and the stream it was
+        written to. The times line up with --timeline's samples.
+
+    --child-stdout MODE
+        Where COMMAND's stdout goes (unless --log-child is used): `inherit`
+        leaves it as it is, `stderr` sends it to stderr, and `null` discards
+        it. Defaults to `stderr` when using `--output -`, so the results are
+        the only thing written to stdout, and `inherit` otherwise.
+
+    --stdout FILE
+    --stderr FILE
+        Write COMMAND's stdout or stderr to FILE, so it isn't mixed up with
+        what max_rss writes. They can be the same FILE. The results include
+        each FILE and how many bytes were written to it.
+
+    -q, --quiet
+        Discard COMMAND's stdout and stderr, unless they're sent somewhere
+        with --stdout or --stderr.
+
+    --timeline PATH
+        While COMMAND runs, periodically sample the total RSS and append it
+        to PATH as a line of JSON. Samples are written as they're taken, so
+        this is suitable for very long runs.
+
+    --events PATH, --stream PATH
+        While COMMAND runs, write each event to PATH as a line of JSON, as
+        it happens: a process being spawned (`spawned`, with its `ppid`),
+        exec'ing (`exec`, with its new `name` and `cmdline`) or exiting
+        (`exited`, with its `rss`), and the RSS of each process every
+        --interval (`sample`). Every line has the `event`, the `pid` and the
+        `elapsed_ms` since COMMAND started. Each line is flushed as it's
+        written, so PATH can be followed while COMMAND runs, and what was
+        written survives if {bin} is killed. Only a single run can be given.
+
+    --interval DURATION
+        How often the total RSS is sampled for --timeline, --events, --alert
+        and --count-policy concurrent-peak, e.g.: 50ms, 10s or 1m. Defaults
+        to {interval}s.
+
+        When given, the RSS of every process is also sampled at this interval
+        and the largest value seen is used, rather than only its RSS just
+        before it exits. This catches the true peak of processes whose memory
+        shrinks before they exit, and of programs which a process ran before
+        exec'ing another: these are kept in its `segments`, since they're gone
+        by the time it's seen to exec.
+
+    --backend BACKEND
+        How COMMAND is measured. Defaults to `ptrace`, which traces every
+        process in the tree (it's only available on Linux, so `rusage` is the
+        default elsewhere). `cgroup` instead runs COMMAND in a new cgroup
+        (v2) and reports the most memory the whole tree used at once, as
+        accounted by the kernel (memory.peak, which unlike RSS includes the page
+        cache and kernel memory). It has almost no overhead, but there's no
+        breakdown of each process in the graph. The memory controller must be
+        delegated to the cgroup {bin} runs in, e.g.: by running it with
+        `systemd-run --user --scope -p Delegate=yes {bin} ...`. `kqueue`
+        (macOS and FreeBSD only) watches every process in the tree with
+        kqueue, and reports the most RSS they used at once, sampled every 10ms
+        (or every --interval), so processes which exit between samples are
+        missed.
+        `rusage` only waits for COMMAND and reports the max_rss the kernel
+        gives back for it, which is the largest single process it waited for
+        rather than the whole tree. It's coarse, but needs nothing beyond fork,
+        exec and wait.
+        Possible values: {backends}.
+
+    --source SOURCE
+        Where each process' RSS is taken from. Defaults to `rss`, its RSS just
+        before it exits. `hwm` uses the kernel's own high-water mark instead
+        (VmHWM in /proc/<pid>/status), read at the same time. The exit-time
+        RSS is then also reported, as `max_rss_exit` and each process'
+        `exit_rss`, so the two can be compared. Possible values: {sources}.
+
+    --mem-source FILE
+        Which file in /proc/<pid> each process' RSS is read from, both when
+        sampling and as it exits. Defaults to `smaps_rollup`, which is exact
+        but walks every page table of the process each time. `statm` and
+        `status` (VmRSS) are read from counters the kernel keeps as it goes,
+        so are far cheaper when sampling hundreds of processes every 10ms, but
+        may lag behind by a few pages for each thread. `smaps` breaks the RSS
+        down by mapping, so is the slowest: with --out-dir, it's what is kept
+        for each process rather than smaps_rollup. Only the chosen file is
+        read as a process exits; `statm` and `status` have no USS or swap, so
+        `max_uss` and `max_swap` are left out, and smaps_rollup is read as
+        well only for the PSS a --count-policy of `pss-sum` or
+        `concurrent-peak` needs, or the snapshot --out-dir keeps. Possible
+        values: {mem_sources}.
+
+    --count-policy POLICY
+        Which processes' memory is added up into max_rss, recorded in the
+        results as `count_policy`. Defaults to `root-and-parents`: the RSS of
+        COMMAND, of each process which spawned others and of each which
+        exec'd a new program (like a compiler run by a build system), taken as
+        they exit. A forked process which doesn't exec shares most of its
+        memory with its parent until either writes to it, so it's left out,
+        which suits fork-heavy servers.
+
+        `all` sums the RSS of every process instead, which counts memory
+        shared across a fork more than once.
+        `pss-sum` sums the PSS of every process, which splits each shared page
+        between the processes sharing it. `concurrent-peak` samples the PSS of
+        every process each --interval (and as each exits), and reports the
+        largest total of the processes alive at the same time: the most
+        memory the whole tree used at any one moment. Threads are never
+        counted, since they share their process' memory. Possible values:
+        {policies}.
+
+    --only REGEX
+        Only count the processes whose name or command line (its arguments
+        joined by spaces) matches REGEX towards max_rss, e.g.: `^rustc$` to
+        measure just the compiler invocations of a build. Every process is
+        still traced and appears in the graph, those which don't count are
+        marked `excluded`.
+
+    --exclude REGEX
+        Don't count the processes whose name or command line matches REGEX
+        towards max_rss, e.g.: `^(sh|bash)$`. Applied after --only.
+
+    --record-samples
+        Record the RSS of every process each --interval, and include it in
+        its node of the graph as `samples`: a list of [milliseconds since
+        COMMAND started, rss] pairs. A pair is only added when the RSS has
+        changed, and a final one is added just before the process exits.
+
+    --rotate SIZE, --rotate-every DURATION
+        Rotate the timeline once it reaches SIZE or once it's DURATION old,
+        by renaming it to PATH.1, PATH.2 and so on.
+
+    --timeline-delta SIZE
+        Store each sample in the timeline as the change since the last line,
+        and only when it changed by more than SIZE (which may be 0), e.g.:
+        --timeline-delta 1M. This greatly shrinks the timelines of long runs.
+        The first line of each file and the final sample are always written
+        in full.
+
+    --max-samples N
+        Keep at most N samples in the timeline. Samples are kept in memory,
+        and once there are N of them neighbouring samples are merged, keeping
+        the larger of each, so peaks are never lost. The timeline is written
+        when COMMAND exits, and can't be rotated.
+
+    --checkpoint DURATION
+        Every DURATION, write a summary of the timeline so far to it and to
+        stderr, e.g.: --checkpoint 1h
+
+    --alert SIZE
+        Alert each time the total RSS rises above SIZE while COMMAND runs.
+        Alerts are printed to stderr, and sent to each --alert-notify.
+
+    --alert-notify KIND
+        Where else to send alerts, may be given more than once:
+            exec:CMD     run CMD with `sh -c`, with $MAX_RSS_ALERT_RSS and
+                         $MAX_RSS_ALERT_WATERMARK set
+            socket:PATH  write the alert as a line of JSON to a unix socket
+            desktop      show a desktop notification with `notify-send`
+
+    --alert-cooldown DURATION
+        The minimum time between alerts. Defaults to {alert_cooldown}s.
+
+    --timeout DURATION
+        Stop COMMAND if it's still running after DURATION, e.g.: 30s or 1h,
+        by sending SIGTERM to its process group (and then SIGKILL if it still
+        hasn't exited after --kill-after). The results are written as usual,
+        with `timed_out` set. COMMAND is run in a process group of its own.
+
+    --kill-after DURATION
+        How long COMMAND has to exit after it's sent SIGTERM by --timeout,
+        before it's sent SIGKILL. Defaults to {kill_after}s.
+
+    --kill-remaining[=DURATION]
+        Once COMMAND has exited, send SIGTERM to every process it left running
+        (and then SIGKILL to those still running after --kill-after), rather
+        than waiting for them to exit. They're first given DURATION to exit by
+        themselves, which defaults to 0s. Each one that's still running then
+        is marked with `killed_at_teardown` in the graph.
+
+    --exit-with-root
+        Stop measuring as soon as COMMAND has exited, rather than waiting for
+        every process it started to exit too, e.g.: when measuring a server
+        which leaves long-lived workers behind. Processes which are still
+        running are left as they are, with their current RSS taken as their
+        final value. How many there were is reported as `left_running`.
+
+    --max-procs N
+        Bound how many processes {bin} keeps track of. Once more than N are
+        known, every subtree of processes which have all exited is folded
+        into its closest living ancestor: it still counts towards max_rss
+        and total_pids, but only appears in the graph as a total. Useful when
+        measuring builds which run a very large number of processes.
+
+    -n N, --runs N
+        The number of times to run COMMAND. Defaults to 1, except for the
+        `noise` subcommand where it defaults to {noise_runs}.
+
+        When COMMAND is run more than once, the results are those of the last
+        run, along with the max_rss of every run in `runs` and statistics
+        over them in `stats` (min, max, mean, median and stddev). The runs
+        stop early if one of them fails.
+
+    --exit-with POLICY
+        What {bin} exits with once COMMAND has been measured:
+            child        COMMAND's exit code, or `128 + signal` if it was
+                         killed by a signal
+            error-only   0, unless {bin} itself failed or one of the
+                         conditions in --exit-code-map was met
+            always-zero  0 whenever results were written, even partial ones
+                         or those over --threshold, so measuring never
+                         fails a build
+        Defaults to `error-only`, except under Bazel where it's `child`.
+        Either way, the results include COMMAND's `exit_code`, or its
+        `exit_signal` if it was killed by a signal.
+
     -r, --return-result
-        If set, and COMMAND exits with a non-zero exit code, then {bin} itself
-        will exit with that same exit code and print an error to stderr.
-        If COMMAND exited because of a signal, then the return code will be set
-        to `128 + signal`.
+        The same as --exit-with child. Can be undone with --no-return-result,
+        which is the same as --exit-with error-only.
+
+    --exit-code-map NAME=CODE,...
+        Change the exit codes {bin} uses for conditions it detects itself,
+        e.g.: --exit-code-map threshold=98,exec=99
+            threshold  COMMAND succeeded, but its max_rss was over
+                       --threshold (by default the exit code is unchanged,
+                       except under Bazel where it's 1)
+            exec       COMMAND couldn't be found (default: 127, and when it
+                       was found but couldn't be executed it's 126, like a
+                       shell)
+            regression COMMAND succeeded, but its max_rss regressed from
+                       --baseline (default: 1)
+
+    --verify[=PERCENT]
+        Measure the result with several different sources (smaps_rollup and
+        VmHWM) and warn when they diverge more than the given tolerance.
+        Defaults to {verify_tolerance}%.
+
+    --rusage
+        Also report `ru_maxrss`: the largest RSS of any single process in the
+        tree, as accounted by the kernel when each was reaped, taken from
+        COMMAND's own rusage once it's reaped. Processes which were reaped by
+        something other than their parent (e.g.: orphans adopted by init)
+        aren't included, and neither are --setup steps or earlier runs.
 
-        Can be disabled with --no-return-result.
+    --io
+        Also collect each process' I/O counters from /proc/<pid>/io just
+        before it exits: `rchar` and `wchar` (bytes passed to read and write
+        calls, including those served by the page cache) and `read_bytes` and
+        `write_bytes` (bytes actually fetched from or sent to storage). They're
+        added to each process in the graph and totalled in `io`.
+
+    --no-new-privs
+        Set the no_new_privs flag on COMMAND before it's executed, so neither
+        it nor anything it runs can gain privileges through setuid, setgid or
+        file capabilities (and so can't escape tracing that way). Useful when
+        measuring untrusted or third-party scripts.
+
+    --seccomp PATH
+        Apply the seccomp profile at PATH to COMMAND before it's executed, to
+        sandbox it while it's measured. Profiles use the same JSON format as
+        Docker and other OCI runtimes, e.g.:
+        {{"defaultAction": "SCMP_ACT_ALLOW",
+        "syscalls": [{{"names": ["ptrace"], "action": "SCMP_ACT_ERRNO"}}]}}
+        Implies --no-new-privs.
+
+    --pidns
+        Run COMMAND in a new PID namespace (with a new user namespace when not
+        run as root, where that's permitted), so nothing it runs can escape
+        being measured: COMMAND becomes PID 1 of the namespace, and when it
+        exits every process left in it is killed. As PID 1, COMMAND also
+        ignores any signals it doesn't handle, and adopts orphaned processes.
+        A fresh /proc is mounted for it where possible.
+
+    --passthrough
+        Guarantee that COMMAND's stdin, stdout, stderr and any other inherited
+        file descriptors are exactly the same as if it were run directly. This
+        is the default, but when set explicitly any option which would capture
+        or redirect COMMAND's output is rejected.
 
     -d, --debug
         Print debug logs to stderr.
 
+    --print-schema
+        Print the JSON Schema of the results (written with `--output-format
+        json`) and exit. The results record the `schema_version` they follow.
+
     -v, --version
         Print version information and exit.
 
     -h, --help
         Show this help text.
 
+BAZEL:
+    When run as (or inside) a Bazel test, relative output paths are written
+    to $TEST_UNDECLARED_OUTPUTS_DIR so Bazel collects them, a JUnit report is
+    written to $XML_OUTPUT_FILE, --exit-with defaults to `child`, and
+    exceeding --threshold fails the test.
+
+SIGNALS:
+    SIGINT and SIGTERM sent to {bin} are forwarded to COMMAND (or to its
+    process group, with --timeout), and {bin} keeps measuring until it exits
+    so the results are still written. Pressing Ctrl-C in a terminal already
+    sends SIGINT to COMMAND, so it isn't sent to it twice.
+
 EXAMPLES:
     Using {bin} should be more or less the same as using something like `time`:
 
@@ -64,8 +559,48 @@ EXAMPLES:
             If `find` fails and exits with a non-zero code, then {bin} will
             also exit with that code (--return-result). The results will be
             written to ./results.json, too (--output).
+
+        {bin} -- calibrate
+            Use `--` to run a program which has the same name as a subcommand.
 "#,
-            bin = env!("CARGO_BIN_NAME"),
+            bin = env!("CARGO_PKG_NAME"),
+            noise_runs = crate::noise::DEFAULT_RUNS,
+            interval = DEFAULT_INTERVAL.as_secs_f64(),
+            alert_cooldown = crate::alert::DEFAULT_COOLDOWN.as_secs(),
+            kill_after = crate::timeout::DEFAULT_GRACE.as_secs(),
+            redacted = crate::redact::REDACTED,
+            redact_env = crate::redact::DEFAULT_ENV_PATTERN,
+            verify_tolerance = crate::verify::DEFAULT_TOLERANCE * 100.0,
+            formats = OutputFormat::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            backends = Backend::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            sources = RssSource::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            mem_sources = MemSource::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            policies = CountPolicy::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            clocks = Clock::ALL
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
             crate_name = env!("CARGO_PKG_NAME"),
             crate_version = env!("CARGO_PKG_VERSION"),
             crate_homepage = env!("CARGO_PKG_HOMEPAGE"),
@@ -79,21 +614,223 @@ EXAMPLES:
     );
 }
 
-#[derive(Debug)]
+/// A COMMAND which runs the script with `sh -c`.
+pub fn shell_command(script: &OsStr) -> Vec<OsString> {
+    vec!["sh".into(), "-c".into(), script.to_owned()]
+}
+
+/// Parses an environment variable given as `KEY=VALUE`.
+fn parse_env(s: OsString) -> Result<(OsString, OsString)> {
+    let bytes = s.as_bytes();
+    match bytes.iter().position(|b| *b == b'=') {
+        Some(i) if i > 0 => Ok((
+            OsStr::from_bytes(&bytes[..i]).to_owned(),
+            OsStr::from_bytes(&bytes[i + 1..]).to_owned(),
+        )),
+        _ => bail!("expected KEY=VALUE, got: {}", s.to_string_lossy()),
+    }
+}
+
+/// Parses a percentage such as `5` or `5%`, returning it as a fraction.
+fn parse_percent(s: &str) -> Result<f64> {
+    let n = s.strip_suffix('%').unwrap_or(s).trim().parse::<f64>()?;
+    if !n.is_finite() || n < 0.0 {
+        bail!("invalid percentage: {}", s);
+    }
+
+    Ok(n / 100.0)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Subcommand {
+    /// Trace and measure COMMAND.
+    #[default]
+    Run,
+    /// Measure the overhead of tracing.
+    Calibrate,
+    /// Measure the run-to-run variance of COMMAND.
+    Noise,
+    /// Compare two results files.
+    Compare,
+    /// Print a results file for people to read.
+    Report,
+    /// Combine several results files into one.
+    Merge,
+}
+
+impl Subcommand {
+    const ALL: &'static [(&'static str, Subcommand)] = &[
+        ("run", Subcommand::Run),
+        ("calibrate", Subcommand::Calibrate),
+        ("noise", Subcommand::Noise),
+        ("compare", Subcommand::Compare),
+        ("diff", Subcommand::Compare),
+        ("report", Subcommand::Report),
+        ("merge", Subcommand::Merge),
+    ];
+
+    /// Whether this subcommand requires a COMMAND to be given.
+    fn needs_command(&self) -> bool {
+        matches!(self, Subcommand::Run | Subcommand::Noise)
+    }
+
+    /// The number of files this subcommand expects to be given.
+    fn files(&self) -> RangeInclusive<usize> {
+        match self {
+            Subcommand::Compare => 2..=2,
+            Subcommand::Report => 1..=1,
+            Subcommand::Merge => 2..=usize::MAX,
+            _ => 0..=0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Args {
+    pub subcommand: Subcommand,
     pub debug: bool,
-    pub return_result: bool,
+    pub passthrough: bool,
+    pub no_new_privs: bool,
+    pub pidns: bool,
+    pub seccomp: Option<PathBuf>,
+    pub exit_with: ExitWith,
+    pub exit_codes: ExitCodes,
+    pub runs: Option<usize>,
+    pub pid: Option<i32>,
+    pub max_procs: Option<usize>,
+    pub verify: Option<f64>,
+    pub rusage: bool,
+    pub io: bool,
+    pub badge: Option<PathBuf>,
+    pub threshold: Option<u64>,
+    pub baseline: Option<PathBuf>,
+    pub max_regression: Option<f64>,
+    pub env: Vec<(OsString, OsString)>,
+    pub env_remove: Vec<OsString>,
+    pub clear_env: bool,
+    pub cwd: Option<PathBuf>,
+    pub shell: bool,
+    pub capture_env: bool,
+    pub summary: bool,
+    pub github_summary: bool,
+    pub append: bool,
+    pub tags: Vec<(String, String)>,
+    pub otlp: Option<Endpoint>,
+    pub statsd: Option<Address>,
+    pub redact: Option<Regex>,
+    pub redact_args: Option<Regex>,
     pub output: PathBuf,
+    pub out_dir: Option<PathBuf>,
+    pub output_format: OutputFormat,
+    pub clock: Clock,
+    pub log_child: Option<PathBuf>,
+    pub child_stdout: ChildStdout,
+    pub stdout: Option<PathBuf>,
+    pub stderr: Option<PathBuf>,
+    pub timeline: Option<PathBuf>,
+    pub events: Option<PathBuf>,
+    pub interval: Duration,
+    pub sample_peaks: bool,
+    pub backend: Backend,
+    pub source: RssSource,
+    pub mem_source: MemSource,
+    pub count_policy: CountPolicy,
+    pub filter: ProcessFilter,
+    pub record_samples: bool,
+    pub rotate_size: Option<u64>,
+    pub rotate_every: Option<Duration>,
+    pub timeline_delta: Option<u64>,
+    pub max_samples: Option<usize>,
+    pub checkpoint: Option<Duration>,
+    pub setup: Vec<String>,
+    pub teardown: Vec<String>,
+    pub alert: Option<u64>,
+    pub alert_notify: Vec<Notify>,
+    pub alert_cooldown: Duration,
+    pub timeout: Option<Duration>,
+    pub kill_remaining: Option<Duration>,
+    pub exit_with_root: bool,
+    pub kill_after: Duration,
     pub command: Vec<OsString>,
+    pub files: Vec<PathBuf>,
+    /// The commands to run and compare, with `compare -- <OLD_COMMAND> -- <NEW_COMMAND>`.
+    pub commands: Vec<OsString>,
 }
 
 impl Default for Args {
     fn default() -> Self {
         Args {
+            subcommand: Subcommand::default(),
             debug: false,
-            return_result: false,
-            output: PathBuf::from(format!("./{}.json", env!("CARGO_BIN_NAME"))),
+            passthrough: false,
+            no_new_privs: false,
+            pidns: false,
+            seccomp: None,
+            // a Bazel test's exit code decides whether it passed
+            exit_with: match crate::bazel::test_target() {
+                Some(_) => ExitWith::Child,
+                None => ExitWith::default(),
+            },
+            exit_codes: ExitCodes::default(),
+            runs: None,
+            pid: None,
+            max_procs: None,
+            verify: None,
+            rusage: false,
+            io: false,
+            output: PathBuf::from(format!("./{}.json", env!("CARGO_PKG_NAME"))),
+            badge: None,
+            threshold: None,
+            baseline: None,
+            max_regression: None,
+            env: vec![],
+            env_remove: vec![],
+            clear_env: false,
+            cwd: None,
+            shell: false,
+            capture_env: false,
+            summary: false,
+            append: false,
+            tags: vec![],
+            github_summary: false,
+            otlp: None,
+            statsd: None,
+            redact: None,
+            redact_args: None,
+            out_dir: None,
+            output_format: OutputFormat::default(),
+            clock: Clock::default(),
+            log_child: None,
+            child_stdout: ChildStdout::default(),
+            stdout: None,
+            stderr: None,
+            timeline: None,
+            events: None,
+            interval: DEFAULT_INTERVAL,
+            sample_peaks: false,
+            backend: Backend::default(),
+            source: RssSource::default(),
+            mem_source: MemSource::default(),
+            count_policy: CountPolicy::default(),
+            filter: ProcessFilter::default(),
+            record_samples: false,
+            rotate_size: None,
+            rotate_every: None,
+            timeline_delta: None,
+            max_samples: None,
+            checkpoint: None,
+            setup: vec![],
+            teardown: vec![],
+            alert: None,
+            alert_notify: vec![],
+            alert_cooldown: crate::alert::DEFAULT_COOLDOWN,
+            timeout: None,
+            kill_remaining: None,
+            exit_with_root: false,
+            kill_after: crate::timeout::DEFAULT_GRACE,
             command: vec![],
+            files: vec![],
+            commands: vec![],
         }
     }
 }
@@ -103,39 +840,392 @@ impl Args {
         Args::parse_impl(lexopt::Parser::from_env())
     }
 
+    /// Parses the given arguments, which don't include the program's own name.
+    pub fn parse_from(args: impl IntoIterator<Item = OsString>) -> Result<Args> {
+        Args::parse_impl(lexopt::Parser::from_args(args))
+    }
+
     fn parse_impl(mut parser: Parser) -> Result<Args> {
         use lexopt::prelude::*;
 
         let mut args = Args::default();
+        let mut child_stdout = None;
+        let mut quiet = false;
+
+        // subcommands are only recognised as the very first argument, and if it isn't preceded by
+        // `--`, so programs with the same name can still be run
+        if let Some(mut raw) = parser.try_raw_args() {
+            let subcommand = raw.peek().and_then(|first| {
+                Subcommand::ALL
+                    .iter()
+                    .find_map(|(name, sub)| (first == *name).then_some(*sub))
+            });
+
+            if let Some(subcommand) = subcommand {
+                raw.next();
+                args.subcommand = subcommand;
+            }
+        }
 
         while let Some(arg) = parser.next()? {
             match arg {
+                // -S, --shell
+                Short('S') | Long("shell") => args.shell = true,
+
+                // --exit-with=X
+                Long("exit-with") => {
+                    args.exit_with = parser.value()?.parse()?;
+                }
+
                 // -r, --return-result, --no-return-result
-                Short('r') | Long("return-result") => args.return_result = true,
-                Long("no-return-result") => args.return_result = false,
+                Short('r') | Long("return-result") => args.exit_with = ExitWith::Child,
+                Long("no-return-result") => args.exit_with = ExitWith::ErrorOnly,
+
+                // --exit-code-map=X
+                Long("exit-code-map") => {
+                    args.exit_codes = parser.value()?.parse()?;
+                }
+
+                // --badge=X
+                Long("badge") => {
+                    args.badge = Some(parser.value()?.into());
+                }
+
+                // --threshold=X
+                Long("threshold") => {
+                    args.threshold = Some(parse_size(&parser.value()?.string()?)?);
+                }
+
+                // --baseline=X
+                Long("baseline") => {
+                    args.baseline = Some(parser.value()?.into());
+                }
+
+                // --max-regression=X
+                Long("max-regression") => {
+                    args.max_regression = Some(parse_percent(&parser.value()?.string()?)?);
+                }
+
+                // --env=X
+                Long("env") => {
+                    args.env.push(parse_env(parser.value()?)?);
+                }
+
+                // --tag=X
+                Long("tag") => {
+                    let (key, value) = parse_env(parser.value()?)?;
+                    args.tags.push((
+                        key.to_string_lossy().into_owned(),
+                        value.to_string_lossy().into_owned(),
+                    ));
+                }
+
+                // --env-remove=X
+                Long("env-remove") => {
+                    args.env_remove.push(parser.value()?);
+                }
+
+                // --clear-env
+                Long("clear-env") => args.clear_env = true,
+
+                // --cwd=X
+                Long("cwd") => {
+                    args.cwd = Some(parser.value()?.into());
+                }
+
+                // --capture-env
+                Long("capture-env") => args.capture_env = true,
+
+                // --summary
+                Long("summary") => args.summary = true,
+
+                // --github-summary
+                Long("github-summary") => args.github_summary = true,
+
+                // --append
+                Long("append") => args.append = true,
+
+                // --otlp=X
+                Long("otlp") => {
+                    args.otlp = Some(Endpoint::parse(&parser.value()?.string()?)?);
+                }
+
+                // --statsd=X
+                Long("statsd") => {
+                    args.statsd = Some(Address::parse(&parser.value()?.string()?)?);
+                }
+
+                // --redact=X
+                Long("redact") => {
+                    args.redact = Some(Regex::new(&parser.value()?.string()?)?);
+                }
+
+                // --redact-args=X
+                Long("redact-args") => {
+                    args.redact_args = Some(Regex::new(&parser.value()?.string()?)?);
+                }
+
+                // --setup=X
+                Long("setup") => {
+                    args.setup.push(parser.value()?.string()?);
+                }
+
+                // --teardown=X
+                Long("teardown") => {
+                    args.teardown.push(parser.value()?.string()?);
+                }
+
+                // --log-child=X
+                Long("log-child") => {
+                    args.log_child = Some(parser.value()?.into());
+                }
+
+                // --child-stdout=X
+                Long("child-stdout") => {
+                    child_stdout = Some(parser.value()?.parse()?);
+                }
+
+                // --stdout=X
+                Long("stdout") => {
+                    args.stdout = Some(parser.value()?.into());
+                }
+
+                // --stderr=X
+                Long("stderr") => {
+                    args.stderr = Some(parser.value()?.into());
+                }
+
+                // -q, --quiet
+                Short('q') | Long("quiet") => quiet = true,
+
+                // --timeline=X
+                Long("timeline") => {
+                    args.timeline = Some(parser.value()?.into());
+                }
+
+                // --events=X, --stream=X
+                Long("events" | "stream") => {
+                    args.events = Some(parser.value()?.into());
+                }
+
+                // --interval=X
+                Long("interval") => {
+                    args.interval = parse_duration(&parser.value()?.string()?)?;
+                    if args.interval.is_zero() {
+                        bail!("--interval must be greater than zero");
+                    }
+                    args.sample_peaks = true;
+                }
+
+                // --backend=X
+                Long("backend") => {
+                    args.backend = parser.value()?.parse()?;
+                }
+
+                // --source=X
+                Long("source") => {
+                    args.source = parser.value()?.parse()?;
+                }
+
+                // --mem-source=X
+                Long("mem-source") => {
+                    args.mem_source = parser.value()?.parse()?;
+                }
+
+                // --count-policy=X
+                Long("count-policy") => {
+                    args.count_policy = parser.value()?.parse()?;
+                }
+
+                // --only=X
+                Long("only") => {
+                    args.filter.only = Some(Regex::new(&parser.value()?.string()?)?);
+                }
+
+                // --exclude=X
+                Long("exclude") => {
+                    args.filter.exclude = Some(Regex::new(&parser.value()?.string()?)?);
+                }
+
+                // --record-samples
+                Long("record-samples") => args.record_samples = true,
+
+                // --rotate=X
+                Long("rotate") => {
+                    args.rotate_size = Some(parse_size(&parser.value()?.string()?)?);
+                }
+
+                // --rotate-every=X
+                Long("rotate-every") => {
+                    args.rotate_every = Some(parse_duration(&parser.value()?.string()?)?);
+                }
+
+                // --timeline-delta=X
+                Long("timeline-delta") => {
+                    args.timeline_delta = Some(parse_size(&parser.value()?.string()?)?);
+                }
+
+                // --max-samples=X
+                Long("max-samples") => {
+                    let max = parser.value()?.parse()?;
+                    if max < 2 {
+                        bail!("--max-samples must be at least 2");
+                    }
+                    args.max_samples = Some(max);
+                }
+
+                // --checkpoint=X
+                Long("checkpoint") => {
+                    args.checkpoint = Some(parse_duration(&parser.value()?.string()?)?);
+                }
+
+                // --alert=X
+                Long("alert") => {
+                    args.alert = Some(parse_size(&parser.value()?.string()?)?);
+                }
+
+                // --alert-notify=X
+                Long("alert-notify") => {
+                    args.alert_notify.push(parser.value()?.parse()?);
+                }
+
+                // --alert-cooldown=X
+                Long("alert-cooldown") => {
+                    args.alert_cooldown = parse_duration(&parser.value()?.string()?)?;
+                }
+
+                // --timeout=X
+                Long("timeout") => {
+                    let timeout = parse_duration(&parser.value()?.string()?)?;
+                    if timeout.is_zero() {
+                        bail!("--timeout must be greater than zero");
+                    }
+                    args.timeout = Some(timeout);
+                }
+
+                // --kill-after=X
+                Long("kill-after") => {
+                    args.kill_after = parse_duration(&parser.value()?.string()?)?;
+                }
+
+                // --kill-remaining, --kill-remaining=X
+                Long("kill-remaining") => {
+                    args.kill_remaining = Some(match parser.optional_value() {
+                        Some(value) => parse_duration(&value.string()?)?,
+                        None => Duration::ZERO,
+                    });
+                }
+
+                // --exit-with-root
+                Long("exit-with-root") => args.exit_with_root = true,
+
+                // --max-procs=X
+                Long("max-procs") => {
+                    let max = parser.value()?.parse()?;
+                    if max == 0 {
+                        bail!("--max-procs must be greater than zero");
+                    }
+                    args.max_procs = Some(max);
+                }
+
+                // -n=X, --runs=X
+                Short('n') | Long("runs") => {
+                    let runs = parser.value()?.parse()?;
+                    if runs == 0 {
+                        bail!("--runs must be greater than zero");
+                    }
+                    args.runs = Some(runs);
+                }
+
+                // -p=X, --pid=X
+                Short('p') | Long("pid") => {
+                    let pid = parser.value()?.parse()?;
+                    if pid <= 0 {
+                        bail!("--pid must be greater than zero");
+                    }
+                    args.pid = Some(pid);
+                }
 
                 // -o=X, --output=X
                 Short('o') | Long("output") => {
                     args.output = parser.value()?.into();
                 }
 
+                // --out-dir=X
+                Long("out-dir") => {
+                    args.out_dir = Some(parser.value()?.into());
+                }
+
+                // -O=X, --output-format=X, --format=X
+                Short('O') | Long("output-format") | Long("format") => {
+                    args.output_format = parser.value()?.parse()?;
+                }
+
+                // --clock=X
+                Long("clock") => {
+                    args.clock = parser.value()?.parse()?;
+                }
+
                 // -h, --help
                 Short('h') | Long("help") => {
                     print_help();
                     process::exit(0);
                 }
 
+                // --print-schema
+                Long("print-schema") => {
+                    print!("{}", crate::report::SCHEMA);
+                    process::exit(0);
+                }
+
                 // -v, --version
                 Short('v') | Long("version") => {
                     print_version();
                     process::exit(0);
                 }
 
+                // --verify, --verify=X
+                Long("verify") => {
+                    args.verify = Some(match parser.optional_value() {
+                        Some(value) => parse_percent(&value.string()?)?,
+                        None => crate::verify::DEFAULT_TOLERANCE,
+                    });
+                }
+
+                // --rusage
+                Long("rusage") => args.rusage = true,
+
+                // --io
+                Long("io") => args.io = true,
+
+                // --no-new-privs
+                Long("no-new-privs") => {
+                    args.no_new_privs = true;
+                }
+
+                // --pidns
+                Long("pidns") => args.pidns = true,
+
+                // --seccomp=X
+                Long("seccomp") => {
+                    args.seccomp = Some(parser.value()?.into());
+                }
+
+                // --passthrough
+                Long("passthrough") => {
+                    args.passthrough = true;
+                }
+
                 // -d, --debug
                 Short('d') | Long("debug") => {
                     args.debug = true;
                 }
 
+                // some subcommands take files rather than a command
+                Value(other) if *args.subcommand.files().end() > 0 => {
+                    args.files.push(other.into());
+                }
+
                 // collect the rest of the arguments as the command to run
                 Value(other) => {
                     args.command.push(other);
@@ -146,11 +1236,224 @@ impl Args {
             }
         }
 
-        if args.command.is_empty() {
+        if quiet {
+            args.stdout.get_or_insert_with(|| "/dev/null".into());
+            args.stderr.get_or_insert_with(|| "/dev/null".into());
+        }
+
+        if !args.backend.is_available() {
+            bail!(
+                "--backend {} isn't available on {}",
+                args.backend.name(),
+                std::env::consts::OS
+            );
+        }
+        if cfg!(not(target_os = "linux"))
+            && (args.pid.is_some() || args.seccomp.is_some() || args.no_new_privs)
+        {
+            bail!("--pid, --seccomp and --no-new-privs are only available on Linux");
+        }
+
+        if args.pid.is_some() {
+            if args.subcommand != Subcommand::Run || args.runs.is_some() {
+                bail!("--pid can only be used to measure a single run");
+            }
+            if !args.command.is_empty() {
+                bail!("--pid attaches to a running process, so can't be given a COMMAND");
+            }
+            if args.backend != Backend::Ptrace
+                || args.timeout.is_some()
+                || args.log_child.is_some()
+                || child_stdout.is_some()
+                || args.seccomp.is_some()
+                || args.no_new_privs
+                || args.pidns
+                || !args.env.is_empty()
+                || !args.env_remove.is_empty()
+                || args.clear_env
+                || args.cwd.is_some()
+                || args.shell
+                || args.stdout.is_some()
+                || args.stderr.is_some()
+            {
+                bail!(
+                    "--backend, --timeout, --log-child, --child-stdout, --seccomp, --no-new-privs, \
+                    --pidns, --cwd, --shell, --stdout and --env (and the like) only apply to a COMMAND that's started by {}, so can't be used with --pid",
+                    env!("CARGO_PKG_NAME")
+                );
+            }
+        } else if args.subcommand.needs_command() && args.command.is_empty() {
             print_help();
             bail!("No command was given.");
         }
 
+        if args.shell && !args.command.is_empty() {
+            let mut script = OsString::new();
+            for (i, arg) in args.command.iter().enumerate() {
+                if i > 0 {
+                    script.push(" ");
+                }
+                script.push(arg);
+            }
+            args.command = shell_command(&script);
+        }
+
+        if args.timeline.is_none()
+            && (args.rotate_size.is_some()
+                || args.rotate_every.is_some()
+                || args.timeline_delta.is_some()
+                || args.max_samples.is_some()
+                || args.checkpoint.is_some())
+        {
+            bail!(
+                "--rotate, --rotate-every, --timeline-delta, --max-samples and --checkpoint \
+                require --timeline"
+            );
+        }
+
+        if args.max_samples.is_some() && (args.rotate_size.is_some() || args.rotate_every.is_some())
+        {
+            bail!("--max-samples can't be used with --rotate or --rotate-every");
+        }
+
+        if args.events.is_some() && args.runs.is_some_and(|runs| runs > 1) {
+            bail!("--events can only be used to measure a single run");
+        }
+
+        if args.backend != Backend::Ptrace
+            && (args.count_policy != CountPolicy::RootAndParents
+                || args.filter.only.is_some()
+                || args.filter.exclude.is_some()
+                || args.events.is_some()
+                || args.io
+                || args.max_procs.is_some()
+                || args.record_samples
+                || args.source != RssSource::Rss
+                || args.mem_source != MemSource::SmapsRollup
+                || args.verify.is_some())
+        {
+            bail!(
+                "--count-policy, --only, --exclude, --events, --io, --max-procs, --record-samples, \
+                --source, --mem-source and --verify measure each process, so can't be used with \
+                --backend {}",
+                args.backend.name()
+            );
+        }
+
+        if (args.pidns || args.kill_remaining.is_some() || args.exit_with_root)
+            && args.backend != Backend::Ptrace
+        {
+            bail!(
+                "--pidns, --kill-remaining and --exit-with-root can only be used with --backend \
+                ptrace"
+            );
+        }
+
+        if args.exit_with_root && args.kill_remaining.is_some() {
+            bail!("--exit-with-root and --kill-remaining can't be used together");
+        }
+
+        if args.backend == Backend::Rusage
+            && (args.timeline.is_some() || args.alert.is_some() || args.checkpoint.is_some())
+        {
+            bail!(
+                "--timeline, --alert and --checkpoint sample COMMAND while it runs, so can't be used \
+                with --backend rusage"
+            );
+        }
+
+        if args.passthrough && args.log_child.is_some() {
+            bail!("--log-child captures COMMAND's output, so can't be used with --passthrough");
+        }
+
+        if args.log_child.is_some() && child_stdout.is_some() {
+            bail!("--log-child captures COMMAND's output, so can't be used with --child-stdout");
+        }
+
+        if args.stdout.is_some() || args.stderr.is_some() {
+            if args.log_child.is_some() || args.passthrough {
+                bail!(
+                    "--stdout, --stderr and --quiet redirect COMMAND's output, so can't be used with \
+                    --log-child or --passthrough"
+                );
+            }
+            if args.stdout.is_some() && child_stdout.is_some() {
+                bail!("--stdout and --child-stdout can't be used together");
+            }
+        }
+
+        // results written to stdout shouldn't be mixed up with COMMAND's own output
+        let to_stdout = args.out_dir.is_none() && crate::output::is_stdout(&args.output);
+        args.child_stdout = match child_stdout {
+            Some(mode) => mode,
+            None if to_stdout && args.log_child.is_none() && args.pid.is_none() => {
+                ChildStdout::Stderr
+            }
+            None => ChildStdout::Inherit,
+        };
+
+        if args.passthrough && args.child_stdout != ChildStdout::Inherit {
+            bail!(
+                "--child-stdout (which defaults to `stderr` with `--output -`) redirects COMMAND's \
+                output, so can't be used with --passthrough"
+            );
+        }
+
+        if args.baseline.is_none() && args.max_regression.is_some() {
+            bail!("--max-regression requires --baseline");
+        }
+
+        if args.append {
+            if args.out_dir.is_some() || output::is_stdout(&args.output) {
+                bail!("--append can only be used when writing the results to a file");
+            }
+            if args.output_format != OutputFormat::Json {
+                bail!("--append can only be used with --output-format json");
+            }
+        }
+
+        // checked now rather than once COMMAND has finished, when the results would be lost
+        if args.output_format == OutputFormat::Sqlite {
+            if !cfg!(feature = "sqlite") {
+                bail!(
+                    "--output-format sqlite needs {} to be built with the `sqlite` feature",
+                    env!("CARGO_PKG_NAME")
+                );
+            }
+            if args.out_dir.is_none() && output::is_stdout(&args.output) {
+                bail!("--output-format sqlite can only be written to a file, not stdout");
+            }
+        }
+
+        if args.alert.is_none() && !args.alert_notify.is_empty() {
+            bail!("--alert-notify requires --alert");
+        }
+
+        // everything after the first `--` is a value, so the second one separates the commands
+        if args.subcommand == Subcommand::Compare
+            && args.files.len() == 3
+            && args.files[1].as_os_str() == "--"
+        {
+            args.commands = [args.files.remove(0), args.files.remove(1)]
+                .map(OsString::from)
+                .to_vec();
+            args.files.clear();
+        }
+
+        let files = args.subcommand.files();
+        if args.commands.is_empty() && !files.contains(&args.files.len()) {
+            bail!(
+                "Expected {}{} files, but {} were given.",
+                if files.start() == files.end() {
+                    ""
+                } else {
+                    "at least "
+                },
+                files.start(),
+                args.files.len()
+            );
+        }
+
         Ok(args)
     }
 }
@@ -183,6 +1486,100 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn subcommand() -> Result<()> {
+        assert_eq!(args!("foo")?.subcommand, Subcommand::Run);
+        assert_eq!(args!("calibrate")?.subcommand, Subcommand::Calibrate);
+        assert_eq!(args!("calibrate", "-d")?.subcommand, Subcommand::Calibrate);
+        assert_eq!(args!("--", "calibrate")?.subcommand, Subcommand::Run);
+        assert_eq!(args!("--", "calibrate")?.command, vec!["calibrate"]);
+        assert_eq!(args!("-d", "calibrate")?.subcommand, Subcommand::Run);
+        assert_eq!(args!("noise", "foo")?.subcommand, Subcommand::Noise);
+        assert_eq!(args!("noise", "--", "foo")?.command, vec!["foo"]);
+        assert!(args!("noise").is_err());
+        assert_eq!(args!("compare", "a", "b")?.subcommand, Subcommand::Compare);
+        assert_eq!(
+            args!("compare", "a", "--format=html", "b")?.files,
+            vec![PathBuf::from("a"), PathBuf::from("b")]
+        );
+        assert!(args!("compare", "a").is_err());
+        assert!(args!("compare", "a", "b", "c").is_err());
+        assert_eq!(args!("diff", "a", "b")?.subcommand, Subcommand::Compare);
+        assert_eq!(args!("run", "foo")?.subcommand, Subcommand::Run);
+        assert_eq!(args!("run", "foo")?.command, vec!["foo"]);
+        assert_eq!(args!("--", "run")?.command, vec!["run"]);
+        assert_eq!(args!("report", "a")?.files, vec![PathBuf::from("a")]);
+        assert!(args!("report").is_err());
+        assert_eq!(args!("merge", "a", "b", "c")?.files.len(), 3);
+        assert!(args!("merge", "a").is_err());
+        let args = args!("compare", "--runs=3", "--", "gzip -k a", "--", "zstd a")?;
+        assert_eq!(args.commands, vec!["gzip -k a", "zstd a"]);
+        assert!(args.files.is_empty());
+        assert!(args!("compare", "--", "a", "b", "c").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn runs() -> Result<()> {
+        assert_eq!(args!("foo")?.runs, None);
+        assert_eq!(args!("-n", "3", "foo")?.runs, Some(3));
+        assert_eq!(args!("--runs=10", "foo")?.runs, Some(10));
+        assert!(args!("--runs=0", "foo").is_err());
+        assert!(args!("--runs=x", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pid() -> Result<()> {
+        assert_eq!(args!("foo")?.pid, None);
+        assert_eq!(args!("-p", "1234")?.pid, Some(1234));
+        assert_eq!(args!("--pid=1234", "-o", "-")?.pid, Some(1234));
+        assert!(args!("--pid=0").is_err());
+        assert!(args!("--pid=1234", "foo").is_err());
+        assert!(args!("--pid=1234", "--seccomp=p.json").is_err());
+        assert!(args!("noise", "--pid=1234").is_err());
+        assert!(args!("--pid=1234", "--runs=3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn timeout() -> Result<()> {
+        assert_eq!(args!("foo")?.timeout, None);
+        assert_eq!(
+            args!("--timeout=30s", "foo")?.timeout,
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(args!("foo")?.kill_after, crate::timeout::DEFAULT_GRACE);
+        assert_eq!(
+            args!("--kill-after=1m", "foo")?.kill_after,
+            Duration::from_secs(60)
+        );
+        assert_eq!(args!("foo")?.kill_remaining, None);
+        assert_eq!(
+            args!("--kill-remaining", "foo")?.kill_remaining,
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            args!("--kill-remaining=2s", "foo")?.kill_remaining,
+            Some(Duration::from_secs(2))
+        );
+        assert!(args!("--kill-remaining", "--backend=cgroup", "foo").is_err());
+        assert!(!args!("foo")?.exit_with_root);
+        assert!(args!("--exit-with-root", "foo")?.exit_with_root);
+        assert!(args!("--exit-with-root", "--kill-remaining", "foo").is_err());
+        assert!(args!("--timeout=0s", "foo").is_err());
+        assert!(args!("--timeout=1s", "--pid=1234").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn max_procs() -> Result<()> {
+        assert_eq!(args!("foo")?.max_procs, None);
+        assert_eq!(args!("--max-procs=1000", "foo")?.max_procs, Some(1000));
+        assert!(args!("--max-procs=0", "foo").is_err());
+        Ok(())
+    }
+
     #[test]
     fn command_required() -> Result<()> {
         assert!(args!().is_err());
@@ -201,23 +1598,329 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn otlp() -> Result<()> {
+        assert_eq!(args!("foo")?.otlp, None);
+        assert_eq!(
+            args!("--otlp=http://localhost:4318", "foo")?.otlp,
+            Some(Endpoint::parse("localhost:4318")?)
+        );
+        assert!(args!("--otlp=https://localhost:4318", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn statsd() -> Result<()> {
+        assert_eq!(args!("foo")?.statsd, None);
+        assert_eq!(
+            args!("--statsd=localhost:8125", "foo")?.statsd,
+            Some(Address::parse("localhost:8125")?)
+        );
+        assert!(args!("--statsd=localhost", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn backend() -> Result<()> {
+        let default = match cfg!(target_os = "linux") {
+            true => Backend::Ptrace,
+            false => Backend::Rusage,
+        };
+        assert_eq!(args!("foo")?.backend, default);
+        assert_eq!(args!("--backend=cgroup", "foo")?.backend, Backend::Cgroup);
+        assert!(args!("--backend=ebpf", "foo").is_err());
+        assert!(args!("--backend=cgroup", "--record-samples", "foo").is_err());
+        assert!(args!("--backend=cgroup", "--source=hwm", "foo").is_err());
+        assert!(args!("--backend=cgroup", "--timeline=t.json", "foo").is_ok());
+        assert_eq!(args!("--backend=rusage", "foo")?.backend, Backend::Rusage);
+        assert!(args!("--backend=rusage", "--io", "foo").is_err());
+        assert!(args!("--backend=rusage", "--timeline=t.json", "foo").is_err());
+        assert_eq!(
+            args!("--backend=kqueue", "foo").is_ok(),
+            cfg!(any(target_os = "macos", target_os = "freebsd"))
+        );
+        assert!(args!("--backend=kqueue", "--io", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn source() -> Result<()> {
+        assert_eq!(args!("foo")?.source, RssSource::Rss);
+        assert_eq!(args!("--source=hwm", "foo")?.source, RssSource::Hwm);
+        assert_eq!(args!("--source", "rss", "foo")?.source, RssSource::Rss);
+        assert!(args!("--source=pss", "foo").is_err());
+
+        assert_eq!(args!("foo")?.mem_source, MemSource::SmapsRollup);
+        assert_eq!(
+            args!("--mem-source=statm", "foo")?.mem_source,
+            MemSource::Statm
+        );
+        assert_eq!(
+            args!("--mem-source", "smaps", "foo")?.mem_source,
+            MemSource::Smaps
+        );
+        assert!(args!("--mem-source=rss", "foo").is_err());
+        assert!(args!("--backend=rusage", "--mem-source=status", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn count_policy() -> Result<()> {
+        assert_eq!(args!("foo")?.count_policy, CountPolicy::RootAndParents);
+        assert_eq!(
+            args!("--count-policy=concurrent-peak", "foo")?.count_policy,
+            CountPolicy::ConcurrentPeak
+        );
+        assert_eq!(
+            args!("--count-policy", "pss-sum", "foo")?.count_policy,
+            CountPolicy::PssSum
+        );
+        assert!(args!("--count-policy=sum", "foo").is_err());
+        assert!(args!("--backend=cgroup", "--count-policy=all", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn filter() -> Result<()> {
+        let args = args!("foo")?;
+        assert!(args.filter.only.is_none() && args.filter.exclude.is_none());
+        let args = args!("--only=^rustc$", "--exclude", "sh", "foo")?;
+        assert_eq!(
+            args.filter.only.map(|r| r.to_string()),
+            Some("^rustc$".into())
+        );
+        assert_eq!(
+            args.filter.exclude.map(|r| r.to_string()),
+            Some("sh".into())
+        );
+        assert!(args!("--only=(", "foo").is_err());
+        assert!(args!("--backend=rusage", "--exclude=sh", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn child_stdout() -> Result<()> {
+        assert_eq!(args!("foo")?.child_stdout, ChildStdout::Inherit);
+        assert_eq!(args!("-o", "-", "foo")?.child_stdout, ChildStdout::Stderr);
+        assert_eq!(
+            args!("-o", "-", "--child-stdout=null", "foo")?.child_stdout,
+            ChildStdout::Null
+        );
+        assert_eq!(
+            args!("-o", "-", "--log-child=out.log", "foo")?.child_stdout,
+            ChildStdout::Inherit
+        );
+        assert!(args!("--child-stdout=foo", "foo").is_err());
+        assert!(args!("--child-stdout=null", "--log-child=out.log", "foo").is_err());
+        assert!(args!("-o", "-", "--passthrough", "foo").is_err());
+        assert!(args!("-o", "-", "--passthrough", "--child-stdout=inherit", "foo").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn stdout_stderr() -> Result<()> {
+        let args = args!("--stdout=out.txt", "foo")?;
+        assert_eq!(args.stdout, Some("out.txt".into()));
+        assert_eq!(args.stderr, None);
+
+        let args = args!("-q", "--stderr=err.txt", "foo")?;
+        assert_eq!(args.stdout, Some("/dev/null".into()));
+        assert_eq!(args.stderr, Some("err.txt".into()));
+
+        assert!(args!("--stderr=err.txt", "--child-stdout=null", "foo").is_ok());
+        assert!(args!("--stdout=out.txt", "--child-stdout=null", "foo").is_err());
+        assert!(args!("--quiet", "--log-child=out.log", "foo").is_err());
+        assert!(args!("--quiet", "--passthrough", "foo").is_err());
+        assert!(args!("--quiet", "--pid=1234").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn out_dir() -> Result<()> {
+        assert_eq!(args!("foo")?.out_dir, None);
+        assert_eq!(
+            args!("--out-dir", "runs", "foo")?.out_dir,
+            Some(PathBuf::from("runs"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn badge() -> Result<()> {
+        assert_eq!(args!("foo")?.badge, None);
+        assert_eq!(
+            args!("--badge", "badge.json", "foo")?.badge,
+            Some(PathBuf::from("badge.json"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn summary() -> Result<()> {
+        assert!(!args!("foo")?.summary);
+        assert!(args!("--summary", "foo")?.summary);
+        Ok(())
+    }
+
+    #[test]
+    fn env() -> Result<()> {
+        let args = args!("foo")?;
+        assert!(args.env.is_empty() && args.env_remove.is_empty() && !args.clear_env);
+
+        let args = args!(
+            "--env=A=1",
+            "--env",
+            "B==2",
+            "--env=C=",
+            "--env-remove=D",
+            "--clear-env",
+            "foo"
+        )?;
+        assert_eq!(
+            args.env,
+            vec![
+                ("A".into(), "1".into()),
+                ("B".into(), "=2".into()),
+                ("C".into(), "".into())
+            ]
+        );
+        assert_eq!(args.env_remove, vec![OsString::from("D")]);
+        assert!(args.clear_env);
+
+        assert!(args!("--env=A", "foo").is_err());
+        assert!(args!("--env==1", "foo").is_err());
+        assert!(args!("--pid=1234", "--clear-env").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tags() -> Result<()> {
+        assert!(args!("foo")?.tags.is_empty());
+        assert_eq!(
+            args!("--tag=branch=main", "--tag", "cc=clang", "foo")?.tags,
+            vec![
+                ("branch".into(), "main".into()),
+                ("cc".into(), "clang".into())
+            ]
+        );
+        assert!(args!("--tag=branch", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn shell() -> Result<()> {
+        assert!(!args!("foo")?.shell);
+        let args = args!("-S", "make -j && ./run.sh")?;
+        assert!(args.shell);
+        assert_eq!(args.command, vec!["sh", "-c", "make -j && ./run.sh"]);
+        assert_eq!(
+            args!("--shell", "--", "echo", "a", "b")?.command,
+            vec!["sh", "-c", "echo a b"]
+        );
+        assert!(args!("--shell").is_err());
+        assert!(args!("--pid=1234", "--shell").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn cwd() -> Result<()> {
+        assert_eq!(args!("foo")?.cwd, None);
+        assert_eq!(
+            args!("--cwd=bench", "foo")?.cwd,
+            Some(PathBuf::from("bench"))
+        );
+        assert!(args!("--pid=1234", "--cwd=bench").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn capture_env() -> Result<()> {
+        assert!(!args!("foo")?.capture_env);
+        assert!(args!("--capture-env", "foo")?.capture_env);
+        assert!(args!("foo")?.redact.is_none());
+        assert_eq!(
+            args!("--redact=TOKEN|SECRET", "foo")?
+                .redact
+                .map(|r| r.as_str().to_string()),
+            Some("TOKEN|SECRET".into())
+        );
+        assert!(args!("--redact=(", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn redact_args() -> Result<()> {
+        assert!(args!("foo")?.redact_args.is_none());
+        assert!(args!("--redact-args=/home/me", "foo")?
+            .redact_args
+            .is_some());
+        assert!(args!("--redact-args=(", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn baseline() -> Result<()> {
+        let args = args!("--baseline=old.json", "--max-regression=5%", "foo")?;
+        assert_eq!(args.baseline, Some(PathBuf::from("old.json")));
+        assert_eq!(args.max_regression, Some(0.05));
+        assert_eq!(args!("--baseline=old.json", "foo")?.max_regression, None);
+        assert!(args!("--max-regression=5%", "foo").is_err());
+        assert!(args!("--baseline=old.json", "--max-regression=x", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn threshold() -> Result<()> {
+        assert_eq!(args!("foo")?.threshold, None);
+        assert_eq!(args!("--threshold=1K", "foo")?.threshold, Some(1024));
+        assert!(args!("--threshold=foo", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn output_format() -> Result<()> {
+        assert_eq!(args!("foo")?.output_format, OutputFormat::Json);
+        assert_eq!(
+            args!("--output-format=yaml", "foo")?.output_format,
+            OutputFormat::Yaml
+        );
+        assert_eq!(args!("-O", "csv", "foo")?.output_format, OutputFormat::Csv);
+        assert!(args!("--append", "-o", "runs.json", "foo")?.append);
+        assert!(args!("--append", "-o", "-", "foo").is_err());
+        assert!(args!("--append", "--out-dir", "runs", "foo").is_err());
+        assert!(args!("--append", "-O", "csv", "foo").is_err());
+        assert!(args!("-O", "sqlite", "-o", "-", "foo").is_err());
+        assert_eq!(
+            args!("-O", "sqlite", "-o", "r.db", "foo").is_ok(),
+            cfg!(feature = "sqlite")
+        );
+        assert!(args!("-O", "xml", "foo").is_err());
+        Ok(())
+    }
+
     #[test]
     fn return_result() -> Result<()> {
-        assert_eq!(args!("foo")?.return_result, false);
-        assert_eq!(args!("-r", "foo")?.return_result, true);
-        assert_eq!(args!("--return-result", "foo")?.return_result, true);
+        assert_eq!(args!("foo")?.exit_with, ExitWith::ErrorOnly);
+        assert_eq!(args!("-r", "foo")?.exit_with, ExitWith::Child);
+        assert_eq!(args!("--return-result", "foo")?.exit_with, ExitWith::Child);
         assert_eq!(
-            args!("-r", "--no-return-result", "foo")?.return_result,
-            false
+            args!("-r", "--no-return-result", "foo")?.exit_with,
+            ExitWith::ErrorOnly
         );
         assert_eq!(
-            args!("--return-result", "--no-return-result", "foo")?.return_result,
-            false
+            args!("--exit-with=always-zero", "foo")?.exit_with,
+            ExitWith::AlwaysZero
         );
+        assert_eq!(
+            args!("--exit-with=always-zero", "-r", "foo")?.exit_with,
+            ExitWith::Child
+        );
+        assert!(args!("--exit-with=zero", "foo").is_err());
         Ok(())
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn debug() -> Result<()> {
         assert_eq!(args!("foo")?.debug, false);
         assert_eq!(args!("-d", "foo")?.debug, true);
@@ -225,4 +1928,169 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn clock() -> Result<()> {
+        assert_eq!(args!("foo")?.clock, Clock::Utc);
+        assert_eq!(args!("--clock=local", "foo")?.clock, Clock::Local);
+        assert_eq!(
+            args!("--clock", "monotonic", "foo")?.clock,
+            Clock::Monotonic
+        );
+        assert!(args!("--clock=foo", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn steps() -> Result<()> {
+        let args = args!("--setup=a", "--teardown=c", "--setup", "b", "foo")?;
+        assert_eq!(args.setup, vec!["a", "b"]);
+        assert_eq!(args.teardown, vec!["c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn log_child() -> Result<()> {
+        assert_eq!(args!("foo")?.log_child, None);
+        assert_eq!(
+            args!("--log-child=out.log", "foo")?.log_child,
+            Some(PathBuf::from("out.log"))
+        );
+        assert!(args!("--passthrough", "--log-child=out.log", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn timeline() -> Result<()> {
+        let args = args!("foo")?;
+        assert_eq!(args.timeline, None);
+        assert_eq!(args.interval, Duration::from_secs(1));
+        assert!(!args.sample_peaks);
+        assert!(!args.record_samples);
+        assert!(args!("--record-samples", "foo")?.record_samples);
+
+        let args = args!(
+            "--timeline=t.jsonl",
+            "--interval=500ms",
+            "--rotate=100M",
+            "--rotate-every=1d",
+            "--checkpoint=1h",
+            "--timeline-delta=1M",
+            "foo"
+        )?;
+        assert_eq!(args.timeline, Some(PathBuf::from("t.jsonl")));
+        assert_eq!(args.interval, Duration::from_millis(500));
+        assert!(args.sample_peaks);
+        assert_eq!(args.rotate_size, Some(100 * 1024 * 1024));
+        assert_eq!(args.rotate_every, Some(Duration::from_secs(86400)));
+        assert_eq!(args.checkpoint, Some(Duration::from_secs(3600)));
+        assert_eq!(args.timeline_delta, Some(1024 * 1024));
+
+        assert!(args!("--interval=0", "foo").is_err());
+        assert!(args!("--rotate=100M", "foo").is_err());
+        assert!(args!("--timeline-delta=0", "foo").is_err());
+
+        assert_eq!(
+            args!("--events=e.jsonl", "foo")?.events,
+            Some(PathBuf::from("e.jsonl"))
+        );
+        assert_eq!(
+            args!("--stream", "e.ndjson", "foo")?.events,
+            Some(PathBuf::from("e.ndjson"))
+        );
+        assert!(args!("--events=e.jsonl", "--runs=1", "foo").is_ok());
+        assert!(args!("--events=e.jsonl", "--runs=2", "foo").is_err());
+        assert!(args!("--events=e.jsonl", "--backend=cgroup", "foo").is_err());
+
+        let args = args!("--timeline=t.jsonl", "--max-samples=1000", "foo")?;
+        assert_eq!(args.max_samples, Some(1000));
+        assert!(args!("--timeline=t.jsonl", "--max-samples=1", "foo").is_err());
+        assert!(args!("--timeline=t", "--max-samples=9", "--rotate=1M", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn alert() -> Result<()> {
+        let args = args!("foo")?;
+        assert_eq!(args.alert, None);
+        assert_eq!(args.alert_cooldown, Duration::from_secs(60));
+
+        let args = args!(
+            "--alert=1G",
+            "--alert-notify=desktop",
+            "--alert-notify=exec:true",
+            "--alert-cooldown=5m",
+            "foo"
+        )?;
+        assert_eq!(args.alert, Some(1 << 30));
+        assert_eq!(
+            args.alert_notify,
+            vec![Notify::Desktop, Notify::Exec("true".into())]
+        );
+        assert_eq!(args.alert_cooldown, Duration::from_secs(300));
+
+        assert!(args!("--alert-notify=desktop", "foo").is_err());
+        assert!(args!("--alert=1G", "--alert-notify=foo", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn exit_code_map() -> Result<()> {
+        assert_eq!(args!("foo")?.exit_codes, ExitCodes::default());
+        let args = args!("--exit-code-map=threshold=98", "foo")?;
+        assert_eq!(args.exit_codes.threshold, Some(98));
+        assert_eq!(args.exit_codes.exec, 127);
+        assert!(args!("--exit-code-map=foo=1", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rusage() -> Result<()> {
+        assert!(!args!("foo")?.rusage);
+        assert!(args!("--rusage", "foo")?.rusage);
+        Ok(())
+    }
+
+    #[test]
+    fn io() -> Result<()> {
+        assert!(!args!("foo")?.io);
+        assert!(args!("--io", "foo")?.io);
+        assert!(args!("--backend=cgroup", "--io", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn verify() -> Result<()> {
+        assert_eq!(args!("foo")?.verify, None);
+        assert_eq!(args!("--verify", "foo")?.verify, Some(0.1));
+        assert_eq!(args!("--verify=5", "foo")?.verify, Some(0.05));
+        assert_eq!(args!("--verify=25%", "foo")?.verify, Some(0.25));
+        assert!(args!("--verify=-1", "foo").is_err());
+        assert!(args!("--verify=x", "foo").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn no_new_privs() -> Result<()> {
+        assert!(!args!("foo")?.no_new_privs);
+        assert!(args!("--no-new-privs", "foo")?.no_new_privs);
+        assert!(!args!("foo")?.pidns);
+        assert!(args!("--pidns", "foo")?.pidns);
+        assert!(args!("--pidns", "--backend=rusage", "foo").is_err());
+        assert!(args!("--pidns", "--pid=1").is_err());
+        assert_eq!(
+            args!("--seccomp=profile.json", "foo")?.seccomp,
+            Some(PathBuf::from("profile.json"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn passthrough() -> Result<()> {
+        assert!(!args!("foo")?.passthrough);
+        assert!(args!("--passthrough", "foo")?.passthrough);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,475 @@
+//! Compares two results reports, showing how the max_rss of the run and each of its processes
+//! changed between them.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::child::Inherited;
+use crate::cli::{self, Args, Subcommand};
+use crate::report::argv_hash;
+use crate::stats::Stats;
+
+/// Reads a results report written by `--output-format json`.
+pub fn read_report(path: &Path) -> Result<Value> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Describes the change from `old` to `new`.
+pub fn delta(old: Option<u64>, new: Option<u64>) -> Value {
+    let diff = match (old, new) {
+        (Some(old), Some(new)) => Some(new as i64 - old as i64),
+        _ => None,
+    };
+    let relative = match (old, diff) {
+        (Some(old), Some(diff)) if old > 0 => Some(diff as f64 / old as f64),
+        _ => None,
+    };
+
+    json!({
+        "old": old,
+        "new": new,
+        "diff": diff,
+        "relative": relative,
+    })
+}
+
+/// A process from a report's graph, with what's needed to find it in another report.
+struct Process<'a> {
+    /// Where the process is, as its path from the root (e.g.: `0.1` is the root's second child).
+    position: String,
+    /// The process' name and a hash of its arguments, numbered if several processes share them, so
+    /// the same process can be found even if it moved in the graph. Older reports without names
+    /// only have their position.
+    identity: Option<String>,
+    node: &'a Value,
+}
+
+/// Flattens the process graph, depth first.
+fn processes(report: &Value) -> Vec<Process<'_>> {
+    fn walk<'a>(node: &'a Value, position: String, out: &mut Vec<Process<'a>>) {
+        let identity = node["name"].as_str().map(|name| {
+            let hash = match (node["argv_hash"].as_str(), node["cmdline"].as_array()) {
+                (Some(hash), _) => hash.to_string(),
+                (None, Some(argv)) => argv_hash(
+                    &argv
+                        .iter()
+                        .map(|a| a.as_str().unwrap_or_default().to_string())
+                        .collect::<Vec<_>>(),
+                ),
+                (None, None) => String::new(),
+            };
+            format!("{} {}", name, hash)
+        });
+
+        out.push(Process {
+            position: position.clone(),
+            identity,
+            node,
+        });
+        for (i, child) in node["children"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            walk(child, format!("{}.{}", position, i), out);
+        }
+    }
+
+    let mut out = vec![];
+    if !report["graph"].is_null() {
+        walk(&report["graph"], "0".into(), &mut out);
+    }
+
+    // number repeated identities in the order they appear, so e.g. the second `cc` is matched with
+    // the second `cc` rather than the first
+    let mut seen = HashMap::new();
+    for process in &mut out {
+        if let Some(identity) = &mut process.identity {
+            let n = seen.entry(identity.clone()).or_insert(0);
+            *n += 1;
+            identity.push_str(&format!(" #{}", n));
+        }
+    }
+
+    out
+}
+
+/// Pairs up the processes of two reports: first by identity (name and arguments), and then those
+/// which are left by their position in the graph. Processes are in the order they appear in the old
+/// report, followed by any which are only in the new report.
+fn pair<'a>(old: &'a [Process<'a>], new: &'a [Process<'a>]) -> Vec<[Option<&'a Process<'a>>; 2]> {
+    let mut matched = vec![None; old.len()];
+    let mut taken = vec![false; new.len()];
+
+    let mut find = |i: usize, same: &dyn Fn(&Process) -> bool| {
+        if matched[i].is_none() {
+            if let Some(j) = (0..new.len()).find(|j| !taken[*j] && same(&new[*j])) {
+                matched[i] = Some(j);
+                taken[j] = true;
+            }
+        }
+    };
+    for (i, o) in old.iter().enumerate() {
+        if o.identity.is_some() {
+            find(i, &|n| n.identity == o.identity);
+        }
+    }
+    for (i, o) in old.iter().enumerate() {
+        find(i, &|n| n.position == o.position);
+    }
+
+    let mut pairs = old
+        .iter()
+        .zip(matched)
+        .map(|(o, j)| [Some(o), j.map(|j| &new[j])])
+        .collect::<Vec<_>>();
+    pairs.extend(
+        new.iter()
+            .zip(taken)
+            .filter(|(_, taken)| !taken)
+            .map(|(n, _)| [None, Some(n)]),
+    );
+
+    pairs
+}
+
+/// Compares two reports. Each process is matched with the same process in the other report (see
+/// [`pair`]), and the one whose RSS grew the most is noted in `largest_increase`.
+pub fn compare(old_name: &str, old: &Value, new_name: &str, new: &Value) -> Value {
+    let old_procs = processes(old);
+    let new_procs = processes(new);
+
+    let rss = |p: Option<&Process>| p.and_then(|p| p.node["rss"].as_u64());
+    let processes = pair(&old_procs, &new_procs)
+        .into_iter()
+        .map(|[o, n]| {
+            let either = o.or(n).expect("unpaired process");
+            let mut d = delta(rss(o), rss(n));
+            d["position"] = either.position.clone().into();
+            d["name"] = either.node["name"].clone();
+            d["cmdline"] = either.node["cmdline"].clone();
+            if let (Some(o), Some(n)) = (o, n) {
+                if o.position != n.position {
+                    d["new_position"] = n.position.clone().into();
+                }
+            }
+            d
+        })
+        .collect::<Vec<_>>();
+
+    let largest_increase = processes
+        .iter()
+        .filter(|d| d["diff"].as_i64().is_some_and(|diff| diff > 0))
+        .max_by_key(|d| d["diff"].as_i64())
+        .cloned();
+
+    let mut comparison = json!({
+        "old": old_name,
+        "new": new_name,
+        "max_rss": delta(old["max_rss"].as_u64(), new["max_rss"].as_u64()),
+        "processes": processes,
+        "largest_increase": largest_increase,
+    });
+    let tags = [&old["meta"]["tags"], &new["meta"]["tags"]];
+    if tags.iter().any(|t| !t.is_null()) {
+        comparison["tags"] = json!({ "old": tags[0], "new": tags[1] });
+    }
+
+    comparison
+}
+
+/// Compares a report's max_rss against that of a baseline report, noting whether it grew by more
+/// than `max_regression` (a fraction). A baseline of zero only regresses if it grew at all.
+pub fn check_baseline(baseline: &Value, report: &Value, max_regression: f64) -> Value {
+    let (old, new) = (baseline["max_rss"].as_u64(), report["max_rss"].as_u64());
+    let regressed = match (old, new) {
+        (Some(old), Some(new)) => new as f64 > old as f64 * (1.0 + max_regression),
+        _ => false,
+    };
+
+    let mut result = delta(old, new);
+    result["max_regression"] = max_regression.into();
+    result["regressed"] = regressed.into();
+    result
+}
+
+/// Runs the two commands given to `compare` `--runs` times (once by default), alternating between
+/// them so both are measured under the same conditions, and compares them. With more than one run,
+/// the total compares the median max_rss of each, while the processes compare their last runs.
+pub fn compare_commands(args: &Args, inherited: &Inherited) -> Result<Value> {
+    let runs = args.runs.unwrap_or(1);
+    let setup = |command: &OsString| {
+        let mut args = args.clone();
+        args.subcommand = Subcommand::Run;
+        args.command = cli::shell_command(command);
+        args
+    };
+    let commands = [setup(&args.commands[0]), setup(&args.commands[1])];
+    let names = args
+        .commands
+        .iter()
+        .map(|c| c.to_string_lossy())
+        .collect::<Vec<_>>();
+
+    let mut values = [vec![], vec![]];
+    let mut last = [Value::Null, Value::Null];
+    for i in 0..runs {
+        for (j, command) in commands.iter().enumerate() {
+            if args.debug {
+                eprintln!("::: compare run {}/{}: {}", i + 1, runs, names[j]);
+            }
+
+            let trace = command.backend.trace(command, inherited)?.complete()?;
+            if trace.exit_code != 0 {
+                bail!("`{}` exited with code {}", names[j], trace.exit_code);
+            }
+            values[j].push(trace.report["max_rss"].as_u64().unwrap_or(0));
+            last[j] = trace.report;
+        }
+    }
+
+    let mut comparison = compare(&names[0], &last[0], &names[1], &last[1]);
+    if runs > 1 {
+        let stats = values.each_ref().map(|values| {
+            Stats::new(&values.iter().map(|v| *v as f64).collect::<Vec<_>>()).expect("no runs")
+        });
+        comparison["max_rss"] = delta(
+            Some(stats[0].median.round() as u64),
+            Some(stats[1].median.round() as u64),
+        );
+        comparison["runs"] = json!({ "old": values[0], "new": values[1] });
+        comparison["stats"] = json!({ "old": stats[0].to_json(), "new": stats[1].to_json() });
+    }
+
+    Ok(comparison)
+}
+
+/// Renders a comparison as a self-contained HTML page, with bars showing the old and new values
+/// side by side, and regressions highlighted.
+pub fn render_html(comparison: &Value) -> String {
+    fn escape(s: &str) -> String {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    let rows = std::iter::once(("total".to_string(), &comparison["max_rss"]))
+        .chain(
+            comparison["processes"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|p| {
+                    let position = p["position"].as_str().unwrap_or("?");
+                    let name = match p["name"].as_str() {
+                        Some(name) => format!("{} ({})", name, position),
+                        None => format!("process {}", position),
+                    };
+                    (name, p)
+                }),
+        )
+        .collect::<Vec<_>>();
+
+    let scale = rows
+        .iter()
+        .flat_map(|(_, d)| [d["old"].as_u64(), d["new"].as_u64()])
+        .flatten()
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+
+    let bar = |class: &str, value: Option<u64>| match value {
+        Some(v) => format!(
+            r#"<div class="bar {class}" style="width:{:.2}%"></div><span>{v}</span>"#,
+            v as f64 / scale * 100.0
+        ),
+        None => r#"<span class="missing">-</span>"#.to_string(),
+    };
+
+    let mut body = String::new();
+    for (name, d) in rows {
+        let relative = d["relative"].as_f64();
+        let class = match relative {
+            Some(r) if r > 0.0 => "regression",
+            Some(r) if r < 0.0 => "improvement",
+            _ => "",
+        };
+
+        body.push_str(&format!(
+            r#"<tr class="{class}"><th>{name}</th><td>{old}</td><td>{new}</td><td>{diff}</td></tr>"#,
+            name = escape(&name),
+            old = bar("old", d["old"].as_u64()),
+            new = bar("new", d["new"].as_u64()),
+            diff = match relative {
+                Some(r) => format!("{:+.2}%", r * 100.0),
+                None => "-".into(),
+            }
+        ));
+        body.push('\n');
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{bin} comparison</title>
+<style>
+body {{ font-family: sans-serif; }}
+table {{ border-collapse: collapse; width: 100%; }}
+th, td {{ padding: 4px 8px; text-align: left; border-bottom: 1px solid #ddd; }}
+td {{ width: 35%; }}
+.bar {{ display: inline-block; height: 12px; margin-right: 6px; }}
+.bar.old {{ background: #999; }}
+.bar.new {{ background: #4a90d9; }}
+tr.regression {{ background: #fde8e8; }}
+tr.improvement {{ background: #e8fde8; }}
+</style>
+</head>
+<body>
+<h1>{bin} comparison</h1>
+<p><b>old:</b> {old}<br><b>new:</b> {new}</p>
+<table>
+<tr><th></th><th>old</th><th>new</th><th>change</th></tr>
+{body}</table>
+</body>
+</html>
+"#,
+        bin = env!("CARGO_PKG_NAME"),
+        old = escape(comparison["old"].as_str().unwrap_or_default()),
+        new = escape(comparison["new"].as_str().unwrap_or_default()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(max_rss: u64, children: &[u64]) -> Value {
+        json!({
+            "max_rss": max_rss,
+            "graph": {
+                "id": 1,
+                "rss": max_rss,
+                "children": children.iter().map(|rss| json!({ "id": 2, "rss": rss })).collect::<Vec<_>>()
+            }
+        })
+    }
+
+    #[test]
+    fn deltas() {
+        assert_eq!(
+            delta(Some(100), Some(150)),
+            json!({ "old": 100, "new": 150, "diff": 50, "relative": 0.5 })
+        );
+        assert_eq!(
+            delta(None, Some(150)),
+            json!({ "old": null, "new": 150, "diff": null, "relative": null })
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        let result = compare("a", &report(100, &[10]), "b", &report(200, &[10, 20]));
+        assert_eq!(result["max_rss"]["diff"], 100);
+        assert_eq!(result["processes"][0]["position"], "0");
+        assert_eq!(result["processes"][1]["position"], "0.0");
+        assert_eq!(result["processes"][1]["diff"], 0);
+        assert_eq!(result["processes"][2]["position"], "0.1");
+        assert_eq!(result["processes"][2]["old"], Value::Null);
+        assert_eq!(result["largest_increase"]["position"], "0");
+        assert_eq!(result.get("tags"), None);
+
+        let mut tagged = report(200, &[]);
+        tagged["meta"] = json!({ "tags": { "cc": "clang" } });
+        let result = compare("a", &report(100, &[]), "b", &tagged);
+        assert_eq!(
+            result["tags"],
+            json!({ "old": null, "new": { "cc": "clang" } })
+        );
+    }
+
+    #[test]
+    fn attribution() {
+        let node = |name: &str, argv: &[&str], rss: u64| json!({ "id": 1, "name": name, "cmdline": argv, "rss": rss });
+        let old = json!({
+            "max_rss": 300,
+            "graph": {
+                "id": 1, "name": "make", "rss": 100,
+                "children": [node("cc", &["cc", "a.c"], 100), node("cc", &["cc", "b.c"], 100)],
+            },
+        });
+        // the new build compiles b.c first, and it grew
+        let new = json!({
+            "max_rss": 500,
+            "graph": {
+                "id": 1, "name": "make", "rss": 100,
+                "children": [
+                    node("cc", &["cc", "b.c"], 300),
+                    node("cc", &["cc", "a.c"], 100),
+                    node("ld", &["ld"], 50),
+                ],
+            },
+        });
+
+        let result = compare("a", &old, "b", &new);
+        let processes = result["processes"].as_array().unwrap();
+        assert_eq!(processes.len(), 4);
+        assert_eq!(processes[1]["cmdline"], json!(["cc", "a.c"]));
+        assert_eq!(processes[1]["diff"], 0);
+        assert_eq!(processes[1]["new_position"], "0.1");
+        assert_eq!(processes[2]["diff"], 200);
+        assert_eq!(processes[3]["name"], "ld");
+        assert_eq!(processes[3]["old"], Value::Null);
+        assert_eq!(result["largest_increase"]["cmdline"], json!(["cc", "b.c"]));
+
+        // processes which exec'd something else are still matched by position
+        let mut renamed = new.clone();
+        renamed["graph"]["children"][2]["name"] = "lld".into();
+        let mut old = old;
+        old["graph"]["children"]
+            .as_array_mut()
+            .unwrap()
+            .push(node("ld", &["ld"], 40));
+        let result = compare("a", &old, "b", &renamed);
+        assert_eq!(result["processes"][3]["name"], "ld");
+        assert_eq!(result["processes"][3]["diff"], 10);
+    }
+
+    #[test]
+    fn baseline() {
+        let result = check_baseline(&report(100, &[]), &report(106, &[]), 0.05);
+        assert_eq!(result["diff"], 6);
+        assert_eq!(result["regressed"], true);
+        assert_eq!(
+            check_baseline(&report(100, &[]), &report(105, &[]), 0.05)["regressed"],
+            false
+        );
+        assert_eq!(
+            check_baseline(&report(100, &[]), &report(90, &[]), 0.0)["regressed"],
+            false
+        );
+        assert_eq!(
+            check_baseline(&json!({}), &report(90, &[]), 0.0)["regressed"],
+            false
+        );
+    }
+
+    #[test]
+    fn html() {
+        let result = compare("<a>", &report(100, &[]), "b", &report(200, &[]));
+        let html = render_html(&result);
+        assert!(html.contains("&lt;a&gt;"));
+        assert!(html.contains(r#"<tr class="regression"><th>total</th>"#));
+        assert!(html.contains("+100.00%"));
+    }
+}
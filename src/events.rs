@@ -0,0 +1,287 @@
+//! Streams what happens to COMMAND's processes as it happens, to a file (see `--events`, or
+//! `--stream`) and to anyone following along with
+//! [`Tracer::subscribe`](crate::Tracer::subscribe), so they needn't wait for the results.
+//!
+//! Each line is flushed as soon as it's written, so readers never wait on a buffer.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::unistd::Pid;
+use serde_json::{json, Value};
+
+use crate::cli::Args;
+
+pub struct Events {
+    file: Option<BufWriter<File>>,
+    sender: Option<Sender<Event>>,
+    start: Instant,
+}
+
+impl Events {
+    /// Starts a new events file if one was asked for, and sends each event to `sender` too.
+    pub fn create(
+        args: &Args,
+        start: Instant,
+        sender: Option<Sender<Event>>,
+    ) -> Result<Option<Events>> {
+        let file = match &args.events {
+            Some(path) => {
+                if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                Some(BufWriter::new(file))
+            }
+            None => None,
+        };
+        if file.is_none() && sender.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Events {
+            file,
+            sender,
+            start,
+        }))
+    }
+
+    pub fn spawned(&mut self, pid: Pid, ppid: Option<Pid>) -> Result<()> {
+        self.write(Event::ProcessSpawned {
+            pid: pid.as_raw(),
+            ppid: ppid.map(Pid::as_raw),
+            elapsed_ms: self.elapsed_ms(),
+        })
+    }
+
+    pub fn exec(&mut self, pid: Pid, name: Option<&str>, cmdline: Option<&[String]>) -> Result<()> {
+        self.write(Event::ProcessExec {
+            pid: pid.as_raw(),
+            name: name.map(String::from),
+            cmdline: cmdline.map(<[String]>::to_vec),
+            elapsed_ms: self.elapsed_ms(),
+        })
+    }
+
+    pub fn exited(&mut self, pid: Pid, rss: u64) -> Result<()> {
+        self.write(Event::ProcessExited {
+            pid: pid.as_raw(),
+            rss,
+            elapsed_ms: self.elapsed_ms(),
+        })
+    }
+
+    pub fn sample(&mut self, pid: Pid, rss: u64) -> Result<()> {
+        self.write(Event::Sample {
+            pid: pid.as_raw(),
+            rss,
+            elapsed_ms: self.elapsed_ms(),
+        })
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn write(&mut self, event: Event) -> Result<()> {
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", event.to_json())?;
+            file.flush()?;
+        }
+        if let Some(sender) = &self.sender {
+            // nobody may be listening anymore, but the command is still measured
+            let _ = sender.send(event);
+        }
+        Ok(())
+    }
+}
+
+/// Something that happened while a command was being measured, see `--events` and
+/// [`Tracer::subscribe`](crate::Tracer::subscribe).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A process was spawned. The command itself has no `ppid`.
+    ProcessSpawned {
+        pid: i32,
+        ppid: Option<i32>,
+        elapsed_ms: u64,
+    },
+    /// A process exec'd a new program, which is now its `name` and `cmdline`.
+    ProcessExec {
+        pid: i32,
+        name: Option<String>,
+        cmdline: Option<Vec<String>>,
+        elapsed_ms: u64,
+    },
+    /// A process exited, having used at most `rss` bytes.
+    ProcessExited { pid: i32, rss: u64, elapsed_ms: u64 },
+    /// The current RSS of a process, sampled every `--interval`.
+    Sample { pid: i32, rss: u64, elapsed_ms: u64 },
+}
+
+impl Event {
+    /// Parses a line written with `max_rss --events`.
+    pub fn parse(line: &str) -> Result<Event> {
+        let line = serde_json::from_str::<Value>(line)?;
+        let field = |name: &str| {
+            line[name]
+                .as_u64()
+                .ok_or_else(|| anyhow!("event is missing {}", name))
+        };
+
+        let pid = field("pid")? as i32;
+        let elapsed_ms = field("elapsed_ms")?;
+        Ok(match line["event"].as_str() {
+            Some("spawned") => Event::ProcessSpawned {
+                pid,
+                ppid: line["ppid"].as_i64().map(|ppid| ppid as i32),
+                elapsed_ms,
+            },
+            Some("exec") => Event::ProcessExec {
+                pid,
+                name: line["name"].as_str().map(String::from),
+                cmdline: serde_json::from_value(line["cmdline"].clone())?,
+                elapsed_ms,
+            },
+            Some("exited") => Event::ProcessExited {
+                pid,
+                rss: field("rss")?,
+                elapsed_ms,
+            },
+            Some("sample") => Event::Sample {
+                pid,
+                rss: field("rss")?,
+                elapsed_ms,
+            },
+            _ => bail!("unknown event: {}", line["event"]),
+        })
+    }
+
+    /// The line written for this event with `max_rss --events`, which [`parse`](Event::parse)
+    /// reads back.
+    pub fn to_json(&self) -> Value {
+        let (event, pid, elapsed_ms, mut line) = match self {
+            Event::ProcessSpawned {
+                pid,
+                ppid,
+                elapsed_ms,
+            } => ("spawned", pid, elapsed_ms, json!({ "ppid": ppid })),
+            Event::ProcessExec {
+                pid,
+                name,
+                cmdline,
+                elapsed_ms,
+            } => (
+                "exec",
+                pid,
+                elapsed_ms,
+                json!({ "name": name, "cmdline": cmdline }),
+            ),
+            Event::ProcessExited {
+                pid,
+                rss,
+                elapsed_ms,
+            } => ("exited", pid, elapsed_ms, json!({ "rss": rss })),
+            Event::Sample {
+                pid,
+                rss,
+                elapsed_ms,
+            } => ("sample", pid, elapsed_ms, json!({ "rss": rss })),
+        };
+
+        line["event"] = event.into();
+        line["pid"] = (*pid).into();
+        line["elapsed_ms"] = (*elapsed_ms).into();
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() -> Result<()> {
+        assert_eq!(
+            Event::parse(r#"{"event":"spawned","pid":2,"ppid":1,"elapsed_ms":3}"#)?,
+            Event::ProcessSpawned {
+                pid: 2,
+                ppid: Some(1),
+                elapsed_ms: 3
+            }
+        );
+        assert_eq!(
+            Event::parse(r#"{"event":"spawned","pid":1,"ppid":null,"elapsed_ms":0}"#)?,
+            Event::ProcessSpawned {
+                pid: 1,
+                ppid: None,
+                elapsed_ms: 0
+            }
+        );
+        assert_eq!(
+            Event::parse(r#"{"event":"exited","pid":2,"rss":4096,"elapsed_ms":5}"#)?,
+            Event::ProcessExited {
+                pid: 2,
+                rss: 4096,
+                elapsed_ms: 5
+            }
+        );
+        assert_eq!(
+            Event::parse(
+                r#"{"event":"exec","pid":2,"name":"ls","cmdline":["ls","-l"],"elapsed_ms":4}"#
+            )?,
+            Event::ProcessExec {
+                pid: 2,
+                name: Some("ls".into()),
+                cmdline: Some(vec!["ls".into(), "-l".into()]),
+                elapsed_ms: 4
+            }
+        );
+        assert_eq!(
+            Event::parse(r#"{"event":"sample","pid":2,"rss":1024,"elapsed_ms":4}"#)?,
+            Event::Sample {
+                pid: 2,
+                rss: 1024,
+                elapsed_ms: 4
+            }
+        );
+
+        assert!(Event::parse(r#"{"event":"exited","pid":2,"elapsed_ms":5}"#).is_err());
+        assert!(Event::parse(r#"{"event":"other","pid":2,"elapsed_ms":5}"#).is_err());
+        Ok(())
+    }
+    #[test]
+    fn round_trips() -> Result<()> {
+        let events = [
+            Event::ProcessSpawned {
+                pid: 2,
+                ppid: None,
+                elapsed_ms: 0,
+            },
+            Event::ProcessExec {
+                pid: 2,
+                name: Some("ls".into()),
+                cmdline: None,
+                elapsed_ms: 1,
+            },
+            Event::Sample {
+                pid: 2,
+                rss: 1024,
+                elapsed_ms: 2,
+            },
+            Event::ProcessExited {
+                pid: 2,
+                rss: 4096,
+                elapsed_ms: 3,
+            },
+        ];
+        for event in events {
+            assert_eq!(Event::parse(&event.to_json().to_string())?, event);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! Tells the tracer when COMMAND couldn't be executed, so it can be reported like a shell would
+//! (e.g.: `foo: command not found`, exiting with 127) rather than looking like a normal run.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+
+use anyhow::Result;
+use nix::errno::Errno;
+#[cfg(target_os = "linux")]
+use nix::sys::ptrace;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::Pid;
+
+use crate::child::{self, Trace};
+use crate::cli::Args;
+use crate::report::{Report, SCHEMA_VERSION};
+
+/// The exit code a shell uses for a command which was found, but couldn't be executed.
+const NOT_EXECUTABLE: i32 = 126;
+
+/// A pipe the forked child sends the reason its exec failed through. The pipe is closed on exec,
+/// so if COMMAND was started the tracer reads nothing at all.
+pub struct ExecCheck {
+    read: File,
+    write: File,
+}
+
+impl ExecCheck {
+    pub fn create() -> Result<ExecCheck> {
+        let (read, write) = child::pipe()?;
+
+        // SAFETY: we created these descriptors, and nothing else uses them
+        Ok(unsafe {
+            ExecCheck {
+                read: File::from_raw_fd(read),
+                write: File::from_raw_fd(write),
+            }
+        })
+    }
+
+    /// The write end, which has to be kept open until exec.
+    pub fn fd(&self) -> RawFd {
+        self.write.as_raw_fd()
+    }
+
+    /// Sends why exec failed. Must only be called in the forked child.
+    pub fn send(&self, errno: Errno) {
+        let _ = (&self.write).write_all(&(errno as i32).to_ne_bytes());
+    }
+
+    /// Waits until COMMAND has been executed, or returns why it couldn't be. Must only be called
+    /// in the tracer, once the child is running.
+    pub fn wait(self, args: &Args) -> Result<Option<ExecFailed>> {
+        // the child has its own copy of the write end, and we'll never see EOF while we hold ours
+        drop(self.write);
+
+        let mut errno = [0; 4];
+        match (&self.read).read_exact(&mut errno) {
+            Ok(()) => Ok(Some(ExecFailed::new(
+                args,
+                Errno::from_i32(i32::from_ne_bytes(errno)),
+            ))),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Why COMMAND couldn't be executed.
+#[derive(Debug)]
+pub struct ExecFailed {
+    command: String,
+    errno: Errno,
+    /// What to exit with: `--exit-code-map exec` (127) when COMMAND wasn't found, and 126 when it
+    /// was but couldn't be executed.
+    pub exit_code: i32,
+}
+
+impl ExecFailed {
+    fn new(args: &Args, errno: Errno) -> ExecFailed {
+        ExecFailed {
+            command: args.command[0].to_string_lossy().into_owned(),
+            errno,
+            exit_code: match errno {
+                Errno::ENOENT => args.exit_codes.exec,
+                _ => NOT_EXECUTABLE,
+            },
+        }
+    }
+
+    /// Waits for the child to exit, so it isn't left behind, and returns the results of a COMMAND
+    /// which never started.
+    pub fn finish(self, child: Pid) -> Result<Trace> {
+        loop {
+            match waitpid(child, None)? {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => break,
+                // it may be traced, and stopped on its way out
+                _ => {
+                    #[cfg(target_os = "linux")]
+                    let _ = ptrace::cont(child, None);
+                }
+            }
+        }
+
+        let report = Report {
+            schema_version: Some(SCHEMA_VERSION),
+            exit_code: Some(self.exit_code),
+            error: Some(self.to_string()),
+            ..Report::default()
+        };
+
+        Ok(Trace {
+            report: serde_json::to_value(report)?,
+            exit_code: self.exit_code,
+            snapshots: Default::default(),
+            error: Some(self.into()),
+        })
+    }
+}
+
+impl fmt::Display for ExecFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errno {
+            Errno::ENOENT => write!(f, "{}: command not found", self.command),
+            Errno::EACCES => write!(f, "{}: permission denied", self.command),
+            errno => write!(f, "{}: {}", self.command, errno.desc().to_lowercase()),
+        }
+    }
+}
+
+impl std::error::Error for ExecFailed {}
+
+#[cfg(test)]
+mod tests {
+    use crate::exitcode::ExitCodes;
+
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let mut args = Args {
+            command: vec!["foo".into()],
+            ..Args::default()
+        };
+        let failed = ExecFailed::new(&args, Errno::ENOENT);
+        assert_eq!(failed.to_string(), "foo: command not found");
+        assert_eq!(failed.exit_code, 127);
+
+        let failed = ExecFailed::new(&args, Errno::EACCES);
+        assert_eq!(failed.to_string(), "foo: permission denied");
+        assert_eq!(failed.exit_code, 126);
+
+        args.exit_codes = "exec=99".parse::<ExitCodes>().unwrap();
+        assert_eq!(ExecFailed::new(&args, Errno::ENOENT).exit_code, 99);
+        assert_eq!(ExecFailed::new(&args, Errno::ENOEXEC).exit_code, 126);
+    }
+}
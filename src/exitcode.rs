@@ -0,0 +1,141 @@
+//! The exit codes used for conditions detected by max_rss itself, rather than by COMMAND, which
+//! can be changed to fit in with existing conventions (e.g.: a CI runner's reserved codes), and
+//! whether COMMAND's own exit code is passed on (see `--exit-with`).
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitCodes {
+    /// Used when COMMAND succeeded but its max_rss was over `--threshold`. When `None`, exceeding
+    /// the threshold doesn't change the exit code.
+    pub threshold: Option<i32>,
+    /// Used when COMMAND couldn't be found (see [`ExecFailed`](crate::exec::ExecFailed)).
+    pub exec: i32,
+    /// Used when COMMAND succeeded but its max_rss regressed from `--baseline` by more than
+    /// `--max-regression`.
+    pub regression: i32,
+}
+
+impl Default for ExitCodes {
+    fn default() -> Self {
+        ExitCodes {
+            // exceeding the threshold fails a Bazel test, even if the command succeeded
+            threshold: crate::bazel::test_target().map(|_| 1),
+            exec: 127,
+            regression: 1,
+        }
+    }
+}
+
+impl ExitCodes {
+    pub const NAMES: &'static [&'static str] = &["threshold", "exec", "regression"];
+}
+
+/// What max_rss exits with once COMMAND has been measured.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExitWith {
+    /// COMMAND's exit code, or `128 + signal` if it was killed by a signal.
+    Child,
+    /// Zero, unless max_rss itself failed or a condition in [`ExitCodes`] was met.
+    #[default]
+    ErrorOnly,
+    /// Zero whenever results were written, so measuring never fails a build.
+    AlwaysZero,
+}
+
+impl ExitWith {
+    pub const ALL: &'static [(&'static str, ExitWith)] = &[
+        ("child", ExitWith::Child),
+        ("error-only", ExitWith::ErrorOnly),
+        ("always-zero", ExitWith::AlwaysZero),
+    ];
+
+    /// The exit code to use once COMMAND exited with `code`, or was killed by `signal`.
+    pub fn exit_code(&self, code: Option<i32>, signal: Option<i32>) -> i32 {
+        match self {
+            ExitWith::Child => code.or(signal.map(|s| 128 + s)).unwrap_or(0),
+            ExitWith::ErrorOnly | ExitWith::AlwaysZero => 0,
+        }
+    }
+}
+
+impl FromStr for ExitWith {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExitWith::ALL
+            .iter()
+            .find_map(|(name, policy)| (*name == s).then_some(*policy))
+            .ok_or_else(|| anyhow!("unknown --exit-with: {}", s))
+    }
+}
+
+impl FromStr for ExitCodes {
+    type Err = Error;
+
+    /// Parses a list of overrides such as `threshold=98,exec=99`. Anything not mentioned keeps its
+    /// default.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut codes = ExitCodes::default();
+        for pair in s.split(',').filter(|p| !p.is_empty()) {
+            let (name, code) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected NAME=CODE, got: {}", pair))?;
+            let code = match code.parse::<i32>() {
+                Ok(code) if (0..=255).contains(&code) => code,
+                _ => bail!("invalid exit code for {}: {}", name, code),
+            };
+
+            match name {
+                "threshold" => codes.threshold = Some(code),
+                "exec" => codes.exec = code,
+                "regression" => codes.regression = code,
+                _ => bail!(
+                    "unknown exit code name: {} (expected one of: {})",
+                    name,
+                    ExitCodes::NAMES.join(", ")
+                ),
+            }
+        }
+
+        Ok(codes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let codes = "threshold=98,exec=99".parse::<ExitCodes>().unwrap();
+        assert_eq!(codes.threshold, Some(98));
+        assert_eq!(codes.exec, 99);
+
+        let codes = "threshold=98".parse::<ExitCodes>().unwrap();
+        assert_eq!(codes.exec, 127);
+        assert_eq!(codes.regression, 1);
+
+        let codes = "regression=3".parse::<ExitCodes>().unwrap();
+        assert_eq!(codes.regression, 3);
+
+        assert!("threshold".parse::<ExitCodes>().is_err());
+        assert!("threshold=256".parse::<ExitCodes>().is_err());
+        assert!("timeout=1".parse::<ExitCodes>().is_err());
+    }
+
+    #[test]
+    fn exit_with() {
+        assert_eq!(ExitWith::Child.exit_code(Some(3), None), 3);
+        assert_eq!(ExitWith::Child.exit_code(None, Some(9)), 128 + 9);
+        assert_eq!(ExitWith::ErrorOnly.exit_code(Some(3), None), 0);
+        assert_eq!(ExitWith::AlwaysZero.exit_code(None, Some(9)), 0);
+        assert_eq!(
+            "always-zero".parse::<ExitWith>().unwrap(),
+            ExitWith::AlwaysZero
+        );
+        assert!("zero".parse::<ExitWith>().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Support for running in a GitHub Actions workflow (see `--github-summary`), so the results show
+//! up on the run's summary page and as annotations on pull requests.
+//! See: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::units::format_bytes;
+
+/// Returns the file the job's markdown summary is appended to, if running in GitHub Actions.
+pub fn step_summary_file() -> Option<PathBuf> {
+    env::var_os("GITHUB_STEP_SUMMARY").map(PathBuf::from)
+}
+
+/// Renders a markdown table of the headline numbers, with a row for the command.
+pub fn summary(name: &str, report: &Value) -> String {
+    let warning = |v: &Value| if v == true { " :warning:" } else { "" };
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+    let baseline = &report["baseline"];
+
+    format!(
+        "| Command | Peak RSS | Processes | Duration | vs baseline |\n\
+        | --- | --- | --- | --- | --- |\n\
+        | `{}` | {}{} | {} | {} | {}{} |\n",
+        name.replace('|', "\\|"),
+        or_dash(report["max_rss"].as_u64().map(format_bytes)),
+        warning(&report["over_threshold"]),
+        or_dash(report["total_pids"].as_u64().map(|n| n.to_string())),
+        or_dash(
+            report["wall_time_ms"]
+                .as_f64()
+                .map(|ms| format!("{:.2}s", ms / 1000.0))
+        ),
+        or_dash(
+            baseline["relative"]
+                .as_f64()
+                .map(|r| format!("{:+.1}%", r * 100.0))
+        ),
+        warning(&baseline["regressed"]),
+    )
+}
+
+/// Returns a `::warning::` workflow command for each threshold that was exceeded.
+pub fn annotations(name: &str, report: &Value) -> Vec<String> {
+    let mut warnings = vec![];
+    if report["over_threshold"] == true {
+        warnings.push(format!(
+            "max_rss of {} is {}, over the threshold of {}",
+            name,
+            format_bytes(report["max_rss"].as_u64().unwrap_or(0)),
+            format_bytes(report["threshold"].as_u64().unwrap_or(0)),
+        ));
+    }
+    let baseline = &report["baseline"];
+    if baseline["regressed"] == true {
+        warnings.push(format!(
+            "max_rss of {} regressed by {:.1}% from the baseline, more than the {:.1}% allowed",
+            name,
+            baseline["relative"].as_f64().unwrap_or(0.0) * 100.0,
+            baseline["max_regression"].as_f64().unwrap_or(0.0) * 100.0,
+        ));
+    }
+
+    warnings
+        .into_iter()
+        .map(|message| format!("::warning title={}::{}", env!("CARGO_PKG_NAME"), message))
+        .collect()
+}
+
+/// Appends the summary to the job's summary (when there is one), and writes the annotations to
+/// `out`.
+pub fn report(name: &str, report: &Value, out: &mut dyn Write) -> Result<()> {
+    if let Some(path) = step_summary_file() {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        write!(file, "{}", summary(name, report))?;
+    }
+
+    for annotation in annotations(name, report) {
+        writeln!(out, "{}", annotation)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn table() {
+        let report = json!({
+            "max_rss": 2048,
+            "total_pids": 3,
+            "wall_time_ms": 1500.0,
+            "baseline": { "relative": 0.25, "regressed": true },
+        });
+        assert_eq!(
+            summary("ls", &report).lines().last(),
+            Some("| `ls` | 2 KiB | 3 | 1.50s | +25.0% :warning: |")
+        );
+        assert_eq!(
+            summary("ls", &json!({ "max_rss": 2048 })).lines().last(),
+            Some("| `ls` | 2 KiB | - | - | - |")
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        assert!(annotations("ls", &json!({ "max_rss": 2048 })).is_empty());
+
+        let report = json!({
+            "max_rss": 2048,
+            "threshold": 1024,
+            "over_threshold": true,
+            "baseline": { "relative": 0.5, "regressed": true, "max_regression": 0.1 },
+        });
+        assert_eq!(
+            annotations("ls", &report),
+            [
+                "::warning title=max_rss::max_rss of ls is 2 KiB, over the threshold of 1 KiB",
+                "::warning title=max_rss::max_rss of ls regressed by 50.0% from the baseline, \
+                more than the 10.0% allowed",
+            ]
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! The kqueue backend, for macOS and FreeBSD. COMMAND and each of its descendants are watched with
+//! kqueue, so new processes are seen as soon as they fork, and the RSS of every one that's still
+//! running is sampled while they run. Unlike `rusage` this covers the whole tree at once, but it's
+//! only as good as the samples: a process which starts and exits between two of them is missed.
+//!
+//! Only finding each process' children and reading its RSS differ between the two: macOS has
+//! `proc_listchildpids` and `proc_pid_rusage` for them, and FreeBSD has the `kern.proc` sysctl.
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+#[cfg(target_os = "freebsd")]
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use nix::errno::Errno;
+use nix::libc::{self, timespec};
+use nix::sys::event::{EventFilter, EventFlag, FilterFlag, KEvent, Kqueue};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{fork, ForkResult, Pid};
+
+use crate::alert::Alert;
+use crate::child::{self, Forwarding, Inherited, Trace};
+use crate::childlog::{ChildLog, ChildOutput, LogCapture};
+use crate::cli::Args;
+use crate::exec::ExecCheck;
+#[cfg(target_os = "freebsd")]
+use crate::platform;
+use crate::report::{Report, SCHEMA_VERSION};
+use crate::rusage::{self, max_rss_bytes};
+use crate::timeline::Timeline;
+use crate::timeout::Timeout;
+
+/// How often every process is sampled, unless `--interval` is given.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The processes in COMMAND's tree, each of which is watched for forks and exits.
+struct Tree {
+    kqueue: Kqueue,
+    /// Every process that's still running.
+    running: HashSet<Pid>,
+    /// How many processes have been seen in all.
+    seen: usize,
+}
+
+impl Tree {
+    fn new() -> Result<Tree> {
+        Ok(Tree {
+            kqueue: Kqueue::new()?,
+            running: HashSet::new(),
+            seen: 0,
+        })
+    }
+
+    /// Starts watching `pid`, along with any children it already has.
+    fn watch(&mut self, pid: Pid) -> Result<()> {
+        if self.running.contains(&pid) {
+            return Ok(());
+        }
+
+        let event = KEvent::new(
+            pid.as_raw() as usize,
+            EventFilter::EVFILT_PROC,
+            EventFlag::EV_ADD,
+            FilterFlag::NOTE_FORK | FilterFlag::NOTE_EXIT,
+            0,
+            0,
+        );
+        match self.kqueue.kevent(&[event], &mut [], None) {
+            Ok(_) => {}
+            // it's already exited, so there's nothing left of it to measure
+            Err(Errno::ESRCH) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        self.running.insert(pid);
+        self.seen += 1;
+
+        // it may have forked before it was being watched
+        for child in children(pid) {
+            self.watch(child)?;
+        }
+
+        Ok(())
+    }
+
+    /// Waits up to `timeout` for processes to fork or exit, and keeps track of them.
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        let empty = KEvent::new(
+            0,
+            EventFilter::EVFILT_PROC,
+            EventFlag::empty(),
+            FilterFlag::empty(),
+            0,
+            0,
+        );
+        let mut events = [empty; 64];
+        let timeout = timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        };
+        let n = match self.kqueue.kevent(&[], &mut events, Some(timeout)) {
+            Ok(n) => n,
+            // we were interrupted by a signal, which is forwarded on to COMMAND
+            Err(Errno::EINTR) => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        for event in &events[..n] {
+            let pid = Pid::from_raw(event.ident() as i32);
+            // there's no telling which child was forked, so all of them are looked at
+            if event.fflags().contains(FilterFlag::NOTE_FORK) {
+                for child in children(pid) {
+                    self.watch(child)?;
+                }
+            }
+            if event.fflags().contains(FilterFlag::NOTE_EXIT) {
+                self.running.remove(&pid);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The total RSS of every process that's still running.
+    fn sample(&self) -> u64 {
+        self.running.iter().filter_map(|pid| rss(*pid)).sum()
+    }
+}
+
+/// The children of `pid`, which has none once it's exited.
+#[cfg(target_os = "macos")]
+fn children(pid: Pid) -> Vec<Pid> {
+    let mut pids = vec![0; 64];
+    loop {
+        let size = mem::size_of_val(pids.as_slice()) as libc::c_int;
+        // SAFETY: pids is valid for writes of `size` bytes
+        let n = unsafe { libc::proc_listchildpids(pid.as_raw(), pids.as_mut_ptr().cast(), size) };
+        if n < 0 {
+            return vec![];
+        }
+
+        // a full buffer may have been too small to hold them all
+        let n = n as usize;
+        if n < pids.len() {
+            return pids[..n].iter().map(|pid| Pid::from_raw(*pid)).collect();
+        }
+        pids.resize(pids.len() * 2, 0);
+    }
+}
+
+/// The current RSS of `pid`, unless it's already exited.
+#[cfg(target_os = "macos")]
+fn rss(pid: Pid) -> Option<u64> {
+    let mut info = mem::MaybeUninit::<libc::rusage_info_v2>::uninit();
+    // SAFETY: RUSAGE_INFO_V2 fills in a rusage_info_v2
+    let res = unsafe {
+        libc::proc_pid_rusage(pid.as_raw(), libc::RUSAGE_INFO_V2, info.as_mut_ptr().cast())
+    };
+
+    // SAFETY: it was filled in, since the call succeeded
+    (res == 0).then(|| unsafe { info.assume_init() }.ri_resident_size)
+}
+
+/// The children of `pid`, which has none once it's exited.
+#[cfg(target_os = "freebsd")]
+fn children(pid: Pid) -> Vec<Pid> {
+    kinfo_procs(libc::KERN_PROC_PROC, 0)
+        .iter()
+        .filter(|info| info.ki_ppid == pid.as_raw())
+        .map(|info| Pid::from_raw(info.ki_pid))
+        .collect()
+}
+
+/// The current RSS of `pid`, unless it's already exited.
+#[cfg(target_os = "freebsd")]
+fn rss(pid: Pid) -> Option<u64> {
+    kinfo_procs(libc::KERN_PROC_PID, pid.as_raw())
+        .first()
+        // zombies have no memory left, and report none
+        .filter(|info| info.ki_stat != libc::SZOMB)
+        .map(|info| info.ki_rssize as u64 * platform::page_size())
+}
+
+/// Reads the processes selected by `op` and `arg` from the `kern.proc` sysctl, which are none if
+/// it fails (as it does when a pid is asked for which has already exited).
+#[cfg(target_os = "freebsd")]
+fn kinfo_procs(op: libc::c_int, arg: libc::c_int) -> Vec<libc::kinfo_proc> {
+    let mib = [libc::CTL_KERN, libc::KERN_PROC, op, arg];
+    let size_of = mem::size_of::<libc::kinfo_proc>();
+    loop {
+        let mut size = 0;
+        // SAFETY: without a buffer, this only writes the size that's needed to `size`
+        let res = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                ptr::null_mut(),
+                &mut size,
+                ptr::null(),
+                0,
+            )
+        };
+        if res != 0 {
+            return vec![];
+        }
+
+        // with room to spare, since more processes may start before they're read
+        let mut procs = Vec::<libc::kinfo_proc>::with_capacity(size / size_of + 16);
+        let mut size = procs.capacity() * size_of;
+        // SAFETY: procs is valid for writes of `size` bytes
+        let res = unsafe {
+            libc::sysctl(
+                mib.as_ptr(),
+                mib.len() as libc::c_uint,
+                procs.as_mut_ptr().cast(),
+                &mut size,
+                ptr::null(),
+                0,
+            )
+        };
+        match Errno::result(res) {
+            Ok(_) => {
+                // SAFETY: the kernel filled in this many of them
+                unsafe { procs.set_len(size / size_of) };
+                return procs;
+            }
+            // even more processes started than there was room for, so try again
+            Err(Errno::ENOMEM) => continue,
+            Err(_) => return vec![],
+        }
+    }
+}
+
+/// Runs the COMMAND given in `args`, and samples the RSS of its whole tree until it's all exited.
+pub fn trace(args: &Args, inherited: &Inherited) -> Result<Trace> {
+    let log = ChildLog::create(args.log_child.as_deref())?;
+    let output = ChildOutput::create(args.stdout.as_deref(), args.stderr.as_deref())?;
+    let check = ExecCheck::create()?;
+
+    let child = match unsafe { fork() }? {
+        ForkResult::Child => {
+            match rusage::exec_command(args, inherited, log.as_ref(), output.as_ref(), &check)? {}
+        }
+        ForkResult::Parent { child } => child,
+    };
+    if let Some(failed) = check.wait(args)? {
+        return failed.finish(child);
+    }
+
+    if args.debug {
+        eprintln!("::: pid of tracee: {:?}", child);
+    }
+
+    let capture = log.map(|log| log.start(child)).transpose()?;
+    let mut timeout = Timeout::create(args, child, Instant::now());
+    let _forwarding = Forwarding::start(args, child)?;
+
+    let mut tree = Tree::new()?;
+    tree.watch(child)?;
+
+    let interval = match args.sample_peaks {
+        true => args.interval,
+        false => SAMPLE_INTERVAL,
+    };
+    let mut timeline = Timeline::create(args)?;
+    let mut alert = Alert::create(args);
+    let mut last_sample: Option<Instant> = None;
+    let mut sampled_peak = 0;
+
+    let mut exit_code = None;
+    let mut exit_signal = None;
+    let mut usage = None;
+    loop {
+        if usage.is_none() {
+            let (status, exited) = child::wait4(child, WaitPidFlag::WNOHANG)?;
+            match status {
+                WaitStatus::Exited(_, code) => exit_code = Some(code),
+                WaitStatus::Signaled(_, signal, _) => exit_signal = Some(signal as i32),
+                _ => {}
+            }
+            usage = exited;
+        }
+
+        // just as when tracing, descendants which outlive COMMAND are still measured
+        if usage.is_some() && tree.running.is_empty() {
+            break;
+        }
+
+        if let Some(timeout) = &mut timeout {
+            timeout.check();
+        }
+
+        if last_sample.is_none_or(|last| last.elapsed() >= interval) {
+            last_sample = Some(Instant::now());
+            let current = tree.sample();
+            sampled_peak = sampled_peak.max(current);
+            if let Some(timeline) = &mut timeline {
+                timeline.sample(current)?;
+            }
+            if let Some(alert) = &mut alert {
+                alert.check(current);
+            }
+        }
+
+        // rather than sleeping, this wakes up as soon as anything forks or exits
+        tree.wait(SAMPLE_INTERVAL.min(interval))?;
+    }
+
+    // COMMAND's own peak may have come between two samples
+    let usage = usage.expect("COMMAND hasn't exited");
+    let rusage_max_rss = max_rss_bytes(&usage);
+
+    let report = Report {
+        schema_version: Some(SCHEMA_VERSION),
+        max_rss: sampled_peak.max(rusage_max_rss),
+        rusage_max_rss: Some(rusage_max_rss),
+        ru_maxrss: args.rusage.then_some(rusage_max_rss),
+        utime_ms: Some(rusage::millis(usage.ru_utime)),
+        stime_ms: Some(rusage::millis(usage.ru_stime)),
+        minor_faults: Some(usage.ru_minflt as u64),
+        major_faults: Some(usage.ru_majflt as u64),
+        total_processes: Some(tree.seen),
+        sample_interval_ms: Some(interval.as_secs_f64() * 1000.0),
+        exit_code,
+        exit_signal,
+        timed_out: timeout.as_ref().map(Timeout::timed_out),
+        timeline: timeline.map(Timeline::finish).transpose()?,
+        alerts: alert.as_ref().map(Alert::fired),
+        child_log: capture.map(LogCapture::finish),
+        child_output: output.map(ChildOutput::finish),
+        ..Report::default()
+    };
+
+    Ok(Trace {
+        report: serde_json::to_value(report)?,
+        exit_code: args.exit_with.exit_code(exit_code, exit_signal),
+        snapshots: HashMap::new(),
+        error: None,
+    })
+}
@@ -0,0 +1,63 @@
+//! Helpers for using max_rss from other Rust projects.
+//!
+//! Commands are measured in-process by the same tracer the `max_rss` binary uses, see
+//! [`measure`](mod@measure) for what that means for the caller.
+
+// only the rusage backend is built outside of Linux, and much of the rest is there for the others
+#![cfg_attr(not(target_os = "linux"), allow(dead_code))]
+
+mod alert;
+#[doc(hidden)]
+pub mod app;
+mod backend;
+mod bazel;
+#[cfg(all(feature = "criterion", target_os = "linux"))]
+pub mod bench;
+mod calibrate;
+#[cfg(target_os = "linux")]
+mod cgroup;
+mod child;
+mod childlog;
+mod cli;
+mod compare;
+mod events;
+mod exec;
+mod exitcode;
+mod github;
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+mod kqueue;
+#[cfg(target_os = "linux")]
+pub mod measure;
+mod merge;
+mod noise;
+mod otlp;
+mod output;
+mod permission;
+#[cfg(target_os = "linux")]
+mod pidns;
+mod platform;
+mod policy;
+#[cfg(target_os = "linux")]
+mod proc;
+mod redact;
+pub mod report;
+mod rundir;
+mod rusage;
+pub mod samples;
+#[cfg(target_os = "linux")]
+mod seccomp;
+mod sqlite;
+mod stats;
+mod statsd;
+mod steps;
+mod time;
+mod timeline;
+mod timeout;
+#[cfg(target_os = "linux")]
+mod tracer;
+mod units;
+mod verify;
+
+#[cfg(target_os = "linux")]
+pub use measure::{measure, Event, MeasureRss, Subscription, Tracer};
+pub use report::{ProcessNode, Report};
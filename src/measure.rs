@@ -0,0 +1,199 @@
+//! A one-shot API for measuring the max_rss of a [`Command`], for callers who just want the number.
+//!
+//! ```no_run
+//! use std::process::Command;
+//!
+//! use max_rss::MeasureRss;
+//!
+//! let report = Command::new("ls").arg("-la").measure_rss().unwrap();
+//! println!("ls used {} bytes", report.max_rss);
+//! ```
+//!
+//! [`Tracer`] does the same, but lets `max_rss` flags be passed too:
+//!
+//! ```no_run
+//! use std::process::Command;
+//!
+//! use max_rss::Tracer;
+//!
+//! let report = Tracer::spawn(Command::new("make")).arg("--io").run().unwrap();
+//! println!("make read {:?} bytes", report.io.map(|io| io.rchar));
+//! ```
+//!
+//! Or [`Tracer::subscribe`] can be used to follow along while the command runs:
+//!
+//! ```no_run
+//! use std::process::Command;
+//!
+//! use max_rss::{Event, Tracer};
+//!
+//! let subscription = Tracer::spawn(Command::new("make")).subscribe().unwrap();
+//! for event in subscription.events() {
+//!     if let Event::ProcessExited { pid, rss, .. } = event {
+//!         println!("{} exited, having used {} bytes", pid, rss);
+//!     }
+//! }
+//! let report = subscription.wait().unwrap();
+//! ```
+//!
+//! The command is traced from within the calling process, which has some consequences:
+//!
+//! * While it runs, the tracer waits for (and so reaps) every child of the calling process, not
+//!   just the command's, so nothing else may be running children at the same time. Only one
+//!   command is measured at a time, and others wait their turn.
+//! * The calling process becomes a child subreaper (see `PR_SET_CHILD_SUBREAPER`), so that
+//!   orphaned descendants can be measured too, and this isn't undone afterwards.
+//! * SIGINT and SIGTERM are forwarded to the command while it runs, and SIGALRM is used for
+//!   `--timeout`, so any handlers of the caller's own for these are replaced.
+
+use std::ffi::{OsStr, OsString};
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::backend::Backend;
+use crate::child::Inherited;
+use crate::cli::Args;
+pub use crate::events::Event;
+use crate::report::Report;
+use crate::tracer;
+
+/// Held while a command is traced, since the tracer waits for every child of this process and only
+/// one can do that at a time.
+static TRACING: Mutex<()> = Mutex::new(());
+
+/// Traces the command and waits for it to finish.
+///
+/// The command is run exactly as it's configured (its environment, working directory, stdio and
+/// so on), but no [`Child`](std::process::Child) is returned, so output it's given
+/// [`Stdio::piped`](std::process::Stdio::piped) for can't be read. A command which fails is still
+/// measured, see [`Report::exit_code`].
+pub fn measure(command: Command) -> Result<Report> {
+    Tracer::spawn(command).run()
+}
+
+/// Measures a command with extra `max_rss` flags, see [`Tracer::spawn`].
+#[derive(Debug)]
+pub struct Tracer {
+    command: Command,
+    args: Vec<OsString>,
+}
+
+impl Tracer {
+    /// Prepares to measure the command, which isn't run until [`run`](Tracer::run) is called.
+    pub fn spawn(command: Command) -> Tracer {
+        Tracer {
+            command,
+            args: vec![],
+        }
+    }
+
+    /// Passes a flag to `max_rss`, such as `--io` or `--interval`.
+    ///
+    /// Only what `--backend ptrace` measures in a single run can be used: flags for other
+    /// backends, `--runs` and `--pid` are rejected, and those which change how the results are
+    /// written (`--output`, `--summary` and so on) have no effect.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Tracer {
+        self.args.push(arg.as_ref().to_owned());
+        self
+    }
+
+    /// Passes several flags to `max_rss`, see [`arg`](Tracer::arg).
+    pub fn args<I, S>(mut self, args: I) -> Tracer
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Traces the command and waits for it to finish, see [`measure`].
+    pub fn run(mut self) -> Result<Report> {
+        trace(&mut self.command, self.args, None)
+    }
+
+    /// Traces the command in the background, sending each [`Event`] as it happens.
+    pub fn subscribe(self) -> Result<Subscription> {
+        let Tracer { mut command, args } = self;
+        let (sender, receiver) = mpsc::channel();
+        // the sender is dropped once tracing has finished, which ends the events
+        let handle = thread::spawn(move || trace(&mut command, args, Some(sender)));
+
+        Ok(Subscription {
+            events: receiver,
+            handle,
+        })
+    }
+}
+
+/// A command being measured in the background, see [`Tracer::subscribe`].
+#[derive(Debug)]
+pub struct Subscription {
+    events: Receiver<Event>,
+    handle: JoinHandle<Result<Report>>,
+}
+
+impl Subscription {
+    /// The events as they happen. Iterating over them ends once the command has finished.
+    pub fn events(&self) -> &Receiver<Event> {
+        &self.events
+    }
+
+    /// Waits for the command to finish, and returns its results.
+    pub fn wait(self) -> Result<Report> {
+        self.handle
+            .join()
+            .map_err(|_| anyhow!("the measuring thread panicked"))?
+    }
+}
+
+/// Traces `command` with the given `max_rss` flags, see [`Tracer`].
+fn trace(
+    command: &mut Command,
+    flags: Vec<OsString>,
+    events: Option<Sender<Event>>,
+) -> Result<Report> {
+    // the flags are checked just as they would be on the command line
+    let mut argv = flags;
+    argv.push("--".into());
+    argv.push(command.get_program().to_owned());
+    argv.extend(command.get_args().map(OsStr::to_owned));
+    let args = Args::parse_from(argv)?;
+    if args.backend != Backend::Ptrace {
+        bail!(
+            "--backend {} can't be used to measure in-process",
+            args.backend.name()
+        );
+    }
+    if args.runs.is_some_and(|n| n > 1) || args.pid.is_some() {
+        bail!("--runs and --pid can't be used to measure in-process");
+    }
+
+    // a previous trace which panicked has nothing left for us to clean up
+    let _tracing = TRACING.lock().unwrap_or_else(PoisonError::into_inner);
+    let inherited = Inherited::capture()?;
+    let start = Instant::now();
+    let mut trace = tracer::trace_command(&args, &inherited, Some(command), events)?.complete()?;
+    trace.report["backend"] = args.backend.name().into();
+    trace.report["wall_time_ms"] = (start.elapsed().as_secs_f64() * 1000.0).into();
+
+    Ok(serde_json::from_value(trace.report)?)
+}
+
+/// Adds [`measure_rss`](MeasureRss::measure_rss) to [`Command`].
+pub trait MeasureRss {
+    /// Traces the command and waits for it to finish, see [`measure`].
+    fn measure_rss(&mut self) -> Result<Report>;
+}
+
+impl MeasureRss for Command {
+    fn measure_rss(&mut self) -> Result<Report> {
+        trace(self, vec![], None)
+    }
+}
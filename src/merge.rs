@@ -0,0 +1,62 @@
+//! Combines several results reports into one, e.g.: those from CI jobs which each measured a shard
+//! of a test suite.
+
+use serde_json::{json, Value};
+
+/// Merges the reports, which are given along with the names of the files they came from.
+pub fn merge(reports: &[(String, Value)]) -> Value {
+    let max_rss = reports
+        .iter()
+        .filter_map(|(_, report)| report["max_rss"].as_u64())
+        .max();
+    let total_pids = reports
+        .iter()
+        .map(|(_, report)| report["total_pids"].as_u64())
+        .sum::<Option<u64>>();
+    let over_threshold = reports
+        .iter()
+        .any(|(_, report)| report["over_threshold"] == true);
+
+    let reports = reports
+        .iter()
+        .map(|(file, report)| json!({ "file": file, "report": report }))
+        .collect::<Vec<_>>();
+
+    json!({
+        "max_rss": max_rss.unwrap_or(0),
+        "total_pids": total_pids,
+        "over_threshold": over_threshold,
+        "reports": reports,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merged() {
+        let reports = [
+            ("a.json".into(), json!({ "max_rss": 100, "total_pids": 2 })),
+            ("b.json".into(), json!({ "max_rss": 300, "total_pids": 1 })),
+        ];
+        let merged = merge(&reports);
+        assert_eq!(merged["max_rss"], 300);
+        assert_eq!(merged["total_pids"], 3);
+        assert_eq!(merged["over_threshold"], false);
+        assert_eq!(merged["reports"][1]["file"], "b.json");
+        assert_eq!(merged["reports"][1]["report"]["max_rss"], 300);
+
+        // the rusage backend doesn't count processes
+        let reports = [
+            ("a.json".into(), json!({ "max_rss": 100, "total_pids": 2 })),
+            (
+                "b.json".into(),
+                json!({ "max_rss": 300, "over_threshold": true }),
+            ),
+        ];
+        let merged = merge(&reports);
+        assert_eq!(merged["total_pids"], Value::Null);
+        assert_eq!(merged["over_threshold"], true);
+    }
+}
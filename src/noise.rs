@@ -0,0 +1,80 @@
+//! Measures the inherent run-to-run variance of a COMMAND's max_rss on this machine, so that
+//! regression thresholds can be chosen from data rather than guesswork.
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::child::Inherited;
+use crate::cli::Args;
+use crate::stats::Stats;
+
+/// How many times the COMMAND is run if `--runs` isn't given.
+pub const DEFAULT_RUNS: usize = 20;
+
+/// Recommends a regression threshold (as a fraction) from the observed noise: it should be wide
+/// enough that neither three standard deviations nor the full observed spread would trip it.
+fn recommend_threshold(stats: &Stats) -> f64 {
+    let spread = if stats.median == 0.0 {
+        0.0
+    } else {
+        (stats.max - stats.min) / stats.median
+    };
+
+    // round up to the nearest half a percent, so the suggestion is easy to use
+    let threshold = (stats.cv() * 3.0).max(spread);
+    (threshold * 200.0).ceil() / 200.0
+}
+
+pub fn noise(args: &Args, inherited: &Inherited) -> Result<Value> {
+    let runs = args.runs.unwrap_or(DEFAULT_RUNS);
+
+    let mut values = vec![];
+    for i in 0..runs {
+        if args.debug {
+            eprintln!("::: noise run {}/{}", i + 1, runs);
+        }
+
+        let trace = args.backend.trace(args, inherited)?.complete()?;
+        values.push(trace.report["max_rss"].as_u64().unwrap_or(0));
+    }
+
+    let stats = Stats::new(&values.iter().map(|v| *v as f64).collect::<Vec<_>>());
+    Ok(json!({
+        "runs": values,
+        "stats": stats.as_ref().map(Stats::to_json),
+        "cv": stats.as_ref().map(Stats::cv),
+        "recommended_threshold": stats.as_ref().map(recommend_threshold),
+    }))
+}
+
+/// Prints a human readable summary of the noise measurements.
+pub fn print_summary(report: &Value) {
+    let stats = &report["stats"];
+    eprintln!(
+        "runs: {}, min: {:.0}, median: {:.0}, max: {:.0}, stddev: {:.0} ({:.2}%)",
+        report["runs"].as_array().map(|r| r.len()).unwrap_or(0),
+        stats["min"].as_f64().unwrap_or_default(),
+        stats["median"].as_f64().unwrap_or_default(),
+        stats["max"].as_f64().unwrap_or_default(),
+        stats["stddev"].as_f64().unwrap_or_default(),
+        report["cv"].as_f64().unwrap_or_default() * 100.0,
+    );
+    eprintln!(
+        "recommended regression threshold: {:.1}%",
+        report["recommended_threshold"].as_f64().unwrap_or_default() * 100.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold() {
+        let stats = Stats::new(&[100.0, 100.0, 100.0]).unwrap();
+        assert_eq!(recommend_threshold(&stats), 0.0);
+
+        let stats = Stats::new(&[100.0, 101.0, 102.0]).unwrap();
+        assert_eq!(recommend_threshold(&stats), 0.03);
+    }
+}
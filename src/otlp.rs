@@ -0,0 +1,248 @@
+//! Pushes the results to an OpenTelemetry collector as OTLP gauges (see `--otlp`), so they land in
+//! an observability backend without a conversion step.
+//!
+//! This speaks OTLP's JSON encoding over plain HTTP/1.1, rather than pulling in an HTTP client and
+//! protobuf for what's a single request at the end of a run.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::report::{ProcessNode, Report};
+
+/// The port OTLP/HTTP collectors listen on by default.
+const DEFAULT_PORT: u16 = 4318;
+
+/// How long to wait on the collector before giving up.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the metrics are sent, from an endpoint like `http://localhost:4318`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn parse(s: &str) -> Result<Endpoint> {
+        if s.starts_with("https://") {
+            bail!("only plain http is supported for --otlp: {}", s);
+        }
+
+        let s = s.strip_prefix("http://").unwrap_or(s);
+        let (authority, path) = match s.find('/') {
+            Some(i) => s.split_at(i),
+            None => (s, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| anyhow!("invalid port for --otlp: {}", port))?,
+            ),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            bail!("missing host for --otlp: {}", s);
+        }
+
+        Ok(Endpoint {
+            host: host.into(),
+            port,
+            // collectors take metrics at this path unless told otherwise
+            path: match path {
+                "" | "/" => "/v1/metrics".into(),
+                path => path.into(),
+            },
+        })
+    }
+}
+
+fn nanos(time: SystemTime) -> String {
+    let nanos = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    nanos.to_string()
+}
+
+fn gauge(name: &str, unit: &str, points: Vec<Value>) -> Value {
+    json!({
+        "name": name,
+        "unit": unit,
+        "gauge": { "dataPoints": points },
+    })
+}
+
+fn point(time: SystemTime, value: u64, attributes: &[(&str, Value)]) -> Value {
+    let attributes = attributes
+        .iter()
+        .map(|(key, value)| json!({ "key": key, "value": value }))
+        .collect::<Vec<_>>();
+
+    // 64-bit integers are strings in OTLP's JSON encoding
+    json!({
+        "timeUnixNano": nanos(time),
+        "asInt": value.to_string(),
+        "attributes": attributes,
+    })
+}
+
+/// Every process' samples (see `--record-samples`), as points in time.
+fn samples(node: &ProcessNode, command: &Value, started_at: SystemTime, points: &mut Vec<Value>) {
+    for (ms, rss) in node.samples.iter().flatten() {
+        let pid = json!({ "intValue": node.id.to_string() });
+        let time = started_at + Duration::from_millis(*ms);
+        points.push(point(
+            time,
+            *rss,
+            &[("command", command.clone()), ("process.pid", pid)],
+        ));
+    }
+    for child in node.children.iter().flatten() {
+        samples(child, command, started_at, points);
+    }
+}
+
+/// Builds the request body, with a gauge for each of the headline numbers.
+pub fn metrics(command: &str, report: &Value, started_at: SystemTime) -> Result<Value> {
+    let typed = serde_json::from_value::<Report>(report.clone())?;
+    let now = SystemTime::now();
+    let command = json!({ "stringValue": command });
+    let attributes = [("command", command.clone())];
+
+    let mut metrics = vec![gauge(
+        "max_rss",
+        "By",
+        vec![point(now, typed.max_rss, &attributes)],
+    )];
+    let others = [
+        (
+            "max_rss.total_pids",
+            "{process}",
+            typed.total_pids.map(|n| n as u64),
+        ),
+        (
+            "max_rss.wall_time",
+            "ms",
+            report["wall_time_ms"].as_f64().map(|ms| ms as u64),
+        ),
+        ("max_rss.user_time", "ms", typed.utime_ms),
+        ("max_rss.system_time", "ms", typed.stime_ms),
+    ];
+    for (name, unit, value) in others {
+        if let Some(value) = value {
+            metrics.push(gauge(name, unit, vec![point(now, value, &attributes)]));
+        }
+    }
+
+    let mut points = vec![];
+    if let Some(graph) = &typed.graph {
+        samples(graph, &command, started_at, &mut points);
+    }
+    if !points.is_empty() {
+        metrics.push(gauge("max_rss.process.rss", "By", points));
+    }
+
+    Ok(json!({
+        "resourceMetrics": [{
+            "resource": {
+                "attributes": [
+                    { "key": "service.name", "value": { "stringValue": env!("CARGO_PKG_NAME") } },
+                ],
+            },
+            "scopeMetrics": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "metrics": metrics,
+            }],
+        }],
+    }))
+}
+
+/// Sends the results to the collector at `endpoint`.
+pub fn export(
+    endpoint: &Endpoint,
+    command: &str,
+    report: &Value,
+    started_at: SystemTime,
+) -> Result<()> {
+    let body = metrics(command, report, started_at)?.to_string();
+
+    let addr = (endpoint.host.as_str(), endpoint.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("failed to resolve {}", endpoint.host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)
+        .with_context(|| format!("failed to connect to {}:{}", endpoint.host, endpoint.port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        endpoint.path,
+        endpoint.host,
+        endpoint.port,
+        body.len(),
+        body
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status = response.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => bail!("the collector responded with: {}", status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints() -> Result<()> {
+        let endpoint = Endpoint::parse("http://localhost:4318")?;
+        assert_eq!(endpoint.host, "localhost");
+        assert_eq!(endpoint.port, 4318);
+        assert_eq!(endpoint.path, "/v1/metrics");
+
+        let endpoint = Endpoint::parse("collector/otlp/v1/metrics")?;
+        assert_eq!(endpoint.port, DEFAULT_PORT);
+        assert_eq!(endpoint.path, "/otlp/v1/metrics");
+
+        assert!(Endpoint::parse("https://localhost:4318").is_err());
+        assert!(Endpoint::parse("localhost:http").is_err());
+        assert!(Endpoint::parse(":4318").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn gauges() -> Result<()> {
+        let report = json!({
+            "max_rss": 2048,
+            "total_pids": 2,
+            "graph": { "id": 7, "rss": 2048, "samples": [[0, 1024], [10, 2048]] },
+        });
+
+        let body = metrics("foo", &report, UNIX_EPOCH)?;
+        let metrics = &body["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "max_rss");
+        let point = &metrics[0]["gauge"]["dataPoints"][0];
+        assert_eq!(point["asInt"], "2048");
+        assert_eq!(point["attributes"][0]["value"]["stringValue"], "foo");
+        assert_eq!(metrics[1]["name"], "max_rss.total_pids");
+
+        let samples = &metrics[2];
+        assert_eq!(samples["name"], "max_rss.process.rss");
+        let point = &samples["gauge"]["dataPoints"][1];
+        assert_eq!(point["timeUnixNano"], "10000000");
+        assert_eq!(point["attributes"][1]["value"]["intValue"], "7");
+        Ok(())
+    }
+}
@@ -0,0 +1,1472 @@
+//! Writers which serialise the results of a run into the various supported output formats.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use nix::errno::Errno;
+use nix::fcntl::{flock, FlockArg};
+use serde_json::Value;
+
+/// Serialises a results report into a particular format.
+pub trait ResultWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()>;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Csv,
+    Yaml,
+    Toml,
+    Markdown,
+    Prometheus,
+    Html,
+    Gitlab,
+    Dot,
+    Bencher,
+    Sqlite,
+}
+
+impl OutputFormat {
+    pub const ALL: &'static [(&'static str, OutputFormat)] = &[
+        ("json", OutputFormat::Json),
+        ("csv", OutputFormat::Csv),
+        ("yaml", OutputFormat::Yaml),
+        ("toml", OutputFormat::Toml),
+        ("md", OutputFormat::Markdown),
+        ("prometheus", OutputFormat::Prometheus),
+        ("html", OutputFormat::Html),
+        ("gitlab", OutputFormat::Gitlab),
+        ("dot", OutputFormat::Dot),
+        ("bencher", OutputFormat::Bencher),
+        ("sqlite", OutputFormat::Sqlite),
+    ];
+
+    /// The file extension used for this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Prometheus => "prom",
+            OutputFormat::Html => "html",
+            OutputFormat::Gitlab => "txt",
+            OutputFormat::Dot => "dot",
+            OutputFormat::Bencher => "json",
+            OutputFormat::Sqlite => "db",
+        }
+    }
+
+    pub fn writer(&self) -> Box<dyn ResultWriter> {
+        match self {
+            OutputFormat::Json => Box::new(JsonWriter),
+            OutputFormat::Csv => Box::new(CsvWriter),
+            OutputFormat::Yaml => Box::new(YamlWriter),
+            OutputFormat::Toml => Box::new(TomlWriter),
+            OutputFormat::Markdown => Box::new(MarkdownWriter),
+            OutputFormat::Prometheus => Box::new(PrometheusWriter),
+            OutputFormat::Html => Box::new(HtmlWriter),
+            OutputFormat::Gitlab => Box::new(GitlabWriter),
+            OutputFormat::Dot => Box::new(DotWriter),
+            OutputFormat::Bencher => Box::new(BencherWriter),
+            OutputFormat::Sqlite => Box::new(SqliteWriter),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        OutputFormat::ALL
+            .iter()
+            .find_map(|(name, format)| (*name == s).then_some(*format))
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown output format: {}, expected one of: {}",
+                    s,
+                    OutputFormat::ALL
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+/// Expands `{name}` placeholders in the template using `lookup`. Literal braces can be written as
+/// `{{` and `}}`.
+fn expand_template(template: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                match lookup(&name) {
+                    Some(value) => out.push_str(&value),
+                    None => bail!("unknown placeholder in output path: {{{}}}", name),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Resolves placeholders in the output path:
+///  - `{cmd}`: the basename of the COMMAND
+///  - `{date}`: the current UTC date and time
+///  - `{n}`: the smallest number (starting at 1) which results in a path that doesn't exist yet,
+///    which is claimed by creating an empty file there, so instances running at the same time never
+///    pick the same one
+pub fn resolve_path(output: &Path, cmd: &str) -> Result<PathBuf> {
+    resolve_with(output, cmd, |path| {
+        fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map(drop)
+    })
+}
+
+/// Like [`resolve_path`], but claims `{n}` by creating a directory there instead of a file.
+pub fn resolve_dir(output: &Path, cmd: &str) -> Result<PathBuf> {
+    resolve_with(output, cmd, |path| fs::create_dir(path))
+}
+
+fn resolve_with(
+    output: &Path,
+    cmd: &str,
+    claim: impl Fn(&Path) -> io::Result<()>,
+) -> Result<PathBuf> {
+    let Some(template) = output.to_str().filter(|s| s.contains(['{', '}'])) else {
+        return Ok(output.to_path_buf());
+    };
+
+    let date = crate::time::DateTime::now().compact();
+    let lookup = |name: &str, n: Option<usize>| match name {
+        "cmd" => Some(cmd.to_string()),
+        "date" => Some(date.clone()),
+        "n" => n.map(|n| n.to_string()),
+        _ => None,
+    };
+
+    // expand once without a counter, to see if one is even needed
+    if let Ok(path) = expand_template(template, |name| lookup(name, None)) {
+        return Ok(PathBuf::from(path));
+    }
+
+    for n in 1.. {
+        let path = PathBuf::from(expand_template(template, |name| lookup(name, Some(n)))?);
+        fs::create_dir_all(parent_dir(&path))?;
+        match claim(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(Error::new(e).context(format!("failed to create {}", path.display())))
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+/// Whether the output path means stdout.
+pub fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Writes the report to stdout.
+pub fn write_stdout(format: OutputFormat, report: &Value) -> Result<()> {
+    let mut buf = vec![];
+    format.writer().write(report, &mut buf)?;
+    // some formats don't end with a newline, which is unfriendly in a terminal
+    if !buf.ends_with(b"\n") {
+        buf.push(b'\n');
+    }
+
+    let mut out = io::stdout().lock();
+    out.write_all(&buf)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Whether the output path is something other than a regular file or directory, e.g.: `/dev/null`
+/// or a named pipe. Renaming a file over one of these would replace it (given the permission to),
+/// and there's nothing to protect with a lockfile, so they're simply written to.
+fn is_special(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| !m.is_file() && !m.is_dir())
+}
+
+fn write_special(path: &Path, format: OutputFormat, report: &Value) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let mut file = BufWriter::new(file);
+    format.writer().write(report, &mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// The lockfile kept next to an output file, e.g.: `results.json.lock` for `results.json`.
+fn lock_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid output path: {}", path.display()))?;
+    Ok(parent_dir(path).join(format!("{}.lock", name.to_string_lossy())))
+}
+
+/// An advisory lock on an output file's lockfile, see [`lock_file`]. The lockfile is removed while
+/// the lock is still held, and then the lock is released when this is dropped.
+struct Lock {
+    // the lock is held for as long as this is open
+    _file: File,
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // anyone already waiting on it finds it's gone once they have the lock, and tries again
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Takes an advisory lock on the output file's lockfile (creating it and its directory if needed),
+/// waiting for any other instance which holds it.
+fn lock_file(path: &Path) -> Result<Lock> {
+    fs::create_dir_all(parent_dir(path))?;
+    let lock_path = lock_path(path)?;
+    let mut warned = false;
+    loop {
+        let lock = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .with_context(|| format!("failed to open {}", lock_path.display()))?;
+        match flock(lock.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                if !warned {
+                    eprintln!(
+                        "{}: warning: another instance is writing to {}, waiting for it to finish",
+                        env!("CARGO_PKG_NAME"),
+                        path.display()
+                    );
+                    warned = true;
+                }
+                flock(lock.as_raw_fd(), FlockArg::LockExclusive)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        // the instance we waited for removed the lockfile before releasing it, so the lock we have
+        // is on a file nobody else will see, and another may have since created a new one
+        let locked = lock.metadata()?;
+        let current = fs::metadata(&lock_path);
+        if current.is_ok_and(|m| (m.dev(), m.ino()) == (locked.dev(), locked.ino())) {
+            return Ok(Lock {
+                _file: lock,
+                path: lock_path,
+            });
+        }
+    }
+}
+
+/// Writes the report to the given path, in a way that's safe even if other instances are writing to
+/// the same path at the same time.
+///
+/// While holding an advisory lock on the destination's lockfile, the report is written to a
+/// temporary file next to the destination which is then renamed over it. Renaming is atomic, so
+/// readers never see a partially written file, and the lock lets us detect (and wait for) other
+/// writers.
+///
+/// The sqlite format is the exception, since runs are added to the database rather than replacing
+/// it, see [`crate::sqlite::append`].
+pub fn write_file(path: &Path, format: OutputFormat, report: &Value) -> Result<()> {
+    if format == OutputFormat::Sqlite {
+        return crate::sqlite::append(path, report);
+    }
+    if is_special(path) {
+        return write_special(path, format, report);
+    }
+
+    let lock = lock_file(path)?;
+    replace(path, format, report)?;
+
+    // the lock is released when the file is closed
+    drop(lock);
+
+    Ok(())
+}
+
+/// The directory a file is in, which for a bare file name is the current directory.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Writes the report to a temporary file next to the given path, and then renames it over it. The
+/// caller should hold the lock on its lockfile.
+fn replace(path: &Path, format: OutputFormat, report: &Value) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid output path: {}", path.display()))?;
+    let tmp = parent_dir(path).join(format!(".{}.{}.tmp", name.to_string_lossy(), process::id()));
+
+    let result = (|| {
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        format.writer().write(report, &mut file)?;
+        file.into_inner()?.sync_all()?;
+
+        fs::rename(&tmp, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+
+    result
+}
+
+/// Whether the path is for JSON Lines, which `--append` adds a line to rather than an array entry.
+fn is_json_lines(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("jsonl" | "ndjson")
+    )
+}
+
+/// Adds the report to the results already at the given path (see `--append`), numbered with a
+/// `run_id` which follows on from the runs already there, and with the `timestamp` it was added.
+///
+/// JSON Lines files (`.jsonl` or `.ndjson`) get a line per run. Otherwise the file holds an array
+/// of runs, and a file holding a single run (from before `--append` was used) becomes the first.
+pub fn append_file(path: &Path, report: &Value, timestamp: Option<String>) -> Result<()> {
+    if is_special(path) {
+        let mut report = report.clone();
+        report["run_id"] = 1.into();
+        report["timestamp"] = timestamp.into();
+        return write_special(path, OutputFormat::Json, &report);
+    }
+
+    let lock = lock_file(path)?;
+
+    let existing = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::new(e).context(format!("failed to read {}", path.display()))),
+    };
+    let parse_error = || format!("failed to parse {}, so can't append to it", path.display());
+
+    let mut report = report.clone();
+    report["timestamp"] = timestamp.into();
+    if is_json_lines(path) {
+        let runs = existing.lines().filter(|l| !l.trim().is_empty()).count();
+        report["run_id"] = (runs + 1).into();
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            writeln!(file)?;
+        }
+        writeln!(file, "{}", report)?;
+        file.sync_all()?;
+    } else {
+        let mut runs = match serde_json::from_str::<Value>(&existing) {
+            _ if existing.trim().is_empty() => vec![],
+            Ok(Value::Array(runs)) => runs,
+            Ok(run @ Value::Object(_)) => vec![run],
+            _ => bail!(parse_error()),
+        };
+        report["run_id"] = (runs.len() + 1).into();
+        runs.push(report);
+        replace(path, OutputFormat::Json, &Value::Array(runs))?;
+    }
+
+    drop(lock);
+    Ok(())
+}
+
+/// Creates a shields.io endpoint badge (see https://shields.io/badges/endpoint-badge) showing the
+/// max_rss of a run. If a threshold is given, the badge is green when under it and red otherwise.
+pub fn badge(max_rss: u64, threshold: Option<u64>) -> Value {
+    let color = match threshold {
+        Some(threshold) if max_rss > threshold => "red",
+        Some(_) => "green",
+        None => "blue",
+    };
+
+    serde_json::json!({
+        "schemaVersion": 1,
+        "label": "max RSS",
+        "message": crate::units::format_bytes(max_rss),
+        "color": color,
+    })
+}
+
+/// Formats the headline numbers of a report for people to read, in the spirit of `time -v`. Lines
+/// for anything the report doesn't have (e.g.: the exit code, when COMMAND was killed by a signal)
+/// are left out.
+pub fn summary(report: &Value) -> String {
+    let bytes = |v: &Value| {
+        v.as_u64()
+            .map(|b| format!("{} ({} bytes)", crate::units::format_bytes(b), b))
+    };
+    let secs = |v: &Value| v.as_f64().map(|ms| format!("{:.2}s", ms / 1000.0));
+    let faults = match (
+        report["minor_faults"].as_u64(),
+        report["major_faults"].as_u64(),
+    ) {
+        (Some(minor), Some(major)) => Some(format!("{} minor, {} major", minor, major)),
+        _ => None,
+    };
+    let events = report["events"].is_object().then(|| {
+        let count = |key: &str| report["events"][key].as_u64().unwrap_or(0);
+        format!(
+            "{} forks, {} vforks, {} clones, {} execs",
+            count("forks"),
+            count("vforks"),
+            count("clones"),
+            count("execs")
+        )
+    });
+    let threshold = report["threshold"].as_u64().map(|t| {
+        let over = report["over_threshold"].as_bool() == Some(true);
+        format!(
+            "{} ({})",
+            crate::units::format_bytes(t),
+            if over { "exceeded" } else { "not exceeded" }
+        )
+    });
+
+    let number = |v: &Value| v.as_i64().map(|n| n.to_string());
+    let flag = |v: &Value| (v.as_bool() == Some(true)).then(|| "yes".to_string());
+
+    let lines = [
+        ("max rss", bytes(&report["max_rss"])),
+        ("threshold", threshold),
+        (
+            "processes",
+            // older results only counted processes and threads together
+            number(&report["total_processes"]).or_else(|| number(&report["total_pids"])),
+        ),
+        ("threads", number(&report["total_threads"])),
+        ("events", events),
+        ("wall time", secs(&report["wall_time_ms"])),
+        ("user time", secs(&report["utime_ms"])),
+        ("system time", secs(&report["stime_ms"])),
+        ("page faults", faults),
+        ("exit code", number(&report["exit_code"])),
+        ("exit signal", number(&report["exit_signal"])),
+        ("timed out", flag(&report["timed_out"])),
+        ("killed", number(&report["killed_at_teardown"])),
+        ("detached", number(&report["left_running"])),
+        ("interrupted", flag(&report["interrupted"])),
+        ("error", report["error"].as_str().map(String::from)),
+    ];
+
+    let mut out = String::new();
+    for (label, value) in lines {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{:<13}{}", format!("{}:", label), value);
+        }
+    }
+
+    out
+}
+
+/// Formats the process graph as an indented tree, one process per line with its name, pid and RSS,
+/// and how it ended unless that was successfully.
+pub fn tree(report: &Value) -> String {
+    fn walk(node: &Value, depth: usize, out: &mut String) {
+        let thread = if node["thread"] == true {
+            ", thread"
+        } else {
+            ""
+        };
+        // it's still shown, but its RSS isn't part of the total
+        let excluded = if node["excluded"] == true {
+            ", excluded"
+        } else {
+            ""
+        };
+        let status = &node["exit_status"];
+        let failed = match (status["exited"].as_i64(), status["signaled"].as_str()) {
+            (Some(code), _) if code != 0 => format!(", exited with {}", code),
+            (_, Some(signal)) => format!(", killed by {}", signal),
+            _ => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "{:indent$}{} ({}{}{}): {}{}",
+            "",
+            node["name"].as_str().unwrap_or("?"),
+            display(&node["id"]),
+            thread,
+            excluded,
+            crate::units::format_bytes(node["rss"].as_u64().unwrap_or(0)),
+            failed,
+            indent = depth * 2
+        );
+        for child in node["children"].as_array().into_iter().flatten() {
+            walk(child, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    if !report["graph"].is_null() {
+        walk(&report["graph"], 0, &mut out);
+    }
+
+    out
+}
+
+/// Returns the top-level fields of the report which are plain values (not objects or arrays).
+fn scalars(report: &Value) -> Vec<(&str, &Value)> {
+    match report.as_object() {
+        Some(map) => map
+            .iter()
+            .filter(|(_, v)| !v.is_object() && !v.is_array())
+            .map(|(k, v)| (k.as_str(), v))
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Flattens the process graph into a list of `(parent, node)` pairs, depth first.
+fn processes(report: &Value) -> Vec<(Option<&Value>, &Value)> {
+    fn walk<'a>(
+        parent: Option<&'a Value>,
+        node: &'a Value,
+        out: &mut Vec<(Option<&'a Value>, &'a Value)>,
+    ) {
+        out.push((parent, node));
+        if let Some(children) = node["children"].as_array() {
+            for child in children {
+                walk(Some(&node["id"]), child, out);
+            }
+        }
+    }
+
+    let mut out = vec![];
+    if !report["graph"].is_null() {
+        walk(None, &report["graph"], &mut out);
+    }
+
+    out
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub struct JsonWriter;
+
+impl ResultWriter for JsonWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        write!(out, "{}", report)?;
+        Ok(())
+    }
+}
+
+pub struct CsvWriter;
+
+impl CsvWriter {
+    fn escape(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl ResultWriter for CsvWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let fields = scalars(report);
+
+        let header = fields
+            .iter()
+            .map(|(k, _)| CsvWriter::escape(k))
+            .collect::<Vec<_>>()
+            .join(",");
+        let row = fields
+            .iter()
+            .map(|(_, v)| CsvWriter::escape(&display(v)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        writeln!(out, "{}\n{}", header, row)?;
+        Ok(())
+    }
+}
+
+pub struct YamlWriter;
+
+impl YamlWriter {
+    /// Keys are left bare unless YAML could read them as something else (e.g.: `a: b`, `#`, `-1`
+    /// or `null`), in which case they're quoted like string values are.
+    fn key(key: &str) -> String {
+        const RESERVED: &[&str] = &["null", "true", "false", "yes", "no", "on", "off", "y", "n"];
+        let bare = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            && !RESERVED.contains(&key.to_ascii_lowercase().as_str());
+        if bare {
+            key.to_string()
+        } else {
+            Value::from(key).to_string()
+        }
+    }
+
+    fn scalar(value: &Value) -> String {
+        match value {
+            Value::Null => "null".into(),
+            // JSON strings are valid YAML strings, so we can re-use its escaping rules
+            other => other.to_string(),
+        }
+    }
+
+    fn write_value(buf: &mut String, value: &Value, indent: usize) {
+        let pad = "  ".repeat(indent);
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (k, v) in map {
+                    let k = YamlWriter::key(k);
+                    match v {
+                        Value::Object(m) if !m.is_empty() => {
+                            let _ = writeln!(buf, "{}{}:", pad, k);
+                            YamlWriter::write_value(buf, v, indent + 1);
+                        }
+                        Value::Array(a) if !a.is_empty() => {
+                            let _ = writeln!(buf, "{}{}:", pad, k);
+                            YamlWriter::write_value(buf, v, indent);
+                        }
+                        _ => {
+                            let _ = writeln!(buf, "{}{}: {}", pad, k, YamlWriter::scalar(v));
+                        }
+                    }
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for item in items {
+                    match item {
+                        Value::Object(m) if !m.is_empty() => {
+                            // render the first line of the nested object on the same line as the dash
+                            let mut nested = String::new();
+                            YamlWriter::write_value(&mut nested, item, indent + 1);
+                            let nested = nested.trim_start();
+                            let _ = write!(buf, "{}- {}", pad, nested);
+                        }
+                        Value::Array(a) if !a.is_empty() => {
+                            let _ = writeln!(buf, "{}-", pad);
+                            YamlWriter::write_value(buf, item, indent + 1);
+                        }
+                        _ => {
+                            let _ = writeln!(buf, "{}- {}", pad, YamlWriter::scalar(item));
+                        }
+                    }
+                }
+            }
+            Value::Object(_) => {
+                let _ = writeln!(buf, "{}{{}}", pad);
+            }
+            Value::Array(_) => {
+                let _ = writeln!(buf, "{}[]", pad);
+            }
+            other => {
+                let _ = writeln!(buf, "{}{}", pad, YamlWriter::scalar(other));
+            }
+        }
+    }
+}
+
+impl ResultWriter for YamlWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let mut buf = String::new();
+        YamlWriter::write_value(&mut buf, report, 0);
+        write!(out, "---\n{}", buf)?;
+        Ok(())
+    }
+}
+
+/// Writes TOML, which has no null, so null values are left out.
+pub struct TomlWriter;
+
+impl TomlWriter {
+    fn key(key: &str) -> String {
+        let bare = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if bare {
+            key.to_string()
+        } else {
+            Value::from(key).to_string()
+        }
+    }
+
+    /// Whether the value must be written as an array of tables, rather than inline.
+    fn is_table_array(value: &Value) -> bool {
+        value
+            .as_array()
+            .is_some_and(|a| !a.is_empty() && a.iter().all(Value::is_object))
+    }
+
+    fn inline(value: &Value) -> String {
+        match value {
+            Value::Array(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .filter(|v| !v.is_null())
+                    .map(TomlWriter::inline)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Object(map) => format!(
+                "{{ {} }}",
+                map.iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| format!("{} = {}", TomlWriter::key(k), TomlWriter::inline(v)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            // JSON strings are valid TOML basic strings, so we can re-use its escaping rules
+            other => other.to_string(),
+        }
+    }
+
+    /// Writes a table's plain values first, since everything after a table header belongs to it,
+    /// then its tables and arrays of tables.
+    fn write_table(buf: &mut String, path: &str, map: &serde_json::Map<String, Value>) {
+        for (k, v) in map {
+            if !v.is_null() && !v.is_object() && !TomlWriter::is_table_array(v) {
+                let _ = writeln!(buf, "{} = {}", TomlWriter::key(k), TomlWriter::inline(v));
+            }
+        }
+
+        for (k, v) in map {
+            let path = match path {
+                "" => TomlWriter::key(k),
+                _ => format!("{}.{}", path, TomlWriter::key(k)),
+            };
+            if let Value::Object(m) = v {
+                let _ = writeln!(buf, "\n[{}]", path);
+                TomlWriter::write_table(buf, &path, m);
+            } else if TomlWriter::is_table_array(v) {
+                for item in v.as_array().into_iter().flatten() {
+                    let _ = writeln!(buf, "\n[[{}]]", path);
+                    if let Value::Object(m) = item {
+                        TomlWriter::write_table(buf, &path, m);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl ResultWriter for TomlWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let Value::Object(map) = report else {
+            bail!("only objects can be written as TOML");
+        };
+
+        let mut buf = String::new();
+        TomlWriter::write_table(&mut buf, "", map);
+        write!(out, "{}", buf)?;
+        Ok(())
+    }
+}
+
+pub struct MarkdownWriter;
+
+impl ResultWriter for MarkdownWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "| Field | Value |")?;
+        writeln!(out, "| --- | --- |")?;
+        for (k, v) in scalars(report) {
+            writeln!(out, "| {} | {} |", k, display(v))?;
+        }
+
+        let procs = processes(report);
+        if !procs.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "| PID | Parent | RSS |")?;
+            writeln!(out, "| --- | --- | --- |")?;
+            for (parent, node) in procs {
+                writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    display(&node["id"]),
+                    parent.map(display).unwrap_or_default(),
+                    display(&node["rss"])
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the numeric top-level fields of the report as metrics, with names prefixed by the name of
+/// this program (unless they already are). Booleans are reported as 0 or 1.
+fn metrics(report: &Value) -> Vec<(String, String)> {
+    scalars(report)
+        .into_iter()
+        .filter_map(|(k, v)| {
+            let value = match v {
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => (*b as u8).to_string(),
+                _ => return None,
+            };
+
+            let name = if k.starts_with(env!("CARGO_PKG_NAME")) {
+                k.to_string()
+            } else {
+                format!("{}_{}", env!("CARGO_PKG_NAME"), k)
+            };
+
+            Some((name, value))
+        })
+        .collect()
+}
+
+pub struct PrometheusWriter;
+
+impl PrometheusWriter {
+    /// The `--tag`s of the run as labels, e.g.: `branch="main",cc="clang"`.
+    fn tags(report: &Value) -> Vec<String> {
+        let escape = |s: &str| {
+            s.replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        };
+        let sanitise = |key: &str| {
+            key.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect::<String>()
+        };
+
+        match report["meta"]["tags"].as_object() {
+            Some(tags) => tags
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", sanitise(k), escape(&display(v))))
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+impl ResultWriter for PrometheusWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let tags = PrometheusWriter::tags(report);
+        let labels = |extra: Option<String>| {
+            let labels = extra.into_iter().chain(tags.clone()).collect::<Vec<_>>();
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels.join(","))
+            }
+        };
+
+        for (name, value) in metrics(report) {
+            writeln!(out, "# TYPE {} gauge", name)?;
+            writeln!(out, "{}{} {}", name, labels(None), value)?;
+        }
+
+        let processes = processes(report);
+        if !processes.is_empty() {
+            writeln!(out, "# TYPE {}_process_rss gauge", env!("CARGO_PKG_NAME"))?;
+        }
+        for (_, node) in processes {
+            writeln!(
+                out,
+                "{}_process_rss{} {}",
+                env!("CARGO_PKG_NAME"),
+                labels(Some(format!("pid=\"{}\"", display(&node["id"])))),
+                display(&node["rss"])
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the `metrics.txt` format consumed by GitLab's Metrics Reports, which is one `name value`
+/// pair per line. See: https://docs.gitlab.com/ee/ci/testing/metrics_reports.html
+pub struct GitlabWriter;
+
+impl ResultWriter for GitlabWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        for (name, value) in metrics(report) {
+            writeln!(out, "{} {}", name, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the process graph as a Graphviz digraph, with each process labelled by its name, pid and
+/// RSS, e.g.: `max_rss -O dot -o tree.dot -- make && dot -Tsvg tree.dot > tree.svg`
+pub struct DotWriter;
+
+impl DotWriter {
+    fn quote(value: &str) -> String {
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        format!("\"{}\"", escaped)
+    }
+}
+
+impl ResultWriter for DotWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let procs = processes(report);
+        if procs.is_empty() {
+            bail!("the dot format needs a process graph, which only the ptrace backend reports");
+        }
+
+        writeln!(out, "digraph {} {{", env!("CARGO_PKG_NAME"))?;
+        writeln!(out, "  node [shape=box];")?;
+        for (parent, node) in procs {
+            let id = display(&node["id"]);
+            let mut label = format!(
+                "{} ({})\n{}",
+                node["name"].as_str().unwrap_or("?"),
+                id,
+                crate::units::format_bytes(node["rss"].as_u64().unwrap_or(0))
+            );
+            if let Some(pids) = node["evicted"]["pids"].as_u64() {
+                let _ = write!(label, "\n+{} exited", pids);
+            }
+
+            writeln!(
+                out,
+                "  {} [label={}];",
+                DotWriter::quote(&id),
+                DotWriter::quote(&label)
+            )?;
+            if let Some(parent) = parent {
+                writeln!(
+                    out,
+                    "  {} -> {};",
+                    DotWriter::quote(&display(parent)),
+                    DotWriter::quote(&id)
+                )?;
+            }
+        }
+        writeln!(out, "}}")?;
+
+        Ok(())
+    }
+}
+
+/// Writes Bencher Metric Format, for tracking results over time with https://bencher.dev, e.g.:
+/// `bencher run --adapter json --file results.json 'max_rss -O bencher -o results.json -- make'`
+///
+/// The benchmark is named after COMMAND, and each measure has a fixed slug (`max-rss`, `max-uss`
+/// and `max-swap` in bytes, `total-pids`, and `wall-time` in milliseconds) so they line up from one
+/// run to the next. With `--runs`, `max-rss` is the mean with the min and max as its bounds.
+/// See: https://bencher.dev/docs/reference/bencher-metric-format/
+pub struct BencherWriter;
+
+impl BencherWriter {
+    const MEASURES: &'static [(&'static str, &'static str)] = &[
+        ("max-rss", "max_rss"),
+        ("max-uss", "max_uss"),
+        ("max-swap", "max_swap"),
+        ("total-pids", "total_pids"),
+        ("wall-time", "wall_time_ms"),
+    ];
+}
+
+impl ResultWriter for BencherWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        let mut measures = serde_json::Map::new();
+        for (slug, key) in BencherWriter::MEASURES {
+            if report[key].is_number() {
+                measures.insert(
+                    slug.to_string(),
+                    serde_json::json!({ "value": report[key] }),
+                );
+            }
+        }
+
+        let stats = &report["stats"];
+        if stats.is_object() {
+            measures.insert(
+                "max-rss".into(),
+                serde_json::json!({
+                    "value": stats["mean"],
+                    "lower_value": stats["min"],
+                    "upper_value": stats["max"],
+                }),
+            );
+        }
+
+        let name = report["command"].as_str().unwrap_or(env!("CARGO_PKG_NAME"));
+        serde_json::to_writer(&mut *out, &serde_json::json!({ name: measures }))?;
+        writeln!(out)?;
+
+        Ok(())
+    }
+}
+
+/// SQLite databases are written in place rather than streamed, see [`write_file`].
+pub struct SqliteWriter;
+
+impl ResultWriter for SqliteWriter {
+    fn write(&self, _report: &Value, _out: &mut dyn Write) -> Result<()> {
+        bail!("the sqlite format can only be written to a file, see --output")
+    }
+}
+
+pub struct HtmlWriter;
+
+impl ResultWriter for HtmlWriter {
+    fn write(&self, report: &Value, out: &mut dyn Write) -> Result<()> {
+        // only comparisons have a visual representation
+        if report.get("old").is_none() || report.get("new").is_none() {
+            bail!("the html format is only supported by the compare subcommand");
+        }
+
+        write!(out, "{}", crate::compare::render_html(report))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn render(format: OutputFormat, report: &Value) -> String {
+        let mut buf = vec![];
+        format.writer().write(report, &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    fn report() -> Value {
+        json!({
+            "max_rss": 2048,
+            "total_pids": 2,
+            "exit_code": null,
+            "graph": {
+                "id": 1,
+                "rss": 1024,
+                "children": [{ "id": 2, "rss": 1024, "children": null }]
+            }
+        })
+    }
+
+    #[test]
+    fn write() {
+        let dir = std::env::temp_dir().join(format!("max_rss-output-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("out.json");
+        fs::write(&path, "old").unwrap();
+        write_file(&path, OutputFormat::Json, &report()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), report().to_string());
+
+        // no temporary files or lockfiles should be left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append() {
+        let dir = std::env::temp_dir().join(format!("max_rss-append-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+
+        // a file written before --append was used becomes the first run
+        let path = dir.join("runs.json");
+        write_file(&path, OutputFormat::Json, &json!({ "max_rss": 1 })).unwrap();
+        append_file(&path, &json!({ "max_rss": 2 }), Some("t".into())).unwrap();
+        append_file(&path, &json!({ "max_rss": 3 }), None).unwrap();
+        let runs = serde_json::from_str::<Value>(&read("runs.json")).unwrap();
+        assert_eq!(runs[0], json!({ "max_rss": 1 }));
+        assert_eq!(
+            runs[1],
+            json!({ "max_rss": 2, "run_id": 2, "timestamp": "t" })
+        );
+        assert_eq!(runs[2]["run_id"], 3);
+
+        let path = dir.join("runs.jsonl");
+        append_file(&path, &json!({ "max_rss": 1 }), None).unwrap();
+        append_file(&path, &json!({ "max_rss": 2 }), None).unwrap();
+        let lines = read("runs.jsonl");
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            serde_json::from_str::<Value>(lines[1]).unwrap()["run_id"],
+            2
+        );
+
+        let path = dir.join("broken.json");
+        fs::write(&path, "{").unwrap();
+        assert!(append_file(&path, &json!({ "max_rss": 1 }), None).is_err());
+
+        // no temporary files or lockfiles should be left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn template() {
+        let lookup = |name: &str| (name == "x").then(|| "1".to_string());
+        assert_eq!(expand_template("a{x}b", lookup).unwrap(), "a1b");
+        assert_eq!(expand_template("{{x}}", lookup).unwrap(), "{x}");
+        assert_eq!(expand_template("{x}{x}", lookup).unwrap(), "11");
+        assert!(expand_template("{y}", lookup).is_err());
+    }
+
+    #[test]
+    fn resolve() {
+        let dir = std::env::temp_dir().join(format!("max_rss-resolve-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = resolve_path(&dir.join("plain.json"), "ls").unwrap();
+        assert_eq!(path, dir.join("plain.json"));
+
+        let path = resolve_path(&dir.join("{cmd}-{n}.json"), "ls").unwrap();
+        assert_eq!(path, dir.join("ls-1.json"));
+        assert!(path.exists());
+        let path = resolve_path(&dir.join("{cmd}-{n}.json"), "ls").unwrap();
+        assert_eq!(path, dir.join("ls-2.json"));
+
+        // instances running at the same time each claim a path of their own
+        let template = dir.join("parallel-{n}.json");
+        let mut paths = thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|_| scope.spawn(|| resolve_path(&template, "ls").unwrap()))
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 8);
+
+        let path = resolve_path(&dir.join("{date}.json"), "ls").unwrap();
+        assert_eq!(
+            path.file_name().unwrap().len(),
+            "20240102T030405Z.json".len()
+        );
+
+        assert!(resolve_path(&dir.join("{nope}.json"), "ls").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn locks() {
+        let dir = std::env::temp_dir().join(format!("max_rss-locks-{}", process::id()));
+        let (a, b) = (dir.join("a.json"), dir.join("b.json"));
+        let lock = lock_file(&a).unwrap();
+        assert!(dir.join("a.json.lock").exists());
+
+        // only writers of the same file wait for each other
+        let try_lock = |path: &Path| {
+            let file = File::open(lock_path(path).unwrap()).unwrap();
+            flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock)
+        };
+        drop(lock_file(&b).unwrap());
+        assert!(!dir.join("b.json.lock").exists());
+        assert_eq!(try_lock(&a), Err(Errno::EWOULDBLOCK));
+
+        // a writer still waiting when the lockfile is removed takes a new one
+        let waiting = thread::spawn(move || {
+            let lock = lock_file(&a).unwrap();
+            assert!(lock.path.exists());
+        });
+        thread::sleep(Duration::from_millis(50));
+        drop(lock);
+        waiting.join().unwrap();
+        assert!(!dir.join("a.json.lock").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn special() {
+        use std::os::unix::fs::FileTypeExt;
+
+        // devices are written to as they are, rather than replaced
+        let path = Path::new("/dev/null");
+        write_file(path, OutputFormat::Json, &report()).unwrap();
+        append_file(path, &report(), None).unwrap();
+        assert!(fs::metadata(path).unwrap().file_type().is_char_device());
+        assert!(!Path::new("/dev/null.lock").exists());
+    }
+
+    #[test]
+    fn gitlab() {
+        let mut report = report();
+        report["over_threshold"] = true.into();
+        assert_eq!(
+            render(OutputFormat::Gitlab, &report),
+            "max_rss 2048\nmax_rss_over_threshold 1\nmax_rss_total_pids 2\n"
+        );
+    }
+
+    #[test]
+    fn bencher() {
+        let mut report = report();
+        report["command"] = "ls".into();
+        assert_eq!(
+            render(OutputFormat::Bencher, &report),
+            "{\"ls\":{\"max-rss\":{\"value\":2048},\"total-pids\":{\"value\":2}}}\n"
+        );
+
+        report["stats"] = json!({ "min": 1024.0, "max": 4096.0, "mean": 2048.0 });
+        let rendered = serde_json::from_str::<Value>(&render(OutputFormat::Bencher, &report));
+        assert_eq!(
+            rendered.unwrap()["ls"]["max-rss"],
+            json!({ "value": 2048.0, "lower_value": 1024.0, "upper_value": 4096.0 })
+        );
+    }
+
+    #[test]
+    fn trees() {
+        let mut report = report();
+        report["graph"]["name"] = "sh".into();
+        assert_eq!(tree(&report), "sh (1): 1 KiB\n  ? (2): 1 KiB\n");
+        report["graph"]["children"][0]["thread"] = true.into();
+        assert!(tree(&report).ends_with("  ? (2, thread): 1 KiB\n"));
+        report["graph"]["excluded"] = true.into();
+        assert!(tree(&report).starts_with("sh (1, excluded): 1 KiB\n"));
+        report["graph"]["excluded"] = Value::Null;
+
+        report["graph"]["exit_status"] = json!({ "exited": 0 });
+        report["graph"]["children"][0]["exit_status"] = json!({ "signaled": "SIGSEGV" });
+        assert_eq!(
+            tree(&report),
+            "sh (1): 1 KiB\n  ? (2, thread): 1 KiB, killed by SIGSEGV\n"
+        );
+        assert_eq!(tree(&json!({ "max_rss": 1 })), "");
+    }
+
+    #[test]
+    fn badges() {
+        let mib = 1024 * 1024;
+        assert_eq!(
+            badge(412 * mib, None),
+            json!({ "schemaVersion": 1, "label": "max RSS", "message": "412 MiB", "color": "blue" })
+        );
+        assert_eq!(badge(412 * mib, Some(500 * mib))["color"], "green");
+        assert_eq!(badge(412 * mib, Some(400 * mib))["color"], "red");
+    }
+
+    #[test]
+    fn summaries() {
+        let report = json!({
+            "max_rss": 412 * 1024 * 1024,
+            "total_pids": 3,
+            "wall_time_ms": 1234.5,
+            "utime_ms": 330,
+            "stime_ms": 10,
+            "minor_faults": 204,
+            "major_faults": 0,
+            "exit_code": null,
+            "timed_out": false,
+        });
+        assert_eq!(
+            summary(&report),
+            "\
+max rss:     412 MiB (432013312 bytes)
+processes:   3
+wall time:   1.23s
+user time:   0.33s
+system time: 0.01s
+page faults: 204 minor, 0 major
+"
+        );
+
+        let report = json!({ "max_rss": 2048, "total_processes": 1, "total_threads": 4 });
+        assert_eq!(
+            summary(&report),
+            "max rss:     2 KiB (2048 bytes)\nprocesses:   1\nthreads:     4\n"
+        );
+
+        let report =
+            json!({ "max_rss": 2048, "threshold": 1024, "over_threshold": true, "exit_code": 1 });
+        assert_eq!(
+            summary(&report),
+            "max rss:     2 KiB (2048 bytes)\nthreshold:   1 KiB (exceeded)\nexit code:   1\n"
+        );
+
+        let report = json!({ "max_rss": 2048, "exit_code": null, "exit_signal": 9 });
+        assert_eq!(
+            summary(&report),
+            "max rss:     2 KiB (2048 bytes)\nexit signal: 9\n"
+        );
+    }
+
+    #[test]
+    fn dot() {
+        let mut report = report();
+        report["graph"]["name"] = "s\"h".into();
+        report["graph"]["children"][0]["evicted"] = json!({ "pids": 3 });
+        assert_eq!(
+            render(OutputFormat::Dot, &report),
+            r#"digraph max_rss {
+  node [shape=box];
+  "1" [label="s\"h (1)\n1 KiB"];
+  "2" [label="? (2)\n1 KiB\n+3 exited"];
+  "1" -> "2";
+}
+"#
+        );
+
+        let mut buf = vec![];
+        assert!(DotWriter
+            .write(&json!({ "max_rss": 1, "graph": null }), &mut buf)
+            .is_err());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!(
+            "md".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Markdown
+        );
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            render(OutputFormat::Csv, &report()),
+            "exit_code,max_rss,total_pids\n,2048,2\n"
+        );
+    }
+
+    #[test]
+    fn yaml() {
+        assert_eq!(
+            render(OutputFormat::Yaml, &report()),
+            r#"---
+exit_code: null
+graph:
+  children:
+  - children: null
+    id: 2
+    rss: 1024
+  id: 1
+  rss: 1024
+max_rss: 2048
+total_pids: 2
+"#
+        );
+
+        // keys which would be read as something else are quoted
+        let report = json!({ "tags": { "a: b": 1, "#c": 2, "-1": 3, "null": 4, "d-e": 5 } });
+        assert_eq!(
+            render(OutputFormat::Yaml, &report),
+            r##"---
+tags:
+  "#c": 2
+  "-1": 3
+  "a: b": 1
+  d-e: 5
+  "null": 4
+"##
+        );
+    }
+
+    #[test]
+    fn toml() {
+        let mut report = report();
+        report["environment"] = json!({ "kernel": "6.1", "limits": { "memory": 1024 } });
+        report["setup"] = json!(["make", null]);
+        assert_eq!(
+            render(OutputFormat::Toml, &report),
+            r#"max_rss = 2048
+setup = ["make"]
+total_pids = 2
+
+[environment]
+kernel = "6.1"
+
+[environment.limits]
+memory = 1024
+
+[graph]
+id = 1
+rss = 1024
+
+[[graph.children]]
+id = 2
+rss = 1024
+"#
+        );
+    }
+
+    #[test]
+    fn prometheus() {
+        assert_eq!(
+            render(OutputFormat::Prometheus, &report()),
+            r#"# TYPE max_rss gauge
+max_rss 2048
+# TYPE max_rss_total_pids gauge
+max_rss_total_pids 2
+# TYPE max_rss_process_rss gauge
+max_rss_process_rss{pid="1"} 1024
+max_rss_process_rss{pid="2"} 1024
+"#
+        );
+    }
+
+    #[test]
+    fn prometheus_tags() {
+        let mut report = json!({ "max_rss": 2048 });
+        report["meta"] = json!({ "tags": { "branch": "ma\"in", "cc-name": "clang" } });
+        assert_eq!(
+            render(OutputFormat::Prometheus, &report),
+            "# TYPE max_rss gauge\nmax_rss{branch=\"ma\\\"in\",cc_name=\"clang\"} 2048\n"
+        );
+    }
+}
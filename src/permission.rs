@@ -0,0 +1,281 @@
+//! Explains why a process couldn't be traced, since all ptrace itself says is EPERM, whichever of
+//! the many things that restrict it was responsible.
+
+use std::fmt;
+use std::fs;
+
+use anyhow::Result;
+use nix::errno::Errno;
+use nix::sys::signal::{kill, Signal};
+use nix::sys::wait::waitpid;
+use nix::unistd::Pid;
+
+use crate::child::Trace;
+use crate::platform;
+use crate::report::{Report, SCHEMA_VERSION};
+
+/// The capability which lets a process trace others whatever `kernel.yama.ptrace_scope` is.
+const CAP_SYS_PTRACE: u32 = 19;
+
+/// What is known to restrict ptrace on this system, read when tracing wasn't permitted.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Restrictions {
+    /// The value of `kernel.yama.ptrace_scope`, if Yama is enabled.
+    ptrace_scope: Option<u32>,
+    /// Whether we have CAP_SYS_PTRACE.
+    cap_sys_ptrace: bool,
+    /// Whether we're running under a seccomp filter, which may not allow ptrace.
+    seccomp: bool,
+    /// The container we're running in, if any.
+    container: Option<&'static str>,
+    /// What's already tracing the process, since a process can only have one tracer.
+    tracer: Option<i32>,
+}
+
+impl Restrictions {
+    fn read(pid: Pid) -> Restrictions {
+        let ptrace_scope = fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope")
+            .ok()
+            .and_then(|text| text.trim().parse().ok());
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let tracer = fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+
+        Restrictions {
+            ptrace_scope,
+            cap_sys_ptrace: status_field(&status, "CapEff:")
+                .and_then(|caps| u64::from_str_radix(caps, 16).ok())
+                .is_some_and(|caps| caps & (1 << CAP_SYS_PTRACE) != 0),
+            seccomp: status_field(&status, "Seccomp:") == Some("2"),
+            container: platform::container(),
+            tracer: status_field(&tracer, "TracerPid:")
+                .and_then(|pid| pid.parse().ok())
+                .filter(|pid| *pid != 0),
+        }
+    }
+
+    /// Picks the most likely reason tracing wasn't permitted. Only processes which aren't our own
+    /// descendants are restricted by `ptrace_scope` 1.
+    fn cause(&self, descendant: bool) -> Cause {
+        match (self.ptrace_scope, self.tracer) {
+            (_, Some(tracer)) => Cause::AlreadyTraced(tracer),
+            (Some(3), _) => Cause::YamaDisabled,
+            (Some(2), _) if !self.cap_sys_ptrace => Cause::YamaAdminOnly,
+            (Some(1), _) if !self.cap_sys_ptrace && !descendant => Cause::YamaDescendantsOnly,
+            _ if self.seccomp => Cause::Seccomp,
+            _ => match self.container {
+                Some(container) => Cause::Container(container),
+                None => Cause::Unknown,
+            },
+        }
+    }
+}
+
+/// Finds the line starting with `field` in a `/proc/<pid>/status` file, and returns its value.
+fn status_field<'a>(status: &'a str, field: &str) -> Option<&'a str> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field))
+        .map(str::trim)
+}
+
+/// Why tracing wasn't permitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cause {
+    /// The process already has a tracer, e.g.: a debugger, or itself with PTRACE_TRACEME (which
+    /// some programs do to deter debuggers).
+    AlreadyTraced(i32),
+    /// `kernel.yama.ptrace_scope` is 3, which disables ptrace entirely.
+    YamaDisabled,
+    /// `kernel.yama.ptrace_scope` is 2, which only allows processes with CAP_SYS_PTRACE to trace.
+    YamaAdminOnly,
+    /// `kernel.yama.ptrace_scope` is 1, which only allows processes to trace their descendants.
+    YamaDescendantsOnly,
+    /// A seccomp filter (such as a container runtime's default profile) doesn't allow ptrace.
+    Seccomp,
+    /// We're in a container, which likely doesn't allow ptrace without CAP_SYS_PTRACE.
+    Container(&'static str),
+    Unknown,
+}
+
+impl Cause {
+    /// How the cause is reported in `error_cause`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Cause::AlreadyTraced(_) => "already_traced",
+            Cause::YamaDisabled => "yama_disabled",
+            Cause::YamaAdminOnly => "yama_admin_only",
+            Cause::YamaDescendantsOnly => "yama_descendants_only",
+            Cause::Seccomp => "seccomp",
+            Cause::Container(_) => "container",
+            Cause::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cause::AlreadyTraced(tracer) => write!(
+                f,
+                "it's already being traced by {}, and a process can only have one tracer",
+                tracer
+            ),
+            Cause::YamaDisabled => write!(
+                f,
+                "kernel.yama.ptrace_scope is 3, which disables ptrace until the next reboot"
+            ),
+            Cause::YamaAdminOnly => write!(
+                f,
+                "kernel.yama.ptrace_scope is 2, which only allows processes with CAP_SYS_PTRACE \
+                to use ptrace"
+            ),
+            Cause::YamaDescendantsOnly => write!(
+                f,
+                "kernel.yama.ptrace_scope is 1, which only allows processes to trace their own \
+                descendants (unless they have CAP_SYS_PTRACE)"
+            ),
+            Cause::Seccomp => write!(
+                f,
+                "we're running under a seccomp filter which doesn't allow ptrace, such as a \
+                container runtime's default profile"
+            ),
+            Cause::Container(container) => write!(
+                f,
+                "we're running in a {} container, which likely doesn't allow ptrace without \
+                CAP_SYS_PTRACE (e.g.: `--cap-add SYS_PTRACE`)",
+                container
+            ),
+            Cause::Unknown => write!(f, "the reason is unknown"),
+        }
+    }
+}
+
+/// Tracing wasn't permitted by the system, so nothing could be measured.
+#[derive(Debug)]
+pub struct TraceDenied {
+    pid: Pid,
+    errno: Errno,
+    /// Whether the process was started by us, rather than attached to with `--pid`.
+    started: bool,
+    pub cause: Cause,
+}
+
+impl TraceDenied {
+    /// Works out why tracing `pid` failed with `errno`. `started` is whether it's the COMMAND we
+    /// started ourselves, rather than one attached to with `--pid`.
+    pub fn new(pid: Pid, errno: Errno, started: bool) -> TraceDenied {
+        TraceDenied {
+            pid,
+            errno,
+            started,
+            cause: Restrictions::read(pid).cause(started),
+        }
+    }
+
+    /// Kills the child before it can run COMMAND (since it couldn't be measured), and returns the
+    /// results of a COMMAND which never started.
+    pub fn finish(self, child: Pid) -> Result<Trace> {
+        let _ = kill(child, Signal::SIGKILL);
+        let _ = waitpid(child, None);
+
+        let report = Report {
+            schema_version: Some(SCHEMA_VERSION),
+            error: Some(self.to_string()),
+            error_cause: Some(self.cause.name().into()),
+            ..Report::default()
+        };
+
+        Ok(Trace {
+            report: serde_json::to_value(report)?,
+            exit_code: 1,
+            snapshots: Default::default(),
+            error: Some(self.into()),
+        })
+    }
+}
+
+impl fmt::Display for TraceDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "not permitted to trace {} ({}): {}",
+            self.pid,
+            self.errno.desc().to_lowercase(),
+            self.cause
+        )?;
+
+        // the other backends don't use ptrace at all, but can only measure processes they start
+        if self.started {
+            write!(
+                f,
+                "; `--backend cgroup` or `--backend rusage` can measure COMMAND without ptrace"
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for TraceDenied {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn causes() {
+        let restrictions = Restrictions::default();
+        assert_eq!(restrictions.cause(true), Cause::Unknown);
+
+        let scope = |ptrace_scope, cap_sys_ptrace| Restrictions {
+            ptrace_scope: Some(ptrace_scope),
+            cap_sys_ptrace,
+            ..Restrictions::default()
+        };
+        assert_eq!(scope(3, true).cause(true), Cause::YamaDisabled);
+        assert_eq!(scope(2, false).cause(true), Cause::YamaAdminOnly);
+        assert_eq!(scope(2, true).cause(true), Cause::Unknown);
+        assert_eq!(scope(1, false).cause(false), Cause::YamaDescendantsOnly);
+        assert_eq!(scope(1, false).cause(true), Cause::Unknown);
+
+        // a tracer is the reason whatever else applies
+        let traced = Restrictions {
+            tracer: Some(42),
+            ..scope(3, false)
+        };
+        assert_eq!(traced.cause(true), Cause::AlreadyTraced(42));
+
+        let contained = Restrictions {
+            container: Some("docker"),
+            ..scope(1, false)
+        };
+        assert_eq!(contained.cause(true), Cause::Container("docker"));
+        let filtered = Restrictions {
+            seccomp: true,
+            ..contained
+        };
+        assert_eq!(filtered.cause(true), Cause::Seccomp);
+    }
+
+    #[test]
+    fn messages() {
+        let denied = TraceDenied {
+            pid: Pid::from_raw(42),
+            errno: Errno::EPERM,
+            started: true,
+            cause: Cause::YamaDisabled,
+        };
+        assert_eq!(
+            denied.to_string(),
+            "not permitted to trace 42 (operation not permitted): kernel.yama.ptrace_scope is 3, \
+            which disables ptrace until the next reboot; `--backend cgroup` or `--backend rusage` \
+            can measure COMMAND without ptrace"
+        );
+
+        let attached = TraceDenied {
+            started: false,
+            ..denied
+        };
+        assert!(!attached.to_string().contains("--backend"));
+    }
+}
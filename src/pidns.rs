@@ -0,0 +1,79 @@
+//! Runs COMMAND in a new PID namespace (see `--pidns`), so that nothing it runs can escape being
+//! measured: when COMMAND (PID 1 of the namespace) exits, the kernel kills everything left in it.
+
+use std::convert::Infallible;
+use std::fs;
+
+use anyhow::{Context, Result};
+use nix::mount::{mount, MsFlags};
+use nix::sched::{clone, CloneFlags};
+use nix::sys::signal::Signal;
+use nix::unistd::{getgid, getuid, Gid, Pid, Uid};
+
+/// The stack the child runs on until it execs COMMAND, which needs only a little of it.
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Starts `child` in a new PID namespace, and returns its pid (as we see it). If `child` fails, the
+/// error is printed and it exits with `exit_code`, since it can't be returned to our caller as it
+/// would be after `fork`.
+///
+/// Unless we're root this also needs a new user namespace, in which COMMAND keeps the same uid and
+/// gid it would otherwise have had.
+pub fn spawn(mut child: impl FnMut() -> Result<Infallible>, exit_code: i32) -> Result<Pid> {
+    let (uid, gid) = (getuid(), getgid());
+    let mut flags = CloneFlags::CLONE_NEWPID | CloneFlags::CLONE_NEWNS;
+    if !uid.is_root() {
+        flags |= CloneFlags::CLONE_NEWUSER;
+    }
+
+    let mut stack = vec![0; STACK_SIZE];
+    let run = Box::new(|| {
+        // without this COMMAND would run as the overflow uid, i.e.: `nobody`
+        let mapped = match flags.contains(CloneFlags::CLONE_NEWUSER) {
+            true => map_ids(uid, gid),
+            false => Ok(()),
+        };
+        mount_proc();
+        match mapped.and_then(|_| child()) {
+            Ok(never) => match never {},
+            Err(e) => {
+                eprintln!("Error: {:?}", e);
+                exit_code as isize
+            }
+        }
+    });
+
+    // SAFETY: the child runs on its own copy of our memory (since CLONE_VM isn't set), just like
+    // after fork, and only until it execs or exits
+    unsafe { clone(run, &mut stack, flags, Some(Signal::SIGCHLD as i32)) }.context(
+        "failed to create a PID namespace for --pidns (unprivileged user namespaces may be \
+        disabled, or not permitted in this container)",
+    )
+}
+
+/// Maps our uid and gid into the new user namespace. Must be called in the child.
+fn map_ids(uid: Uid, gid: Gid) -> Result<()> {
+    // unprivileged processes must give up setgroups before they can map their gid
+    fs::write("/proc/self/setgroups", "deny")?;
+    fs::write("/proc/self/uid_map", format!("{uid} {uid} 1"))?;
+    fs::write("/proc/self/gid_map", format!("{gid} {gid} 1"))?;
+    Ok(())
+}
+
+/// Mounts a /proc for the new PID namespace, so tools like `ps` see its processes rather than
+/// ours. This is best-effort, since /proc can't be remounted in some containers. Must be called in
+/// the child.
+fn mount_proc() {
+    const NONE: Option<&str> = None;
+
+    // so the mount isn't propagated back to our namespace
+    if mount(NONE, "/", NONE, MsFlags::MS_REC | MsFlags::MS_PRIVATE, NONE).is_ok() {
+        let _ = mount(
+            Some("proc"),
+            "/proc",
+            Some("proc"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+            NONE,
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Detects whether we're running inside a container or virtual machine, and what memory limit is in
+//! effect, so that results from constrained environments aren't mistaken for bare metal ones.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::units::format_bytes;
+
+/// Warn when the measured peak is more than this fraction of the effective memory limit.
+const SAFE_FRACTION: f64 = 0.8;
+
+/// Guesses the container runtime from marker files and the process' cgroup.
+fn detect_container(cgroup: &str) -> Option<&'static str> {
+    if Path::new("/.dockerenv").exists() {
+        return Some("docker");
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some("podman");
+    }
+
+    container_from_cgroup(cgroup)
+}
+
+fn container_from_cgroup(cgroup: &str) -> Option<&'static str> {
+    [
+        ("kubepods", "kubernetes"),
+        ("docker", "docker"),
+        ("libpod", "podman"),
+        ("lxc", "lxc"),
+        ("containerd", "containerd"),
+    ]
+    .into_iter()
+    .find_map(|(needle, name)| cgroup.contains(needle).then_some(name))
+}
+
+/// Guesses the container runtime we're running in, if any.
+pub fn container() -> Option<&'static str> {
+    detect_container(&fs::read_to_string("/proc/self/cgroup").unwrap_or_default())
+}
+
+/// Guesses the hypervisor from the firmware's vendor, falling back to the CPU's hypervisor flag.
+fn detect_vm() -> Option<String> {
+    let vendor = fs::read_to_string("/sys/class/dmi/id/sys_vendor").unwrap_or_default();
+    if let Some(name) = vm_from_vendor(&vendor) {
+        return Some(name.into());
+    }
+
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    cpuinfo
+        .lines()
+        .filter(|l| l.starts_with("flags"))
+        .any(|l| l.split_ascii_whitespace().any(|f| f == "hypervisor"))
+        .then(|| "unknown".into())
+}
+
+fn vm_from_vendor(vendor: &str) -> Option<&'static str> {
+    [
+        ("QEMU", "qemu"),
+        ("VMware", "vmware"),
+        ("innotek", "virtualbox"),
+        ("Xen", "xen"),
+        ("Microsoft Corporation", "hyper-v"),
+        ("Amazon EC2", "aws"),
+        ("Google", "gce"),
+    ]
+    .into_iter()
+    .find_map(|(needle, name)| vendor.contains(needle).then_some(name))
+}
+
+/// Returns the path of the process' cgroup v2 group, from `/proc/self/cgroup`.
+fn cgroup_v2_path(cgroup: &str) -> Option<&str> {
+    cgroup.lines().find_map(|l| l.strip_prefix("0::"))
+}
+
+/// Parses a cgroup memory limit, where `max` (or a huge v1 value) means unlimited.
+fn parse_limit(text: &str) -> Option<u64> {
+    text.trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|limit| *limit < i64::MAX as u64 / 2)
+}
+
+/// Finds the tightest cgroup memory limit that applies to us, checking each ancestor group too.
+fn cgroup_memory_limit(cgroup: &str) -> Option<u64> {
+    if let Some(path) = cgroup_v2_path(cgroup) {
+        return Path::new("/sys/fs/cgroup")
+            .join(path.trim_start_matches('/'))
+            .ancestors()
+            .take_while(|p| p.starts_with("/sys/fs/cgroup"))
+            .filter_map(|p| fs::read_to_string(p.join("memory.max")).ok())
+            .filter_map(|text| parse_limit(&text))
+            .min();
+    }
+
+    fs::read_to_string("/sys/fs/cgroup/memory/memory.limit_in_bytes")
+        .ok()
+        .and_then(|text| parse_limit(&text))
+}
+
+fn mem_total() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let kb = meminfo
+        .lines()
+        .find(|l| l.starts_with("MemTotal:"))?
+        .split_ascii_whitespace()
+        .nth(1)?
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kb * 1024)
+}
+
+/// Describes the environment COMMAND ran in.
+pub fn detect() -> Value {
+    let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let cgroup_limit = cgroup_memory_limit(&cgroup);
+    let mem_total = mem_total();
+
+    json!({
+        "container": detect_container(&cgroup),
+        "virtualization": detect_vm(),
+        "mem_total": mem_total,
+        "cgroup_memory_limit": cgroup_limit,
+        "memory_limit": cgroup_limit.into_iter().chain(mem_total).min(),
+    })
+}
+
+/// The size of a page of memory, which the kernel counts memory in.
+pub fn page_size() -> u64 {
+    // SAFETY: sysconf has no preconditions
+    match unsafe { nix::libc::sysconf(nix::libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
+
+/// Reads a value from `/proc/sys/kernel`, such as `hostname` or `osrelease`.
+fn kernel_value(name: &str) -> Option<String> {
+    let text = fs::read_to_string(Path::new("/proc/sys/kernel").join(name)).ok()?;
+    Some(text.trim().to_string())
+}
+
+/// Describes the machine COMMAND ran on, so results can be told apart when they're collected from
+/// several.
+pub fn host() -> Value {
+    json!({
+        "hostname": kernel_value("hostname"),
+        "kernel": kernel_value("osrelease"),
+        "page_size": page_size(),
+    })
+}
+
+/// Warns when the measured peak leaves little headroom under the effective memory limit, since
+/// the COMMAND may have been constrained (e.g.: by reclaim or swapping) in ways bare metal isn't.
+pub fn warn_headroom(max_rss: u64, environment: &Value) {
+    let Some(limit) = environment["memory_limit"].as_u64() else {
+        return;
+    };
+
+    if max_rss as f64 > limit as f64 * SAFE_FRACTION {
+        eprintln!(
+            "{}: warning: max_rss of {} is close to the effective memory limit of {}, results may be affected",
+            env!("CARGO_PKG_NAME"),
+            format_bytes(max_rss),
+            format_bytes(limit)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containers() {
+        assert_eq!(
+            container_from_cgroup("0::/kubepods.slice/kubepods-pod1.slice/cri-containerd-a.scope"),
+            Some("kubernetes")
+        );
+        assert_eq!(
+            container_from_cgroup("0::/system.slice/docker-abc.scope"),
+            Some("docker")
+        );
+        assert_eq!(
+            container_from_cgroup("0::/user.slice/user-1000.slice"),
+            None
+        );
+    }
+
+    #[test]
+    fn vms() {
+        assert_eq!(vm_from_vendor("QEMU\n"), Some("qemu"));
+        assert_eq!(vm_from_vendor("Dell Inc.\n"), None);
+    }
+
+    #[test]
+    fn cgroups() {
+        assert_eq!(cgroup_v2_path("0::/user.slice\n"), Some("/user.slice"));
+        assert_eq!(cgroup_v2_path("4:memory:/docker/abc\n"), None);
+        assert_eq!(parse_limit("max\n"), None);
+        assert_eq!(parse_limit("9223372036854771712\n"), None);
+        assert_eq!(parse_limit("536870912\n"), Some(512 << 20));
+    }
+
+    #[test]
+    fn hosts() {
+        let host = host();
+        assert!(!host["hostname"].as_str().unwrap().is_empty());
+        assert!(host["kernel"].as_str().unwrap().contains('.'));
+        assert!(host["page_size"].as_u64().unwrap().is_power_of_two());
+    }
+
+    #[test]
+    fn environment() {
+        let environment = detect();
+        assert!(environment["mem_total"].as_u64().unwrap() > 0);
+        assert!(environment["memory_limit"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,249 @@
+//! Which processes' memory is added up into max_rss, and how (see `--count-policy`), and where it's
+//! read from (see `--source` and `--mem-source`).
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+use nix::unistd::Pid;
+use regex::Regex;
+
+/// Which processes count towards the total, and which of their values is summed. Different
+/// workloads suit different policies: a server which forks workers shares most of its memory with
+/// them, while a build system execs processes which share nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CountPolicy {
+    /// The RSS of the root, every process which spawned others and every process which exec'd, each
+    /// taken as it exits.
+    #[default]
+    RootAndParents,
+    /// The RSS of every process, each taken as it exits.
+    All,
+    /// The PSS of every process, each taken as it exits.
+    PssSum,
+    /// The largest total PSS of the processes which were alive at the same time, sampled while
+    /// they run.
+    ConcurrentPeak,
+}
+
+impl CountPolicy {
+    pub const ALL: &'static [(&'static str, CountPolicy)] = &[
+        ("root-and-parents", CountPolicy::RootAndParents),
+        ("all", CountPolicy::All),
+        ("pss-sum", CountPolicy::PssSum),
+        ("concurrent-peak", CountPolicy::ConcurrentPeak),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        CountPolicy::ALL
+            .iter()
+            .find_map(|(name, policy)| (policy == self).then_some(*name))
+            .expect("unnamed policy")
+    }
+}
+
+impl FromStr for CountPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CountPolicy::ALL
+            .iter()
+            .find_map(|(name, policy)| (*name == s).then_some(*policy))
+            .ok_or_else(|| anyhow!("unknown --count-policy: {}", s))
+    }
+}
+
+/// Which file in `/proc/<pid>` the RSS of each process is read from (see `--mem-source`). They all
+/// count the same resident pages, but take the kernel very different amounts of work to produce.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MemSource {
+    /// `smaps_rollup`, which walks the process' page tables, so is exact.
+    #[default]
+    SmapsRollup,
+    /// `smaps`, which does the same but breaks it down by mapping, so is the slowest to read.
+    Smaps,
+    /// `statm`, which is read from counters the kernel keeps as it goes, so is by far the cheapest,
+    /// but may lag behind by a few pages for each thread.
+    Statm,
+    /// `VmRSS` in `status`, from the same counters as `statm`, alongside much else.
+    Status,
+}
+
+impl MemSource {
+    pub const ALL: &'static [(&'static str, MemSource)] = &[
+        ("smaps_rollup", MemSource::SmapsRollup),
+        ("smaps", MemSource::Smaps),
+        ("statm", MemSource::Statm),
+        ("status", MemSource::Status),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        MemSource::ALL
+            .iter()
+            .find_map(|(name, source)| (source == self).then_some(*name))
+            .expect("unnamed mem source")
+    }
+}
+
+impl FromStr for MemSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MemSource::ALL
+            .iter()
+            .find_map(|(name, source)| (*name == s).then_some(*source))
+            .ok_or_else(|| anyhow!("unknown --mem-source: {}", s))
+    }
+}
+
+/// Where the value of each process' RSS is taken from when it exits.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RssSource {
+    /// The `Rss` in `smaps_rollup`, which is what's left just before the process exits.
+    #[default]
+    Rss,
+    /// The kernel's own high-water mark, `VmHWM` in `status`.
+    Hwm,
+}
+
+impl RssSource {
+    pub const ALL: &'static [(&'static str, RssSource)] =
+        &[("rss", RssSource::Rss), ("hwm", RssSource::Hwm)];
+
+    pub fn name(&self) -> &'static str {
+        RssSource::ALL
+            .iter()
+            .find_map(|(name, source)| (source == self).then_some(*name))
+            .expect("unnamed source")
+    }
+}
+
+impl FromStr for RssSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RssSource::ALL
+            .iter()
+            .find_map(|(name, source)| (*name == s).then_some(*source))
+            .ok_or_else(|| anyhow!("unknown --source: {}", s))
+    }
+}
+
+/// Which processes count at all, by the name or command line they exec'd (see `--only` and
+/// `--exclude`). Processes which are filtered out are still traced and appear in the graph, they
+/// just don't add to the total.
+#[derive(Debug, Default, Clone)]
+pub struct ProcessFilter {
+    pub only: Option<Regex>,
+    pub exclude: Option<Regex>,
+}
+
+impl ProcessFilter {
+    /// Whether a process with this name and command line (its arguments joined by spaces) is
+    /// filtered out. Processes which haven't been named yet only count if there's no `--only`.
+    pub fn excludes(&self, name: Option<&str>, cmdline: Option<&[String]>) -> bool {
+        if self.only.is_none() && self.exclude.is_none() {
+            return false;
+        }
+
+        let cmdline = cmdline.map(|args| args.join(" "));
+        let matches = |pattern: &Regex| {
+            name.is_some_and(|name| pattern.is_match(name))
+                || cmdline.as_deref().is_some_and(|c| pattern.is_match(c))
+        };
+        self.only.as_ref().is_some_and(|only| !matches(only))
+            || self.exclude.as_ref().is_some_and(matches)
+    }
+}
+
+/// Keeps the latest PSS of each process which is alive, and the largest total they've had at once.
+/// PSS splits shared pages between the processes sharing them, so unlike RSS, memory inherited
+/// across a fork isn't counted twice.
+#[derive(Debug, Default)]
+pub struct ConcurrentPeak {
+    live: HashMap<Pid, u64>,
+    total: u64,
+    peak: u64,
+}
+
+impl ConcurrentPeak {
+    /// Updates the process' PSS. The peak isn't updated until [`ConcurrentPeak::check`], so several
+    /// processes read together can be updated at once.
+    pub fn set(&mut self, pid: Pid, pss: u64) {
+        let old = self.live.insert(pid, pss).unwrap_or(0);
+        self.total = self.total - old + pss;
+    }
+
+    /// Forgets the processes for which `alive` returns false.
+    pub fn retain(&mut self, mut alive: impl FnMut(Pid) -> bool) {
+        let total = &mut self.total;
+        self.live.retain(|pid, pss| {
+            let keep = alive(*pid);
+            if !keep {
+                *total -= *pss;
+            }
+            keep
+        });
+    }
+
+    /// Returns the current total, keeping it if it's the largest yet.
+    pub fn check(&mut self) -> u64 {
+        self.peak = self.peak.max(self.total);
+        self.total
+    }
+
+    pub fn peak(&self) -> u64 {
+        self.peak
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for (name, policy) in CountPolicy::ALL {
+            assert_eq!(policy.name(), *name);
+            assert_eq!(name.parse::<CountPolicy>().unwrap(), *policy);
+        }
+        assert!("sum".parse::<CountPolicy>().is_err());
+    }
+
+    #[test]
+    fn concurrent_peak() {
+        let (a, b) = (Pid::from_raw(1), Pid::from_raw(2));
+        let mut peak = ConcurrentPeak::default();
+        peak.set(a, 10);
+        assert_eq!(peak.check(), 10);
+
+        // both are alive at once
+        peak.set(a, 20);
+        peak.set(b, 30);
+        assert_eq!(peak.check(), 50);
+
+        // a exits and b shrinks, which doesn't lower the peak
+        peak.retain(|pid| pid != a);
+        peak.set(b, 5);
+        assert_eq!(peak.check(), 5);
+        assert_eq!(peak.peak(), 50);
+    }
+
+    #[test]
+    fn filters() {
+        let cmdline = ["rustc".to_string(), "--edition=2021".to_string()];
+        let filter = |only: Option<&str>, exclude: Option<&str>| ProcessFilter {
+            only: only.map(|p| Regex::new(p).unwrap()),
+            exclude: exclude.map(|p| Regex::new(p).unwrap()),
+        };
+
+        assert!(!filter(None, None).excludes(None, None));
+        assert!(!filter(Some("^rustc$"), None).excludes(Some("rustc"), None));
+        assert!(filter(Some("^rustc$"), None).excludes(Some("make"), None));
+        assert!(filter(Some("^rustc$"), None).excludes(None, None));
+        assert!(!filter(Some("edition"), None).excludes(Some("rustc"), Some(&cmdline)));
+        assert!(filter(None, Some("^sh$")).excludes(Some("sh"), None));
+        assert!(!filter(None, Some("^sh$")).excludes(None, None));
+        assert!(filter(Some("rustc"), Some("2021")).excludes(Some("rustc"), Some(&cmdline)));
+    }
+}
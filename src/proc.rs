@@ -0,0 +1,930 @@
+//! Helpers for reading process information out of `/proc`.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use nix::unistd::Pid;
+
+use crate::platform;
+use crate::policy::MemSource;
+
+pub fn get_rss(pid: Pid) -> Result<u64> {
+    match read_smaps_rollup(pid) {
+        Ok(text) => parse_rss(text.as_bytes()),
+        // kernels before 4.14 don't have smaps_rollup, but statm counts the same pages
+        Err(e)
+            if e.downcast_ref::<io::Error>().map(io::Error::kind) == Some(ErrorKind::NotFound) =>
+        {
+            parse_statm(&fs::read(format!("/proc/{}/statm", pid))?)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub fn read_smaps_rollup(pid: Pid) -> Result<String> {
+    Ok(fs::read_to_string(format!("/proc/{}/smaps_rollup", pid))?)
+}
+
+pub fn read_smaps(pid: Pid) -> Result<String> {
+    Ok(fs::read_to_string(format!("/proc/{}/smaps", pid))?)
+}
+
+pub fn parse_rss(smaps_rollup: &[u8]) -> Result<u64> {
+    // extract value: "Rss:      <VALUE> kb"
+    parse_kb_field(smaps_rollup, "Rss:")
+}
+
+/// Reads the process' Proportional Set Size: its RSS, but with each page shared with other processes
+/// divided between them.
+pub fn get_pss(pid: Pid) -> Result<u64> {
+    parse_pss(read_smaps_rollup(pid)?.as_bytes())
+}
+
+/// Parses the PSS out of `smaps_rollup`, or `smaps` (whose mappings are summed).
+pub fn parse_pss(smaps: &[u8]) -> Result<u64> {
+    sum_kb_field(smaps, "Pss:")
+}
+
+/// Parses the Unique Set Size out of `smaps_rollup`, or `smaps` (whose mappings are summed): the
+/// memory mapped by this process alone, which would be freed if it exited.
+pub fn parse_uss(smaps: &[u8]) -> Result<u64> {
+    Ok(sum_kb_field(smaps, "Private_Clean:")? + sum_kb_field(smaps, "Private_Dirty:")?)
+}
+
+/// Parses how much of the process' memory has been swapped out of `smaps_rollup`, or `smaps` (whose
+/// mappings are summed). Swapped pages aren't resident, so aren't included in its RSS.
+pub fn parse_swap(smaps: &[u8]) -> Result<u64> {
+    sum_kb_field(smaps, "Swap:")
+}
+
+/// Parses the resident size out of `/proc/<pid>/statm`, which is its second field, in pages.
+pub fn parse_statm(statm: &[u8]) -> Result<u64> {
+    let mut fields = statm.split(|b| b.is_ascii_whitespace());
+    fields
+        .nth(1)
+        .and_then(parse_number)
+        .map(|pages| pages * platform::page_size())
+        .ok_or_else(|| anyhow!("failed to find resident value in statm"))
+}
+
+/// Sums the RSS of every mapping in `/proc/<pid>/smaps`, which each have an `Rss:` line of their own.
+pub fn parse_smaps_rss(smaps: &[u8]) -> Result<u64> {
+    // a process with no mappings left, like one which is exiting, has nothing in its smaps at all
+    if smaps.is_empty() {
+        return Ok(0);
+    }
+
+    sum_kb_field(smaps, "Rss:")
+}
+
+/// Sums every line of the form `<field>   <value> kB`, returning the total in bytes. `smaps` has one
+/// for each mapping, whereas `smaps_rollup` has just the one.
+fn sum_kb_field(smaps: &[u8], field: &str) -> Result<u64> {
+    let mut lines = smaps
+        .split(|b| *b == b'\n')
+        .filter(|line| line.starts_with(field.as_bytes()))
+        .peekable();
+    if lines.peek().is_none() {
+        bail!("failed to find {} value", field);
+    }
+
+    lines
+        .map(|line| find_kb_field(line, field.as_bytes()).map(|kb| kb * 1024))
+        .sum::<Option<u64>>()
+        .ok_or_else(|| anyhow!("failed to parse {} value", field))
+}
+
+impl MemSource {
+    /// Reads the process' RSS from this source.
+    pub fn get_rss(&self, pid: Pid) -> Result<u64> {
+        match self {
+            MemSource::SmapsRollup => get_rss(pid),
+            source => source.parse(&fs::read(format!("/proc/{}/{}", pid, source.name()))?),
+        }
+    }
+
+    /// Parses the RSS out of the contents of this source's file.
+    pub fn parse(&self, text: &[u8]) -> Result<u64> {
+        match self {
+            MemSource::SmapsRollup => parse_rss(text),
+            MemSource::Smaps => parse_smaps_rss(text),
+            MemSource::Statm => parse_statm(text),
+            MemSource::Status => parse_kb_field(text, "VmRSS:"),
+        }
+    }
+
+    /// Reads the RSS out of this source's file, which is kept open and re-read from the start.
+    fn read(&self, file: &mut File, buf: &mut Vec<u8>) -> Result<u64> {
+        match self {
+            MemSource::SmapsRollup => read_field(file, buf, "Rss:"),
+            MemSource::Status => read_field(file, buf, "VmRSS:"),
+            // these are either tiny, or have to be read in full anyway
+            source => {
+                buf.clear();
+                file.seek(SeekFrom::Start(0))?;
+                file.read_to_end(buf)?;
+                source.parse(buf)
+            }
+        }
+    }
+}
+
+/// How much more of `smaps_rollup` an [`RssReader`] reads at a time, until it finds the line it's
+/// after.
+const READ_CHUNK: usize = 256;
+
+/// The most `/proc` files kept open at once (across every [`RssReader`]), so a large tree
+/// can't exhaust our file descriptors. Any processes past this are read the slow way.
+const MAX_OPEN_FILES: usize = 256;
+
+/// Reading fewer processes than this at once isn't worth spreading across threads.
+const PARALLEL_THRESHOLD: usize = 64;
+
+/// The most threads an [`RssPool`] reads with.
+const MAX_THREADS: usize = 8;
+
+/// Reads the RSS of processes over and over, as is done when sampling. Each process' file (its
+/// `smaps_rollup`, unless another [`MemSource`] is used) is kept open and re-read from the start,
+/// and the same buffer is reused for every read, rather than formatting its path, opening it and
+/// allocating for its contents each time.
+#[derive(Debug)]
+pub struct RssReader {
+    files: HashMap<Pid, File>,
+    max_open: usize,
+    buf: Vec<u8>,
+    source: MemSource,
+}
+
+impl Default for RssReader {
+    fn default() -> Self {
+        RssReader::with_max_open(MAX_OPEN_FILES, MemSource::default())
+    }
+}
+
+impl RssReader {
+    fn with_max_open(max_open: usize, source: MemSource) -> RssReader {
+        RssReader {
+            files: HashMap::new(),
+            max_open,
+            buf: vec![],
+            source,
+        }
+    }
+
+    pub fn get_rss(&mut self, pid: Pid) -> Result<u64> {
+        let source = self.source;
+        self.get(
+            pid,
+            |file, buf| source.read(file, buf),
+            |pid| source.get_rss(pid),
+        )
+    }
+
+    pub fn get_pss(&mut self, pid: Pid) -> Result<u64> {
+        match self.source {
+            MemSource::SmapsRollup => {
+                self.get(pid, |file, buf| read_field(file, buf, "Pss:"), get_pss)
+            }
+            // only smaps_rollup has it, and that's not the file kept open
+            _ => get_pss(pid),
+        }
+    }
+
+    /// Reads the process' file with `read`, or with `slow` if it can't be kept open.
+    fn get(
+        &mut self,
+        pid: Pid,
+        read: impl FnOnce(&mut File, &mut Vec<u8>) -> Result<u64>,
+        slow: impl FnOnce(Pid) -> Result<u64>,
+    ) -> Result<u64> {
+        if self.files.len() >= self.max_open && !self.files.contains_key(&pid) {
+            return slow(pid);
+        }
+
+        let file = match self.files.entry(pid) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                match File::open(format!("/proc/{}/{}", pid, self.source.name())) {
+                    Ok(file) => entry.insert(file),
+                    Err(_) => return slow(pid),
+                }
+            }
+        };
+
+        let value = read(file, &mut self.buf);
+
+        // the process has most likely gone, so there's no point keeping it open
+        if value.is_err() {
+            self.files.remove(&pid);
+        }
+
+        value
+    }
+
+    /// Closes the files of the processes for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(Pid) -> bool) {
+        self.files.retain(|pid, _| keep(*pid));
+    }
+}
+
+/// A set of [`RssReader`]s, so large trees can be read across several threads. Each process is
+/// always read by the same reader, so it keeps the benefit of its file being kept open.
+#[derive(Debug)]
+pub struct RssPool {
+    readers: Vec<RssReader>,
+}
+
+impl Default for RssPool {
+    fn default() -> Self {
+        RssPool::new(MemSource::default())
+    }
+}
+
+impl RssPool {
+    /// Creates a pool which reads the RSS of each process from `source`.
+    pub fn new(source: MemSource) -> RssPool {
+        let threads = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(MAX_THREADS);
+        RssPool::with_threads(threads, source)
+    }
+
+    fn with_threads(threads: usize, source: MemSource) -> RssPool {
+        RssPool {
+            readers: (0..threads)
+                .map(|_| RssReader::with_max_open(MAX_OPEN_FILES / threads, source))
+                .collect(),
+        }
+    }
+
+    fn index(&self, pid: Pid) -> usize {
+        pid.as_raw() as usize % self.readers.len()
+    }
+
+    pub fn get_rss(&mut self, pid: Pid) -> Result<u64> {
+        let index = self.index(pid);
+        self.readers[index].get_rss(pid)
+    }
+
+    /// Reads the RSS of each of the given processes, skipping any which couldn't be read. When
+    /// there are many, they're read concurrently so the sampling interval can be kept even for huge
+    /// trees.
+    pub fn read(&mut self, pids: &[Pid]) -> Vec<(Pid, u64)> {
+        self.read_with(pids, RssReader::get_rss)
+    }
+
+    /// Reads the PSS of each of the given processes, the same way as [`RssPool::read`].
+    pub fn read_pss(&mut self, pids: &[Pid]) -> Vec<(Pid, u64)> {
+        self.read_with(pids, RssReader::get_pss)
+    }
+
+    fn read_with(
+        &mut self,
+        pids: &[Pid],
+        get: fn(&mut RssReader, Pid) -> Result<u64>,
+    ) -> Vec<(Pid, u64)> {
+        let threads = self.readers.len();
+        if threads == 1 || pids.len() < PARALLEL_THRESHOLD {
+            return pids
+                .iter()
+                .filter_map(|pid| {
+                    let index = self.index(*pid);
+                    Some((*pid, get(&mut self.readers[index], *pid).ok()?))
+                })
+                .collect();
+        }
+
+        thread::scope(|scope| {
+            let handles = self
+                .readers
+                .iter_mut()
+                .enumerate()
+                .map(|(i, reader)| {
+                    scope.spawn(move || {
+                        pids.iter()
+                            .filter(|pid| pid.as_raw() as usize % threads == i)
+                            .filter_map(|pid| Some((*pid, get(reader, *pid).ok()?)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_default())
+                .collect()
+        })
+    }
+
+    /// Closes the files of the processes for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(Pid) -> bool) {
+        for reader in &mut self.readers {
+            reader.retain(&mut keep);
+        }
+    }
+}
+
+/// Reads a file such as `smaps_rollup` from the start, but only as far as the `field` line (`Rss:`
+/// and `Pss:` are both near the top).
+fn read_field(file: &mut File, buf: &mut Vec<u8>, field: &str) -> Result<u64> {
+    buf.clear();
+    file.seek(SeekFrom::Start(0))?;
+    loop {
+        let len = buf.len();
+        buf.resize(len + READ_CHUNK, 0);
+        // the tracer is woken by a signal every so often, which may interrupt the read
+        let n = match file.read(&mut buf[len..]) {
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {
+                buf.truncate(len);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        buf.truncate(len + n);
+
+        // a value cut off part way through isn't terminated, so won't be found until it's complete
+        if let Some(kb) = find_kb_field(buf, field.as_bytes()) {
+            return Ok(kb * 1024);
+        }
+        if n == 0 {
+            return parse_kb_field(buf, field);
+        }
+    }
+}
+
+/// Reads the kernel's high-water mark of the process' RSS, from `VmHWM` in `/proc/<pid>/status`.
+pub fn get_hwm(pid: Pid) -> Result<u64> {
+    let status = fs::read(format!("/proc/{}/status", pid))?;
+    parse_kb_field(&status, "VmHWM:")
+}
+
+/// Reads the id of the thread group (i.e. the process) a task belongs to, from `Tgid` in
+/// `/proc/<pid>/status`. It's the task's own id unless the task is a thread.
+pub fn get_tgid(pid: Pid) -> Result<Pid> {
+    let status = fs::read(format!("/proc/{}/status", pid))?;
+    find_kb_field(&status, b"Tgid:")
+        .map(|tgid| Pid::from_raw(tgid as i32))
+        .ok_or_else(|| anyhow!("failed to find Tgid: value"))
+}
+
+/// Parses a line of the form `<field>   <value> kB`, returning the value in bytes.
+fn parse_kb_field(text: &[u8], field: &str) -> Result<u64> {
+    find_kb_field(text, field.as_bytes())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| anyhow!("failed to find {} value", field))
+}
+
+/// Finds the line starting with `field` and parses the number which follows it. The number must be
+/// followed by whitespace, so a partially read value is never mistaken for a complete one.
+fn find_kb_field(text: &[u8], field: &[u8]) -> Option<u64> {
+    let mut rest = text;
+    loop {
+        if let Some(value) = rest.strip_prefix(field) {
+            let value = value.trim_ascii_start();
+            let end = value.iter().position(|b| b.is_ascii_whitespace())?;
+            return parse_number(&value[..end]);
+        }
+
+        let newline = rest.iter().position(|b| *b == b'\n')?;
+        rest = &rest[newline + 1..];
+    }
+}
+
+/// Parses a number made up of only ASCII digits, without allocating.
+fn parse_number(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.iter().try_fold(0u64, |n, b| {
+        b.is_ascii_digit()
+            .then(|| n.checked_mul(10)?.checked_add((b - b'0') as u64))
+            .flatten()
+    })
+}
+
+/// Whether the process is still alive, i.e.: it exists and isn't a zombie.
+pub fn is_alive(pid: Pid) -> bool {
+    match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        // the state is the first field after the command name, which itself is wrapped in
+        // parentheses and may contain spaces or parentheses itself
+        Ok(stat) => match stat.rfind(')') {
+            Some(idx) => !matches!(stat[idx + 1..].trim_start().chars().next(), Some('Z' | 'X')),
+            None => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Returns the direct children of each thread of the given process.
+pub fn get_children(pid: Pid) -> Vec<Pid> {
+    let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return vec![];
+    };
+
+    tasks
+        .filter_map(|task| task.ok())
+        .filter_map(|task| fs::read_to_string(task.path().join("children")).ok())
+        .flat_map(|children| {
+            children
+                .split_ascii_whitespace()
+                .filter_map(|s| s.parse::<i32>().ok())
+                .map(Pid::from_raw)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Returns the ids of each thread of the given process, including its leader.
+pub fn get_tasks(pid: Pid) -> Vec<Pid> {
+    let Ok(tasks) = fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return vec![];
+    };
+
+    tasks
+        .filter_map(|task| task.ok())
+        .filter_map(|task| task.file_name().to_str()?.parse::<i32>().ok())
+        .map(Pid::from_raw)
+        .collect()
+}
+
+/// What's read from a single thread's `stat`: the CPU time it has used, how long it has been
+/// running for, and how many page faults it has caused.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+    pub utime: Duration,
+    pub stime: Duration,
+    pub wall: Duration,
+    /// Faults which were resolved without any I/O, e.g.: by mapping a page which was already in the
+    /// page cache.
+    pub minor_faults: u64,
+    /// Faults which had to read the page in from disk (or swap), a sign of memory pressure.
+    pub major_faults: u64,
+}
+
+/// Reads the `stat` of the thread `pid`. This reads `/proc/<pid>/task/<pid>/stat` rather than
+/// `/proc/<pid>/stat`, since the latter includes every thread in the process and threads are
+/// measured separately.
+pub fn get_stat(pid: Pid) -> Result<Stat> {
+    let stat = fs::read_to_string(format!("/proc/{}/task/{}/stat", pid, pid))?;
+    let fields = parse_stat(&stat).ok_or_else(|| anyhow!("failed to parse stat of {}", pid))?;
+
+    let ticks = |n: u64| Duration::from_secs_f64(n as f64 / clock_ticks() as f64);
+    Ok(Stat {
+        utime: ticks(fields.utime),
+        stime: ticks(fields.stime),
+        wall: uptime()?.saturating_sub(ticks(fields.starttime)),
+        minor_faults: fields.minflt,
+        major_faults: fields.majflt,
+    })
+}
+
+/// The fields we use from `/proc/<pid>/stat`, as they are there: times are in clock ticks, and
+/// `starttime` is relative to boot.
+#[derive(Debug, PartialEq, Eq)]
+struct StatFields {
+    minflt: u64,
+    majflt: u64,
+    utime: u64,
+    stime: u64,
+    starttime: u64,
+}
+
+fn parse_stat(stat: &str) -> Option<StatFields> {
+    // the command name is wrapped in parentheses and may contain spaces or parentheses itself, so
+    // the fields are counted from after it, starting with the state (the 3rd)
+    let (_, rest) = stat.rsplit_once(')')?;
+    let fields = rest.split_ascii_whitespace().collect::<Vec<_>>();
+    let field = |n: usize| fields.get(n - 3)?.parse().ok();
+    Some(StatFields {
+        minflt: field(10)?,
+        majflt: field(12)?,
+        utime: field(14)?,
+        stime: field(15)?,
+        starttime: field(22)?,
+    })
+}
+
+fn clock_ticks() -> u64 {
+    // SAFETY: sysconf has no preconditions
+    match unsafe { nix::libc::sysconf(nix::libc::_SC_CLK_TCK) } {
+        ticks if ticks > 0 => ticks as u64,
+        _ => 100,
+    }
+}
+
+/// How long it's been since boot, which is what process start times are relative to.
+fn uptime() -> Result<Duration> {
+    let mut now = nix::libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: now is valid for writes
+    nix::errno::Errno::result(unsafe {
+        nix::libc::clock_gettime(nix::libc::CLOCK_BOOTTIME, &mut now)
+    })?;
+    Ok(Duration::new(now.tv_sec as u64, now.tv_nsec as u32))
+}
+
+/// A thread's I/O counters, in bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Io {
+    /// Passed to read-like syscalls, whether or not it came from storage.
+    pub rchar: u64,
+    /// Passed to write-like syscalls, whether or not it reached storage.
+    pub wchar: u64,
+    /// Actually fetched from storage.
+    pub read_bytes: u64,
+    /// Actually sent (or to be sent) to storage.
+    pub write_bytes: u64,
+}
+
+impl Io {
+    pub fn add(&mut self, other: Io) {
+        self.rchar += other.rchar;
+        self.wchar += other.wchar;
+        self.read_bytes += other.read_bytes;
+        self.write_bytes += other.write_bytes;
+    }
+}
+
+/// Reads the I/O counters of the thread `pid`, from its own task directory for the same reason as
+/// [`get_stat`]. Like the rest of `/proc/<pid>/io`, this needs the same access as tracing does.
+pub fn get_io(pid: Pid) -> Result<Io> {
+    let io = fs::read(format!("/proc/{}/task/{}/io", pid, pid))?;
+    parse_io(&io)
+}
+
+fn parse_io(io: &[u8]) -> Result<Io> {
+    // these aren't in kB, but are laid out the same way
+    let field = |name: &str| {
+        find_kb_field(io, name.as_bytes()).ok_or_else(|| anyhow!("failed to find {} value", name))
+    };
+    Ok(Io {
+        rchar: field("rchar:")?,
+        wchar: field("wchar:")?,
+        read_bytes: field("read_bytes:")?,
+        write_bytes: field("write_bytes:")?,
+    })
+}
+
+/// Reads the process' name (as set by exec, or changed at runtime with `PR_SET_NAME`).
+pub fn get_comm(pid: Pid) -> Result<String> {
+    let comm = fs::read_to_string(format!("/proc/{}/comm", pid))?;
+    Ok(comm.trim_end_matches('\n').to_string())
+}
+
+/// Reads the process' arguments, which some programs rewrite at runtime to describe themselves
+/// (e.g.: `postgres: checkpointer`).
+pub fn get_cmdline(pid: Pid) -> Result<Vec<String>> {
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid))?;
+    Ok(parse_cmdline(&cmdline))
+}
+
+fn parse_cmdline(cmdline: &[u8]) -> Vec<String> {
+    cmdline
+        .strip_suffix(b"\0")
+        .unwrap_or(cmdline)
+        .split(|b| *b == 0)
+        .filter(|_| !cmdline.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect()
+}
+
+/// Whether the process' executable is a 32-bit ELF binary (e.g.: from a multilib toolchain on a
+/// 64-bit host). Nothing we read from `/proc` differs for these, but it's worth noting in the graph.
+pub fn is_32bit_exe(pid: Pid) -> bool {
+    use std::io::Read;
+
+    let mut header = [0; 5];
+    fs::File::open(format!("/proc/{}/exe", pid))
+        .and_then(|mut f| f.read_exact(&mut header))
+        .is_ok_and(|_| elf_class(&header) == Some(32))
+}
+
+/// Returns the word size of an ELF binary from its header, or `None` if it isn't one.
+fn elf_class(header: &[u8]) -> Option<u32> {
+    const ELFCLASS32: u8 = 1;
+    const ELFCLASS64: u8 = 2;
+
+    match header {
+        [0x7f, b'E', b'L', b'F', ELFCLASS32, ..] => Some(32),
+        [0x7f, b'E', b'L', b'F', ELFCLASS64, ..] => Some(64),
+        _ => None,
+    }
+}
+
+/// Whether the process' executable has the setuid or setgid bits set, or has file capabilities.
+/// When such an executable is run under ptrace, the kernel refuses to grant it its privileges, and
+/// tracing may be lost if it is re-executed by something else.
+pub fn is_privileged_exe(pid: Pid) -> bool {
+    let Ok(exe) = fs::read_link(format!("/proc/{}/exe", pid)) else {
+        return false;
+    };
+
+    is_privileged_path(&exe)
+}
+
+fn is_privileged_path(path: &Path) -> bool {
+    const S_ISUID: u32 = 0o4000;
+    const S_ISGID: u32 = 0o2000;
+
+    let setid = fs::metadata(path)
+        .map(|m| m.permissions().mode() & (S_ISUID | S_ISGID) != 0)
+        .unwrap_or(false);
+
+    setid || has_file_caps(path)
+}
+
+fn has_file_caps(path: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    // SAFETY: both strings are valid and nul terminated, and passing a null buffer with a zero
+    // size only queries the size of the attribute
+    let size = unsafe {
+        nix::libc::getxattr(
+            path.as_ptr(),
+            c"security.capability".as_ptr(),
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+
+    size > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::unistd::getpid;
+
+    use super::*;
+
+    /// `smaps_rollup` as written by a 6.x kernel.
+    const SMAPS_ROLLUP: &[u8] = b"\
+55d0c0a4b000-7ffd5a5f7000 ---p 00000000 00:00 0                          [rollup]
+Rss:                6440 kB
+Pss:                1353 kB
+Pss_Dirty:           468 kB
+Pss_Anon:            436 kB
+Pss_File:            917 kB
+Pss_Shmem:             0 kB
+Shared_Clean:       5900 kB
+Shared_Dirty:          0 kB
+Private_Clean:        84 kB
+Private_Dirty:       456 kB
+Referenced:         6440 kB
+Anonymous:           436 kB
+LazyFree:              0 kB
+AnonHugePages:         0 kB
+ShmemPmdMapped:        0 kB
+FilePmdMapped:         0 kB
+Shared_Hugetlb:        0 kB
+Private_Hugetlb:       0 kB
+Swap:                  0 kB
+SwapPss:               0 kB
+Locked:                0 kB
+";
+
+    #[test]
+    fn kb_field() {
+        let text = b"Name:\tfoo\nVmHWM:\t    1234 kB\nVmRSS:\t     999 kB\n";
+        assert_eq!(parse_kb_field(text, "VmHWM:").unwrap(), 1234 * 1024);
+        assert_eq!(parse_kb_field(text, "VmRSS:").unwrap(), 999 * 1024);
+        assert!(parse_kb_field(text, "Rss:").is_err());
+        assert!(parse_kb_field(b"Rss: abc kB", "Rss:").is_err());
+        assert!(parse_kb_field(b"Rss: 12a kB", "Rss:").is_err());
+        assert!(parse_kb_field(b"Rss: 99999999999999999999 kB", "Rss:").is_err());
+    }
+
+    #[test]
+    fn rss() {
+        assert_eq!(parse_rss(SMAPS_ROLLUP).unwrap(), 6440 * 1024);
+
+        // older kernels, and those with fewer fields, are still found
+        assert_eq!(parse_rss(b"[rollup]\nRss:\t12 kB\n").unwrap(), 12 * 1024);
+        assert!(parse_rss(b"[rollup]\nPss: 12 kB\n").is_err());
+        assert!(parse_rss(b"").is_err());
+    }
+
+    #[test]
+    fn uss() {
+        assert_eq!(parse_uss(SMAPS_ROLLUP).unwrap(), (84 + 456) * 1024);
+        assert!(parse_uss(b"[rollup]\nRss:\t12 kB\nPrivate_Dirty:\t4 kB\n").is_err());
+
+        // smaps has a set of fields for each mapping, which are added up
+        let smaps = b"Private_Clean:\t1 kB\nPrivate_Dirty:\t2 kB\nPrivate_Clean:\t4 kB\nPrivate_Dirty:\t8 kB\n";
+        assert_eq!(parse_uss(smaps).unwrap(), 15 * 1024);
+    }
+
+    #[test]
+    fn swap() {
+        assert_eq!(parse_swap(SMAPS_ROLLUP).unwrap(), 0);
+        assert_eq!(
+            parse_swap(b"SwapPss:\t1 kB\nSwap:\t8 kB\n").unwrap(),
+            8 * 1024
+        );
+        assert!(parse_swap(b"SwapPss:\t1 kB\n").is_err());
+    }
+
+    #[test]
+    fn rss_truncated() {
+        // a read may stop anywhere, but must never produce a value which isn't the complete one
+        for len in 0..SMAPS_ROLLUP.len() {
+            let rss = find_kb_field(&SMAPS_ROLLUP[..len], b"Rss:");
+            assert!(rss.is_none() || rss == Some(6440), "{}: {:?}", len, rss);
+        }
+    }
+
+    #[test]
+    fn statm() {
+        let page = platform::page_size();
+        assert_eq!(
+            parse_statm(b"2048 300 250 5 0 120 0\n").unwrap(),
+            300 * page
+        );
+        assert!(parse_statm(b"2048").is_err());
+        assert!(parse_statm(b"").is_err());
+    }
+
+    #[test]
+    fn cmdline() {
+        assert_eq!(parse_cmdline(b"ls\0-la\0"), vec!["ls", "-la"]);
+        assert_eq!(
+            parse_cmdline(b"postgres: checkpointer"),
+            vec!["postgres: checkpointer"]
+        );
+        assert!(parse_cmdline(b"").is_empty());
+        assert!(!get_cmdline(getpid()).unwrap().is_empty());
+        assert!(!get_comm(getpid()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stat() {
+        let stat = "42 (a) b (c)) S 1 42 42 0 -1 4194560 100 0 0 0 25 7 0 0 20 0 1 0 9000 0 0\n";
+        assert_eq!(
+            parse_stat(stat),
+            Some(StatFields {
+                minflt: 100,
+                majflt: 0,
+                utime: 25,
+                stime: 7,
+                starttime: 9000,
+            })
+        );
+        assert_eq!(parse_stat("42 (sh) S 1 42"), None);
+        assert_eq!(parse_stat(""), None);
+
+        // we've been running for at least as long as we've used the CPU for
+        let stat = get_stat(getpid()).unwrap();
+        assert!(stat.wall >= stat.utime);
+        assert!(stat.minor_faults > 0);
+    }
+
+    #[test]
+    fn io() {
+        let io = b"rchar: 4096\nwchar: 12\nsyscr: 3\nsyscw: 1\nread_bytes: 0\nwrite_bytes: 8192\n\
+            cancelled_write_bytes: 0\n";
+        assert_eq!(
+            parse_io(io).unwrap(),
+            Io {
+                rchar: 4096,
+                wchar: 12,
+                read_bytes: 0,
+                write_bytes: 8192,
+            }
+        );
+        assert!(parse_io(b"rchar: 4096\n").is_err());
+        assert!(get_io(getpid()).unwrap().rchar > 0);
+    }
+
+    #[test]
+    fn elf() {
+        assert_eq!(elf_class(b"\x7fELF\x01\x01"), Some(32));
+        assert_eq!(elf_class(b"\x7fELF\x02"), Some(64));
+        assert_eq!(elf_class(b"#!/bin/sh"), None);
+        assert_eq!(elf_class(b""), None);
+
+        // this test binary is built for the host, which is 64-bit wherever this is run
+        assert!(!is_32bit_exe(getpid()));
+    }
+
+    #[test]
+    fn rss_reader() {
+        let mut reader = RssReader::default();
+        assert!(reader.get_rss(getpid()).unwrap() > 0);
+        assert!(reader.get_rss(getpid()).unwrap() > 0);
+        assert_eq!(reader.files.len(), 1);
+
+        assert!(reader.get_rss(Pid::from_raw(i32::MAX)).is_err());
+        assert_eq!(reader.files.len(), 1);
+
+        // the same file is read for PSS, which is at most RSS (give or take, as the two are read
+        // at different times)
+        let pss = reader.get_pss(getpid()).unwrap();
+        assert!(pss > 0 && pss <= get_rss(getpid()).unwrap() * 2);
+        assert_eq!(reader.files.len(), 1);
+
+        reader.retain(|_| false);
+        assert!(reader.files.is_empty());
+    }
+
+    #[test]
+    fn mem_sources() {
+        let smaps = b"55d4a2c00000-55d4a2c02000 r--p 00000000 fd:01 1234 /usr/bin/sleep\n\
+            Size:                  8 kB\n\
+            Rss:                   8 kB\n\
+            7ffd1c3a1000-7ffd1c3c2000 rw-p 00000000 00:00 0 [stack]\n\
+            Size:                132 kB\n\
+            Rss:                  12 kB\n";
+        assert_eq!(parse_smaps_rss(smaps).unwrap(), 20 * 1024);
+        assert_eq!(parse_smaps_rss(b"").unwrap(), 0);
+        assert_eq!(
+            MemSource::Status
+                .parse(b"Name:\tsleep\nVmRSS:\t  6440 kB\n")
+                .unwrap(),
+            6440 * 1024
+        );
+        assert_eq!("statm".parse::<MemSource>().unwrap(), MemSource::Statm);
+        assert!("rss".parse::<MemSource>().is_err());
+
+        // they all count the same pages, give or take what changed between reads
+        let rss = get_rss(getpid()).unwrap();
+        for (name, source) in MemSource::ALL {
+            let mut reader = RssReader::with_max_open(1, *source);
+            for value in [source.get_rss(getpid()), reader.get_rss(getpid())] {
+                let value = value.unwrap();
+                assert!(value > rss / 2 && value < rss * 2, "{}: {}", name, value);
+            }
+            assert_eq!(reader.files.len(), 1);
+            assert!(reader.get_pss(getpid()).unwrap() > 0);
+        }
+    }
+
+    #[test]
+    fn rss_pool() {
+        let pids = vec![getpid(); PARALLEL_THRESHOLD];
+        let rss = get_rss(getpid()).unwrap();
+
+        // the values are read at slightly different times, so only roughly compare them
+        let mut pool = RssPool::with_threads(4, MemSource::default());
+        let values = pool.read(&pids);
+        assert_eq!(values.len(), pids.len());
+        let total = values.iter().map(|(_, rss)| rss).sum::<u64>();
+        assert!(
+            total >= rss * PARALLEL_THRESHOLD as u64 / 2,
+            "{} vs {}",
+            total,
+            rss
+        );
+        assert!(pool.read(&[]).is_empty());
+
+        let mut pool = RssPool::with_threads(1, MemSource::default());
+        assert_eq!(pool.read(&pids[..2]).len(), 2);
+        assert_eq!(pool.read_pss(&pids[..2]).len(), 2);
+        pool.retain(|_| false);
+        assert!(pool.readers[0].files.is_empty());
+    }
+
+    #[test]
+    fn hwm() {
+        assert!(get_hwm(nix::unistd::getpid()).unwrap() > 0);
+    }
+
+    #[test]
+    fn tasks() {
+        assert!(get_tasks(getpid()).contains(&getpid()));
+        assert!(get_tasks(Pid::from_raw(i32::MAX)).is_empty());
+    }
+
+    #[test]
+    fn tgid() {
+        assert_eq!(get_tgid(getpid()).unwrap(), getpid());
+        let thread = std::thread::spawn(|| get_tgid(nix::unistd::gettid()).unwrap());
+        assert_eq!(thread.join().unwrap(), getpid());
+    }
+
+    #[test]
+    fn alive() {
+        assert!(is_alive(getpid()));
+        assert!(!is_alive(Pid::from_raw(i32::MAX)));
+    }
+
+    #[test]
+    fn privileged_path() {
+        assert!(!is_privileged_path(Path::new("/proc/self/exe")));
+        assert!(!is_privileged_path(Path::new("/does/not/exist")));
+    }
+}
@@ -0,0 +1,93 @@
+//! Redacts sensitive values from results before they're written, so they can be shared safely.
+
+use std::ffi::OsString;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// What redacted values are replaced with.
+pub const REDACTED: &str = "<redacted>";
+
+/// Environment variables with names matching this are redacted if `--redact` isn't given.
+pub const DEFAULT_ENV_PATTERN: &str = "(?i)TOKEN|SECRET|KEY|PASS|CREDENTIAL|AUTH";
+
+/// Returns the given environment variables as an object, with the values of those whose names
+/// match `pattern` redacted.
+fn redact_env(vars: impl Iterator<Item = (String, String)>, pattern: &Regex) -> Value {
+    let map = vars
+        .map(|(name, value)| match pattern.is_match(&name) {
+            true => (name, REDACTED.into()),
+            false => (name, value.into()),
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(map)
+}
+
+/// Replaces every match of `pattern` in the string.
+pub fn redact_str(s: &str, pattern: &Regex) -> String {
+    pattern.replace_all(s, REDACTED).into_owned()
+}
+
+/// Replaces every match of `pattern` in all of the string values in the report. Object keys are
+/// left alone, since they're part of the report's structure.
+pub fn redact_strings(value: &mut Value, pattern: &Regex) {
+    match value {
+        Value::String(s) => *s = redact_str(s, pattern),
+        Value::Array(values) => values.iter_mut().for_each(|v| redact_strings(v, pattern)),
+        Value::Object(map) => map.values_mut().for_each(|v| redact_strings(v, pattern)),
+        _ => {}
+    }
+}
+
+/// Captures the environment COMMAND is run with (see `child::command_env`).
+pub fn capture_env(vars: Vec<(OsString, OsString)>, pattern: Option<&Regex>) -> Value {
+    let default = Regex::new(DEFAULT_ENV_PATTERN).expect("invalid default pattern");
+    let vars = vars.into_iter().map(|(k, v)| {
+        (
+            k.to_string_lossy().into_owned(),
+            v.to_string_lossy().into_owned(),
+        )
+    });
+
+    redact_env(vars, pattern.unwrap_or(&default))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn env() {
+        let vars = [
+            ("HOME", "/home/me"),
+            ("GITHUB_TOKEN", "abc"),
+            ("api_key", "def"),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+
+        assert_eq!(
+            redact_env(vars, &Regex::new(DEFAULT_ENV_PATTERN).unwrap()),
+            json!({ "HOME": "/home/me", "GITHUB_TOKEN": REDACTED, "api_key": REDACTED })
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let mut report = json!({
+            "/home/me": "/home/me/bin",
+            "args": ["--token=hunter2", 1, null],
+        });
+        redact_strings(&mut report, &Regex::new("/home/me|hunter2").unwrap());
+        assert_eq!(
+            report,
+            json!({
+                "/home/me": "<redacted>/bin",
+                "args": ["--token=<redacted>", 1, null],
+            })
+        );
+    }
+}
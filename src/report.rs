@@ -0,0 +1,490 @@
+//! Typed versions of the results `max_rss` writes with `--output-format json`, so they can be read
+//! without poking at a [`Value`].
+//!
+//! ```no_run
+//! let report = max_rss::report::read("results.json".as_ref()).unwrap();
+//! for child in report.graph.iter().flat_map(|root| root.children.iter().flatten()) {
+//!     println!("{:?} used {} bytes", child.name, child.rss);
+//! }
+//! ```
+//!
+//! Fields which only some backends or flags report are optional. Anything else in the results
+//! (such as the `environment`, or a `--baseline` comparison) is kept as JSON in [`Report::other`].
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// The version of [`SCHEMA`] that results follow, which changes when fields are removed or change
+/// meaning (but not when they're added).
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A JSON Schema describing the results, see `max_rss --print-schema`.
+pub const SCHEMA: &str = include_str!("report.schema.json");
+
+/// The results of measuring a command.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// The [`SCHEMA_VERSION`] the results follow, which is missing from results written before it
+    /// was added.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// The max_rss of the command and all of its descendants, in bytes.
+    pub max_rss: u64,
+    /// Which processes' memory was added up into max_rss (see `--count-policy`), only reported by
+    /// the ptrace backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_policy: Option<String>,
+    /// Where each process' RSS was taken from (see `--source`), only reported by the ptrace backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Which file in `/proc/<pid>` each process' RSS was read from (see `--mem-source`), only
+    /// reported by the ptrace backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mem_source: Option<String>,
+    /// The max_rss from each process' RSS just before it exited, when that isn't the `source`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rss_exit: Option<u64>,
+    /// The USS and swap, only when read from smaps or smaps_rollup (see `--mem-source`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uss: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_swap: Option<u64>,
+    /// The command's own max_rss, according to the kernel.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rusage_max_rss: Option<u64>,
+    /// The largest max_rss of any process the command waited for, with `--rusage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ru_maxrss: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utime_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stime_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minor_faults: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub major_faults: Option<u64>,
+    /// The I/O of every process, with `--io`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io: Option<Io>,
+    /// Every process and thread the command ran.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_pids: Option<usize>,
+    /// How many of `total_pids` were processes, which is what tools like `ps` count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_processes: Option<usize>,
+    /// How many of `total_pids` were threads, which share their process' memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_threads: Option<usize>,
+    /// How many processes counted towards max_rss.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_reads: Option<usize>,
+    /// How many times the command and its descendants forked, exec'd and so on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<EventCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_interval_ms: Option<f64>,
+    /// Whether some processes couldn't be traced, and were polled instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub degraded: Option<bool>,
+    /// The command's exit code, unless it was killed by a signal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The signal which killed the command, if it didn't exit by itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_signal: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timed_out: Option<bool>,
+    /// How many processes were still running once the command exited, and were killed with
+    /// `--kill-remaining`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub killed_at_teardown: Option<usize>,
+    /// How many processes were still running once the command exited, and were left running with
+    /// `--exit-with-root`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_running: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attached: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interrupted: Option<bool>,
+    /// The results of `--verify`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<Value>,
+    /// A summary of the `--timeline`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeline: Option<Value>,
+    /// How many alerts were fired, with `--alert`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alerts: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub child_log: Option<Value>,
+    /// Where COMMAND's stdout and stderr were written, and how many bytes, with `--stdout`,
+    /// `--stderr` or `--quiet`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub child_output: Option<Value>,
+    /// The peak the cgroup backend measured, and how.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cgroup: Option<Value>,
+    /// The command's process and its descendants, only reported by the ptrace backend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub graph: Option<ProcessNode>,
+    /// Why tracing failed part way through, in which case the rest are partial results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Why COMMAND couldn't be traced at all, when the system didn't permit it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_cause: Option<String>,
+    /// Every other field in the results.
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// A process in [`Report::graph`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessNode {
+    /// The process' pid.
+    pub id: i32,
+    /// The process' name, which is the same as its `comm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The process' name according to the kernel (`/proc/<pid>/comm`), which is truncated to 15
+    /// bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<Vec<String>>,
+    /// A hash of `cmdline` (see [`argv_hash`]), so processes can be matched between reports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub argv_hash: Option<String>,
+    /// Where the process is in the graph, as its path from the root (e.g.: `0.1` is the root's
+    /// second child).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<String>,
+    /// Set when this is a thread of its parent, whose RSS it shares (so it's never counted).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thread: Option<bool>,
+    /// The process' RSS, in bytes.
+    pub rss: u64,
+    /// The process' RSS just before it exited, when that isn't the `source`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_rss: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uss: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap: Option<u64>,
+    /// Set when the process couldn't be traced, and was polled instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub untraced: Option<bool>,
+    /// Set when the process exec'd a setuid/setgid binary or one with file capabilities.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privileged_exec: Option<bool>,
+    /// Set when the process was orphaned and reparented to `max_rss` before it was known, so it's
+    /// under the root rather than its original parent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orphaned: Option<bool>,
+    /// Set when the process was still running once the command exited, and was killed with
+    /// `--kill-remaining`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub killed_at_teardown: Option<bool>,
+    /// Set when the process was filtered out by `--only` or `--exclude`, so didn't count towards
+    /// max_rss.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excluded: Option<bool>,
+    /// Set when the process exec'd a 32-bit binary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elf32: Option<bool>,
+    /// When the process started and ended, in milliseconds since the command started. Missing for
+    /// processes which were already running when `--pid` attached to them, or which were no longer
+    /// traced when they ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended_at: Option<u64>,
+    /// How long the process ran for, when both of the above are known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// How the process ended, missing from results written before it was added.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<ExitStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub utime_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stime_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_time_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minor_faults: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub major_faults: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io: Option<Io>,
+    /// How many times the process forked, exec'd and so on, missing if it did none of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<EventCounts>,
+    /// The totals of descendants which were dropped with `--max-procs`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evicted: Option<Evicted>,
+    /// The process' RSS over time as `(milliseconds since the command started, rss)`, with
+    /// `--record-samples`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples: Option<Vec<(u64, u64)>>,
+    /// The programs the process ran before it exec'd the one it's named after, oldest first. Its
+    /// `rss` (and the rest) are only those of its last program.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segments: Option<Vec<Segment>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<ProcessNode>>,
+}
+
+/// A program which a process ran until it exec'd another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<Vec<String>>,
+    /// The largest RSS sampled while the program ran, missing if it wasn't sampled (see
+    /// `--interval`), since it's gone by the time the process is seen to exec.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rss: Option<u64>,
+    /// When the program was exec'd and replaced, in milliseconds since the command started.
+    pub started_at: u64,
+    pub ended_at: u64,
+}
+
+/// How a process in the graph ended, e.g.: `{"exited": 0}`, `{"signaled": "SIGSEGV"}` or
+/// `"detached"`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitStatus {
+    /// The process exited by itself, with this code.
+    Exited(i32),
+    /// The process was killed by this signal.
+    Signaled(String),
+    /// The process was no longer traced when it ended (or when tracing stopped), so how it ended
+    /// isn't known.
+    Detached,
+}
+
+/// The totals of exited descendants which were dropped (see `--max-procs`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Evicted {
+    pub pids: usize,
+    pub rss: u64,
+    pub uss: u64,
+    pub swap: u64,
+    pub utime_ms: u64,
+    pub stime_ms: u64,
+    pub minor_faults: u64,
+    pub major_faults: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub io: Option<Io>,
+}
+
+/// How many times processes were created and programs executed, as seen by ptrace. `clones`
+/// includes every new thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventCounts {
+    pub forks: u64,
+    pub vforks: u64,
+    pub clones: u64,
+    pub execs: u64,
+}
+
+impl EventCounts {
+    pub fn add(&mut self, other: EventCounts) {
+        self.forks += other.forks;
+        self.vforks += other.vforks;
+        self.clones += other.clones;
+        self.execs += other.execs;
+    }
+}
+
+/// I/O counters, from `/proc/<pid>/io`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Io {
+    pub rchar: u64,
+    pub wchar: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+/// Hashes a process' arguments (with 64-bit FNV-1a, as hex), which unlike [`std::hash::Hash`] gives
+/// the same result across builds and platforms.
+pub fn argv_hash(cmdline: &[String]) -> String {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in cmdline.join("\0").bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Reads the results written to a file with `--output-format json`.
+pub fn read(path: &Path) -> Result<Report> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn round_trips() -> Result<()> {
+        let value = json!({
+            "max_rss": 300,
+            "total_pids": 2,
+            "exit_code": 0,
+            "backend": "ptrace",
+            "graph": {
+                "id": 1,
+                "name": "sh",
+                "rss": 100,
+                "samples": [[0, 50], [10, 100]],
+                "children": [{ "id": 2, "rss": 200 }],
+            },
+        });
+
+        let report = serde_json::from_value::<Report>(value)?;
+        assert_eq!(report.max_rss, 300);
+        assert_eq!(report.total_pids, Some(2));
+        assert_eq!(report.source, None);
+        assert_eq!(report.other["backend"], "ptrace");
+        let graph = report.graph.as_ref().expect("no graph");
+        assert_eq!(graph.samples, Some(vec![(0, 50), (10, 100)]));
+        assert_eq!(graph.children.as_ref().map(|c| c[0].rss), Some(200));
+
+        let value = serde_json::to_value(&report)?;
+        assert_eq!(value["backend"], "ptrace");
+        assert_eq!(value["graph"]["children"][0]["id"], 2);
+        // fields which weren't reported are left out, rather than written as null
+        assert!(value.get("source").is_none());
+        assert!(value["graph"].get("exit_rss").is_none());
+        assert_eq!(serde_json::from_value::<Report>(value)?, report);
+        Ok(())
+    }
+
+    #[test]
+    fn schema() -> Result<()> {
+        let schema = serde_json::from_str::<Value>(SCHEMA)?;
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            SCHEMA_VERSION
+        );
+
+        // every field we know about is described, so each is set for it to be serialized
+        let graph = ProcessNode {
+            id: 1,
+            name: Some(Default::default()),
+            comm: Some(Default::default()),
+            cmdline: Some(Default::default()),
+            argv_hash: Some(Default::default()),
+            position: Some(Default::default()),
+            thread: Some(Default::default()),
+            rss: 1,
+            exit_rss: Some(Default::default()),
+            uss: Some(Default::default()),
+            swap: Some(Default::default()),
+            untraced: Some(Default::default()),
+            privileged_exec: Some(Default::default()),
+            orphaned: Some(Default::default()),
+            killed_at_teardown: Some(Default::default()),
+            excluded: Some(Default::default()),
+            elf32: Some(Default::default()),
+            started_at: Some(Default::default()),
+            ended_at: Some(Default::default()),
+            duration_ms: Some(Default::default()),
+            exit_status: Some(ExitStatus::Detached),
+            utime_ms: Some(Default::default()),
+            stime_ms: Some(Default::default()),
+            wall_time_ms: Some(Default::default()),
+            minor_faults: Some(Default::default()),
+            major_faults: Some(Default::default()),
+            io: Some(Default::default()),
+            events: Some(Default::default()),
+            evicted: Some(serde_json::from_value(json!({
+                "pids": 0, "rss": 0, "uss": 0, "swap": 0, "utime_ms": 0, "stime_ms": 0,
+                "minor_faults": 0, "major_faults": 0,
+            }))?),
+            samples: Some(Default::default()),
+            segments: Some(Default::default()),
+            children: Some(vec![]),
+        };
+        let report = Report {
+            schema_version: Some(Default::default()),
+            max_rss: 1,
+            count_policy: Some(Default::default()),
+            source: Some(Default::default()),
+            mem_source: Some(Default::default()),
+            max_rss_exit: Some(Default::default()),
+            max_uss: Some(Default::default()),
+            max_swap: Some(Default::default()),
+            rusage_max_rss: Some(Default::default()),
+            ru_maxrss: Some(Default::default()),
+            utime_ms: Some(Default::default()),
+            stime_ms: Some(Default::default()),
+            minor_faults: Some(Default::default()),
+            major_faults: Some(Default::default()),
+            io: Some(Default::default()),
+            total_pids: Some(Default::default()),
+            total_processes: Some(Default::default()),
+            total_threads: Some(Default::default()),
+            total_reads: Some(Default::default()),
+            events: Some(Default::default()),
+            sample_interval_ms: Some(Default::default()),
+            degraded: Some(Default::default()),
+            exit_code: Some(Default::default()),
+            exit_signal: Some(Default::default()),
+            timed_out: Some(Default::default()),
+            killed_at_teardown: Some(Default::default()),
+            left_running: Some(Default::default()),
+            attached: Some(Default::default()),
+            interrupted: Some(Default::default()),
+            verify: Some(Default::default()),
+            timeline: Some(Default::default()),
+            alerts: Some(Default::default()),
+            child_log: Some(Default::default()),
+            child_output: Some(Default::default()),
+            cgroup: Some(Default::default()),
+            graph: Some(graph),
+            error: Some(Default::default()),
+            error_cause: Some(Default::default()),
+            other: Map::new(),
+        };
+        let value = serde_json::to_value(&report)?;
+        for key in value.as_object().expect("report isn't an object").keys() {
+            assert!(
+                schema["properties"].get(key).is_some(),
+                "{} is missing",
+                key
+            );
+        }
+        for key in value["graph"]
+            .as_object()
+            .expect("graph isn't an object")
+            .keys()
+        {
+            let process = &schema["$defs"]["process"]["properties"];
+            assert!(process.get(key).is_some(), "graph.{} is missing", key);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn argv_hashes() {
+        assert_eq!(argv_hash(&[]), "cbf29ce484222325");
+        assert_eq!(argv_hash(&["a".into()]), "af63dc4c8601ec8c");
+        assert_ne!(
+            argv_hash(&["ls".into(), "-la".into()]),
+            argv_hash(&["ls -la".into()])
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! A per-run directory which collects all the artifacts of a run in one place, along with an index
+//! describing them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::output::{self, OutputFormat};
+
+pub const INDEX_NAME: &str = "index.json";
+
+#[derive(Debug)]
+pub struct RunDir {
+    path: PathBuf,
+    /// Paths of the artifacts written to the directory, relative to it.
+    artifacts: Vec<(&'static str, String)>,
+}
+
+impl RunDir {
+    /// Creates a new, uniquely named, run directory inside `base`.
+    pub fn create(base: &Path, cmd: &str) -> Result<RunDir> {
+        let path = output::resolve_dir(&base.join("{cmd}-{date}-{n}"), cmd)?;
+        fs::create_dir_all(&path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+
+        Ok(RunDir {
+            path,
+            artifacts: vec![],
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the results report into the directory.
+    pub fn write_report(&mut self, format: OutputFormat, report: &Value) -> Result<()> {
+        let name = format!("results.{}", format.extension());
+        output::write_file(&self.path.join(&name), format, report)?;
+        self.artifacts.push(("results", name));
+        Ok(())
+    }
+
+    /// Writes an arbitrary artifact into the directory. The name may contain subdirectories.
+    pub fn write_artifact(
+        &mut self,
+        kind: &'static str,
+        name: &str,
+        contents: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&path, contents)?;
+        self.artifacts.push((kind, name.to_string()));
+        Ok(())
+    }
+
+    /// Writes the index, which lists all the artifacts in the directory.
+    pub fn write_index(&self, command: &[String]) -> Result<()> {
+        let mut artifacts = serde_json::Map::new();
+        for (kind, name) in &self.artifacts {
+            let entry = artifacts.entry(*kind).or_insert_with(|| json!([]));
+            entry
+                .as_array_mut()
+                .expect("artifact list")
+                .push(name.clone().into());
+        }
+
+        let index = json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "command": command,
+            "artifacts": artifacts,
+        });
+
+        fs::write(self.path.join(INDEX_NAME), index.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn run_dir() {
+        let base = std::env::temp_dir().join(format!("max_rss-rundir-{}", process::id()));
+
+        let mut dir = RunDir::create(&base, "ls").unwrap();
+        assert!(dir.path().starts_with(&base));
+        assert!(dir
+            .path()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("ls-"));
+
+        dir.write_report(OutputFormat::Json, &json!({ "max_rss": 1 }))
+            .unwrap();
+        dir.write_artifact("smaps", "smaps/1.txt", "Rss: 4 kB")
+            .unwrap();
+        dir.write_index(&["ls".into()]).unwrap();
+
+        let index = fs::read_to_string(dir.path().join(INDEX_NAME)).unwrap();
+        let index = serde_json::from_str::<Value>(&index).unwrap();
+        assert_eq!(index["command"], json!(["ls"]));
+        assert_eq!(index["artifacts"]["results"], json!(["results.json"]));
+        assert_eq!(index["artifacts"]["smaps"], json!(["smaps/1.txt"]));
+
+        // a second run shouldn't clobber the first
+        let other = RunDir::create(&base, "ls").unwrap();
+        assert_ne!(dir.path(), other.path());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
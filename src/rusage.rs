@@ -0,0 +1,215 @@
+//! The rusage backend, which only waits for COMMAND and reports the `ru_maxrss` the kernel gives
+//! back from `wait4`. It's coarse (the largest single process COMMAND waited for, rather than the
+//! whole tree at once) but needs nothing beyond fork, exec and wait, so works wherever they do.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::ffi::{CString, OsStr};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::libc::{rusage, timeval};
+use nix::sys::wait::{WaitPidFlag, WaitStatus};
+use nix::unistd::{chdir, execve, fork, setpgid, ForkResult, Pid};
+
+use crate::child::{self, Forwarding, Inherited, Trace};
+use crate::childlog::{ChildLog, ChildOutput, LogCapture};
+use crate::cli::Args;
+use crate::exec::ExecCheck;
+use crate::report::{Report, SCHEMA_VERSION};
+#[cfg(target_os = "linux")]
+use crate::seccomp::Filter;
+use crate::timeout::Timeout;
+
+/// How often we check whether COMMAND has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// `ru_maxrss` is in kilobytes everywhere but macOS, where it's in bytes.
+pub fn max_rss_bytes(usage: &rusage) -> u64 {
+    if cfg!(target_os = "macos") {
+        usage.ru_maxrss as u64
+    } else {
+        usage.ru_maxrss as u64 * 1024
+    }
+}
+
+pub fn millis(time: timeval) -> u64 {
+    time.tv_sec as u64 * 1000 + time.tv_usec as u64 / 1000
+}
+
+/// Executes `argv` with the environment `envp`, searching the `PATH` in it for the program if its
+/// name has no slash, as `execvpe` does (which not every platform has). Only returns if none of the
+/// candidates could be executed, with the error from the most telling one.
+fn execvpe(argv: &[CString], envp: &[CString], path: Option<&OsStr>) -> Errno {
+    let name = argv[0].as_bytes();
+    if name.contains(&b'/') {
+        return execve(&argv[0], argv, envp).expect_err("failed to execve");
+    }
+
+    let mut errno = Errno::ENOENT;
+    for dir in env::split_paths(path.unwrap_or(OsStr::new("/usr/bin:/bin"))) {
+        let Ok(candidate) = CString::new(
+            dir.join(OsStr::from_bytes(name))
+                .into_os_string()
+                .into_vec(),
+        ) else {
+            continue;
+        };
+        match execve(&candidate, argv, envp).expect_err("failed to execve") {
+            // keep looking, but report that it was found if nothing else can be executed either
+            Errno::EACCES => errno = Errno::EACCES,
+            Errno::ENOENT | Errno::ENOTDIR => {}
+            e => return e,
+        }
+    }
+
+    errno
+}
+
+/// Sets up the forked child and executes the COMMAND given in `args`. This is all the other backends
+/// do too, minus whatever they need from Linux. Only returns if something failed before the COMMAND
+/// could be executed. Must only be called in the forked child.
+pub fn exec_command(
+    args: &Args,
+    inherited: &Inherited,
+    log: Option<&ChildLog>,
+    output: Option<&ChildOutput>,
+    check: &ExecCheck,
+    #[cfg(target_os = "linux")] seccomp: Option<&Filter>,
+) -> Result<Infallible> {
+    let argv = args
+        .command
+        .iter()
+        .map(|s| CString::new(s.as_bytes()).unwrap())
+        .collect::<Vec<CString>>();
+    let env = child::command_env(args);
+    let path = env
+        .iter()
+        .find_map(|(key, value)| (key == "PATH").then(|| value.clone()));
+    let envp = env
+        .into_iter()
+        .map(|(key, value)| {
+            let mut var = key.into_vec();
+            var.push(b'=');
+            var.extend(value.into_vec());
+            CString::new(var).unwrap()
+        })
+        .collect::<Vec<CString>>();
+
+    match log {
+        Some(log) => log.redirect()?,
+        None => args.child_stdout.redirect()?,
+    }
+    if let Some(output) = output {
+        output.redirect()?;
+    }
+    inherited.restore(&[check.fd()])?;
+
+    // this is inherited across fork and exec, so covers the whole tree
+    #[cfg(target_os = "linux")]
+    if args.no_new_privs {
+        // SAFETY: PR_SET_NO_NEW_PRIVS takes no pointers
+        Errno::result(unsafe { nix::libc::prctl(nix::libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+    }
+
+    if let Some(cwd) = &args.cwd {
+        chdir(cwd).with_context(|| format!("failed to change directory to {}", cwd.display()))?;
+    }
+
+    // so the whole tree can be stopped at once if it times out, without stopping us as well
+    if args.timeout.is_some() {
+        setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+    }
+
+    // this is done as late as possible, so the profile only needs to allow exec itself
+    #[cfg(target_os = "linux")]
+    if let Some(seccomp) = seccomp {
+        seccomp.apply()?;
+    }
+
+    check.send(execvpe(&argv, &envp, path.as_deref()));
+    process::exit(args.exit_codes.exec);
+}
+
+/// Runs the COMMAND given in `args`, and reports what the kernel says about it once it exits.
+pub fn trace(args: &Args, inherited: &Inherited) -> Result<Trace> {
+    let log = ChildLog::create(args.log_child.as_deref())?;
+    let output = ChildOutput::create(args.stdout.as_deref(), args.stderr.as_deref())?;
+    let check = ExecCheck::create()?;
+    #[cfg(target_os = "linux")]
+    let seccomp = args.seccomp.as_deref().map(Filter::load).transpose()?;
+
+    let child = match unsafe { fork() }? {
+        ForkResult::Child => {
+            match exec_command(
+                args,
+                inherited,
+                log.as_ref(),
+                output.as_ref(),
+                &check,
+                #[cfg(target_os = "linux")]
+                seccomp.as_ref(),
+            )? {}
+        }
+        ForkResult::Parent { child } => child,
+    };
+    if let Some(failed) = check.wait(args)? {
+        return failed.finish(child);
+    }
+
+    if args.debug {
+        eprintln!("::: pid of tracee: {:?}", child);
+    }
+
+    let capture = log.map(|log| log.start(child)).transpose()?;
+    let mut timeout = Timeout::create(args, child, Instant::now());
+    let _forwarding = Forwarding::start(args, child)?;
+
+    // polled rather than blocking, so the timeout can still fire
+    let (status, usage) = loop {
+        if let (status, Some(usage)) = child::wait4(child, WaitPidFlag::WNOHANG)? {
+            break (status, usage);
+        }
+        if let Some(timeout) = &mut timeout {
+            timeout.check();
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let (exit_code, exit_signal) = match status {
+        WaitStatus::Exited(_, code) => (Some(code), None),
+        WaitStatus::Signaled(_, signal, _) => (None, Some(signal as i32)),
+        _ => (None, None),
+    };
+    let ru_maxrss = args.rusage.then(|| max_rss_bytes(&usage));
+
+    let report = Report {
+        schema_version: Some(SCHEMA_VERSION),
+        max_rss: max_rss_bytes(&usage),
+        rusage_max_rss: Some(max_rss_bytes(&usage)),
+        ru_maxrss,
+        utime_ms: Some(millis(usage.ru_utime)),
+        stime_ms: Some(millis(usage.ru_stime)),
+        minor_faults: Some(usage.ru_minflt as u64),
+        major_faults: Some(usage.ru_majflt as u64),
+        exit_code,
+        exit_signal,
+        timed_out: timeout.as_ref().map(Timeout::timed_out),
+        child_log: capture.map(LogCapture::finish),
+        child_output: output.map(ChildOutput::finish),
+        ..Report::default()
+    };
+
+    Ok(Trace {
+        report: serde_json::to_value(report)?,
+        exit_code: args.exit_with.exit_code(exit_code, exit_signal),
+        snapshots: HashMap::new(),
+        error: None,
+    })
+}
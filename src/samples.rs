@@ -0,0 +1,79 @@
+//! Reads timelines written with `--timeline`, reconstructing the absolute RSS of each sample even
+//! when they were stored as deltas (with `--timeline-delta`).
+//!
+//! ```no_run
+//! for sample in max_rss::samples::read("timeline.jsonl".as_ref()).unwrap() {
+//!     println!("{}ms: {} bytes", sample.elapsed_ms, sample.rss);
+//! }
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+/// A single sample from a timeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// The milliseconds since COMMAND started.
+    pub elapsed_ms: u64,
+    /// The wall-clock time of the sample, unless the timeline was written with `--clock monotonic`.
+    pub time: Option<String>,
+    /// The total RSS, in bytes.
+    pub rss: u64,
+}
+
+/// Reads the samples from a timeline file, skipping any other lines (such as checkpoints).
+///
+/// Each rotated file starts with an absolute sample, so they can be read independently.
+pub fn read(path: &Path) -> Result<Vec<Sample>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse(&text).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Parses the lines of a timeline.
+pub fn parse(text: &str) -> Result<Vec<Sample>> {
+    let mut samples = vec![];
+    let mut last: Option<u64> = None;
+    for line in text.lines().filter(|l| !l.trim().is_empty()) {
+        let line = serde_json::from_str::<Value>(line)?;
+        let rss = match (line["rss"].as_u64(), line["delta"].as_i64(), last) {
+            (Some(rss), _, _) => rss,
+            (None, Some(delta), Some(last)) => last.saturating_add_signed(delta),
+            (None, Some(_), None) => bail!("delta sample without a preceding absolute sample"),
+            (None, None, _) => continue,
+        };
+
+        last = Some(rss);
+        samples.push(Sample {
+            elapsed_ms: line["elapsed_ms"].as_u64().unwrap_or(0),
+            time: line["time"].as_str().map(String::from),
+            rss,
+        });
+    }
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconstructs_deltas() -> Result<()> {
+        let text = r#"
+{"elapsed_ms":0,"time":null,"rss":100}
+{"elapsed_ms":1,"time":null,"delta":20}
+{"elapsed_ms":2,"time":null,"checkpoint":{"samples":2}}
+{"elapsed_ms":3,"time":null,"delta":-70}
+{"elapsed_ms":4,"time":null,"rss":10}
+"#;
+        let rss = parse(text)?.iter().map(|s| s.rss).collect::<Vec<_>>();
+        assert_eq!(rss, vec![100, 120, 50, 10]);
+
+        assert!(parse(r#"{"elapsed_ms":0,"delta":1}"#).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,450 @@
+//! Applies an OCI-style seccomp profile (as used by Docker, Podman and runc) to COMMAND just before
+//! it's executed, so untrusted code can be sandboxed while still being traced.
+//!
+//! The profile is compiled into a classic BPF program here, rather than with libseccomp. Only the
+//! native architecture is allowed, and syscalls from any other ABI are killed.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use nix::errno::Errno;
+use nix::libc::{self, sock_filter, sock_fprog};
+use serde_json::Value;
+
+/// Pairs each `SYS_*` constant with its name.
+macro_rules! syscalls {
+    ($($name:ident),* $(,)?) => {
+        &[$((stringify!($name), libc::$name as u32)),*]
+    };
+}
+
+/// The syscalls shared by every supported architecture.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[rustfmt::skip]
+const COMMON_SYSCALLS: &[(&str, u32)] = syscalls!(
+    SYS_io_setup, SYS_io_destroy, SYS_io_submit, SYS_io_cancel, SYS_io_getevents, SYS_setxattr,
+    SYS_lsetxattr, SYS_fsetxattr, SYS_getxattr, SYS_lgetxattr, SYS_fgetxattr, SYS_listxattr,
+    SYS_llistxattr, SYS_flistxattr, SYS_removexattr, SYS_lremovexattr, SYS_fremovexattr, SYS_getcwd,
+    SYS_lookup_dcookie, SYS_eventfd2, SYS_epoll_create1, SYS_epoll_ctl, SYS_epoll_pwait, SYS_dup,
+    SYS_dup3, SYS_fcntl, SYS_inotify_init1, SYS_inotify_add_watch, SYS_inotify_rm_watch, SYS_ioctl,
+    SYS_ioprio_set, SYS_ioprio_get, SYS_flock, SYS_mknodat, SYS_mkdirat, SYS_unlinkat,
+    SYS_symlinkat, SYS_linkat, SYS_umount2, SYS_mount, SYS_pivot_root, SYS_nfsservctl, SYS_statfs,
+    SYS_fstatfs, SYS_truncate, SYS_ftruncate, SYS_fallocate, SYS_faccessat, SYS_chdir, SYS_fchdir,
+    SYS_chroot, SYS_fchmod, SYS_fchmodat, SYS_fchownat, SYS_fchown, SYS_openat, SYS_close,
+    SYS_vhangup, SYS_pipe2, SYS_quotactl, SYS_getdents64, SYS_lseek, SYS_read, SYS_write, SYS_readv,
+    SYS_writev, SYS_pread64, SYS_pwrite64, SYS_preadv, SYS_pwritev, SYS_pselect6, SYS_ppoll,
+    SYS_signalfd4, SYS_vmsplice, SYS_splice, SYS_tee, SYS_readlinkat, SYS_newfstatat, SYS_fstat,
+    SYS_sync, SYS_fsync, SYS_fdatasync, SYS_timerfd_create, SYS_timerfd_settime,
+    SYS_timerfd_gettime, SYS_utimensat, SYS_acct, SYS_capget, SYS_capset, SYS_personality, SYS_exit,
+    SYS_exit_group, SYS_waitid, SYS_set_tid_address, SYS_unshare, SYS_futex, SYS_set_robust_list,
+    SYS_get_robust_list, SYS_nanosleep, SYS_getitimer, SYS_setitimer, SYS_kexec_load,
+    SYS_init_module, SYS_delete_module, SYS_timer_create, SYS_timer_gettime, SYS_timer_getoverrun,
+    SYS_timer_settime, SYS_timer_delete, SYS_clock_settime, SYS_clock_gettime, SYS_clock_getres,
+    SYS_clock_nanosleep, SYS_syslog, SYS_ptrace, SYS_sched_setparam, SYS_sched_setscheduler,
+    SYS_sched_getscheduler, SYS_sched_getparam, SYS_sched_setaffinity, SYS_sched_getaffinity,
+    SYS_sched_yield, SYS_sched_get_priority_max, SYS_sched_get_priority_min,
+    SYS_sched_rr_get_interval, SYS_restart_syscall, SYS_kill, SYS_tkill, SYS_tgkill,
+    SYS_sigaltstack, SYS_rt_sigsuspend, SYS_rt_sigaction, SYS_rt_sigprocmask, SYS_rt_sigpending,
+    SYS_rt_sigtimedwait, SYS_rt_sigqueueinfo, SYS_rt_sigreturn, SYS_setpriority, SYS_getpriority,
+    SYS_reboot, SYS_setregid, SYS_setgid, SYS_setreuid, SYS_setuid, SYS_setresuid, SYS_getresuid,
+    SYS_setresgid, SYS_getresgid, SYS_setfsuid, SYS_setfsgid, SYS_times, SYS_setpgid, SYS_getpgid,
+    SYS_getsid, SYS_setsid, SYS_getgroups, SYS_setgroups, SYS_uname, SYS_sethostname,
+    SYS_setdomainname, SYS_getrusage, SYS_umask, SYS_prctl, SYS_getcpu, SYS_gettimeofday,
+    SYS_settimeofday, SYS_adjtimex, SYS_getpid, SYS_getppid, SYS_getuid, SYS_geteuid, SYS_getgid,
+    SYS_getegid, SYS_gettid, SYS_sysinfo, SYS_mq_open, SYS_mq_unlink, SYS_mq_timedsend,
+    SYS_mq_timedreceive, SYS_mq_notify, SYS_mq_getsetattr, SYS_msgget, SYS_msgctl, SYS_msgrcv,
+    SYS_msgsnd, SYS_semget, SYS_semctl, SYS_semtimedop, SYS_semop, SYS_shmget, SYS_shmctl,
+    SYS_shmat, SYS_shmdt, SYS_socket, SYS_socketpair, SYS_bind, SYS_listen, SYS_accept, SYS_connect,
+    SYS_getsockname, SYS_getpeername, SYS_sendto, SYS_recvfrom, SYS_setsockopt, SYS_getsockopt,
+    SYS_shutdown, SYS_sendmsg, SYS_recvmsg, SYS_readahead, SYS_brk, SYS_munmap, SYS_mremap,
+    SYS_add_key, SYS_request_key, SYS_keyctl, SYS_clone, SYS_execve, SYS_mmap, SYS_swapon,
+    SYS_swapoff, SYS_mprotect, SYS_msync, SYS_mlock, SYS_munlock, SYS_mlockall, SYS_munlockall,
+    SYS_mincore, SYS_madvise, SYS_remap_file_pages, SYS_mbind, SYS_get_mempolicy, SYS_set_mempolicy,
+    SYS_migrate_pages, SYS_move_pages, SYS_rt_tgsigqueueinfo, SYS_perf_event_open, SYS_accept4,
+    SYS_recvmmsg, SYS_wait4, SYS_prlimit64, SYS_fanotify_init, SYS_fanotify_mark,
+    SYS_name_to_handle_at, SYS_open_by_handle_at, SYS_clock_adjtime, SYS_syncfs, SYS_setns,
+    SYS_sendmmsg, SYS_process_vm_readv, SYS_process_vm_writev, SYS_kcmp, SYS_finit_module,
+    SYS_sched_setattr, SYS_sched_getattr, SYS_renameat2, SYS_seccomp, SYS_getrandom,
+    SYS_memfd_create, SYS_bpf, SYS_execveat, SYS_userfaultfd, SYS_membarrier, SYS_mlock2,
+    SYS_copy_file_range, SYS_preadv2, SYS_pwritev2, SYS_pkey_mprotect, SYS_pkey_alloc,
+    SYS_pkey_free, SYS_statx, SYS_rseq, SYS_kexec_file_load, SYS_pidfd_send_signal,
+    SYS_io_uring_setup, SYS_io_uring_enter, SYS_io_uring_register, SYS_open_tree, SYS_move_mount,
+    SYS_fsopen, SYS_fsconfig, SYS_fsmount, SYS_fspick, SYS_pidfd_open, SYS_clone3, SYS_close_range,
+    SYS_openat2, SYS_pidfd_getfd, SYS_faccessat2, SYS_process_madvise, SYS_epoll_pwait2,
+    SYS_mount_setattr, SYS_quotactl_fd, SYS_landlock_create_ruleset, SYS_landlock_add_rule,
+    SYS_landlock_restrict_self, SYS_memfd_secret, SYS_process_mrelease, SYS_futex_waitv,
+    SYS_set_mempolicy_home_node,
+);
+
+/// The legacy syscalls which only x86_64 has.
+#[cfg(target_arch = "x86_64")]
+#[rustfmt::skip]
+const ARCH_SYSCALLS: &[(&str, u32)] = syscalls!(
+    SYS_open, SYS_stat, SYS_lstat, SYS_poll, SYS_access, SYS_pipe, SYS_select, SYS_dup2, SYS_pause,
+    SYS_alarm, SYS_sendfile, SYS_fork, SYS_vfork, SYS_getdents, SYS_rename, SYS_mkdir, SYS_rmdir,
+    SYS_creat, SYS_link, SYS_unlink, SYS_symlink, SYS_readlink, SYS_chmod, SYS_chown, SYS_lchown,
+    SYS_getrlimit, SYS_getpgrp, SYS_utime, SYS_mknod, SYS_uselib, SYS_ustat, SYS_sysfs,
+    SYS_modify_ldt, SYS__sysctl, SYS_arch_prctl, SYS_setrlimit, SYS_iopl, SYS_ioperm,
+    SYS_create_module, SYS_get_kernel_syms, SYS_query_module, SYS_getpmsg, SYS_putpmsg,
+    SYS_afs_syscall, SYS_tuxcall, SYS_security, SYS_time, SYS_set_thread_area, SYS_get_thread_area,
+    SYS_epoll_create, SYS_epoll_ctl_old, SYS_epoll_wait_old, SYS_fadvise64, SYS_epoll_wait,
+    SYS_utimes, SYS_vserver, SYS_inotify_init, SYS_futimesat, SYS_renameat, SYS_sync_file_range,
+    SYS_signalfd, SYS_eventfd,
+);
+
+#[cfg(not(target_arch = "x86_64"))]
+const ARCH_SYSCALLS: &[(&str, u32)] = &[];
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const COMMON_SYSCALLS: &[(&str, u32)] = &[];
+
+/// The native architecture, as it appears in `seccomp_data` and in profiles.
+#[cfg(target_arch = "x86_64")]
+const ARCH: (u32, &[&str]) = (0xc000_003e, &["SCMP_ARCH_X86_64", "amd64", "x86_64"]);
+#[cfg(target_arch = "aarch64")]
+const ARCH: (u32, &[&str]) = (0xc000_00b7, &["SCMP_ARCH_AARCH64", "arm64", "aarch64"]);
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const ARCH: (u32, &[&str]) = (0, &[]);
+
+/// Set on the syscall numbers of the x32 ABI, which shares x86_64's architecture.
+#[cfg(target_arch = "x86_64")]
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+/// Offsets into `struct seccomp_data`.
+const NR_OFFSET: u32 = 0;
+const ARCH_OFFSET: u32 = 4;
+const ARGS_OFFSET: u32 = 16;
+
+const LD: u16 = (libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16;
+const RET: u16 = (libc::BPF_RET | libc::BPF_K) as u16;
+const AND: u16 = (libc::BPF_ALU | libc::BPF_AND | libc::BPF_K) as u16;
+const JEQ: u16 = (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16;
+const JGT: u16 = (libc::BPF_JMP | libc::BPF_JGT | libc::BPF_K) as u16;
+const JGE: u16 = (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16;
+
+fn stmt(code: u16, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter { code, jt, jf, k }
+}
+
+fn syscall_number(name: &str) -> Option<u32> {
+    COMMON_SYSCALLS
+        .iter()
+        .chain(ARCH_SYSCALLS)
+        .find_map(|(n, nr)| (n.strip_prefix("SYS_") == Some(name)).then_some(*nr))
+}
+
+/// Where a jump within a rule goes.
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// Skip this many instructions.
+    Skip(u8),
+    /// Give up on this rule, and move on to the next.
+    Fail,
+}
+
+use Target::{Fail, Skip};
+
+/// An instruction within a rule, whose jumps are resolved once the rule's length is known.
+#[derive(Debug, Clone, Copy)]
+enum Insn {
+    Stmt(u16, u32),
+    Jump(u16, u32, Target, Target),
+}
+
+/// Loads the high or low 32 bits of a syscall argument.
+fn load_arg(index: u32, high: bool) -> Insn {
+    Insn::Stmt(LD, ARGS_OFFSET + index * 8 + if high { 4 } else { 0 })
+}
+
+/// Compiles a condition on a syscall argument, which falls through when it holds and jumps to
+/// `Fail` otherwise. Arguments are 64-bit, so are compared in two halves.
+fn compile_arg(arg: &Value) -> Result<Vec<Insn>> {
+    let index = arg["index"]
+        .as_u64()
+        .filter(|i| *i < 6)
+        .ok_or_else(|| anyhow!("invalid argument index: {}", arg["index"]))? as u32;
+    let value = arg["value"].as_u64().unwrap_or(0);
+    let (hi, lo) = ((value >> 32) as u32, value as u32);
+
+    let op = arg["op"].as_str().unwrap_or_default();
+    Ok(match op {
+        "SCMP_CMP_EQ" => vec![
+            load_arg(index, true),
+            Insn::Jump(JEQ, hi, Skip(0), Fail),
+            load_arg(index, false),
+            Insn::Jump(JEQ, lo, Skip(0), Fail),
+        ],
+        "SCMP_CMP_NE" => vec![
+            load_arg(index, true),
+            Insn::Jump(JEQ, hi, Skip(0), Skip(2)),
+            load_arg(index, false),
+            Insn::Jump(JEQ, lo, Fail, Skip(0)),
+        ],
+        "SCMP_CMP_MASKED_EQ" => {
+            let expected = arg["valueTwo"].as_u64().unwrap_or(0);
+            vec![
+                load_arg(index, true),
+                Insn::Stmt(AND, hi),
+                Insn::Jump(JEQ, (expected >> 32) as u32, Skip(0), Fail),
+                load_arg(index, false),
+                Insn::Stmt(AND, lo),
+                Insn::Jump(JEQ, expected as u32, Skip(0), Fail),
+            ]
+        }
+        "SCMP_CMP_GT" | "SCMP_CMP_GE" => vec![
+            load_arg(index, true),
+            Insn::Jump(JGT, hi, Skip(3), Skip(0)),
+            Insn::Jump(JEQ, hi, Skip(0), Fail),
+            load_arg(index, false),
+            Insn::Jump(
+                if op == "SCMP_CMP_GT" { JGT } else { JGE },
+                lo,
+                Skip(0),
+                Fail,
+            ),
+        ],
+        "SCMP_CMP_LT" | "SCMP_CMP_LE" => vec![
+            load_arg(index, true),
+            Insn::Jump(JGT, hi, Fail, Skip(0)),
+            Insn::Jump(JEQ, hi, Skip(0), Skip(2)),
+            load_arg(index, false),
+            Insn::Jump(
+                if op == "SCMP_CMP_LT" { JGE } else { JGT },
+                lo,
+                Fail,
+                Skip(0),
+            ),
+        ],
+        _ => bail!("unsupported argument comparison: {:?}", op),
+    })
+}
+
+/// Converts an action such as `SCMP_ACT_ERRNO` into the value a filter returns for it.
+fn compile_action(action: &Value, errno: Option<u64>) -> Result<u32> {
+    Ok(match action.as_str().unwrap_or_default() {
+        "SCMP_ACT_ALLOW" => libc::SECCOMP_RET_ALLOW,
+        "SCMP_ACT_LOG" => libc::SECCOMP_RET_LOG,
+        "SCMP_ACT_ERRNO" => {
+            let errno = errno.unwrap_or(libc::EPERM as u64);
+            libc::SECCOMP_RET_ERRNO | (errno as u32 & libc::SECCOMP_RET_DATA)
+        }
+        "SCMP_ACT_TRAP" => libc::SECCOMP_RET_TRAP,
+        "SCMP_ACT_KILL" | "SCMP_ACT_KILL_THREAD" => libc::SECCOMP_RET_KILL_THREAD,
+        "SCMP_ACT_KILL_PROCESS" => libc::SECCOMP_RET_KILL_PROCESS,
+        // max_rss is already the tracer, and doesn't handle seccomp stops
+        other => bail!("unsupported action: {:?}", other),
+    })
+}
+
+/// Whether a syscall entry applies here. Entries which are only for certain capabilities are
+/// skipped, since COMMAND isn't granted any, as are those for other architectures.
+fn applies(entry: &Value) -> bool {
+    let includes = &entry["includes"];
+    let caps = includes["caps"].as_array().is_some_and(|c| !c.is_empty());
+    let arches = includes["arches"].as_array().filter(|a| !a.is_empty());
+    let native = arches.is_none_or(|a| {
+        a.iter()
+            .any(|a| ARCH.1.contains(&a.as_str().unwrap_or_default()))
+    });
+
+    !caps && native
+}
+
+/// A compiled seccomp filter.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    program: Vec<sock_filter>,
+}
+
+impl Filter {
+    /// Reads and compiles a profile.
+    pub fn load(path: &Path) -> Result<Filter> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let profile = serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Filter::compile(&profile)
+            .with_context(|| format!("invalid seccomp profile {}", path.display()))
+    }
+
+    fn compile(profile: &Value) -> Result<Filter> {
+        if ARCH.0 == 0 {
+            bail!("seccomp profiles aren't supported on this architecture");
+        }
+
+        let default_errno = profile["defaultErrnoRet"].as_u64();
+        let default = compile_action(&profile["defaultAction"], default_errno)?;
+
+        // anything not for this architecture (or ABI) is killed
+        let mut program = vec![
+            stmt(LD, ARCH_OFFSET),
+            jump(JEQ, ARCH.0, 1, 0),
+            stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+        ];
+        #[cfg(target_arch = "x86_64")]
+        program.extend([
+            stmt(LD, NR_OFFSET),
+            jump(JGE, X32_SYSCALL_BIT, 0, 1),
+            stmt(RET, libc::SECCOMP_RET_KILL_PROCESS),
+        ]);
+
+        for entry in profile["syscalls"].as_array().into_iter().flatten() {
+            if !applies(entry) {
+                continue;
+            }
+
+            let action = compile_action(
+                &entry["action"],
+                entry["errnoRet"].as_u64().or(default_errno),
+            )?;
+            if action == default {
+                continue;
+            }
+
+            let mut checks = vec![];
+            for arg in entry["args"].as_array().into_iter().flatten() {
+                checks.extend(compile_arg(arg)?);
+            }
+
+            let names = entry["names"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .chain(entry.get("name"))
+                .filter_map(Value::as_str);
+            for name in names {
+                // profiles are shared between architectures, so may name syscalls we don't have
+                let Some(nr) = syscall_number(name) else {
+                    continue;
+                };
+
+                let mut rule = vec![
+                    Insn::Stmt(LD, NR_OFFSET),
+                    Insn::Jump(JEQ, nr, Skip(0), Fail),
+                ];
+                rule.extend(&checks);
+                rule.push(Insn::Stmt(RET, action));
+                program.extend(assemble(&rule)?);
+            }
+        }
+
+        program.push(stmt(RET, default));
+        if program.len() > u16::MAX as usize {
+            bail!("the profile is too large ({} instructions)", program.len());
+        }
+
+        Ok(Filter { program })
+    }
+
+    /// Installs the filter on the calling process, along with no_new_privs (which an unprivileged
+    /// process must set first). Must only be called in the forked child, just before exec.
+    pub fn apply(&self) -> Result<()> {
+        let prog = sock_fprog {
+            len: self.program.len() as u16,
+            filter: self.program.as_ptr() as *mut sock_filter,
+        };
+
+        // SAFETY: neither call retains the pointer, which is valid for the length given
+        unsafe {
+            Errno::result(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            Errno::result(libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const sock_fprog,
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resolves a rule's jumps, where `Fail` goes to the instruction after the rule.
+fn assemble(rule: &[Insn]) -> Result<Vec<sock_filter>> {
+    let resolve = |at: usize, target: Target| -> Result<u8> {
+        match target {
+            Skip(n) => Ok(n),
+            Fail => u8::try_from(rule.len() - at - 1).map_err(|_| anyhow!("rule is too long")),
+        }
+    };
+
+    rule.iter()
+        .enumerate()
+        .map(|(at, insn)| {
+            Ok(match *insn {
+                Insn::Stmt(code, k) => stmt(code, k),
+                Insn::Jump(code, k, jt, jf) => jump(code, k, resolve(at, jt)?, resolve(at, jf)?),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn syscalls() {
+        assert_eq!(syscall_number("read"), Some(libc::SYS_read as u32));
+        assert_eq!(
+            syscall_number("exit_group"),
+            Some(libc::SYS_exit_group as u32)
+        );
+        assert_eq!(syscall_number("not_a_syscall"), None);
+    }
+
+    #[test]
+    fn compiles() -> Result<()> {
+        let allow_all = Filter::compile(&json!({ "defaultAction": "SCMP_ACT_ALLOW" }))?;
+        let header = allow_all.program.len() - 1;
+
+        let filter = Filter::compile(&json!({
+            "defaultAction": "SCMP_ACT_ALLOW",
+            "syscalls": [
+                { "names": ["uname", "no_such_syscall"], "action": "SCMP_ACT_ERRNO" },
+                { "names": ["read"], "action": "SCMP_ACT_ALLOW" },
+                { "names": ["mount"], "action": "SCMP_ACT_KILL", "includes": { "caps": ["CAP_SYS_ADMIN"] } },
+                {
+                    "names": ["personality"],
+                    "action": "SCMP_ACT_ERRNO",
+                    "errnoRet": 22,
+                    "args": [{ "index": 0, "value": 8, "op": "SCMP_CMP_NE" }]
+                }
+            ]
+        }))?;
+
+        // uname: load, compare, return
+        let uname = &filter.program[header..header + 3];
+        assert_eq!(uname[1].k, libc::SYS_uname as u32);
+        assert_eq!((uname[1].jt, uname[1].jf), (0, 1));
+        assert_eq!(uname[2].k, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32);
+
+        // personality: load, compare, 4 for the argument, return
+        let personality = &filter.program[header + 3..header + 10];
+        assert_eq!((personality[1].jt, personality[1].jf), (0, 5));
+        assert_eq!((personality[3].jt, personality[3].jf), (0, 2));
+        assert_eq!((personality[5].jt, personality[5].jf), (1, 0));
+        assert_eq!(personality[6].k, libc::SECCOMP_RET_ERRNO | 22);
+
+        assert_eq!(filter.program.len(), header + 11);
+        assert_eq!(filter.program[header + 10].k, libc::SECCOMP_RET_ALLOW);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects() {
+        assert!(Filter::compile(&json!({})).is_err());
+        assert!(Filter::compile(&json!({ "defaultAction": "SCMP_ACT_TRACE" })).is_err());
+        assert!(Filter::compile(&json!({
+            "defaultAction": "SCMP_ACT_ALLOW",
+            "syscalls": [{ "names": ["read"], "action": "SCMP_ACT_ERRNO", "args": [{ "index": 6, "op": "SCMP_CMP_EQ" }] }]
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Appends runs to an SQLite database (see `--output-format sqlite`), so results collected over
+//! many runs can be queried with SQL. Each run is a row in `runs`, with its processes in
+//! `processes` and any `--record-samples` in `samples`, both keyed by `run_id`.
+//!
+//! This needs the `sqlite` feature, since it bundles SQLite itself.
+
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::Value;
+
+#[cfg(feature = "sqlite")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    command TEXT,
+    started_at TEXT,
+    finished_at TEXT,
+    max_rss INTEGER NOT NULL,
+    total_pids INTEGER,
+    wall_time_ms REAL,
+    exit_code INTEGER,
+    exit_signal INTEGER,
+    tags TEXT,
+    report TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS processes (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    pid INTEGER NOT NULL,
+    parent_pid INTEGER,
+    position TEXT,
+    name TEXT,
+    cmdline TEXT,
+    rss INTEGER NOT NULL,
+    utime_ms INTEGER,
+    stime_ms INTEGER,
+    wall_time_ms INTEGER
+);
+CREATE TABLE IF NOT EXISTS samples (
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    pid INTEGER NOT NULL,
+    elapsed_ms INTEGER NOT NULL,
+    rss INTEGER NOT NULL
+);
+";
+
+/// Adds the run to the database at `path`, creating it (and its tables) if needed.
+#[cfg(feature = "sqlite")]
+pub fn append(path: &Path, report: &Value) -> Result<()> {
+    use anyhow::Context;
+    use rusqlite::{params, Connection, Transaction};
+
+    fn insert(tx: &Transaction, run_id: i64, parent: Option<i64>, node: &Value) -> Result<()> {
+        let pid = node["id"].as_i64();
+        tx.execute(
+            "INSERT INTO processes (run_id, pid, parent_pid, position, name, cmdline, rss, \
+            utime_ms, stime_ms, wall_time_ms) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                run_id,
+                pid,
+                parent,
+                node["position"].as_str(),
+                node["name"].as_str(),
+                (!node["cmdline"].is_null()).then(|| node["cmdline"].to_string()),
+                node["rss"].as_i64().unwrap_or(0),
+                node["utime_ms"].as_i64(),
+                node["stime_ms"].as_i64(),
+                node["wall_time_ms"].as_i64(),
+            ],
+        )?;
+        for sample in node["samples"].as_array().into_iter().flatten() {
+            tx.execute(
+                "INSERT INTO samples (run_id, pid, elapsed_ms, rss) VALUES (?1, ?2, ?3, ?4)",
+                params![run_id, pid, sample[0].as_i64(), sample[1].as_i64()],
+            )?;
+        }
+        for child in node["children"].as_array().into_iter().flatten() {
+            insert(tx, run_id, pid, child)?;
+        }
+
+        Ok(())
+    }
+
+    let mut db =
+        Connection::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    // other instances may be appending to the same database
+    db.busy_timeout(std::time::Duration::from_secs(30))?;
+    db.execute_batch(SCHEMA)?;
+
+    // kept as JSON, which can be queried with e.g.: `tags ->> '$.branch'`
+    let tags = report["meta"]["tags"].is_object();
+    let tx = db.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (command, started_at, finished_at, max_rss, total_pids, wall_time_ms, \
+        exit_code, exit_signal, tags, report) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            report["command"].as_str(),
+            report["meta"]["started_at"].as_str(),
+            report["meta"]["finished_at"].as_str(),
+            report["max_rss"].as_i64().unwrap_or(0),
+            report["total_pids"].as_i64(),
+            report["wall_time_ms"].as_f64(),
+            report["exit_code"].as_i64(),
+            report["exit_signal"].as_i64(),
+            tags.then(|| report["meta"]["tags"].to_string()),
+            report.to_string(),
+        ],
+    )?;
+    let run_id = tx.last_insert_rowid();
+    if !report["graph"].is_null() {
+        insert(&tx, run_id, None, &report["graph"])?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn append(_path: &Path, _report: &Value) -> Result<()> {
+    anyhow::bail!(
+        "the sqlite format isn't available, since {} was built without the `sqlite` feature",
+        env!("CARGO_PKG_NAME")
+    )
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use rusqlite::Connection;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn appends() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("max_rss-sqlite-{}.db", std::process::id()));
+        let report = json!({
+            "command": "sh",
+            "meta": { "started_at": "2024-01-02T03:04:05Z", "tags": { "branch": "main" } },
+            "max_rss": 3072,
+            "total_pids": 2,
+            "graph": {
+                "id": 1,
+                "name": "sh",
+                "rss": 1024,
+                "samples": [[0, 512], [10, 1024]],
+                "children": [{ "id": 2, "name": "ls", "cmdline": ["ls"], "rss": 2048 }],
+            },
+        });
+        append(&path, &report)?;
+        append(&path, &report)?;
+
+        let db = Connection::open(&path)?;
+        let count = |sql: &str| db.query_row(sql, [], |row| row.get::<_, i64>(0));
+        assert_eq!(count("SELECT COUNT(*) FROM runs")?, 2);
+        assert_eq!(count("SELECT COUNT(*) FROM processes WHERE run_id = 2")?, 2);
+        assert_eq!(count("SELECT COUNT(*) FROM samples")?, 4);
+        assert_eq!(
+            count("SELECT COUNT(*) FROM runs WHERE tags ->> '$.branch' = 'main'")?,
+            2
+        );
+        assert_eq!(
+            count("SELECT COUNT(*) FROM runs WHERE started_at = '2024-01-02T03:04:05Z'")?,
+            2
+        );
+        assert_eq!(
+            count("SELECT parent_pid FROM processes WHERE name = 'ls' AND run_id = 1")?,
+            1
+        );
+        assert_eq!(
+            db.query_row("SELECT cmdline FROM processes WHERE pid = 2", [], |row| row
+                .get::<_, String>(0))?,
+            r#"["ls"]"#
+        );
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
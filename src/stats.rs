@@ -0,0 +1,93 @@
+//! Simple descriptive statistics over repeated measurements.
+
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Sample standard deviation.
+    pub stddev: f64,
+}
+
+impl Stats {
+    /// Returns `None` if there are no values.
+    pub fn new(values: &[f64]) -> Option<Stats> {
+        if values.is_empty() {
+            return None;
+        }
+
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).expect("unordered value"));
+
+        let n = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / n as f64;
+        let median = if n.is_multiple_of(2) {
+            (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+        } else {
+            sorted[n / 2]
+        };
+        let stddev = if n > 1 {
+            (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        Some(Stats {
+            min: sorted[0],
+            max: sorted[n - 1],
+            mean,
+            median,
+            stddev,
+        })
+    }
+
+    /// The coefficient of variation, i.e.: the standard deviation relative to the mean.
+    pub fn cv(&self) -> f64 {
+        if self.mean == 0.0 {
+            0.0
+        } else {
+            self.stddev / self.mean
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "median": self.median,
+            "stddev": self.stddev,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(Stats::new(&[]), None);
+    }
+
+    #[test]
+    fn single() {
+        let stats = Stats::new(&[4.0]).unwrap();
+        assert_eq!(stats.median, 4.0);
+        assert_eq!(stats.stddev, 0.0);
+        assert_eq!(stats.cv(), 0.0);
+    }
+
+    #[test]
+    fn many() {
+        let stats = Stats::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(stats.min, 2.0);
+        assert_eq!(stats.max, 9.0);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.median, 4.5);
+        assert!((stats.stddev - 2.138).abs() < 0.001);
+    }
+}
@@ -0,0 +1,92 @@
+//! Sends the headline numbers to statsd as gauges (see `--statsd`), for dashboards which are fed by
+//! statsd or graphite.
+
+use std::net::UdpSocket;
+
+use anyhow::{anyhow, bail, Result};
+use serde_json::Value;
+
+/// Where the gauges are sent, from an address like `localhost:8125`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    host: String,
+    port: u16,
+}
+
+impl Address {
+    pub fn parse(s: &str) -> Result<Address> {
+        let (host, port) = s
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("expected HOST:PORT for --statsd, got: {}", s))?;
+        let port = port
+            .parse()
+            .map_err(|_| anyhow!("invalid port for --statsd: {}", port))?;
+        if host.is_empty() {
+            bail!("missing host for --statsd: {}", s);
+        }
+
+        Ok(Address {
+            host: host.into(),
+            port,
+        })
+    }
+}
+
+/// Replaces anything which would break up a statsd metric name (such as `.`, `:` or `|`).
+fn sanitise(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// The gauges for a run, one per line, e.g.: `max_rss.ls.max_rss:1234|g`.
+pub fn gauges(command: &str, report: &Value) -> String {
+    let prefix = format!("{}.{}", env!("CARGO_PKG_NAME"), sanitise(command));
+
+    ["max_rss", "total_pids", "wall_time_ms"]
+        .into_iter()
+        .filter_map(|key| {
+            let value = report[key].as_f64()?;
+            Some(format!("{}.{}:{}|g\n", prefix, key, value.round() as u64))
+        })
+        .collect()
+}
+
+/// Sends the gauges in a single packet.
+pub fn send(address: &Address, command: &str, report: &Value) -> Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect((address.host.as_str(), address.port))?;
+    socket.send(gauges(command, report).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn addresses() -> Result<()> {
+        let address = Address::parse("localhost:8125")?;
+        assert_eq!(address.host, "localhost");
+        assert_eq!(address.port, 8125);
+
+        assert!(Address::parse("localhost").is_err());
+        assert!(Address::parse("localhost:statsd").is_err());
+        assert!(Address::parse(":8125").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lines() {
+        let report = json!({ "max_rss": 2048, "total_pids": null, "wall_time_ms": 12.6 });
+        assert_eq!(
+            gauges("my.cmd", &report),
+            "max_rss.my_cmd.max_rss:2048|g\nmax_rss.my_cmd.wall_time_ms:13|g\n"
+        );
+    }
+}
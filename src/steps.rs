@@ -0,0 +1,85 @@
+//! Setup and teardown steps, which are run around COMMAND but aren't measured.
+
+use std::process::Command;
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+/// Runs a step with `sh -c`, returning a description of how it went.
+fn run(kind: &str, cmd: &str, debug: bool) -> Result<Value> {
+    if debug {
+        eprintln!("::: running {} step: {}", kind, cmd);
+    }
+
+    let start = Instant::now();
+    let status = Command::new("sh")
+        .args(["-c", cmd])
+        .status()
+        .with_context(|| format!("failed to run {} step: {}", kind, cmd))?;
+
+    Ok(json!({
+        "cmd": cmd,
+        "exit_code": status.code(),
+        "wall_time_ms": start.elapsed().as_secs_f64() * 1000.0,
+    }))
+}
+
+/// Runs each setup step in order, stopping at the first one that fails since COMMAND can't be
+/// expected to work without it.
+pub fn setup(steps: &[String], debug: bool) -> Result<Value> {
+    let mut results = vec![];
+    for cmd in steps {
+        let result = run("setup", cmd, debug)?;
+        if result["exit_code"] != 0 {
+            bail!("setup step failed ({}): {}", result["exit_code"], cmd);
+        }
+        results.push(result);
+    }
+
+    Ok(results.into())
+}
+
+/// Runs every teardown step in order. Failures only warn, so that later steps still get a chance
+/// to clean up.
+pub fn teardown(steps: &[String], debug: bool) -> Value {
+    let mut results = vec![];
+    for cmd in steps {
+        match run("teardown", cmd, debug) {
+            Ok(result) => {
+                if result["exit_code"] != 0 {
+                    eprintln!(
+                        "{}: warning: teardown step failed ({}): {}",
+                        env!("CARGO_PKG_NAME"),
+                        result["exit_code"],
+                        cmd
+                    );
+                }
+                results.push(result);
+            }
+            Err(e) => eprintln!("{}: warning: {:#}", env!("CARGO_PKG_NAME"), e),
+        }
+    }
+
+    results.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setup_stops_on_failure() {
+        let steps = ["true".to_string(), "exit 3".to_string()];
+        let err = setup(&steps, false).unwrap_err();
+        assert_eq!(err.to_string(), "setup step failed (3): exit 3");
+    }
+
+    #[test]
+    fn teardown_runs_everything() {
+        let steps = ["exit 1".to_string(), "true".to_string()];
+        let results = teardown(&steps, false);
+        assert_eq!(results[0]["exit_code"], 1);
+        assert_eq!(results[1]["exit_code"], 0);
+    }
+}
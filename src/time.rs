@@ -0,0 +1,194 @@
+//! Minimal time formatting, so we don't need to depend on a full date/time library.
+
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Error};
+
+/// Which clock timestamps in the results are taken from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// Wall clock time in UTC.
+    #[default]
+    Utc,
+    /// Wall clock time in the local timezone, with its offset from UTC.
+    Local,
+    /// No wall clock time at all, only durations measured with the monotonic clock.
+    Monotonic,
+}
+
+impl Clock {
+    pub const ALL: &'static [(&'static str, Clock)] = &[
+        ("utc", Clock::Utc),
+        ("local", Clock::Local),
+        ("monotonic", Clock::Monotonic),
+    ];
+
+    /// Formats the time as an RFC 3339 timestamp, unless this clock is monotonic only.
+    pub fn timestamp(&self, time: SystemTime) -> Option<String> {
+        match self {
+            Clock::Utc => Some(DateTime::from_system_time(time).rfc3339()),
+            Clock::Local => Some(DateTime::local(time).rfc3339()),
+            Clock::Monotonic => None,
+        }
+    }
+}
+
+impl FromStr for Clock {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Clock::ALL
+            .iter()
+            .find_map(|(name, clock)| (*name == s).then_some(*clock))
+            .ok_or_else(|| anyhow!("unknown clock: {}", s))
+    }
+}
+
+/// A broken down date and time, with its offset from UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// Seconds east of UTC.
+    pub offset: i64,
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+impl DateTime {
+    pub fn from_unix(secs: i64) -> DateTime {
+        let days = secs.div_euclid(86400);
+        let rem = secs.rem_euclid(86400);
+
+        // see: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            offset: 0,
+        }
+    }
+
+    pub fn from_system_time(time: SystemTime) -> DateTime {
+        DateTime::from_unix(unix_secs(time))
+    }
+
+    /// Converts to the local timezone, as configured by `TZ` or `/etc/localtime`.
+    pub fn local(time: SystemTime) -> DateTime {
+        let secs = unix_secs(time);
+
+        // SAFETY: localtime_r is given valid pointers, and doesn't keep them
+        let offset = unsafe {
+            let t = secs as nix::libc::time_t;
+            let mut tm = std::mem::zeroed::<nix::libc::tm>();
+            match nix::libc::localtime_r(&t, &mut tm).is_null() {
+                true => 0,
+                false => tm.tm_gmtoff,
+            }
+        };
+
+        DateTime {
+            offset,
+            ..DateTime::from_unix(secs + offset)
+        }
+    }
+
+    pub fn now() -> DateTime {
+        DateTime::from_system_time(SystemTime::now())
+    }
+
+    /// Formats as a compact timestamp which is safe to use in file names, e.g.: `20240102T030405Z`.
+    pub fn compact(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Formats as an RFC 3339 timestamp, e.g.: `2024-01-02T03:04:05Z` or `2024-01-02T13:04:05+10:00`.
+    pub fn rfc3339(&self) -> String {
+        let offset = match self.offset {
+            0 => "Z".to_string(),
+            o => format!(
+                "{}{:02}:{:02}",
+                if o < 0 { '-' } else { '+' },
+                o.abs() / 3600,
+                o.abs() % 3600 / 60
+            ),
+        };
+
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, offset
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoch() {
+        assert_eq!(DateTime::from_unix(0).compact(), "19700101T000000Z");
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(DateTime::from_unix(951782400).compact(), "20000229T000000Z");
+        assert_eq!(
+            DateTime::from_unix(1704164645).compact(),
+            "20240102T030405Z"
+        );
+        assert_eq!(DateTime::from_unix(-1).compact(), "19691231T235959Z");
+    }
+
+    #[test]
+    fn rfc3339() {
+        let date = DateTime::from_unix(1704164645);
+        assert_eq!(date.rfc3339(), "2024-01-02T03:04:05Z");
+        assert_eq!(
+            DateTime {
+                offset: -(5 * 3600 + 30 * 60),
+                ..date
+            }
+            .rfc3339(),
+            "2024-01-02T03:04:05-05:30"
+        );
+    }
+
+    #[test]
+    fn clocks() {
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1704164645);
+        assert_eq!(
+            Clock::Utc.timestamp(time).as_deref(),
+            Some("2024-01-02T03:04:05Z")
+        );
+        assert!(Clock::Local.timestamp(time).is_some());
+        assert_eq!(Clock::Monotonic.timestamp(time), None);
+        assert!("foo".parse::<Clock>().is_err());
+    }
+}
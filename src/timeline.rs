@@ -0,0 +1,416 @@
+//! Streams periodic samples of the total RSS to a file while COMMAND runs.
+//!
+//! Samples are written out as they're taken rather than kept in memory, and the file can be rotated
+//! by size or age, so very long runs (such as multi-day soak tests) stay bounded both in memory and
+//! in the size of each file.
+//!
+//! With `--timeline-delta`, samples are stored as the change since the last line that was written,
+//! and only when that change is larger than the given epsilon. The first line of each file is
+//! always absolute, so rotated files can be read on their own (see `max_rss::samples`).
+//!
+//! With `--max-samples`, samples are instead kept in memory and decimated as needed so there are
+//! never more than the given number, and the timeline is written out when COMMAND exits.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+use crate::cli::Args;
+use crate::time::Clock;
+use crate::units::format_bytes;
+
+/// Running totals over every sample taken, so a summary is available without keeping the samples.
+#[derive(Debug, Default, Clone)]
+struct Summary {
+    samples: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+    last: u64,
+}
+
+impl Summary {
+    fn add(&mut self, rss: u64) {
+        self.min = if self.samples == 0 {
+            rss
+        } else {
+            self.min.min(rss)
+        };
+        self.max = self.max.max(rss);
+        self.sum += rss as u128;
+        self.last = rss;
+        self.samples += 1;
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "samples": self.samples,
+            "min": self.min,
+            "max": self.max,
+            "mean": (self.samples > 0).then(|| (self.sum / self.samples as u128) as u64),
+            "last": self.last,
+        })
+    }
+}
+
+/// A bounded set of samples. Once it's full, neighbouring samples are merged (keeping the larger of
+/// each pair) and each retained sample then stands for twice as many samples as before, so peaks
+/// are never lost.
+#[derive(Debug)]
+struct Decimated {
+    max: usize,
+    samples: Vec<(Value, u64)>,
+    /// How many samples each retained sample stands for.
+    stride: u64,
+    pending: Option<(Value, u64)>,
+    pending_count: u64,
+}
+
+impl Decimated {
+    fn new(max: usize) -> Decimated {
+        Decimated {
+            max,
+            samples: Vec::with_capacity(max),
+            stride: 1,
+            pending: None,
+            pending_count: 0,
+        }
+    }
+
+    fn add(&mut self, line: Value, rss: u64) {
+        if self.pending.as_ref().is_none_or(|(_, max)| rss > *max) {
+            self.pending = Some((line, rss));
+        }
+
+        self.pending_count += 1;
+        if self.pending_count == self.stride {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            if self.samples.len() >= self.max {
+                self.compact();
+            }
+            self.samples.push(pending);
+        }
+        self.pending_count = 0;
+    }
+
+    fn compact(&mut self) {
+        let samples = std::mem::take(&mut self.samples);
+        let mut samples = samples.into_iter();
+        while let Some(a) = samples.next() {
+            self.samples.push(match samples.next() {
+                Some(b) if b.1 > a.1 => b,
+                _ => a,
+            });
+        }
+        self.stride *= 2;
+    }
+
+    fn finish(mut self) -> Vec<(Value, u64)> {
+        self.flush();
+        self.samples
+    }
+}
+
+pub struct Timeline {
+    path: PathBuf,
+    file: BufWriter<File>,
+    clock: Clock,
+    rotate_size: Option<u64>,
+    rotate_every: Option<Duration>,
+    checkpoint: Option<Duration>,
+    delta: Option<u64>,
+    decimated: Option<Decimated>,
+    start: Instant,
+    opened: Instant,
+    written: u64,
+    rotations: usize,
+    last_checkpoint: Instant,
+    /// The RSS as of the last line written to the current file, which deltas are relative to.
+    last_written: Option<u64>,
+    summary: Summary,
+}
+
+/// The path a timeline file is moved to when it's rotated for the `n`th time.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn open(path: &Path) -> Result<BufWriter<File>> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+impl Timeline {
+    /// Starts a new timeline, if one was asked for.
+    pub fn create(args: &Args) -> Result<Option<Timeline>> {
+        let Some(path) = &args.timeline else {
+            return Ok(None);
+        };
+
+        let now = Instant::now();
+        Ok(Some(Timeline {
+            path: path.clone(),
+            file: open(path)?,
+            clock: args.clock,
+            rotate_size: args.rotate_size,
+            rotate_every: args.rotate_every,
+            checkpoint: args.checkpoint,
+            delta: args.timeline_delta,
+            decimated: args.max_samples.map(Decimated::new),
+            start: now,
+            opened: now,
+            written: 0,
+            rotations: 0,
+            last_checkpoint: now,
+            last_written: None,
+            summary: Summary::default(),
+        }))
+    }
+
+    /// Records a sample of the total RSS, and a checkpoint summary if one is due.
+    pub fn sample(&mut self, rss: u64) -> Result<()> {
+        self.summary.add(rss);
+        let line = self.line();
+        match &mut self.decimated {
+            Some(decimated) => decimated.add(line, rss),
+            None => self.write_sample(line, rss, false)?,
+        }
+
+        if let Some(every) = self.checkpoint {
+            if self.last_checkpoint.elapsed() >= every {
+                self.last_checkpoint = Instant::now();
+                self.write_checkpoint()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes a sample, either in full or as a delta. Deltas within the epsilon are skipped unless
+    /// `force` is set.
+    fn write_sample(&mut self, mut line: Value, rss: u64, force: bool) -> Result<()> {
+        self.rotate_if_due()?;
+
+        match (self.delta, self.last_written) {
+            (Some(epsilon), Some(last)) => {
+                let change = rss as i64 - last as i64;
+                if change.unsigned_abs() <= epsilon && !(force && change != 0) {
+                    return Ok(());
+                }
+                line["delta"] = change.into();
+            }
+            _ => line["rss"] = rss.into(),
+        }
+
+        self.last_written = Some(rss);
+        self.write_line(&line)
+    }
+
+    /// The fields common to every line.
+    fn line(&self) -> Value {
+        json!({
+            "time": self.clock.timestamp(SystemTime::now()),
+            "elapsed_ms": self.start.elapsed().as_millis() as u64,
+        })
+    }
+
+    fn write_checkpoint(&mut self) -> Result<()> {
+        let mut line = self.line();
+        line["checkpoint"] = self.summary.to_json();
+        self.write_line(&line)?;
+
+        eprintln!(
+            "{}: checkpoint: {} samples, min: {}, max: {}, last: {}",
+            env!("CARGO_PKG_NAME"),
+            self.summary.samples,
+            format_bytes(self.summary.min),
+            format_bytes(self.summary.max),
+            format_bytes(self.summary.last),
+        );
+
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &Value) -> Result<()> {
+        self.rotate_if_due()?;
+
+        let text = format!("{}\n", line);
+        self.file.write_all(text.as_bytes())?;
+        self.written += text.len() as u64;
+
+        Ok(())
+    }
+
+    fn rotate_if_due(&mut self) -> Result<()> {
+        let too_big = self.rotate_size.is_some_and(|size| self.written >= size);
+        let too_old = self
+            .rotate_every
+            .is_some_and(|every| self.opened.elapsed() >= every);
+        if too_big || too_old {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the current file out of the way, and starts a new one in its place.
+    fn rotate(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.rotations += 1;
+        fs::rename(&self.path, rotated_path(&self.path, self.rotations))?;
+
+        self.file = open(&self.path)?;
+        self.opened = Instant::now();
+        self.written = 0;
+        self.last_written = None;
+
+        Ok(())
+    }
+
+    /// Flushes the timeline, and returns a summary of it for the results.
+    pub fn finish(mut self) -> Result<Value> {
+        let mut retained = None;
+        if let Some(decimated) = self.decimated.take() {
+            let samples = decimated.finish();
+            retained = Some(samples.len());
+
+            // replace anything written while running (i.e. checkpoints) with the retained samples
+            self.file = open(&self.path)?;
+            self.written = 0;
+            self.last_written = None;
+            for (line, rss) in samples {
+                self.write_sample(line, rss, false)?;
+            }
+        }
+
+        // make sure the timeline ends on the last value sampled, even if it was within the epsilon
+        if self.delta.is_some() && self.summary.samples > 0 {
+            let line = self.line();
+            self.write_sample(line, self.summary.last, true)?;
+        }
+        self.file.flush()?;
+
+        let mut summary = self.summary.to_json();
+        summary["path"] = self.path.to_string_lossy().into();
+        summary["rotations"] = self.rotations.into();
+        if let Some(retained) = retained {
+            summary["retained"] = retained.into();
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut summary = Summary::default();
+        assert_eq!(summary.to_json()["mean"], Value::Null);
+
+        for rss in [30, 10, 20] {
+            summary.add(rss);
+        }
+        assert_eq!(
+            summary.to_json(),
+            json!({ "samples": 3, "min": 10, "max": 30, "mean": 20, "last": 20 })
+        );
+    }
+
+    #[test]
+    fn rotates() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("max_rss-timeline-{}", process::id()));
+        let path = dir.join("timeline.jsonl");
+
+        let args = Args {
+            timeline: Some(path.clone()),
+            rotate_size: Some(1),
+            ..Args::default()
+        };
+        let mut timeline = Timeline::create(&args)?.expect("no timeline");
+        for rss in [1, 2, 3] {
+            timeline.sample(rss)?;
+        }
+        let summary = timeline.finish()?;
+
+        assert_eq!(summary["rotations"], 2);
+        assert!(fs::read_to_string(rotated_path(&path, 1))?.contains(r#""rss":1"#));
+        assert!(fs::read_to_string(rotated_path(&path, 2))?.contains(r#""rss":2"#));
+        assert!(fs::read_to_string(&path)?.contains(r#""rss":3"#));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn decimates() {
+        let mut decimated = Decimated::new(4);
+        for (i, rss) in [1, 2, 9, 3, 4, 5, 1, 6, 2, 1].into_iter().enumerate() {
+            decimated.add(json!(i), rss);
+        }
+
+        // never more than 4 samples are kept, and the peak at index 2 is one of them
+        let samples = decimated.finish();
+        assert_eq!(
+            samples,
+            vec![(json!(2), 9), (json!(5), 5), (json!(7), 6), (json!(9), 1)]
+        );
+    }
+
+    #[test]
+    fn deltas() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("max_rss-timeline-delta-{}", process::id()));
+        let path = dir.join("timeline.jsonl");
+
+        let args = Args {
+            timeline: Some(path.clone()),
+            timeline_delta: Some(10),
+            ..Args::default()
+        };
+        let mut timeline = Timeline::create(&args)?.expect("no timeline");
+        for rss in [100, 105, 120, 50, 55] {
+            timeline.sample(rss)?;
+        }
+        let summary = timeline.finish()?;
+        assert_eq!(summary["samples"], 5);
+
+        let text = fs::read_to_string(&path)?;
+        let lines = text
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(lines[0]["rss"], 100);
+        assert_eq!(lines[1]["delta"], 20);
+        assert_eq!(lines[2]["delta"], -70);
+        assert_eq!(lines[3]["delta"], 5);
+        assert_eq!(lines.len(), 4);
+
+        let rss = crate::samples::read(&path)?
+            .iter()
+            .map(|s| s.rss)
+            .collect::<Vec<_>>();
+        assert_eq!(rss, vec![100, 120, 50, 55]);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+//! Stops COMMAND if it runs for too long (see `--timeout`), so a hung process doesn't hang us too.
+//! Likewise stops whatever COMMAND leaves running once it exits (see `--kill-remaining`).
+
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::killpg;
+use nix::sys::signal::Signal::{self, SIGKILL, SIGTERM};
+use nix::unistd::Pid;
+
+use crate::cli::Args;
+
+/// How long COMMAND has to exit after being sent SIGTERM, before it's sent SIGKILL, if
+/// `--kill-after` isn't given.
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(5);
+
+/// How often the deadline is checked while waiting for COMMAND.
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub struct Timeout {
+    /// COMMAND's process group, which it's put into when there's a timeout.
+    pgid: Pid,
+    timeout: Duration,
+    deadline: Instant,
+    grace: Duration,
+    /// When COMMAND was sent SIGTERM, if it has been.
+    terminated: Option<Instant>,
+    killed: bool,
+}
+
+impl Timeout {
+    pub fn create(args: &Args, child: Pid, start: Instant) -> Option<Timeout> {
+        let timeout = args.timeout?;
+        Some(Timeout {
+            pgid: child,
+            timeout,
+            deadline: start + timeout,
+            grace: args.kill_after,
+            terminated: None,
+            killed: false,
+        })
+    }
+
+    /// Signals COMMAND's process group if its time is up: first with SIGTERM, and then with SIGKILL
+    /// if it still hasn't exited once the grace period is over.
+    pub fn check(&mut self) {
+        match self.terminated {
+            None if Instant::now() >= self.deadline => {
+                self.signal(SIGTERM);
+                self.terminated = Some(Instant::now());
+            }
+            Some(at) if !self.killed && at.elapsed() >= self.grace => {
+                self.signal(SIGKILL);
+                self.killed = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn signal(&self, signal: Signal) {
+        eprintln!(
+            "{}: COMMAND timed out after {:?}, sending {}",
+            env!("CARGO_PKG_NAME"),
+            self.timeout,
+            signal
+        );
+
+        // the group may already be gone, in which case there's nothing left to stop
+        let _ = killpg(self.pgid, signal);
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.terminated.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub struct KillRemaining {
+    /// How long processes may keep running after COMMAND exits, before they're sent SIGTERM.
+    grace: Duration,
+    /// How long they have to exit after SIGTERM, before they're sent SIGKILL.
+    kill_after: Duration,
+    /// When COMMAND exited, once it has.
+    exited: Option<Instant>,
+    /// When the remaining processes were sent SIGTERM, if they have been.
+    terminated: Option<Instant>,
+    killed: bool,
+}
+
+impl KillRemaining {
+    pub fn create(args: &Args) -> Option<KillRemaining> {
+        Some(KillRemaining {
+            grace: args.kill_remaining?,
+            kill_after: args.kill_after,
+            exited: None,
+            terminated: None,
+            killed: false,
+        })
+    }
+
+    /// Starts the grace period, if it hasn't already been started.
+    pub fn exited(&mut self) {
+        self.exited.get_or_insert_with(Instant::now);
+    }
+
+    /// Which signal (if any) the remaining processes should be sent now: SIGTERM once the grace
+    /// period is over, and then SIGKILL if they still haven't exited after `--kill-after`.
+    pub fn check(&mut self) -> Option<Signal> {
+        match (self.exited, self.terminated) {
+            (Some(at), None) if at.elapsed() >= self.grace => {
+                self.terminated = Some(Instant::now());
+                Some(SIGTERM)
+            }
+            (_, Some(at)) if !self.killed && at.elapsed() >= self.kill_after => {
+                self.killed = true;
+                Some(SIGKILL)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,1734 @@
+//! The ptrace based tracer, which follows the COMMAND and all of its descendants.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::process::CommandExt;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use nix::errno::Errno;
+use nix::sys::pthread::{pthread_kill, pthread_self};
+use nix::sys::ptrace::{self, Event, Options};
+use nix::sys::signal::Signal::{SIGALRM, SIGINT, SIGSTOP, SIGTERM, SIGTSTP, SIGTTIN, SIGTTOU};
+use nix::sys::signal::{kill, sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{chdir, close, execvpe, fork, pause, setpgid, ForkResult, Pid};
+use serde_json::Map;
+
+use crate::alert::{self, Alert};
+use crate::child::{self, command_env, wait4, Forwarding, Inherited, Trace};
+use crate::childlog::{ChildLog, ChildOutput, LogCapture};
+use crate::cli::Args;
+use crate::events::{self, Events};
+use crate::exec::ExecCheck;
+use crate::permission::TraceDenied;
+use crate::pidns;
+use crate::policy::{ConcurrentPeak, CountPolicy, MemSource, ProcessFilter, RssSource};
+use crate::proc;
+use crate::report::{self, EventCounts, ExitStatus, ProcessNode, Report, Segment};
+use crate::seccomp::Filter;
+use crate::timeline::Timeline;
+use crate::timeout::{self, KillRemaining, Timeout};
+use crate::verify::{self, Source};
+
+#[derive(Debug, Default, Clone)]
+struct ProcInfo {
+    /// Whether this process has exited.
+    exited: bool,
+
+    /// All known children of this process, including its threads.
+    children: Vec<Pid>,
+
+    /// The process this is a thread of, or `None` when it's a process in its own right. Threads
+    /// share their process' memory, so they never count towards the total.
+    leader: Option<Pid>,
+
+    /// Measured RSS for this process. Captured at the last moment before process exit, or taken
+    /// from its high-water mark with `--source hwm`.
+    rss: u64,
+
+    /// The RSS captured just before exit, kept only when `rss` was taken from another source so the
+    /// two can be compared.
+    exit_rss: Option<u64>,
+
+    /// The process' Unique Set Size (`Private_Clean` + `Private_Dirty`), read alongside its RSS at
+    /// exit. Missing for processes whose `smaps_rollup` couldn't be read then.
+    uss: Option<u64>,
+
+    /// The process' Proportional Set Size at exit, which is what's counted with `--count-policy
+    /// pss-sum`.
+    pss: Option<u64>,
+
+    /// How much of the process' memory was swapped out at exit. This isn't included in its RSS, so
+    /// when it's large the RSS is lower than what the process really used.
+    swap: Option<u64>,
+
+    /// Whether we lost the ability to trace this process (for example, because it exec'd a
+    /// privileged binary and was detached from us). Such processes are polled via `/proc` instead,
+    /// so their values are less accurate.
+    untraced: bool,
+
+    /// Whether this process exec'd a setuid/setgid binary or one with file capabilities.
+    privileged_exec: bool,
+
+    /// Whether this process was orphaned and reparented to us before we knew of it, in which case
+    /// its original parent isn't known and it's kept under the root instead.
+    orphaned: bool,
+
+    /// Whether this process was still running once the root exited, and was killed with
+    /// `--kill-remaining`.
+    killed_at_teardown: bool,
+
+    /// When this process last exec'd a new program, after which none of its memory is shared with
+    /// its parent any more.
+    execd: Option<Duration>,
+
+    /// How many times this process (or any of its threads) forked, cloned and exec'd.
+    events: EventCounts,
+
+    /// The programs this process ran before its last exec. `rss` is only that of its last program,
+    /// while the largest of them all is what counts towards the total.
+    segments: Vec<Segment>,
+
+    /// Whether this process was filtered out by `--only` or `--exclude`, so doesn't count towards
+    /// the total. Decided whenever it's named, until which it's the same as its parent.
+    excluded: bool,
+
+    /// The process' name and arguments. These are read again whenever the process execs and just
+    /// before it exits, since some programs change them at runtime to something more meaningful.
+    name: Option<String>,
+    cmdline: Option<Vec<String>>,
+
+    /// Whether this process exec'd a 32-bit binary.
+    elf32: bool,
+
+    /// When the process started and ended, since COMMAND started. Processes which were already
+    /// running when we attached have no start, and those we stopped tracing have no end.
+    started: Option<Duration>,
+    ended: Option<Duration>,
+
+    /// How the process ended, once it has. Processes we stopped tracing before then are reported as
+    /// detached.
+    exit_status: Option<ExitStatus>,
+
+    /// The CPU time used by this process, how long it ran for and the page faults it caused, read
+    /// just before it exits (or when we stop measuring it).
+    stat: Option<proc::Stat>,
+
+    /// The process' I/O counters just before it exits, only read with `--io`.
+    io: Option<proc::Io>,
+
+    /// The kernel's high-water mark of this process' RSS, only read when verifying or when it's the
+    /// source of `rss`.
+    hwm: Option<u64>,
+
+    /// The process' RSS over time, only recorded with `--record-samples`.
+    samples: Series,
+
+    /// What's left of descendants which have exited and were dropped to bound our own memory use.
+    evicted: Evicted,
+}
+
+impl ProcInfo {
+    /// The largest RSS of any program the process ran, which is what counts towards the total.
+    fn peak_rss(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter_map(|s| s.rss)
+            .fold(self.rss, u64::max)
+    }
+}
+
+/// A process' RSS over time, as `(milliseconds since COMMAND started, rss)` points. A point is only
+/// kept when the value changes, so long-lived idle processes stay small.
+#[derive(Debug, Default, Clone)]
+struct Series(Vec<(u64, u64)>);
+
+impl Series {
+    fn push(&mut self, elapsed: Duration, rss: u64) {
+        if self.0.last().is_none_or(|(_, last)| *last != rss) {
+            self.0.push((elapsed.as_millis() as u64, rss));
+        }
+    }
+
+    /// The points, which are serialised as `[[ms, rss], ...]` since that's compact and easy to
+    /// stream or plot.
+    fn to_report(&self) -> Option<Vec<(u64, u64)>> {
+        (!self.0.is_empty()).then(|| self.0.clone())
+    }
+}
+
+/// The totals of exited subtrees which were dropped from the process table (see `--max-procs`).
+#[derive(Debug, Default, Clone, Copy)]
+struct Evicted {
+    pids: usize,
+    threads: usize,
+    rss: u64,
+    exit_rss: u64,
+    uss: u64,
+    pss: u64,
+    swap: u64,
+    reads: usize,
+    hwm: u64,
+    untraced: bool,
+    utime: Duration,
+    stime: Duration,
+    minor_faults: u64,
+    major_faults: u64,
+    io: Option<proc::Io>,
+}
+
+impl Evicted {
+    fn add(&mut self, other: Evicted) {
+        self.pids += other.pids;
+        self.threads += other.threads;
+        self.rss += other.rss;
+        self.exit_rss += other.exit_rss;
+        self.uss += other.uss;
+        self.pss += other.pss;
+        self.swap += other.swap;
+        self.reads += other.reads;
+        self.hwm += other.hwm;
+        self.untraced |= other.untraced;
+        self.utime += other.utime;
+        self.stime += other.stime;
+        self.minor_faults += other.minor_faults;
+        self.major_faults += other.major_faults;
+        add_io(&mut self.io, other.io);
+    }
+}
+
+/// Adds I/O counters to a total, which stays `None` until there's something in it.
+fn add_io(total: &mut Option<proc::Io>, io: Option<proc::Io>) {
+    if let Some(io) = io {
+        total.get_or_insert_with(proc::Io::default).add(io);
+    }
+}
+
+fn io_report(io: proc::Io) -> report::Io {
+    report::Io {
+        rchar: io.rchar,
+        wchar: io.wchar,
+        read_bytes: io.read_bytes,
+        write_bytes: io.write_bytes,
+    }
+}
+
+/// Whether a process' memory counts towards the total. With the default `root-and-parents` policy,
+/// only when:
+///  - the process was the parent `tracee` process we created ourselves
+///  - the process itself spawned other processes
+///  - the process exec'd a new program (such as a compiler run by a build system)
+///
+/// because linux uses copy-on-write for new processes, even if a process forks many times it won't
+/// use more memory, unless one of the new children itself allocates more memory. Exec'ing replaces
+/// all of that, so what's left is the new program's own. The other policies count every process.
+/// Threads never count, since their memory is their process'.
+fn counted(pid: Pid, root: Pid, info: &ProcInfo, policy: CountPolicy) -> bool {
+    info.leader.is_none()
+        && !info.excluded
+        && match policy {
+            CountPolicy::RootAndParents => {
+                pid == root
+                    || info.execd.is_some()
+                    || !info.children.is_empty()
+                    || info.evicted.pids > 0
+            }
+            CountPolicy::All | CountPolicy::PssSum | CountPolicy::ConcurrentPeak => true,
+        }
+}
+
+/// Removes the subtree at `pid` from the process table, returning its totals.
+fn fold(pid: Pid, root: Pid, procs: &mut HashMap<Pid, ProcInfo>, policy: CountPolicy) -> Evicted {
+    let info = procs.remove(&pid).expect("untracked pid");
+    let mut evicted = info.evicted;
+    evicted.pids += 1;
+    evicted.threads += info.leader.is_some() as usize;
+    evicted.untraced |= info.untraced;
+    // unlike memory, every process' CPU time and faults are its own
+    if let Some(stat) = info.stat {
+        evicted.utime += stat.utime;
+        evicted.stime += stat.stime;
+        evicted.minor_faults += stat.minor_faults;
+        evicted.major_faults += stat.major_faults;
+    }
+    add_io(&mut evicted.io, info.io);
+    if counted(pid, root, &info, policy) {
+        evicted.rss += info.peak_rss();
+        evicted.exit_rss += info.exit_rss.unwrap_or(info.rss);
+        evicted.uss += info.uss.unwrap_or(0);
+        evicted.pss += info.pss.unwrap_or(info.rss);
+        evicted.swap += info.swap.unwrap_or(0);
+        evicted.reads += 1;
+        evicted.hwm += info.hwm.unwrap_or(0);
+    }
+
+    for child in info.children {
+        evicted.add(fold(child, root, procs, policy));
+    }
+
+    evicted
+}
+
+/// Folds every subtree in which all processes have exited into the closest process which hasn't,
+/// so a long running build doesn't keep every process it ever ran in memory. Returns whether the
+/// whole subtree at `pid` has exited, in which case it's left to its parent to fold.
+fn evict_exited(
+    pid: Pid,
+    root: Pid,
+    procs: &mut HashMap<Pid, ProcInfo>,
+    policy: CountPolicy,
+) -> bool {
+    let mut exited = procs[&pid].exited;
+    let mut done = vec![];
+    for child in procs[&pid].children.clone() {
+        if evict_exited(child, root, procs, policy) {
+            done.push(child);
+        } else {
+            exited = false;
+        }
+    }
+
+    if exited && pid != root {
+        return true;
+    }
+
+    for child in done {
+        let evicted = fold(child, root, procs, policy);
+        let info = procs.get_mut(&pid).expect("untracked pid");
+        info.evicted.add(evicted);
+        info.children.retain(|c| *c != child);
+    }
+
+    false
+}
+
+fn tree(pid: Pid, position: String, table: &HashMap<Pid, ProcInfo>) -> ProcessNode {
+    let info = table.get(&pid).expect("untracked pid");
+    let children = info
+        .children
+        .iter()
+        .enumerate()
+        .map(|(i, child)| tree(*child, format!("{}.{}", position, i), table))
+        .collect::<Vec<_>>();
+
+    // the duration is taken from the rounded times, so the three always add up
+    let started_at = info.started.map(|d| d.as_millis() as u64);
+    let ended_at = info.ended.map(|d| d.as_millis() as u64);
+
+    ProcessNode {
+        id: pid.as_raw(),
+        name: info.name.clone(),
+        comm: info.name.clone(),
+        cmdline: info.cmdline.clone(),
+        argv_hash: info.cmdline.as_deref().map(report::argv_hash),
+        position: Some(position),
+        thread: info.leader.is_some().then_some(true),
+        rss: info.rss,
+        exit_rss: info.exit_rss,
+        uss: info.uss,
+        swap: info.swap,
+        untraced: info.untraced.then_some(true),
+        privileged_exec: info.privileged_exec.then_some(true),
+        orphaned: info.orphaned.then_some(true),
+        killed_at_teardown: info.killed_at_teardown.then_some(true),
+        excluded: info.excluded.then_some(true),
+        elf32: info.elf32.then_some(true),
+        started_at,
+        ended_at,
+        duration_ms: started_at
+            .zip(ended_at)
+            .map(|(started, ended)| ended.saturating_sub(started)),
+        exit_status: match &info.exit_status {
+            Some(status) => Some(status.clone()),
+            None if info.untraced || !info.exited => Some(ExitStatus::Detached),
+            None => None,
+        },
+        utime_ms: info.stat.map(|s| s.utime.as_millis() as u64),
+        stime_ms: info.stat.map(|s| s.stime.as_millis() as u64),
+        wall_time_ms: info.stat.map(|s| s.wall.as_millis() as u64),
+        minor_faults: info.stat.map(|s| s.minor_faults),
+        major_faults: info.stat.map(|s| s.major_faults),
+        io: info.io.map(io_report),
+        evicted: (info.evicted.pids > 0).then(|| report::Evicted {
+            pids: info.evicted.pids,
+            rss: info.evicted.rss,
+            uss: info.evicted.uss,
+            swap: info.evicted.swap,
+            utime_ms: info.evicted.utime.as_millis() as u64,
+            stime_ms: info.evicted.stime.as_millis() as u64,
+            minor_faults: info.evicted.minor_faults,
+            major_faults: info.evicted.major_faults,
+            io: info.evicted.io.map(io_report),
+        }),
+        samples: info.samples.to_report(),
+        segments: (!info.segments.is_empty()).then(|| info.segments.clone()),
+        events: (info.events != EventCounts::default()).then_some(info.events),
+        children: (!children.is_empty()).then_some(children),
+    }
+}
+
+/// Decodes the wait status a process is exiting with, as given by `PTRACE_EVENT_EXIT`.
+fn exit_status(status: i32) -> Option<ExitStatus> {
+    use nix::libc::{WEXITSTATUS, WIFEXITED, WIFSIGNALED, WTERMSIG};
+
+    if WIFEXITED(status) {
+        Some(ExitStatus::Exited(WEXITSTATUS(status)))
+    } else if WIFSIGNALED(status) {
+        let signal = WTERMSIG(status);
+        Some(ExitStatus::Signaled(Signal::try_from(signal).map_or_else(
+            |_| signal.to_string(),
+            |signal| signal.as_str().into(),
+        )))
+    } else {
+        None
+    }
+}
+
+/// Reads the process' current name and arguments, keeping the last known ones if that fails (for
+/// example, because it has already gone).
+fn relabel(pid: Pid, procs: &mut HashMap<Pid, ProcInfo>, filter: &ProcessFilter) {
+    let info = procs.get_mut(&pid).expect("untracked pid");
+    if let Ok(name) = proc::get_comm(pid) {
+        info.name = Some(name);
+    }
+    if let Ok(cmdline) = proc::get_cmdline(pid) {
+        // kernel threads and zombies have no arguments at all, which is no better than what we had
+        if !cmdline.is_empty() {
+            info.cmdline = Some(cmdline);
+        }
+    }
+    info.excluded = filter.excludes(info.name.as_deref(), info.cmdline.as_deref());
+}
+
+/// Called when we've lost the ability to trace a process that is still alive. Rather than losing it
+/// (and its descendants) silently, we keep track of it by polling `/proc` instead.
+fn lose_trace(
+    pid: Pid,
+    procs: &mut HashMap<Pid, ProcInfo>,
+    pool: &mut proc::RssPool,
+    filter: &ProcessFilter,
+    debug: bool,
+) {
+    if debug {
+        eprintln!("::: {} is no longer traced, polling /proc instead", pid);
+    }
+
+    let info = procs.get_mut(&pid).expect("untracked pid");
+    info.untraced = true;
+    poll_untraced(pid, procs, pool, filter, debug);
+}
+
+/// Updates the RSS of an untraced process, and discovers any new children it may have.
+fn poll_untraced(
+    pid: Pid,
+    procs: &mut HashMap<Pid, ProcInfo>,
+    pool: &mut proc::RssPool,
+    filter: &ProcessFilter,
+    debug: bool,
+) {
+    if !proc::is_alive(pid) {
+        procs.entry(pid).and_modify(|i| i.exited = true);
+        return;
+    }
+
+    // we can't know when the process will exit, so keep the largest value we've seen
+    if let Ok(rss) = pool.get_rss(pid) {
+        procs.entry(pid).and_modify(|i| i.rss = i.rss.max(rss));
+    }
+    if let Ok(stat) = proc::get_stat(pid) {
+        procs.entry(pid).and_modify(|i| i.stat = Some(stat));
+    }
+    relabel(pid, procs, filter);
+    let excluded = procs[&pid].excluded;
+
+    for child in proc::get_children(pid) {
+        if procs.contains_key(&child) {
+            continue;
+        }
+
+        if debug {
+            eprintln!("::: {} discovered untraced child {}", pid, child);
+        }
+
+        procs.insert(
+            child,
+            ProcInfo {
+                untraced: true,
+                excluded,
+                ..ProcInfo::default()
+            },
+        );
+        procs.entry(pid).and_modify(|i| i.children.push(child));
+    }
+}
+
+/// Adds the processes which were orphaned and reparented to us (as a subreaper) that we don't know
+/// of yet, out of our `children`. Traced processes are known from the moment they're created, so
+/// these escaped from untraced ones between polls, and are polled in turn.
+fn adopt_orphans(root: Pid, children: Vec<Pid>, procs: &mut HashMap<Pid, ProcInfo>, debug: bool) {
+    for orphan in children {
+        if procs.contains_key(&orphan) || alert::is_helper(orphan) {
+            continue;
+        }
+
+        if debug {
+            eprintln!("::: adopted orphaned process {}", orphan);
+        }
+
+        procs.insert(
+            orphan,
+            ProcInfo {
+                untraced: true,
+                orphaned: true,
+                ..ProcInfo::default()
+            },
+        );
+        procs.entry(root).and_modify(|i| i.children.push(orphan));
+    }
+}
+
+/// Sends `signal` to every process which is still running (their threads go with them), marking
+/// them as killed at teardown. Returns how many hadn't been marked before.
+fn kill_all(procs: &mut HashMap<Pid, ProcInfo>, signal: Signal, debug: bool) -> usize {
+    let mut marked = 0;
+    for (pid, info) in procs.iter_mut() {
+        if info.exited || info.leader.is_some() {
+            continue;
+        }
+
+        if debug {
+            eprintln!("::: sending {} to remaining process {}", signal, pid);
+        }
+
+        // it may have exited since we last heard from it, in which case it's reported soon
+        let _ = kill(*pid, signal);
+        if !info.killed_at_teardown {
+            info.killed_at_teardown = true;
+            marked += 1;
+        }
+    }
+
+    marked
+}
+
+/// The ptrace events that cause a new process to be created.
+const NEW_CHILD_EVENTS: [i32; 3] = [
+    Event::PTRACE_EVENT_FORK as i32,
+    Event::PTRACE_EVENT_VFORK as i32,
+    Event::PTRACE_EVENT_CLONE as i32,
+];
+
+/// The options set on every process we trace, so we can intercept events of interest.
+fn options() -> Options {
+    Options::PTRACE_O_TRACEEXIT
+        | Options::PTRACE_O_TRACEFORK
+        | Options::PTRACE_O_TRACEVFORK
+        | Options::PTRACE_O_TRACECLONE
+        | Options::PTRACE_O_TRACEEXEC
+}
+
+/// Attaches to a process which is already running, along with each of its threads and all of its
+/// descendants, and adds them to the process table. Any which can't be traced are polled instead.
+fn attach(
+    pid: Pid,
+    parent: Option<Pid>,
+    procs: &mut HashMap<Pid, ProcInfo>,
+    filter: &ProcessFilter,
+) -> Result<()> {
+    // the leader is attached first, so its threads can be added as its children (as if it had
+    // cloned them while traced)
+    let mut tasks = proc::get_tasks(pid);
+    tasks.sort_by_key(|task| *task != pid);
+    if tasks.first() != Some(&pid) {
+        bail!("no such process: {}", pid);
+    }
+
+    for task in tasks {
+        let untraced = match ptrace::seize(task, options()) {
+            Ok(()) => false,
+            Err(Errno::EPERM) if parent.is_none() && task == pid => {
+                bail!(TraceDenied::new(pid, Errno::EPERM, false))
+            }
+            Err(e) if parent.is_none() && task == pid => {
+                bail!("failed to attach to {}: {}", pid, e)
+            }
+            Err(_) => true,
+        };
+
+        procs.insert(
+            task,
+            ProcInfo {
+                untraced,
+                leader: (task != pid).then_some(pid),
+                ..ProcInfo::default()
+            },
+        );
+        if task != pid {
+            procs.entry(pid).and_modify(|i| i.children.push(task));
+        }
+    }
+
+    relabel(pid, procs, filter);
+    if let Some(parent) = parent {
+        procs.entry(parent).and_modify(|i| i.children.push(pid));
+    }
+
+    for child in proc::get_children(pid) {
+        if !procs.contains_key(&child) {
+            attach(child, Some(pid), procs, filter)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Set when we're interrupted while attached to a process with `--pid`.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_tick(_: nix::libc::c_int) {}
+
+/// Installs a signal handler without `SA_RESTART`, so the signal interrupts the tracer if it's
+/// blocked waiting for an event.
+fn interrupt_on(signal: Signal, handler: extern "C" fn(nix::libc::c_int)) -> nix::Result<()> {
+    let action = SigAction::new(
+        SigHandler::Handler(handler),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    // SAFETY: the handlers only store to an atomic, if anything
+    unsafe { sigaction(signal, &action) }.map(drop)
+}
+
+/// How often processes we're no longer tracing are polled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Wakes the tracer up every so often while it's blocked waiting for events, so it can still
+/// sample and poll. A separate thread signals the tracer's thread directly, since a process-wide
+/// timer's signal could be handled by any of our threads.
+struct Ticker {
+    period: Option<Duration>,
+    thread: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl Ticker {
+    fn new() -> nix::Result<Ticker> {
+        interrupt_on(SIGALRM, on_tick)?;
+        Ok(Ticker {
+            period: None,
+            thread: None,
+        })
+    }
+
+    /// Changes how often the tracer is woken up, if at all.
+    fn set(&mut self, period: Option<Duration>) {
+        if period == self.period {
+            return;
+        }
+
+        // dropping the sender stops the thread straight away
+        if let Some((stop, thread)) = self.thread.take() {
+            drop(stop);
+            let _ = thread.join();
+        }
+
+        self.period = period;
+        if let Some(period) = period {
+            let tracer = pthread_self();
+            let (stop, ticks) = mpsc::channel();
+            let thread = thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = ticks.recv_timeout(period) {
+                    let _ = pthread_kill(tracer, SIGALRM);
+                }
+            });
+            self.thread = Some((stop, thread));
+        }
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        self.set(None);
+    }
+}
+
+/// Takes the current RSS (and stat, and I/O counters with `io`) of every process which is still
+/// alive as its final value, for when we stop measuring before they exit.
+fn read_remaining(procs: &mut HashMap<Pid, ProcInfo>, pool: &mut proc::RssPool, io: bool) {
+    for (pid, info) in procs.iter_mut().filter(|(_, i)| !i.exited) {
+        if let Ok(rss) = pool.get_rss(*pid) {
+            info.rss = info.rss.max(rss);
+        }
+        if let Ok(stat) = proc::get_stat(*pid) {
+            info.stat = Some(stat);
+        }
+        if io {
+            info.io = proc::get_io(*pid).ok().or(info.io);
+        }
+    }
+}
+
+/// Leaves a tracee in a group-stop stopped, while still letting it report events (most importantly
+/// when it's continued) as if it were running.
+fn listen(pid: Pid) -> nix::Result<()> {
+    // SAFETY: PTRACE_LISTEN takes no pointers
+    Errno::result(unsafe {
+        nix::libc::ptrace(
+            nix::libc::PTRACE_LISTEN,
+            pid.as_raw(),
+            std::ptr::null_mut::<nix::libc::c_void>(),
+            std::ptr::null_mut::<nix::libc::c_void>(),
+        )
+    })
+    .map(drop)
+}
+
+/// Stops tracing every process which is still alive, taking its current RSS (and name) as its final
+/// value, and leaves it as it was (i.e.: running, unless it was stopped by a signal).
+fn detach_all(
+    procs: &mut HashMap<Pid, ProcInfo>,
+    pool: &mut proc::RssPool,
+    filter: &ProcessFilter,
+    io: bool,
+) {
+    read_remaining(procs, pool, io);
+    let remaining = procs
+        .iter()
+        .filter(|(_, i)| !i.exited)
+        .map(|(pid, _)| *pid)
+        .collect::<Vec<_>>();
+    let mut tracees = vec![];
+    for pid in remaining {
+        // some may not have exec'd yet, so have never been named
+        relabel(pid, procs, filter);
+
+        // a tracee can only be detached while it's stopped
+        if !procs[&pid].untraced && ptrace::interrupt(pid).is_ok() {
+            tracees.push(pid);
+        }
+    }
+
+    // whichever stop comes first will do, since it's detached from any of them
+    while let Some(pid) = tracees.pop() {
+        match waitpid(pid, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::PtraceEvent(_, _, event)) => {
+                // a new child is already traced, and begins with a stop of its own
+                if NEW_CHILD_EVENTS.contains(&event) {
+                    if let Ok(child) = ptrace::getevent(pid) {
+                        tracees.push(Pid::from_raw(child as i32));
+                    }
+                }
+                let _ = ptrace::detach(pid, None);
+            }
+            // it was about to receive a signal, which it's given as it's detached
+            Ok(WaitStatus::Stopped(_, signal)) => {
+                let _ = ptrace::detach(pid, signal);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Samples the current RSS of every process which is still alive, keeping the largest value seen for
+/// each (with `--interval`), recording it (with `--record-samples`) or writing it out (with
+/// `--events`), and returns their total counted the same way as the result. With `--count-policy
+/// concurrent-peak` their PSS is also read, and its total kept if it's the largest yet.
+fn sample(
+    root: Pid,
+    procs: &mut HashMap<Pid, ProcInfo>,
+    pool: &mut proc::RssPool,
+    args: &Args,
+    elapsed: Duration,
+    mut events: Option<&mut Events>,
+    concurrent: Option<&mut ConcurrentPeak>,
+) -> Result<u64> {
+    let peaks = args.sample_peaks;
+    let every = peaks || args.record_samples || events.is_some();
+
+    // stop holding on to the processes which have gone since the last sample
+    pool.retain(|pid| procs.get(&pid).is_some_and(|i| !i.exited));
+
+    // only the processes which count towards the total need reading, unless we're after peaks or
+    // each process' own RSS
+    let pids = procs
+        .iter()
+        .filter(|(pid, i)| !i.exited && (every || counted(**pid, root, i, args.count_policy)))
+        .map(|(pid, _)| *pid)
+        .collect::<Vec<_>>();
+
+    let mut total = 0;
+    for (pid, rss) in pool.read(&pids) {
+        let info = procs.get_mut(&pid).expect("untracked pid");
+        if peaks {
+            info.rss = info.rss.max(rss);
+        }
+        if args.record_samples {
+            info.samples.push(elapsed, rss);
+        }
+        if let Some(events) = &mut events {
+            events.sample(pid, rss)?;
+        }
+        if counted(pid, root, info, args.count_policy) {
+            total += rss;
+        }
+    }
+
+    // threads share their process' memory, so only processes are read
+    if let Some(concurrent) = concurrent {
+        concurrent.retain(|pid| procs.get(&pid).is_some_and(|i| !i.exited));
+        let pids = procs
+            .iter()
+            .filter(|(_, i)| !i.exited && i.leader.is_none() && !i.excluded)
+            .map(|(pid, _)| *pid)
+            .collect::<Vec<_>>();
+        for (pid, pss) in pool.read_pss(&pids) {
+            concurrent.set(pid, pss);
+        }
+        concurrent.check();
+    }
+
+    Ok(total)
+}
+
+/// Sets up the forked child and executes the COMMAND given in `args`, or `command` instead when
+/// it's given. `prepare` is run just before executing it, but before any seccomp profile is
+/// applied. Only returns if something failed before the COMMAND could be executed. Must only be
+/// called in the forked child.
+#[allow(clippy::too_many_arguments)]
+pub fn exec_command(
+    args: &Args,
+    inherited: &Inherited,
+    log: Option<&ChildLog>,
+    output: Option<&ChildOutput>,
+    check: &ExecCheck,
+    seccomp: Option<&Filter>,
+    command: Option<&mut Command>,
+    prepare: impl FnOnce() -> Result<()>,
+) -> Result<Infallible> {
+    let argv = args
+        .command
+        .iter()
+        .map(|s| CString::new(s.as_bytes()).unwrap())
+        .collect::<Vec<CString>>();
+    let envp = command_env(args)
+        .into_iter()
+        .map(|(key, value)| {
+            let mut var = key.into_vec();
+            var.push(b'=');
+            var.extend(value.into_vec());
+            CString::new(var).unwrap()
+        })
+        .collect::<Vec<CString>>();
+
+    match log {
+        Some(log) => log.redirect()?,
+        None => args.child_stdout.redirect()?,
+    }
+    if let Some(output) = output {
+        output.redirect()?;
+    }
+    inherited.restore(&[check.fd()])?;
+
+    // this is inherited across fork and exec, so covers the whole tree
+    if args.no_new_privs {
+        // SAFETY: PR_SET_NO_NEW_PRIVS takes no pointers
+        Errno::result(unsafe { nix::libc::prctl(nix::libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })?;
+    }
+
+    if let Some(cwd) = &args.cwd {
+        chdir(cwd).with_context(|| format!("failed to change directory to {}", cwd.display()))?;
+    }
+
+    // so the whole tree can be stopped at once if it times out, without stopping us as well
+    if args.timeout.is_some() {
+        setpgid(Pid::from_raw(0), Pid::from_raw(0))?;
+    }
+
+    prepare()?;
+
+    // this is done as late as possible, so the profile only needs to allow exec itself
+    if let Some(seccomp) = seccomp {
+        seccomp.apply()?;
+    }
+
+    // start the program to be measured
+    let e = match command {
+        // which keeps everything the caller configured it with, e.g.: Command::env_clear
+        Some(command) => Errno::from_i32(command.exec().raw_os_error().unwrap_or(0)),
+        None => execvpe(&argv[0], &argv, &envp).expect_err("failed to execvpe"),
+    };
+    check.send(e);
+    process::exit(args.exit_codes.exec);
+}
+
+/// Runs the COMMAND given in `args` under ptrace, and measures it and all of its descendants.
+pub fn trace(args: &Args, inherited: &Inherited) -> Result<Trace> {
+    trace_command(args, inherited, None, None)
+}
+
+/// Like [`trace`], but runs `command` rather than the COMMAND in `args` when it's given, and sends
+/// each event to `sender`, see [`crate::measure`].
+pub fn trace_command(
+    args: &Args,
+    inherited: &Inherited,
+    mut command: Option<&mut Command>,
+    sender: Option<Sender<events::Event>>,
+) -> Result<Trace> {
+    let log = ChildLog::create(args.log_child.as_deref())?;
+    let output = ChildOutput::create(args.stdout.as_deref(), args.stderr.as_deref())?;
+    let check = ExecCheck::create()?;
+    let seccomp = args.seccomp.as_deref().map(Filter::load).transpose()?;
+
+    // list of all currently known processes
+    let mut procs = HashMap::new();
+
+    // descendants which are orphaned are reparented to us rather than to init, so they can't escape
+    // by daemonizing, and we reap them as they exit
+    if args.pid.is_none() {
+        // SAFETY: PR_SET_CHILD_SUBREAPER takes no pointers
+        Errno::result(unsafe { nix::libc::prctl(nix::libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) })?;
+    }
+
+    let child = match args.pid {
+        // a process which is already running is attached to where it is
+        Some(pid) => {
+            let pid = Pid::from_raw(pid);
+            attach(pid, None, &mut procs, &args.filter)?;
+
+            // there's no COMMAND of ours to interrupt, so stop measuring and report instead
+            interrupt_on(SIGINT, on_interrupt)?;
+            interrupt_on(SIGTERM, on_interrupt)?;
+            pid
+        }
+
+        None => {
+            // the child waits until it's been seized before going any further, which (unlike
+            // PTRACE_TRACEME) doesn't need it to stop itself: the wait ends when we close our end
+            let (seized_read, seized_write) = child::pipe()?;
+            // SAFETY: we created these descriptors, and nothing else uses them
+            let (seized_read, seized_write) = unsafe {
+                (
+                    File::from_raw_fd(seized_read),
+                    File::from_raw_fd(seized_write),
+                )
+            };
+
+            // tracee
+            let mut tracee = || {
+                // our copy of the write end would otherwise keep the pipe open forever
+                let _ = close(seized_write.as_raw_fd());
+                while let Err(e) = (&seized_read).read(&mut [0]) {
+                    if e.kind() != ErrorKind::Interrupted {
+                        break;
+                    }
+                }
+                exec_command(
+                    args,
+                    inherited,
+                    log.as_ref(),
+                    output.as_ref(),
+                    &check,
+                    seccomp.as_ref(),
+                    command.as_deref_mut(),
+                    || Ok(()),
+                )
+            };
+            let child = match args.pidns {
+                true => pidns::spawn(tracee, args.exit_codes.exec)?,
+                false => match unsafe { fork() } {
+                    Ok(ForkResult::Child) => match tracee()? {},
+                    Ok(ForkResult::Parent { child }) => child,
+                    Err(e) => panic!("failed to fork: {}", e),
+                },
+            };
+
+            // tracer: attach to the child along with our options, so we can intercept events of
+            // interest, and then let it carry on
+            drop(seized_read);
+            if let Err(e) = ptrace::seize(child, options()) {
+                return TraceDenied::new(child, e, true).finish(child);
+            }
+            drop(seized_write);
+            if let Some(failed) = check.wait(args)? {
+                return failed.finish(child);
+            }
+
+            procs.insert(child, ProcInfo::default());
+            child
+        }
+    };
+
+    if args.debug {
+        eprintln!("::: pid of tracer: {:?}", nix::unistd::getpid());
+        eprintln!("::: pid of tracee: {:?}", child);
+    }
+
+    let capture = log.map(|log| log.start(child)).transpose()?;
+    let start = Instant::now();
+    if args.pid.is_none() {
+        procs
+            .entry(child)
+            .and_modify(|i| i.started = Some(Duration::ZERO));
+    }
+
+    // how the root process ended
+    let mut exit_code = None;
+    let mut exit_signal = None;
+    // the kernel's own account of the root process' maximum rss, as reported by wait4
+    let mut rusage_max_rss = None;
+
+    // copies of each process' smaps_rollup at exit, only kept when there's somewhere to
+    // put them
+    let mut snapshots = HashMap::new();
+
+    let mut timeline = Timeline::create(args)?;
+    let mut events = Events::create(args, start, sender)?;
+    if let Some(events) = &mut events {
+        events.spawned(child, None)?;
+    }
+    let mut alert = Alert::create(args);
+    let mut last_sample: Option<Instant> = None;
+    let mut pool = proc::RssPool::new(args.mem_source);
+
+    // whether we stopped early because we were interrupted while attached
+    let mut interrupted = false;
+    let mut left_running = None;
+    let mut event_counts = EventCounts::default();
+
+    // whether any process has exited since the process table was last pruned
+    let mut prunable = false;
+
+    // the total PSS of the processes alive at once, with `--count-policy concurrent-peak`
+    let mut concurrent =
+        (args.count_policy == CountPolicy::ConcurrentPeak).then(ConcurrentPeak::default);
+
+    // the PSS of exited processes is only read if the count policy uses it
+    let needs_pss = matches!(
+        args.count_policy,
+        CountPolicy::PssSum | CountPolicy::ConcurrentPeak
+    );
+
+    // the total RSS is only sampled while running if something needs it
+    let sampling = args.sample_peaks
+        || concurrent.is_some()
+        || args.record_samples
+        || timeline.is_some()
+        || events.is_some()
+        || alert.is_some();
+    let mut last_poll: Option<Instant> = None;
+    let mut ticker = Ticker::new()?;
+    let mut timeout = Timeout::create(args, child, start);
+    let mut kill_remaining = KillRemaining::create(args);
+    let mut killed_at_teardown = 0;
+    let _forwarding = args
+        .pid
+        .is_none()
+        .then(|| Forwarding::start(args, child))
+        .transpose()?;
+
+    // a failure part way through shouldn't throw away everything measured until then, so errors
+    // while tracing are returned from here and reported alongside the partial results (which is
+    // also why nothing in here may panic: release builds abort rather than unwind)
+    let traced = (|| -> Result<()> {
+        loop {
+            // if all our processes have exited, we're done tracing
+            if procs.iter().all(|(_, t)| t.exited) {
+                break Ok(());
+            }
+
+            // with --exit-with-root, whatever the root left behind is no longer of interest
+            if args.exit_with_root && procs.get(&child).is_some_and(|i| i.exited) {
+                let remaining = procs
+                    .values()
+                    .filter(|i| !i.exited && i.leader.is_none())
+                    .count();
+                if args.debug {
+                    eprintln!("::: root exited, detaching from {} processes", remaining);
+                }
+                detach_all(&mut procs, &mut pool, &args.filter, args.io);
+                left_running = Some(remaining);
+                break Ok(());
+            }
+
+            if INTERRUPTED.load(Ordering::SeqCst) {
+                if args.debug {
+                    eprintln!("::: interrupted, detaching from all processes");
+                }
+                detach_all(&mut procs, &mut pool, &args.filter, args.io);
+                interrupted = true;
+                break Ok(());
+            }
+
+            let status = if procs.values().any(|i| !i.exited && !i.untraced) {
+                // block until any of our tracees has something to report
+                match wait4(Pid::from_raw(-1), WaitPidFlag::__WALL) {
+                    Ok((status, usage)) => {
+                        // a process we attached to isn't our child, so its usage isn't ours to read
+                        if let (true, None, Some(usage)) =
+                            (status.pid() == Some(child), args.pid, usage)
+                        {
+                            rusage_max_rss = Some(usage.ru_maxrss as u64 * 1024);
+                        }
+                        Some(status)
+                    }
+                    // woken up by the ticker, so it's time to sample or poll
+                    Err(Errno::EINTR) => None,
+                    // none of the processes are our tracees anymore, but they may still be alive:
+                    // this happens when the kernel detaches us, so fall back to polling them
+                    Err(Errno::ECHILD) => {
+                        let lost = procs
+                            .iter()
+                            .filter(|(_, i)| !i.exited && !i.untraced)
+                            .map(|(pid, _)| *pid)
+                            .collect::<Vec<_>>();
+                        for pid in lost {
+                            lose_trace(pid, &mut procs, &mut pool, &args.filter, args.debug);
+                        }
+                        None
+                    }
+                    Err(e) => bail!(e),
+                }
+            } else {
+                // only untraced processes are left, and they're polled each tick
+                pause();
+                None
+            };
+
+            if let Some(status) = status {
+                if args.debug {
+                    eprintln!("::: {:?}", &status);
+                }
+                prunable |= matches!(
+                    status,
+                    WaitStatus::PtraceEvent(_, _, value) if value == Event::PTRACE_EVENT_EXIT as i32
+                );
+
+                // a new child may report its first stop before its parent reports creating it, but
+                // anything else we don't know of isn't ours (e.g.: an --alert-notify command)
+                match status {
+                    WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, _, _)
+                        if !procs.contains_key(&pid) =>
+                    {
+                        continue;
+                    }
+                    _ => {
+                        if let Some(pid) = status.pid() {
+                            procs.entry(pid).or_insert_with(|| ProcInfo {
+                                started: Some(start.elapsed()),
+                                ..ProcInfo::default()
+                            });
+                        }
+                    }
+                }
+
+                match status {
+                    WaitStatus::Exited(pid, code) => {
+                        // stop tracking this pid since the process exited
+                        procs.entry(pid).and_modify(|i| {
+                            i.exited = true;
+                            i.ended.get_or_insert(start.elapsed());
+                            i.exit_status = Some(ExitStatus::Exited(code));
+                        });
+
+                        if pid == child {
+                            exit_code = Some(code);
+                        }
+                    }
+                    WaitStatus::Signaled(pid, signal, _) => {
+                        // stop tracking this pid since the process exited
+                        procs.entry(pid).and_modify(|i| {
+                            i.exited = true;
+                            i.ended.get_or_insert(start.elapsed());
+                            i.exit_status = Some(ExitStatus::Signaled(signal.as_str().into()));
+                        });
+
+                        if pid == child {
+                            exit_signal = Some(signal as i32);
+                        }
+                    }
+                    WaitStatus::PtraceEvent(pid, _, value)
+                        if value == Event::PTRACE_EVENT_EXIT as i32 =>
+                    {
+                        // this event fires early during process exit, so it's at this time we
+                        // read the Rss value (and final name) of the process just before it's
+                        // gone
+                        relabel(pid, &mut procs, &args.filter);
+                        let info = procs
+                            .get_mut(&pid)
+                            .with_context(|| format!("exit event from untracked pid {}", pid))?;
+                        if let Ok(stat) = proc::get_stat(pid) {
+                            info.stat = Some(stat);
+                        }
+                        // most processes are detached from here on, so this is the only chance
+                        // to find out how they ended
+                        if let Ok(status) = ptrace::getevent(pid) {
+                            info.exit_status = exit_status(status as i32);
+                        }
+                        info.ended = Some(start.elapsed());
+                        if args.io {
+                            info.io = proc::get_io(pid).ok();
+                        }
+                        let usage = (|| -> Result<_> {
+                            // only the chosen --mem-source is read, and the USS and swap come
+                            // with it if it has them
+                            let (rss, smaps) = match args.mem_source {
+                                MemSource::SmapsRollup => {
+                                    let text = proc::read_smaps_rollup(pid)?;
+                                    (proc::parse_rss(text.as_bytes())?, Some(text))
+                                }
+                                MemSource::Smaps => {
+                                    let text = proc::read_smaps(pid)?;
+                                    (proc::parse_smaps_rss(text.as_bytes())?, Some(text))
+                                }
+                                source => (source.get_rss(pid)?, None),
+                            };
+                            let uss = smaps
+                                .as_ref()
+                                .and_then(|t| proc::parse_uss(t.as_bytes()).ok());
+                            let swap = smaps
+                                .as_ref()
+                                .and_then(|t| proc::parse_swap(t.as_bytes()).ok());
+
+                            // otherwise smaps_rollup is only read if its PSS or a snapshot of it
+                            // is wanted
+                            let smaps = match smaps {
+                                None if needs_pss || args.out_dir.is_some() => {
+                                    proc::read_smaps_rollup(pid).ok()
+                                }
+                                smaps => smaps,
+                            };
+                            let pss = smaps
+                                .as_ref()
+                                .and_then(|t| proc::parse_pss(t.as_bytes()).ok());
+                            if let (Some(_), Some(smaps)) = (&args.out_dir, smaps) {
+                                snapshots.insert(pid.as_raw(), smaps);
+                            }
+                            Ok((rss, uss, swap, pss))
+                        })();
+                        match usage {
+                            // a sampled peak may be larger than what's left just before exit
+                            Ok((rss, uss, swap, pss)) => {
+                                info.rss = info.rss.max(rss);
+                                info.uss = uss;
+                                info.swap = swap;
+                                if args.record_samples {
+                                    info.samples.push(start.elapsed(), rss);
+                                }
+                                info.pss = pss;
+                                // the process may have grown since it was last sampled, and
+                                // may not have lived long enough to be sampled at all
+                                if let (Some(concurrent), Some(pss), None, false) =
+                                    (&mut concurrent, pss, info.leader, info.excluded)
+                                {
+                                    concurrent.set(pid, pss);
+                                    concurrent.check();
+                                }
+                            }
+                            // don't give up on the whole run if we couldn't read it, wait4's
+                            // rusage will still be able to provide a value for the root
+                            Err(e) if args.debug => {
+                                eprintln!("::: {} failed to read rss: {}", pid, e)
+                            }
+                            Err(_) => {}
+                        }
+                        if args.verify.is_some() || args.source == RssSource::Hwm {
+                            info.hwm = proc::get_hwm(pid).ok();
+                        }
+                        if let (RssSource::Hwm, Some(hwm)) = (args.source, info.hwm) {
+                            info.exit_rss = Some(info.rss);
+                            info.rss = hwm;
+                        }
+                        if let Some(events) = &mut events {
+                            events.exited(pid, info.rss)?;
+                        }
+                        if let Some(concurrent) = &mut concurrent {
+                            concurrent.retain(|live| live != pid);
+                        }
+
+                        match if pid == child {
+                            // we never detach from the root since we'll need its exit event to
+                            // capture its return value and resource usage
+                            ptrace::cont(pid, None)
+                        } else {
+                            // in all other cases, we detach here because we can't know if this process will live
+                            // long enough for us to capture its exit events
+                            info.exited = true;
+                            ptrace::detach(pid, None)
+                        } {
+                            Ok(()) => {}
+                            // Intentionally ignore ESRCH errors here, because as per `man 2 ptrace`'s section
+                            // called "Death under ptrace" we cannot assume that the tracee exists at this point
+                            //
+                            // Reasons why ESRCH may be returned:
+                            //  1. tracee no longer exists
+                            //  2. tracee is not ptrace-stopped
+                            //  3. tracee is not traced by us
+                            //
+                            // In our case 2 and 3 should not be possible, so we should be able to safely ignore 1
+                            // In some cases the call to `get_rss` is slow enough, that by the time we sent another
+                            // ptrace request to the process - the process has already died - so explicitly ignore
+                            // the ESRCH error here.
+                            Err(Errno::ESRCH) => {
+                                info.exited = true;
+                            }
+                            Err(e) => bail!(e),
+                        }
+                    }
+                    WaitStatus::PtraceEvent(pid, _, value) if NEW_CHILD_EVENTS.contains(&value) => {
+                        // since we've set PTRACE_O_TRACE* options, all children will automatically
+                        // be made a tracee for us (and begin with a PTRACE_EVENT_STOP), so add them
+                        // to our list of tracked pids and start handling them (the child may have
+                        // already reported its first stop, in which case it's already known)
+                        let new_pid = ptrace::getevent(pid)?;
+                        let new_pid = Pid::from_raw(new_pid as i32);
+
+                        // anything started by a thread belongs to its process, and new threads
+                        // are told apart from processes by their thread group (or if that's
+                        // already gone, by clone() being how threads are created)
+                        let owner = procs.get(&pid).and_then(|i| i.leader).unwrap_or(pid);
+                        let excluded = procs.get(&owner).is_some_and(|i| i.excluded);
+                        let thread = proc::get_tgid(new_pid)
+                            .map_or(value == Event::PTRACE_EVENT_CLONE as i32, |tgid| {
+                                tgid != new_pid
+                            });
+                        let mut counts = EventCounts::default();
+                        if value == Event::PTRACE_EVENT_FORK as i32 {
+                            counts.forks += 1;
+                        } else if value == Event::PTRACE_EVENT_VFORK as i32 {
+                            counts.vforks += 1;
+                        } else {
+                            counts.clones += 1;
+                        }
+                        event_counts.add(counts);
+                        procs.entry(owner).and_modify(|i| i.events.add(counts));
+
+                        let info = procs.entry(new_pid).or_default();
+                        info.leader = thread.then_some(owner);
+                        info.excluded = excluded;
+                        info.started.get_or_insert(start.elapsed());
+                        procs.entry(owner).and_modify(|i| i.children.push(new_pid));
+                        if let Some(events) = &mut events {
+                            events.spawned(new_pid, Some(owner))?;
+                        }
+
+                        ptrace::cont(pid, None)?;
+                    }
+                    WaitStatus::PtraceEvent(pid, _, value)
+                        if value == Event::PTRACE_EVENT_EXEC as i32 =>
+                    {
+                        // the kernel won't grant privileges to a traced process, so privileged
+                        // helpers (sudo, pkexec, etc) are worth noting since they often behave
+                        // differently (or re-exec themselves) when traced
+                        if proc::is_privileged_exe(pid) {
+                            if args.debug {
+                                eprintln!("::: {} exec'd a privileged binary", pid);
+                            }
+                            procs.entry(pid).and_modify(|i| i.privileged_exec = true);
+                        }
+
+                        // what the process ran before is gone by now, so all that's left of it is
+                        // what was sampled while it ran
+                        if let Some(info) = procs.get_mut(&pid) {
+                            if let Some(execd) = info.execd {
+                                info.segments.push(Segment {
+                                    name: info.name.clone(),
+                                    cmdline: info.cmdline.clone(),
+                                    rss: (info.rss > 0).then_some(info.rss),
+                                    started_at: execd.as_millis() as u64,
+                                    ended_at: start.elapsed().as_millis() as u64,
+                                });
+                                info.rss = 0;
+                            }
+                        }
+
+                        relabel(pid, &mut procs, &args.filter);
+                        if let (Some(events), Some(info)) = (&mut events, procs.get(&pid)) {
+                            events.exec(pid, info.name.as_deref(), info.cmdline.as_deref())?;
+                        }
+
+                        // ptrace events and /proc are the same for 32-bit processes, but
+                        // mixed-arch trees are unusual enough to be worth noting
+                        let elf32 = proc::is_32bit_exe(pid);
+                        procs.entry(pid).and_modify(|i| {
+                            i.elf32 = elf32;
+                            i.execd = Some(start.elapsed());
+                            i.events.execs += 1;
+                        });
+                        event_counts.execs += 1;
+
+                        // when a thread other than the leader execs, it takes over the
+                        // leader's pid and its own id disappears without an exit event, so
+                        // stop waiting for it rather than attributing it as a lost process
+                        if let Ok(former) = ptrace::getevent(pid) {
+                            let former = Pid::from_raw(former as i32);
+                            if former != pid {
+                                procs.entry(former).and_modify(|i| i.exited = true);
+                            }
+                        }
+
+                        ptrace::cont(pid, None)?;
+                    }
+                    WaitStatus::PtraceEvent(pid, signal, value)
+                        if value == Event::PTRACE_EVENT_STOP as i32 =>
+                    {
+                        match signal {
+                            // a group-stop (e.g.: from job control, or a SIGSTOP sent from
+                            // outside), so it stays stopped until it's sent a SIGCONT just like
+                            // it would if it weren't traced, rather than being resumed by us
+                            SIGSTOP | SIGTSTP | SIGTTIN | SIGTTOU => listen(pid)?,
+                            // a new child's first stop, or the end of a group-stop, so there's
+                            // nothing to pass on
+                            _ => ptrace::cont(pid, None)?,
+                        }
+                    }
+                    WaitStatus::Stopped(pid, signal) => {
+                        // seized tracees aren't sent any signals of ours (not even a SIGTRAP after
+                        // exec), so whatever it was is passed through to the process
+                        ptrace::cont(pid, Some(signal))?;
+                    }
+                    other => {
+                        // any other event we don't currently handle
+                        if let Some(pid) = other.pid() {
+                            ptrace::cont(pid, None)?;
+                        }
+                    }
+                }
+            }
+
+            // processes we're no longer tracing report nothing, so are polled instead
+            if last_poll.is_none_or(|last| last.elapsed() >= POLL_INTERVAL) {
+                last_poll = Some(Instant::now());
+                let untraced = procs
+                    .iter()
+                    .filter(|(_, i)| i.untraced && !i.exited)
+                    .map(|(pid, _)| *pid)
+                    .collect::<Vec<_>>();
+                let polled = !untraced.is_empty();
+                for pid in untraced {
+                    poll_untraced(pid, &mut procs, &mut pool, &args.filter, args.debug);
+                }
+                if polled && args.pid.is_none() {
+                    let children = proc::get_children(nix::unistd::getpid());
+                    adopt_orphans(child, children, &mut procs, args.debug);
+                }
+            }
+
+            if let Some(max) = args.max_procs.filter(|max| prunable && procs.len() > *max) {
+                let before = procs.len();
+                evict_exited(child, child, &mut procs, args.count_policy);
+                prunable = false;
+                if args.debug {
+                    eprintln!(
+                        "::: evicted {} exited processes (limit: {})",
+                        before - procs.len(),
+                        max
+                    );
+                }
+            }
+
+            if let Some(timeout) = &mut timeout {
+                timeout.check();
+            }
+
+            if let Some(kill_remaining) = &mut kill_remaining {
+                if procs.get(&child).is_some_and(|i| i.exited) {
+                    kill_remaining.exited();
+                }
+                if let Some(signal) = kill_remaining.check() {
+                    killed_at_teardown += kill_all(&mut procs, signal, args.debug);
+                }
+            }
+
+            if sampling && last_sample.is_none_or(|last| last.elapsed() >= args.interval) {
+                last_sample = Some(Instant::now());
+                let rss = sample(
+                    child,
+                    &mut procs,
+                    &mut pool,
+                    args,
+                    start.elapsed(),
+                    events.as_mut(),
+                    concurrent.as_mut(),
+                )?;
+                if let Some(timeline) = &mut timeline {
+                    timeline.sample(rss)?;
+                }
+                if let Some(alert) = &mut alert {
+                    alert.check(rss);
+                }
+            }
+
+            // we only need waking up while nothing's happening if there's sampling, polling or a
+            // deadline to check
+            let polling = procs.values().any(|i| i.untraced && !i.exited);
+            ticker.set(
+                [
+                    sampling.then_some(args.interval),
+                    polling.then_some(POLL_INTERVAL),
+                    (timeout.is_some() || kill_remaining.is_some())
+                        .then_some(timeout::CHECK_INTERVAL),
+                ]
+                .into_iter()
+                .flatten()
+                .min(),
+            );
+        }
+    })();
+
+    let error = traced.err();
+    if error.is_some() {
+        read_remaining(&mut procs, &mut pool, args.io);
+    }
+
+    // the root may have died before we could continue it, so make sure it's reaped
+    if rusage_max_rss.is_none() && args.pid.is_none() {
+        if let Ok((_, Some(usage))) = wait4(child, WaitPidFlag::WNOHANG) {
+            rusage_max_rss = Some(usage.ru_maxrss as u64 * 1024);
+        }
+    }
+
+    // the root's own rusage already includes everything it reaped itself, whereas
+    // getrusage(RUSAGE_CHILDREN) would also include every earlier run, step and --compare command
+    let ru_maxrss = rusage_max_rss.filter(|_| args.rusage);
+
+    let evicted = procs.values().fold(Evicted::default(), |mut acc, i| {
+        acc.add(i.evicted);
+        acc
+    });
+    let (total_rss, total_reads, total_hwm) = procs.iter().fold(
+        (evicted.rss, evicted.reads, evicted.hwm),
+        |acc, (pid, i)| {
+            if counted(*pid, child, i, args.count_policy) {
+                (acc.0 + i.peak_rss(), acc.1 + 1, acc.2 + i.hwm.unwrap_or(0))
+            } else {
+                acc
+            }
+        },
+    );
+
+    // these are totalled the same way as max_rss, from the values read at exit
+    let total = |value: fn(&ProcInfo) -> Option<u64>| {
+        procs
+            .iter()
+            .filter(|(pid, i)| counted(**pid, child, i, args.count_policy))
+            .filter_map(|(_, i)| value(i))
+            .sum::<u64>()
+    };
+    let max_rss = match (args.count_policy, &concurrent) {
+        (_, Some(concurrent)) => concurrent.peak(),
+        // a process polled rather than read at exit only has its RSS
+        (CountPolicy::PssSum, None) => total(|i| Some(i.pss.unwrap_or(i.rss))) + evicted.pss,
+        _ => total_rss,
+    };
+    let max_rss_exit = (args.source != RssSource::Rss)
+        .then(|| total(|i| Some(i.exit_rss.unwrap_or(i.rss))) + evicted.exit_rss);
+    // statm and status have no USS or swap to report
+    let smaps = matches!(args.mem_source, MemSource::SmapsRollup | MemSource::Smaps);
+    let max_uss = smaps.then(|| total(|i| i.uss) + evicted.uss);
+    let max_swap = smaps.then(|| total(|i| i.swap) + evicted.swap);
+
+    // CPU time and faults are totalled over every process, whether or not it counts towards max_rss
+    let (mut utime, mut stime) = (evicted.utime, evicted.stime);
+    let (mut minor_faults, mut major_faults) = (evicted.minor_faults, evicted.major_faults);
+    for stat in procs.values().filter_map(|i| i.stat) {
+        utime += stat.utime;
+        stime += stat.stime;
+        minor_faults += stat.minor_faults;
+        major_faults += stat.major_faults;
+    }
+    let io = args.io.then(|| {
+        procs
+            .values()
+            .fold(evicted.io.unwrap_or_default(), |mut acc, i| {
+                acc.add(i.io.unwrap_or_default());
+                acc
+            })
+    });
+
+    // wait4's rusage isn't compared, since it includes what the child used before exec'ing COMMAND
+    let verify = args.verify.map(|tolerance| {
+        let pairs = [(
+            Source {
+                name: "smaps_rollup",
+                value: max_rss_exit.unwrap_or(total_rss),
+            },
+            Source {
+                name: "vm_hwm",
+                value: total_hwm,
+            },
+        )];
+
+        let result = verify::verify(&pairs, tolerance);
+        for warning in result["warnings"].as_array().into_iter().flatten() {
+            eprintln!(
+                "{}: warning: {}",
+                env!("CARGO_PKG_NAME"),
+                warning.as_str().unwrap_or_default()
+            );
+        }
+
+        result
+    });
+
+    let report = Report {
+        schema_version: Some(report::SCHEMA_VERSION),
+        max_rss,
+        count_policy: Some(args.count_policy.name().into()),
+        source: Some(args.source.name().into()),
+        mem_source: Some(args.mem_source.name().into()),
+        max_rss_exit,
+        max_uss,
+        max_swap,
+        rusage_max_rss,
+        ru_maxrss,
+        utime_ms: Some(utime.as_millis() as u64),
+        stime_ms: Some(stime.as_millis() as u64),
+        minor_faults: Some(minor_faults),
+        major_faults: Some(major_faults),
+        io: io.map(io_report),
+        total_pids: Some(procs.len() + evicted.pids),
+        total_processes: Some(
+            procs.values().filter(|i| i.leader.is_none()).count() + evicted.pids - evicted.threads,
+        ),
+        total_threads: Some(
+            procs.values().filter(|i| i.leader.is_some()).count() + evicted.threads,
+        ),
+        total_reads: Some(total_reads),
+        events: Some(event_counts),
+        sample_interval_ms: args
+            .sample_peaks
+            .then_some(args.interval.as_secs_f64() * 1000.0),
+        degraded: Some(evicted.untraced || procs.values().any(|i| i.untraced)),
+        exit_code: exit_code.filter(|_| !interrupted && error.is_none()),
+        exit_signal: exit_signal.filter(|_| !interrupted && error.is_none()),
+        timed_out: timeout.as_ref().map(Timeout::timed_out),
+        killed_at_teardown: kill_remaining.is_some().then_some(killed_at_teardown),
+        left_running,
+        attached: args.pid.is_some().then_some(true),
+        interrupted: interrupted.then_some(true),
+        verify,
+        timeline: timeline.map(Timeline::finish).transpose()?,
+        alerts: alert.as_ref().map(Alert::fired),
+        child_log: capture.map(LogCapture::finish),
+        child_output: output.map(ChildOutput::finish),
+        cgroup: None,
+        graph: Some(tree(child, "0".into(), &procs)),
+        error: error.as_ref().map(|e| format!("{:#}", e)),
+        error_cause: None,
+        other: Map::new(),
+    };
+
+    Ok(Trace {
+        report: serde_json::to_value(report)?,
+        exit_code: args.exit_with.exit_code(exit_code, exit_signal),
+        snapshots,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(exited: bool, rss: u64, children: &[i32]) -> ProcInfo {
+        ProcInfo {
+            exited,
+            rss,
+            children: children.iter().map(|c| Pid::from_raw(*c)).collect(),
+            ..ProcInfo::default()
+        }
+    }
+
+    #[test]
+    fn series() {
+        let mut series = Series::default();
+        assert_eq!(series.to_report(), None);
+
+        for (ms, rss) in [(0, 10), (5, 10), (10, 20), (15, 10)] {
+            series.push(Duration::from_millis(ms), rss);
+        }
+        assert_eq!(series.to_report(), Some(vec![(0, 10), (10, 20), (15, 10)]));
+    }
+
+    #[test]
+    fn exit_statuses() {
+        assert_eq!(exit_status(0), Some(ExitStatus::Exited(0)));
+        assert_eq!(exit_status(3 << 8), Some(ExitStatus::Exited(3)));
+        // with the core dump flag
+        assert_eq!(
+            exit_status(0x80 | 11),
+            Some(ExitStatus::Signaled("SIGSEGV".into()))
+        );
+        assert_eq!(exit_status(0x7f), None);
+    }
+
+    #[test]
+    fn adopts_orphans() {
+        // any child of ours which isn't known is taken to have been orphaned by COMMAND
+        let root = Pid::from_raw(1);
+        let mut procs = HashMap::from([
+            (root, info(false, 10, &[2])),
+            (Pid::from_raw(2), info(false, 20, &[])),
+        ]);
+        let children = [2, 3].map(Pid::from_raw).to_vec();
+        adopt_orphans(root, children, &mut procs, false);
+
+        let graph = tree(root, "0".into(), &procs);
+        let children = graph.children.expect("no children");
+        assert_eq!(children.iter().map(|c| c.id).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(children[0].orphaned, None);
+        assert_eq!(children[1].orphaned, Some(true));
+        assert_eq!(children[1].untraced, Some(true));
+    }
+
+    #[test]
+    fn evicts_exited_subtrees() {
+        // 1 -> 2 -> (3, 4 -> 5), 1 -> 6, where only 1 and 6 are still running
+        let mut procs = HashMap::from([
+            (Pid::from_raw(1), info(false, 10, &[2, 6])),
+            (Pid::from_raw(2), info(true, 20, &[3, 4])),
+            (Pid::from_raw(3), info(true, 30, &[])),
+            (Pid::from_raw(4), info(true, 40, &[5])),
+            (Pid::from_raw(5), info(true, 50, &[])),
+            (Pid::from_raw(6), info(false, 60, &[])),
+        ]);
+
+        let root = Pid::from_raw(1);
+        assert!(!evict_exited(
+            root,
+            root,
+            &mut procs,
+            CountPolicy::RootAndParents
+        ));
+        assert_eq!(procs.len(), 2);
+
+        // the leaves 3 and 5 didn't count before, and still don't
+        let evicted = procs[&root].evicted;
+        assert_eq!((evicted.pids, evicted.rss, evicted.reads), (4, 60, 2));
+        assert_eq!(procs[&root].children, vec![Pid::from_raw(6)]);
+        assert!(counted(
+            Pid::from_raw(1),
+            root,
+            &procs[&root],
+            CountPolicy::RootAndParents
+        ));
+
+        // processes filtered out by --only or --exclude never count, whatever the policy
+        procs.entry(root).and_modify(|i| i.excluded = true);
+        assert!(!counted(root, root, &procs[&root], CountPolicy::All));
+    }
+}
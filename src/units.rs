@@ -0,0 +1,112 @@
+//! Parsing and formatting of human readable sizes and durations.
+
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Parses a size such as `512`, `100K`, `1.5GiB` or `2 MB` into bytes. Units are always treated as
+/// powers of 1024.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number = match number.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => n,
+        _ => bail!("invalid size: {}", s),
+    };
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        "t" | "tb" | "tib" => 1 << 40,
+        _ => bail!("invalid size unit: {}", unit),
+    };
+
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+/// Parses a duration such as `500ms`, `10s`, `1.5h` or `7d`. Plain numbers are seconds.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number = match number.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => n,
+        _ => bail!("invalid duration: {}", s),
+    };
+
+    let multiplier = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        _ => bail!("invalid duration unit: {}", unit),
+    };
+
+    Ok(Duration::from_secs_f64(number * multiplier))
+}
+
+/// Formats a number of bytes with binary units, e.g.: `412 MiB` or `1.5 GiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 || value >= 100.0 || value.fract() == 0.0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("100K").unwrap(), 100 * 1024);
+        assert_eq!(parse_size("2 MB").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 1536 * 1024 * 1024);
+        assert!(parse_size("").is_err());
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("M").is_err());
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(
+            parse_duration("7d").unwrap(),
+            Duration::from_secs(7 * 86400)
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5M").is_err());
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1 KiB");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(412 * 1024 * 1024 + 1000), "412 MiB");
+    }
+}
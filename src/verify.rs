@@ -0,0 +1,81 @@
+//! Cross-checks the values reported by different measurement sources, to catch cases where the
+//! number we report is likely to be wrong.
+
+use serde_json::{json, Value};
+
+/// The default tolerance used by `--verify`, as a fraction.
+pub const DEFAULT_TOLERANCE: f64 = 0.1;
+
+/// A value as reported by a particular measurement source.
+#[derive(Debug, Clone, Copy)]
+pub struct Source {
+    pub name: &'static str,
+    pub value: u64,
+}
+
+/// The relative difference between two values, as a fraction of the larger one.
+fn divergence(a: u64, b: u64) -> f64 {
+    let max = a.max(b);
+    if max == 0 {
+        0.0
+    } else {
+        a.abs_diff(b) as f64 / max as f64
+    }
+}
+
+/// Compares each pair of comparable sources, returning the results along with a list of warnings
+/// for those which diverge more than the given tolerance.
+pub fn verify(pairs: &[(Source, Source)], tolerance: f64) -> Value {
+    let mut sources = serde_json::Map::new();
+    let mut warnings = vec![];
+
+    for (a, b) in pairs {
+        sources.insert(a.name.into(), a.value.into());
+        sources.insert(b.name.into(), b.value.into());
+
+        let divergence = divergence(a.value, b.value);
+        if divergence > tolerance {
+            warnings.push(format!(
+                "{} ({}) and {} ({}) differ by {:.1}%, which is more than the tolerance of {:.1}%",
+                a.name,
+                a.value,
+                b.name,
+                b.value,
+                divergence * 100.0,
+                tolerance * 100.0
+            ));
+        }
+    }
+
+    json!({
+        "tolerance": tolerance,
+        "sources": sources,
+        "warnings": warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &'static str, value: u64) -> Source {
+        Source { name, value }
+    }
+
+    #[test]
+    fn divergences() {
+        assert_eq!(divergence(0, 0), 0.0);
+        assert_eq!(divergence(100, 50), 0.5);
+        assert_eq!(divergence(50, 100), 0.5);
+    }
+
+    #[test]
+    fn warnings() {
+        let result = verify(&[(source("a", 100), source("b", 95))], 0.1);
+        assert_eq!(result["warnings"], json!([]));
+        assert_eq!(result["sources"], json!({ "a": 100, "b": 95 }));
+
+        let result = verify(&[(source("a", 100), source("b", 50))], 0.1);
+        assert_eq!(result["warnings"].as_array().unwrap().len(), 1);
+    }
+}
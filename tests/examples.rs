@@ -4,6 +4,7 @@ use std::process::{Command, Stdio};
 
 use serde_json::Value;
 
+#[allow(clippy::expect_fun_call)]
 fn cmd(bin: &str, args: &[&str]) -> String {
     let output = Command::new(bin)
         .args(args)
@@ -26,7 +27,11 @@ fn run(example_name: &str) -> Value {
         example_name
     );
 
-    let out = format!("{}.json", example_name);
+    let out = std::env::temp_dir().join(format!(
+        "max_rss-{}-{}.json",
+        example_name,
+        std::process::id()
+    ));
     match fs::remove_file(&out) {
         Ok(_) => {}
         Err(e) if e.kind() == ErrorKind::NotFound => {}
@@ -41,12 +46,13 @@ fn run(example_name: &str) -> Value {
             "--return-result",
             "--debug",
             "--output",
-            &out,
+            out.to_str().unwrap(),
             &bin,
         ],
     );
 
     let text = fs::read_to_string(&out).expect("failed to read output");
+    fs::remove_file(&out).unwrap();
     let json = serde_json::from_str::<Value>(&text).expect("failed to parse JSON");
 
     eprintln!("{}", stderr);
@@ -58,12 +64,38 @@ fn print() {
     let json = run("print");
     assert_eq!(json["total_pids"], 1);
     assert_eq!(json["total_reads"], 1);
+    assert!(json["rusage_max_rss"].as_u64().unwrap() > 0);
+
+    let uss = json["max_uss"].as_u64().unwrap();
+    assert!(uss > 0 && uss <= json["max_rss"].as_u64().unwrap());
+    assert_eq!(json["graph"]["uss"], uss);
+    assert_eq!(json["graph"]["swap"], json["max_swap"]);
+
+    // the only process' times are the totals
+    assert_eq!(json["graph"]["utime_ms"], json["utime_ms"]);
+    assert_eq!(json["graph"]["stime_ms"], json["stime_ms"]);
+    assert!(json["graph"]["wall_time_ms"].is_u64());
+    assert!(json["minor_faults"].as_u64().unwrap() > 0);
+    assert_eq!(json["graph"]["major_faults"], json["major_faults"]);
+
+    let meta = &json["meta"];
+    assert!(meta["command"][0]
+        .as_str()
+        .unwrap()
+        .ends_with("/examples/print"));
+    assert!(meta["started_at"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(json["started_at"], Value::Null);
+    assert!(meta["duration_ms"].as_f64().unwrap() > 0.0);
+    assert_eq!(meta["version"], env!("CARGO_PKG_VERSION"));
+    assert!(meta["page_size"].as_u64().unwrap() > 0);
 }
 
 #[test]
 fn fork() {
     let json = run("fork");
     assert_eq!(json["total_pids"], 2);
+    assert_eq!(json["total_processes"], 2);
+    assert_eq!(json["total_threads"], 0);
     assert_eq!(json["total_reads"], 1);
 }
 
@@ -78,21 +110,39 @@ fn double_fork() {
 fn threads() {
     let json = run("threads");
     assert_eq!(json["total_pids"], 11);
+    assert_eq!(json["total_processes"], 1);
+    assert_eq!(json["total_threads"], 10);
     assert_eq!(json["total_reads"], 1);
+
+    let threads = json["graph"]["children"].as_array().unwrap();
+    assert_eq!(threads.len(), 10);
+    assert!(threads.iter().all(|thread| thread["thread"] == true));
+    assert_eq!(json["graph"]["thread"], Value::Null);
 }
 
 #[test]
 fn fork_threads() {
     let json = run("fork_threads");
     assert_eq!(json["total_pids"], 12);
+    assert_eq!(json["total_processes"], 2);
+    assert_eq!(json["total_threads"], 10);
     assert_eq!(json["total_reads"], 2);
+
+    // the threads are nested under the forked process, not the root
+    let child = &json["graph"]["children"][0];
+    assert_eq!(child["thread"], Value::Null);
+    assert_eq!(child["children"].as_array().unwrap().len(), 10);
 }
 
 #[test]
 fn kill_threads() {
     let json = run("kill_threads");
     assert_eq!(json["total_pids"], 11);
-    assert_eq!(json["total_reads"], 1);
+    // despite the name, these are processes spawned with clone(CLONE_VM | CLONE_VFORK)
+    assert_eq!(json["total_processes"], 11);
+    assert_eq!(json["total_threads"], 0);
+    // each `yes` was exec'd
+    assert_eq!(json["total_reads"], 11);
 }
 
 #[test]
@@ -110,3 +160,1007 @@ fn tracee_exit_1() {
     assert_eq!(json["total_pids"], 1);
     assert_eq!(json["total_reads"], 1);
 }
+
+#[test]
+fn passthrough_signals() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--output",
+            "/dev/null",
+            "cat",
+            "/proc/self/status",
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .expect("failed to run command");
+
+    let status = String::from_utf8_lossy(&output.stdout);
+    let ignored = status
+        .lines()
+        .find_map(|line| line.strip_prefix("SigIgn:"))
+        .map(|mask| u64::from_str_radix(mask.trim(), 16).unwrap())
+        .expect("failed to find SigIgn");
+
+    // SIGPIPE is 13, so it's the 13th bit in the mask
+    assert_eq!(ignored & (1 << 12), 0);
+}
+
+#[test]
+fn output_stdout() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "-", "echo", "hello"])
+        .output()
+        .expect("failed to run command");
+
+    // COMMAND's output is moved to stderr, so stdout is only the results
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["total_pids"], 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("hello"));
+}
+
+#[test]
+fn attach() {
+    // yama only allows tracing descendants, and the process isn't a descendant of max_rss here
+    let scope = fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope").unwrap_or_default();
+    if scope.trim().parse::<u32>().unwrap_or(0) > 0 {
+        return;
+    }
+
+    let mut sleep = Command::new("sleep").arg("1").spawn().unwrap();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--pid",
+            &sleep.id().to_string(),
+            "--output",
+            "-",
+        ])
+        .output()
+        .expect("failed to run command");
+    sleep.wait().unwrap();
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["attached"], true);
+    assert_eq!(json["total_pids"], 1);
+    assert_eq!(json["graph"]["name"], "sleep");
+    assert!(json["max_rss"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn timeout() {
+    // the shell ignores SIGTERM, so has to be killed once the grace period is over
+    let started = std::time::Instant::now();
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--timeout=200ms",
+            "--kill-after=200ms",
+            "--return-result",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "trap '' TERM; sleep 10; sleep 10",
+        ])
+        .output()
+        .expect("failed to run command");
+    assert!(started.elapsed().as_secs() < 10);
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["timed_out"], true);
+    assert_eq!(json["exit_code"], Value::Null);
+    assert_eq!(json["exit_signal"], 9);
+    assert_eq!(output.status.code(), Some(128 + 9));
+}
+
+#[test]
+fn exit_with() {
+    for (policy, code) in [("child", 3), ("error-only", 0), ("always-zero", 0)] {
+        let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+            .args(["--exit-with", policy, "--output", "-", "sh", "-c", "exit 3"])
+            .output()
+            .expect("failed to run command");
+
+        let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+        assert_eq!(json["exit_code"], 3);
+        assert_eq!(output.status.code(), Some(code), "--exit-with {}", policy);
+    }
+
+    // going over the threshold is ignored, too
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--exit-with=always-zero", "--exit-code-map=threshold=98"])
+        .args(["--threshold=1", "--output", "-", "true"])
+        .output()
+        .expect("failed to run command");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn trace_denied() {
+    // the outer run already traces whatever the inner one starts, and there can only be one tracer
+    let bin = env!("CARGO_BIN_EXE_max_rss");
+    let output = Command::new(bin)
+        .args(["--output", "/dev/null", bin, "--output", "-", "touch", "/"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["error_cause"], "already_traced");
+    assert!(json["error"].as_str().unwrap().contains("--backend cgroup"));
+    assert!(json["graph"].is_null());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("not permitted to trace"), "{}", stderr);
+    // COMMAND never ran, since it couldn't be measured
+    assert!(!stderr.contains("touch"), "{}", stderr);
+}
+
+#[test]
+fn otlp() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut head = vec![];
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            head.push(line);
+        }
+        let length = head
+            .iter()
+            .find_map(|l| l.strip_prefix("Content-Length: "))
+            .and_then(|l| l.trim().parse::<usize>().ok())
+            .unwrap();
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        (head[0].clone(), body)
+    });
+
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--otlp", &endpoint, "--output", "/dev/null", "true"])
+        .output()
+        .expect("failed to run command");
+    assert!(output.status.success());
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let (request, body) = server.join().unwrap();
+    assert_eq!(request, "POST /v1/metrics HTTP/1.1\r\n");
+    let json = serde_json::from_slice::<Value>(&body).expect("failed to parse JSON");
+    let metric = &json["resourceMetrics"][0]["scopeMetrics"][0]["metrics"][0];
+    assert_eq!(metric["name"], "max_rss");
+    assert!(metric["gauge"]["dataPoints"][0]["asInt"].as_str().unwrap() != "0");
+}
+
+#[test]
+fn github_summary() {
+    let path = std::env::temp_dir().join(format!("max_rss-summary-{}.md", std::process::id()));
+    fs::write(&path, "# Earlier step\n").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .env("GITHUB_STEP_SUMMARY", &path)
+        .args([
+            "--github-summary",
+            "--threshold=1",
+            "--output",
+            "/dev/null",
+            "true",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let summary = std::fs::read_to_string(&path).unwrap();
+    let lines = summary.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "# Earlier step");
+    assert!(lines[3].starts_with("| `true` | "));
+    assert!(lines[3].contains(":warning:"));
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .starts_with("::warning title=max_rss::max_rss of true is "));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn statsd() {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+        .unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--statsd", &address, "--output", "/dev/null", "true"])
+        .output()
+        .expect("failed to run command");
+    assert!(output.status.success());
+
+    let mut packet = [0; 1024];
+    let len = socket.recv(&mut packet).unwrap();
+    let packet = String::from_utf8_lossy(&packet[..len]);
+    let lines = packet.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("max_rss.true.max_rss:"));
+    assert_eq!(lines[1], "max_rss.true.total_pids:1|g");
+    assert!(lines[2].starts_with("max_rss.true.wall_time_ms:"));
+}
+
+#[test]
+fn backend_rusage() {
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--backend=rusage", "--output", "-", "sh", "-c", "exit 2"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["backend"], "rusage");
+    assert_eq!(json["exit_code"], 2);
+    assert!(json["max_rss"].as_u64().unwrap() > 0);
+    assert_eq!(json["max_rss"], json["rusage_max_rss"]);
+    assert_eq!(json["graph"], Value::Null);
+}
+
+#[test]
+fn backend_rusage_path() {
+    // COMMAND is looked up in the PATH it's given, not ours
+    let run = |path: &str| {
+        Command::new(env!("CARGO_BIN_EXE_max_rss"))
+            .args(["--backend=rusage", "--env", path, "--output", "-", "true"])
+            .output()
+            .expect("failed to run command")
+    };
+
+    let json = serde_json::from_slice::<Value>(&run("PATH=/nonexistent:/usr/bin:/bin").stdout)
+        .expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 0);
+
+    let json = serde_json::from_slice::<Value>(&run("PATH=/nonexistent").stdout)
+        .expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 127);
+    assert_eq!(json["error"], "true: command not found");
+}
+
+#[test]
+fn exec_failed() {
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--output", "-", "max_rss-no-such-command"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["error"], "max_rss-no-such-command: command not found");
+    assert_eq!(json["exit_code"], 127);
+    assert_eq!(output.status.code(), Some(127));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "max_rss: max_rss-no-such-command: command not found"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--output", "-", "/dev/null"])
+        .output()
+        .expect("failed to run command");
+    assert_eq!(output.status.code(), Some(126));
+}
+
+#[test]
+fn forward() {
+    // run the binary itself rather than through cargo, so the signal is sent to max_rss
+    let started = std::time::Instant::now();
+    let child = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--return-result", "--output", "-", "sleep", "10"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run command");
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(started.elapsed().as_secs() < 10);
+
+    // sleep was terminated, but max_rss carried on to write the results
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["exit_signal"], 15);
+    assert_eq!(output.status.code(), Some(128 + 15));
+    assert!(json["max_rss"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn io() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--io",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "head -c 10000 /dev/zero > /dev/null",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    // everything head wrote is counted, wherever it went
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert!(json["io"]["wchar"].as_u64().unwrap() >= 10000);
+    assert!(json["graph"]["io"].is_object());
+}
+
+#[test]
+fn env() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--clear-env",
+            "--env",
+            "FOO=bar",
+            "--capture-env",
+            "--return-result",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "test \"$FOO\" = bar && test -z \"$CARGO\"",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 0);
+    assert_eq!(json["env"], serde_json::json!({ "FOO": "bar" }));
+}
+
+#[test]
+fn cwd() {
+    let dir = std::env::temp_dir();
+    let output = Command::new("cargo")
+        .args(["run", "--", "--cwd"])
+        .arg(&dir)
+        .args(["--return-result", "--output", "-", "sh", "-c", "pwd -P"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 0);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).lines().last(),
+        dir.canonicalize().unwrap().to_str()
+    );
+}
+
+#[test]
+fn stdout_stderr() {
+    let path = std::env::temp_dir().join(format!("max_rss-stdout-{}.txt", std::process::id()));
+    let output = Command::new("cargo")
+        .args(["run", "--", "--quiet", "--stdout"])
+        .arg(&path)
+        .args(["--output", "-", "sh", "-c", "echo hello; echo oops >&2"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello\n");
+    assert_eq!(
+        json["child_output"]["stdout"]["path"],
+        path.to_str().unwrap()
+    );
+    assert_eq!(json["child_output"]["stdout"]["bytes"], 6);
+    assert_eq!(json["child_output"]["stderr"]["path"], "/dev/null");
+    assert!(!String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .any(|line| line == "oops"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn runs() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--runs", "3", "--output", "-", "true"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    let runs = json["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 3);
+    assert_eq!(runs.last(), Some(&json["max_rss"]));
+    assert!(json["stats"]["min"].as_f64() <= json["stats"]["max"].as_f64());
+}
+
+#[test]
+fn compare_commands() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "compare",
+            "--runs",
+            "2",
+            "--output",
+            "-",
+            "--",
+            "true",
+            "--",
+            "sh -c true",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["old"], "true");
+    assert_eq!(json["new"], "sh -c true");
+    assert_eq!(json["runs"]["old"].as_array().unwrap().len(), 2);
+    assert!(json["max_rss"]["diff"].is_i64());
+}
+
+#[test]
+fn report_and_merge() {
+    let dir = std::env::temp_dir().join(format!("max_rss-merge-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (a, b) = (dir.join("a.json"), dir.join("b.json"));
+    fs::write(
+        &a,
+        r#"{"max_rss":1024,"total_pids":1,"graph":{"id":1,"name":"sh","rss":1024}}"#,
+    )
+    .unwrap();
+    fs::write(&b, r#"{"max_rss":4096,"total_pids":2}"#).unwrap();
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "report"])
+        .arg(&a)
+        .output()
+        .expect("failed to run command");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("max rss:     1 KiB (1024 bytes)\n"));
+    assert!(stdout.ends_with("\nsh (1): 1 KiB\n"));
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "merge", "--output", "-"])
+        .args([&a, &b])
+        .output()
+        .expect("failed to run command");
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["max_rss"], 4096);
+    assert_eq!(json["total_pids"], 3);
+    assert_eq!(json["reports"].as_array().unwrap().len(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn stream() {
+    let path = std::env::temp_dir().join(format!("max_rss-stream-{}.ndjson", std::process::id()));
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "-", "--stream"])
+        .arg(&path)
+        .args(["--", "sh", "-c", "ls >/dev/null; true"])
+        .output()
+        .expect("failed to run command");
+    assert!(output.status.success());
+
+    let events = fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<Value>(line).expect("failed to parse JSON"))
+        .collect::<Vec<_>>();
+    let kinds = events
+        .iter()
+        .map(|e| e["event"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds.first(), Some(&Value::from("spawned")));
+    assert_eq!(kinds.last(), Some(&Value::from("exited")));
+    assert!(events
+        .iter()
+        .any(|e| e["event"] == "exec" && e["name"] == "ls" && e["cmdline"][0] == "ls"));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rusage() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--rusage", "--output", "-", "true"])
+        .output()
+        .expect("failed to run command");
+
+    // the root is the only process, so both come from the same accounting
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert!(json["ru_maxrss"].as_u64().unwrap() > 0);
+    assert_eq!(json["ru_maxrss"], json["rusage_max_rss"]);
+}
+
+#[test]
+fn rusage_excludes_setup() {
+    // the setup step holds ~64MiB in a shell variable, which mustn't count towards COMMAND
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--rusage",
+            "--setup",
+            "x=$(head -c 67108864 /dev/zero | tr '\\0' a)",
+            "--output",
+            "-",
+            "true",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert!(json["ru_maxrss"].as_u64().unwrap() < 32 * 1024 * 1024);
+}
+
+#[test]
+fn graph_comm() {
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--output", "-", "sh", "-c", "ls >/dev/null; true"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["graph"]["comm"], "sh");
+    let ls = &json["graph"]["children"][0];
+    assert_eq!(ls["comm"], "ls");
+    assert_eq!(ls["cmdline"], serde_json::json!(["ls"]));
+}
+
+#[test]
+fn verify_agrees() {
+    let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+        .args(["--verify", "--output", "-", "true"])
+        .output()
+        .expect("failed to run command");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("warning"), "{}", stderr);
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["verify"]["warnings"], serde_json::json!([]));
+}
+
+#[test]
+fn source_hwm() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--source", "hwm", "--output", "-", "true"])
+        .output()
+        .expect("failed to run command");
+
+    // the high-water mark can never be lower than what's left at exit
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["source"], "hwm");
+    let exit = json["max_rss_exit"].as_u64().unwrap();
+    assert!(json["max_rss"].as_u64().unwrap() >= exit);
+    assert_eq!(json["graph"]["exit_rss"], exit);
+}
+
+#[test]
+fn count_policies() {
+    let policy = |policy: &str| {
+        let output = Command::new("cargo")
+            .args([
+                "run",
+                "--",
+                "--count-policy",
+                policy,
+                "--output",
+                "-",
+                "sh",
+                "-c",
+                "true & sleep 0.1 & wait",
+            ])
+            .output()
+            .expect("failed to run command");
+        let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+        assert_eq!(json["count_policy"], policy);
+        json
+    };
+
+    // `true` is forked without exec'ing (and spawns nothing), so only counts with the other
+    // policies, while `sleep` is exec'd
+    let default = policy("root-and-parents");
+    assert_eq!(default["total_reads"], 2);
+    let all = policy("all");
+    assert_eq!(all["total_reads"], 3);
+    assert!(all["max_rss"].as_u64() > default["max_rss"].as_u64());
+
+    // every process is alive at once, and each is read at least as it exits
+    for policy in [policy("pss-sum"), policy("concurrent-peak")] {
+        assert_eq!(policy["total_reads"], 3);
+        assert!(policy["max_rss"].as_u64().unwrap() > 0);
+    }
+}
+
+#[test]
+fn filters() {
+    let filtered = |args: &[&str]| {
+        let output = Command::new("cargo")
+            .args(["run", "--", "--output", "-"])
+            .args(args)
+            .args(["sh", "-c", "cat /dev/null; ls >/dev/null"])
+            .output()
+            .expect("failed to run command");
+        serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON")
+    };
+
+    // only cat counts, but sh and ls are still in the graph
+    let only = filtered(&["--only", "^cat$"]);
+    assert_eq!(only["total_reads"], 1);
+    assert_eq!(only["total_processes"], 3);
+    assert_eq!(only["graph"]["excluded"], true);
+    let cat = &only["graph"]["children"][0];
+    assert_eq!(cat["name"], "cat");
+    assert_eq!(cat["excluded"], Value::Null);
+    assert_eq!(only["max_rss"], cat["rss"]);
+
+    let exclude = filtered(&["--exclude", "^sh$"]);
+    assert_eq!(exclude["total_reads"], 2);
+    assert_eq!(exclude["graph"]["excluded"], true);
+    assert_eq!(exclude["graph"]["children"][1]["excluded"], Value::Null);
+}
+
+#[test]
+fn segments() {
+    // sh grows by ~20MB, and then execs sleep which is much smaller
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--interval",
+            "10ms",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "x=$(head -c 20000000 /dev/zero | tr '\\0' a); sleep 0.05; exec sleep 0.05",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    let graph = &json["graph"];
+    assert_eq!(graph["name"], "sleep");
+    assert!(graph["rss"].as_u64().unwrap() < 20_000_000);
+    let segments = graph["segments"].as_array().expect("no segments");
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["name"], "sh");
+    let rss = segments[0]["rss"].as_u64().unwrap();
+    assert!(rss > 20_000_000);
+    // the largest of the two is what counts
+    assert!(json["max_rss"].as_u64().unwrap() >= rss);
+}
+
+#[test]
+fn event_counts() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "true & wait; ls >/dev/null",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    let events = &json["events"];
+    let spawned = |events: &Value| {
+        ["forks", "vforks", "clones"]
+            .iter()
+            .map(|key| events[key].as_u64().unwrap())
+            .sum::<u64>()
+    };
+    // sh itself was started with an exec too
+    assert!(events["execs"].as_u64().unwrap() >= 2);
+    assert!(spawned(events) >= 2);
+    // which were all done by the root process
+    assert!(spawned(&json["graph"]["events"]) >= 2);
+}
+
+#[test]
+fn mem_sources() {
+    for source in ["smaps_rollup", "smaps", "statm", "status"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_max_rss"))
+            .args([
+                "--mem-source",
+                source,
+                "--interval",
+                "10ms",
+                "--output",
+                "-",
+            ])
+            .args([
+                "sh",
+                "-c",
+                "x=$(head -c 20000000 /dev/zero | tr '\\0' a); sleep 0.05",
+            ])
+            .output()
+            .expect("failed to run command");
+
+        let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+        assert_eq!(json["mem_source"], source);
+        let max_rss = json["max_rss"].as_u64().unwrap();
+        assert!(max_rss > 20_000_000, "{}: {}", source, max_rss);
+        // only the smaps files have a USS to report
+        assert_eq!(
+            json["max_uss"].is_u64(),
+            source.starts_with("smaps"),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn exit_statuses() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "sh -c 'kill -SEGV $$'; exit 3",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(
+        json["graph"]["exit_status"],
+        serde_json::json!({ "exited": 3 })
+    );
+    assert_eq!(
+        json["graph"]["children"][0]["exit_status"],
+        serde_json::json!({ "signaled": "SIGSEGV" })
+    );
+}
+
+#[test]
+fn sigtrap_passes_through() {
+    // only signals the tracer causes itself would be suppressed, and a seized tracee has none
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "-", "sh", "-c", "kill -TRAP $$"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(
+        json["graph"]["exit_status"],
+        serde_json::json!({ "signaled": "SIGTRAP" })
+    );
+}
+
+#[test]
+fn group_stops() {
+    // the first sleep is stopped from outside until the second one is done
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "sleep 0.1 & kill -STOP $!; sleep 0.5; kill -CONT $!; wait",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    let stopped = &json["graph"]["children"][0];
+    assert_eq!(stopped["name"], "sleep");
+    assert_eq!(stopped["exit_status"], serde_json::json!({ "exited": 0 }));
+    // it stayed stopped rather than being resumed by the tracer
+    assert!(stopped["duration_ms"].as_u64().unwrap() >= 500);
+}
+
+#[test]
+fn lifetimes() {
+    let output = Command::new("cargo")
+        .args(["run", "--", "--output", "-", "sh", "-c", "sleep 0.2; true"])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    let (root, sleep) = (&json["graph"], &json["graph"]["children"][0]);
+    assert_eq!(root["started_at"], 0);
+    assert!(sleep["duration_ms"].as_u64().unwrap() >= 200);
+
+    // the sleep ran within the lifetime of its parent
+    let ms = |node: &Value, key: &str| node[key].as_u64().unwrap();
+    assert!(ms(sleep, "started_at") >= ms(root, "started_at"));
+    assert!(ms(sleep, "ended_at") <= ms(root, "ended_at"));
+    assert_eq!(
+        ms(sleep, "duration_ms"),
+        ms(sleep, "ended_at") - ms(sleep, "started_at")
+    );
+}
+
+#[test]
+fn daemonized() {
+    // the subshell exits straight away, orphaning the sleep
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "(sleep 0.2 &); true",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["total_processes"], 3);
+    let sleep = &json["graph"]["children"][0]["children"][0];
+    assert_eq!(sleep["name"], "sleep");
+    assert_eq!(sleep["exit_status"], serde_json::json!({ "exited": 0 }));
+    assert!(sleep["duration_ms"].as_u64().unwrap() >= 200);
+}
+
+#[test]
+fn kill_remaining() {
+    // sh exits straight away, leaving the sleep running in the background
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--kill-remaining=200ms",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "sleep 30 &",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["killed_at_teardown"], 1);
+    assert!(json["wall_time_ms"].as_f64().unwrap() < 30_000.0);
+    assert_eq!(json["graph"]["killed_at_teardown"], Value::Null);
+    let sleep = &json["graph"]["children"][0];
+    assert_eq!(sleep["name"], "sleep");
+    assert_eq!(sleep["killed_at_teardown"], true);
+    assert_eq!(
+        sleep["exit_status"],
+        serde_json::json!({ "signaled": "SIGTERM" })
+    );
+}
+
+#[test]
+fn exit_with_root() {
+    // sh exits once the sleep in the background has started, and isn't waited for
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--exit-with-root",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "sleep 5 >/dev/null 2>&1 & sleep 0.2; exit 3",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 3);
+    assert_eq!(json["left_running"], 1);
+    assert!(json["wall_time_ms"].as_f64().unwrap() < 5000.0);
+    let sleep = &json["graph"]["children"][0];
+    assert_eq!(sleep["name"], "sleep");
+    assert_eq!(sleep["exit_status"], "detached");
+
+    // it's left running as it was, rather than stopped
+    let pid = sleep["id"].to_string();
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).expect("sleep isn't running");
+    let state = stat.rsplit(") ").next().unwrap().chars().next().unwrap();
+    assert!(!['T', 't'].contains(&state), "sleep is stopped");
+    let _ = Command::new("kill").arg(&pid).status();
+}
+
+#[test]
+fn pidns() {
+    // the orphaned sleep is killed along with the rest of the namespace when sh exits
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--pidns",
+            "--output",
+            "-",
+            "sh",
+            "-c",
+            "(sleep 5 &); true",
+        ])
+        .output()
+        .expect("failed to run command");
+
+    if String::from_utf8_lossy(&output.stderr).contains("failed to create a PID namespace") {
+        eprintln!("skipping, since PID namespaces aren't permitted here");
+        return;
+    }
+    let json = serde_json::from_slice::<Value>(&output.stdout).expect("failed to parse JSON");
+    assert_eq!(json["exit_code"], 0);
+    assert!(json["wall_time_ms"].as_f64().unwrap() < 5000.0);
+    let orphan = &json["graph"]["children"][0]["children"][0];
+    assert_eq!(
+        orphan["exit_status"],
+        serde_json::json!({ "signaled": "SIGKILL" })
+    );
+}
+
+#[test]
+fn no_new_privs() {
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--no-new-privs",
+            "--output",
+            "/dev/null",
+            "grep",
+            "NoNewPrivs",
+            "/proc/self/status",
+        ])
+        .stderr(Stdio::null())
+        .output()
+        .expect("failed to run command");
+
+    let status = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(status.split_ascii_whitespace().nth(1), Some("1"));
+}
+
+#[test]
+fn seccomp() {
+    let profile = std::env::temp_dir().join(format!("max_rss-seccomp-{}.json", std::process::id()));
+    fs::write(
+        &profile,
+        r#"{"defaultAction": "SCMP_ACT_ALLOW", "syscalls": [{"names": ["uname"], "action": "SCMP_ACT_ERRNO"}]}"#,
+    )
+    .expect("failed to write profile");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            "--return-result",
+            "--output",
+            "/dev/null",
+            "--seccomp",
+        ])
+        .arg(&profile)
+        .arg("uname")
+        .stderr(Stdio::piped())
+        .output()
+        .expect("failed to run command");
+    fs::remove_file(&profile).expect("failed to remove profile");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Operation not permitted"));
+}
@@ -0,0 +1,83 @@
+//! The library's API for measuring a [`Command`] in-process. These are kept apart from the other
+//! tests, since the tracer waits for every child of the test process while it runs.
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+
+use max_rss::{Event, MeasureRss, Tracer};
+
+#[test]
+fn measure() {
+    let report = Command::new("sh")
+        .args(["-c", "exit 3"])
+        .measure_rss()
+        .expect("failed to measure");
+    assert!(report.max_rss > 0);
+    assert_eq!(report.exit_code, Some(3));
+    assert_eq!(report.total_pids, Some(1));
+    assert_eq!(report.graph.map(|g| g.name), Some(Some("sh".into())));
+    assert_eq!(report.other["backend"], "ptrace");
+}
+
+#[test]
+fn measure_command_config() {
+    let mut command = Command::new("/bin/sh");
+    command
+        .args([
+            "-c",
+            r#"[ "$FOO" = bar ] && [ -z "$HOME" ] && [ "$(pwd)" = / ]"#,
+        ])
+        .env_clear()
+        .env("FOO", "bar")
+        .current_dir("/");
+    let report = max_rss::measure(command).expect("failed to measure");
+    assert_eq!(report.exit_code, Some(0));
+}
+
+#[test]
+fn measure_tracer() {
+    let report = Tracer::spawn(Command::new("true"))
+        .arg("--io")
+        .run()
+        .expect("failed to measure");
+    assert!(report.max_rss > 0);
+    assert_eq!(report.exit_code, Some(0));
+    assert!(report.io.is_some());
+
+    assert!(Tracer::spawn(Command::new("true"))
+        .args(["--runs", "2"])
+        .run()
+        .is_err());
+    assert!(Tracer::spawn(Command::new("true"))
+        .args(["--backend", "cgroup"])
+        .run()
+        .is_err());
+}
+
+#[test]
+fn measure_subscribe() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "sleep 0.2 & wait"]);
+    let subscription = Tracer::spawn(command)
+        .args(["--interval", "10ms"])
+        .subscribe()
+        .expect("failed to subscribe");
+    let events = subscription.events().iter().collect::<Vec<_>>();
+    let report = subscription.wait().expect("failed to measure");
+
+    let root = match &events[0] {
+        Event::ProcessSpawned {
+            pid, ppid: None, ..
+        } => *pid,
+        other => panic!("unexpected first event: {:?}", other),
+    };
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, Event::ProcessSpawned { ppid, .. } if *ppid == Some(root))));
+    assert!(events.iter().any(|e| matches!(e, Event::Sample { .. })));
+    assert!(matches!(
+        events.last(),
+        Some(Event::ProcessExited { pid, rss, .. }) if *pid == root && *rss > 0
+    ));
+    assert_eq!(report.total_pids, Some(2));
+}